@@ -0,0 +1,96 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Compare the client info storages side by side with
+//! `cargo bench --features smallvec,arrayvec`, the default `Vec` storage is always measured.
+//!
+//! The octets are decoded from a borrowed slice, so the only allocations measured are the ones
+//! of the storage.
+
+use std::hint::black_box;
+
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion};
+use flood_rs::OutOctetStream;
+
+use conclave_room_serialize::{
+    ClientIdentity, ClientInfo, ClientInfoStorage, ClientInfos, ConnectionIndex, DecodeOptions,
+    PresenceState, RoomInfoCommand, SliceReader, PROTOCOL_VERSION,
+};
+
+fn room_info_octets(client_count: u8) -> Vec<u8> {
+    let room_info = RoomInfoCommand {
+        term: 1,
+        leader_index: ConnectionIndex(0),
+        client_infos: (0..client_count)
+            .map(|index| ClientInfo {
+                identity: ClientIdentity::U64(index as u64),
+                connection_index: ConnectionIndex(index),
+                role_flags: 0,
+                revision: 0,
+                presence: PresenceState::Active,
+                network_stats: None,
+            })
+            .collect::<ClientInfos>(),
+        knowledge_vector: None,
+        quorum_size: 0,
+        votes_for_leader: 0,
+        leader_lease_expires_in_ms: 0,
+    };
+    let mut out_stream = OutOctetStream::new();
+    room_info
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+    out_stream.data
+}
+
+fn bench_storage<C: ClientInfoStorage>(
+    group: &mut BenchmarkGroup<WallTime>,
+    storage: &str,
+    client_count: u8,
+    octets: &[u8],
+) {
+    let options = DecodeOptions::new(PROTOCOL_VERSION);
+    group.bench_with_input(
+        BenchmarkId::new(storage, client_count),
+        octets,
+        |b, octets| {
+            b.iter(|| {
+                black_box(
+                    RoomInfoCommand::<C>::from_cursor_with_options(
+                        &mut SliceReader::new(octets),
+                        &options,
+                    )
+                    .unwrap(),
+                )
+            })
+        },
+    );
+}
+
+fn decode_room_info(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_room_info");
+    for client_count in [2, 8, 16] {
+        let octets = room_info_octets(client_count);
+        bench_storage::<ClientInfos>(&mut group, "vec", client_count, &octets);
+        #[cfg(feature = "smallvec")]
+        bench_storage::<conclave_room_serialize::SmallClientInfos>(
+            &mut group,
+            "smallvec",
+            client_count,
+            &octets,
+        );
+        #[cfg(feature = "arrayvec")]
+        bench_storage::<conclave_room_serialize::ArrayClientInfos>(
+            &mut group,
+            "arrayvec",
+            client_count,
+            &octets,
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode_room_info);
+criterion_main!(benches);
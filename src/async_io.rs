@@ -0,0 +1,44 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Framed commands over the `futures-io` [`AsyncRead`] and [`AsyncWrite`] traits, so async-std
+//! and smol streams can be used without depending on a specific runtime.
+//!
+//! The frames are the ones of [`crate::write_frame`].
+
+use std::io::Result;
+
+use flood_rs::OutOctetStream;
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::framing::decode_payload;
+use crate::{write_frame_with_version, Command, ProtocolVersion};
+
+/// Writes the command as one frame. The frame is encoded before anything is written, so a
+/// command that fails to encode writes nothing.
+pub async fn write_frame_async<C: Command>(
+    command: &C,
+    writer: &mut (impl AsyncWrite + Unpin),
+    version: ProtocolVersion,
+) -> Result<()> {
+    let mut out_stream = OutOctetStream::new();
+    write_frame_with_version(command, &mut out_stream, version)?;
+    writer.write_all(&out_stream.data).await
+}
+
+/// Reads the next frame, `None` if the reader ends before it starts. Ending inside a frame
+/// fails with [`std::io::ErrorKind::UnexpectedEof`].
+pub async fn read_frame_async<C: Command>(
+    reader: &mut (impl AsyncRead + Unpin),
+    version: ProtocolVersion,
+) -> Result<Option<C>> {
+    let mut length = [0u8; 2];
+    if reader.read(&mut length[..1]).await? == 0 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut length[1..]).await?;
+    let mut payload = vec![0u8; u16::from_be_bytes(length) as usize];
+    reader.read_exact(&mut payload).await?;
+    decode_payload(&payload, 0..payload.len(), version).map(Some)
+}
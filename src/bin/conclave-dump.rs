@@ -0,0 +1,274 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Decodes packets pasted from logs or captures, given as hex or base64.
+//!
+//! Every argument is a packet, without arguments every non-empty line of stdin is one.
+
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+
+use conclave_room_serialize::{
+    ClientReceiveCommand, ProtocolVersion, ServerReceiveCommand, PROTOCOL_VERSION,
+};
+
+const USAGE: &str =
+    "usage: conclave-dump [--server | --client] [--hex | --base64] [--version N] [PACKET...]
+
+Decodes each packet and prints the command, or the decode error with its offset.
+  --server     only decode as a command received by the server
+  --client     only decode as a command received by the client
+  --hex        the packets are hex, e.g. `01 00 02` or `0x010002`
+  --base64     the packets are base64
+  --version N  the protocol version to decode with";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    /// Hex if the text only has hex digits, otherwise base64
+    Detect,
+    Hex,
+    Base64,
+}
+
+struct Options {
+    server: bool,
+    client: bool,
+    encoding: Encoding,
+    version: ProtocolVersion,
+    packets: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        server: true,
+        client: true,
+        encoding: Encoding::Detect,
+        version: PROTOCOL_VERSION,
+        packets: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--server" => options.client = false,
+            "--client" => options.server = false,
+            "--hex" => options.encoding = Encoding::Hex,
+            "--base64" => options.encoding = Encoding::Base64,
+            "--version" => {
+                let version = args.next().ok_or("--version needs a value")?;
+                options.version = version
+                    .parse()
+                    .map_err(|_| format!("invalid version '{}'", version))?;
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ => options.packets.push(arg),
+        }
+    }
+    if !options.server && !options.client {
+        return Err("--server and --client can not be combined".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = text
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+        .map(|part| part.strip_prefix("0x").unwrap_or(part))
+        .flat_map(|part| part.bytes())
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".to_string());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).map_err(|err| err.to_string())?;
+            u8::from_str_radix(pair, 16).map_err(|_| format!("invalid hex '{}'", pair))
+        })
+        .collect()
+}
+
+fn base64_value(c: u8) -> Option<u32> {
+    match c {
+        b'A'..=b'Z' => Some((c - b'A') as u32),
+        b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+        b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    }
+}
+
+/// Accepts the standard and the URL-safe alphabet, with or without padding. Padding is only
+/// allowed at the end, and the unused bits of the last character must be zero.
+fn parse_base64(text: &str) -> Result<Vec<u8>, String> {
+    let text: Vec<u8> = text.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    let padding = text.iter().rev().take_while(|c| **c == b'=').count();
+    let digits = &text[..text.len() - padding];
+    if let Some(position) = digits.iter().position(|c| *c == b'=') {
+        return Err(format!(
+            "base64 padding before the end, at character {}",
+            position
+        ));
+    }
+    if digits.len() % 4 == 1 {
+        return Err("base64 can not end with a single character of a group".to_string());
+    }
+    if padding > 0 && (padding > 2 || !text.len().is_multiple_of(4)) {
+        return Err(format!("invalid base64 padding of {} characters", padding));
+    }
+    let mut octets = Vec::new();
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in digits {
+        let value = base64_value(*c).ok_or_else(|| format!("invalid base64 '{}'", *c as char))?;
+        bits = (bits << 6) | value;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            octets.push((bits >> bit_count) as u8);
+        }
+    }
+    if bits & ((1 << bit_count) - 1) != 0 {
+        return Err("base64 ends with bits that are not zero".to_string());
+    }
+    Ok(octets)
+}
+
+fn parse_packet(text: &str, encoding: Encoding) -> Result<Vec<u8>, String> {
+    match encoding {
+        Encoding::Hex => parse_hex(text),
+        Encoding::Base64 => parse_base64(text),
+        Encoding::Detect => parse_hex(text).or_else(|_| parse_base64(text)),
+    }
+}
+
+fn write_trailing(octets: &[u8], consumed: usize, out: &mut impl Write) -> std::io::Result<()> {
+    if consumed < octets.len() {
+        writeln!(
+            out,
+            "    {} trailing octets at offset {}: {:02x?}",
+            octets.len() - consumed,
+            consumed,
+            &octets[consumed..]
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints the decoded packet, returns `false` if it did not decode in any direction
+fn dump(text: &str, options: &Options, out: &mut impl Write) -> std::io::Result<bool> {
+    let octets = match parse_packet(text, options.encoding) {
+        Ok(octets) => octets,
+        Err(err) => {
+            writeln!(out, "invalid packet: {}", err)?;
+            return Ok(false);
+        }
+    };
+    writeln!(out, "{} octets: {:02x?}", octets.len(), octets)?;
+    let mut decoded = false;
+    if options.server {
+        match ServerReceiveCommand::decode_prefix(&octets, options.version) {
+            Ok((command, consumed)) => {
+                writeln!(out, "  server receives: {:?}", command)?;
+                write_trailing(&octets, consumed, out)?;
+                decoded = true;
+            }
+            Err(err) => writeln!(out, "  server receives: error: {}", err)?,
+        }
+    }
+    if options.client {
+        match ClientReceiveCommand::decode_prefix(&octets, options.version) {
+            Ok((command, consumed)) => {
+                writeln!(out, "  client receives: {:?}", command)?;
+                write_trailing(&octets, consumed, out)?;
+                decoded = true;
+            }
+            Err(err) => writeln!(out, "  client receives: error: {}", err)?,
+        }
+    }
+    Ok(decoded)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+
+    let packets = if options.packets.is_empty() {
+        let lines: std::io::Result<Vec<String>> = std::io::stdin().lock().lines().collect();
+        match lines {
+            Ok(lines) => lines
+                .into_iter()
+                .filter(|line| !line.trim().is_empty())
+                .collect(),
+            Err(err) => {
+                eprintln!("reading stdin failed: {}", err);
+                return ExitCode::from(2);
+            }
+        }
+    } else {
+        options.packets.clone()
+    };
+
+    let mut out = std::io::stdout().lock();
+    let mut all_decoded = true;
+    for packet in &packets {
+        match dump(packet, &options, &mut out) {
+            Ok(decoded) => all_decoded &= decoded,
+            Err(err) => {
+                eprintln!("writing output failed: {}", err);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    if all_decoded {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_parse_base64() {
+        assert_eq!(parse_base64("AQID").unwrap(), [1, 2, 3]);
+        assert_eq!(parse_base64("AQI=").unwrap(), [1, 2]);
+        assert_eq!(parse_base64("AQI").unwrap(), [1, 2]);
+        assert_eq!(parse_base64("AQ==").unwrap(), [1]);
+        assert_eq!(parse_base64("AQ").unwrap(), [1]);
+        assert_eq!(parse_base64("_-8 A\n").unwrap(), [0xff, 0xef, 0x00]);
+        assert!(parse_base64("").unwrap().is_empty());
+
+        assert!(parse_base64("AQIDB").is_err());
+        assert!(parse_base64("A").is_err());
+        assert!(parse_base64("A=QI").is_err());
+        assert!(parse_base64("AQ=I").is_err());
+        assert!(parse_base64("AQ=").is_err());
+        assert!(parse_base64("AQ===").is_err());
+        assert!(parse_base64("AQID====").is_err());
+        assert!(parse_base64("AR==").is_err());
+        assert!(parse_base64("AQJ").is_err());
+        assert!(parse_base64("AQ*D").is_err());
+    }
+
+    #[test]
+    fn check_dump_truncated() {
+        let options = parse_args(["--server".to_string()].into_iter()).unwrap();
+        let mut out = Vec::new();
+        assert!(!dump("0f", &options, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("snapshot_request.snapshot_id (at offset 1)"),
+            "{}",
+            out
+        );
+    }
+}
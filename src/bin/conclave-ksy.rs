@@ -0,0 +1,35 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Prints the Kaitai Struct definition of the protocol.
+
+use std::process::ExitCode;
+
+use conclave_room_serialize::{
+    kaitai_struct, ProtocolVersion, PROTOCOL_VERSION, PROTOCOL_VERSION_1,
+};
+
+const USAGE: &str = "usage: conclave-ksy [--version N]
+
+Prints the .ksy definition of a command in protocol version N, the current version by default.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let version: ProtocolVersion = match args.as_slice() {
+        [] => PROTOCOL_VERSION,
+        [flag, version] if flag == "--version" => match version.parse() {
+            Ok(version) if (PROTOCOL_VERSION_1..=PROTOCOL_VERSION).contains(&version) => version,
+            _ => {
+                eprintln!("invalid version '{}'", version);
+                return ExitCode::from(2);
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    print!("{}", kaitai_struct(version));
+    ExitCode::SUCCESS
+}
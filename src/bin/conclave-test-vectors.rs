@@ -0,0 +1,75 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Writes the test vectors to a directory: `manifest.json` describes every vector and
+//! `<name>.bin` holds its octets, including the command type id.
+//!
+//! With `--check` it instead runs such a directory, for example one written by another
+//! implementation, through the decoders of this build.
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use conclave_room_serialize::{run_conformance, test_vector_manifest, test_vectors};
+
+const USAGE: &str = "usage: conclave-test-vectors OUT_DIR
+       conclave-test-vectors --check DIR
+
+Writes manifest.json and one .bin file per test vector to OUT_DIR, creating it if needed.
+With --check, decodes the vectors listed in DIR/manifest.json and reports mismatches.";
+
+fn write_vectors(out_dir: &Path) -> std::io::Result<usize> {
+    std::fs::create_dir_all(out_dir)?;
+    let vectors = test_vectors();
+    for vector in &vectors {
+        std::fs::write(
+            out_dir.join(format!("{}.bin", vector.name)),
+            vector.to_vec()?,
+        )?;
+    }
+    let mut text = test_vector_manifest(&vectors)?;
+    text.push('\n');
+    std::fs::write(out_dir.join("manifest.json"), text)?;
+    Ok(vectors.len())
+}
+
+fn check_vectors(directory: &str) -> ExitCode {
+    match run_conformance(Path::new(directory)) {
+        Ok(report) => {
+            println!("{}", report);
+            if report.is_success() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(err) => {
+            eprintln!("reading test vectors failed: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let out_dir = match args.as_slice() {
+        [flag, directory] if flag == "--check" => return check_vectors(directory),
+        [out_dir] if !out_dir.starts_with('-') => out_dir,
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match write_vectors(Path::new(out_dir)) {
+        Ok(count) => {
+            println!("wrote {} test vectors to {}", count, out_dir);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("writing test vectors failed: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,63 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Fitting commands in a datagram or bandwidth budget, without truncating any of them.
+
+use std::io::Result;
+
+use flood_rs::OutOctetStream;
+
+use crate::{write_frame_with_version, ClientReceiveCommand, ProtocolVersion};
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct BudgetedEncoding {
+    /// The frames of the included commands, see [`crate::write_frame`]
+    pub octets: Vec<u8>,
+    /// Indices of the commands that did not fit, in increasing order
+    pub omitted: Vec<usize>,
+}
+
+/// Frames as many of `commands` as fit in `budget` octets. The commands are considered in
+/// decreasing `priority`, and commands with the same priority in the order they are given. A
+/// command that does not fit is omitted, and the next ones are still tried.
+///
+/// The included commands are written in the order they are given, so their relative order is
+/// kept.
+pub fn encode_budgeted<P: Ord>(
+    commands: &[ClientReceiveCommand],
+    budget: usize,
+    version: ProtocolVersion,
+    priority: impl Fn(&ClientReceiveCommand) -> P,
+) -> Result<BudgetedEncoding> {
+    let frames = commands
+        .iter()
+        .map(|command| {
+            let mut out_stream = OutOctetStream::new();
+            write_frame_with_version(command, &mut out_stream, version)?;
+            Ok(out_stream.data)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut by_priority = (0..commands.len()).collect::<Vec<_>>();
+    by_priority.sort_by(|a, b| priority(&commands[*b]).cmp(&priority(&commands[*a])));
+
+    let mut included = vec![false; commands.len()];
+    let mut remaining = budget;
+    for index in by_priority {
+        if frames[index].len() <= remaining {
+            remaining -= frames[index].len();
+            included[index] = true;
+        }
+    }
+
+    let mut encoding = BudgetedEncoding::default();
+    for (index, frame) in frames.iter().enumerate() {
+        if included[index] {
+            encoding.octets.extend_from_slice(frame);
+        } else {
+            encoding.omitted.push(index);
+        }
+    }
+    Ok(encoding)
+}
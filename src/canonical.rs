@@ -0,0 +1,165 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{OutOctetStream, WriteOctetStream};
+
+use crate::{
+    ClientIdentity, Command, PresenceState, ProtocolError, ProtocolVersion, RoomInfoCommand,
+    SliceReader, DEFAULT_PROTOCOL_VERSION, PROTOCOL_VERSION_10, PROTOCOL_VERSION_11,
+    PROTOCOL_VERSION_12, PROTOCOL_VERSION_2, PROTOCOL_VERSION_4, PROTOCOL_VERSION_7,
+    PROTOCOL_VERSION_8, PROTOCOL_VERSION_9,
+};
+
+/// Verifies that `octets` is exactly the encoding this crate produces for the command it holds.
+///
+/// Fails if decoding fails, if the octets continue after the command, or if re-encoding the
+/// command gives other octets, e.g. because a boolean was encoded as `0x02`.
+pub fn verify_canonical<C: Command>(octets: &[u8]) -> Result<()> {
+    verify_canonical_with_version::<C>(octets, DEFAULT_PROTOCOL_VERSION)
+}
+
+pub fn verify_canonical_with_version<C: Command>(
+    octets: &[u8],
+    version: ProtocolVersion,
+) -> Result<()> {
+    let mut reader = SliceReader::new(octets);
+    let command = C::from_octets_with_version(&mut reader, version)?;
+
+    let mut out_stream = OutOctetStream::new();
+    command.to_octets_with_version(&mut out_stream, version)?;
+
+    if out_stream.data != octets {
+        let offset = out_stream
+            .data
+            .iter()
+            .zip(octets)
+            .position(|(a, b)| a != b)
+            .unwrap_or(out_stream.data.len().min(octets.len()));
+        return Err(ProtocolError::NonCanonical { offset }.into());
+    }
+
+    Ok(())
+}
+
+impl RoomInfoCommand {
+    /// The same room with the client infos sorted by connection index
+    pub fn sorted(&self) -> Self {
+        let mut client_infos = self.client_infos.clone();
+        client_infos.sort_by_key(|client_info| client_info.connection_index);
+        Self {
+            term: self.term,
+            leader_index: self.leader_index,
+            client_infos,
+            knowledge_vector: self.knowledge_vector.clone(),
+            quorum_size: self.quorum_size,
+            votes_for_leader: self.votes_for_leader,
+            leader_lease_expires_in_ms: self.leader_lease_expires_in_ms,
+        }
+    }
+
+    /// An encoding that only depends on the state of the room, e.g. for hashing it to detect
+    /// desyncs in lockstep simulations. Rooms with the same clients in another order encode
+    /// the same, as if [`Self::sorted`].
+    ///
+    /// Fails for rooms that do not pass [`Self::validate`], and for fields that `version`
+    /// would drop or make ambiguous, so that different rooms never encode the same: role flags
+    /// before [`PROTOCOL_VERSION_2`], [`ClientIdentity::Uuid`]s that fit a u64 before
+    /// [`PROTOCOL_VERSION_4`], revisions before [`PROTOCOL_VERSION_7`], presence states
+    /// before [`PROTOCOL_VERSION_8`], network stats before [`PROTOCOL_VERSION_9`], a
+    /// knowledge vector before [`PROTOCOL_VERSION_10`], election info before
+    /// [`PROTOCOL_VERSION_11`] and a leader lease before [`PROTOCOL_VERSION_12`].
+    pub fn to_deterministic_octets(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        self.validate()?;
+        if version < PROTOCOL_VERSION_10 && self.knowledge_vector.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "the knowledge vector needs protocol version {}",
+                    PROTOCOL_VERSION_10
+                ),
+            ));
+        }
+        if version < PROTOCOL_VERSION_11 && (self.quorum_size != 0 || self.votes_for_leader != 0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "the quorum size and votes for the leader need protocol version {}",
+                    PROTOCOL_VERSION_11
+                ),
+            ));
+        }
+        if version < PROTOCOL_VERSION_12 && self.leader_lease_expires_in_ms != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "the leader lease needs protocol version {}",
+                    PROTOCOL_VERSION_12
+                ),
+            ));
+        }
+        for client_info in self.client_infos.iter() {
+            if version < PROTOCOL_VERSION_2 && client_info.role_flags != 0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "role flags of connection {} need protocol version {}",
+                        client_info.connection_index, PROTOCOL_VERSION_2
+                    ),
+                ));
+            }
+            if version < PROTOCOL_VERSION_7 && client_info.revision != 0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "revision of connection {} needs protocol version {}",
+                        client_info.connection_index, PROTOCOL_VERSION_7
+                    ),
+                ));
+            }
+            if version < PROTOCOL_VERSION_8 && client_info.presence != PresenceState::Active {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "presence of connection {} needs protocol version {}",
+                        client_info.connection_index, PROTOCOL_VERSION_8
+                    ),
+                ));
+            }
+            if version < PROTOCOL_VERSION_9 && client_info.network_stats.is_some() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "network stats of connection {} need protocol version {}",
+                        client_info.connection_index, PROTOCOL_VERSION_9
+                    ),
+                ));
+            }
+            if let ClientIdentity::Uuid(id) = client_info.identity {
+                if version < PROTOCOL_VERSION_4 && id <= u64::MAX as u128 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "uuid identity of connection {} would decode as a u64 before \
+                             protocol version {}",
+                            client_info.connection_index, PROTOCOL_VERSION_4
+                        ),
+                    ));
+                }
+            }
+        }
+        self.sorted().to_octets_with_version(stream, version)
+    }
+
+    pub fn to_deterministic_vec(&self, version: ProtocolVersion) -> Result<Vec<u8>> {
+        let mut out_stream = OutOctetStream::new();
+        self.to_deterministic_octets(&mut out_stream, version)?;
+        Ok(out_stream.data)
+    }
+}
@@ -0,0 +1,49 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! A self-describing CBOR encoding of the commands, for diagnostic and admin channels. It uses
+//! the serde representation, so the map keys are the Rust field and variant names.
+
+use std::io::{Error, ErrorKind, Result};
+
+use crate::{ClientReceiveCommand, ServerReceiveCommand};
+
+fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut octets = Vec::new();
+    ciborium::into_writer(value, &mut octets).map_err(|err| match err {
+        ciborium::ser::Error::Io(err) => err,
+        ciborium::ser::Error::Value(message) => Error::new(ErrorKind::InvalidInput, message),
+    })?;
+    Ok(octets)
+}
+
+fn decode<T: serde::de::DeserializeOwned>(octets: &[u8]) -> Result<T> {
+    ciborium::from_reader(octets).map_err(|err| match err {
+        ciborium::de::Error::Io(err) => err,
+        err => Error::new(ErrorKind::InvalidData, err.to_string()),
+    })
+}
+
+impl ServerReceiveCommand {
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        encode(self)
+    }
+
+    pub fn from_cbor(octets: &[u8]) -> Result<Self> {
+        decode(octets)
+    }
+}
+
+impl ClientReceiveCommand {
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        encode(self)
+    }
+
+    /// The command must pass [`Self::validate`], like in the binary format
+    pub fn from_cbor(octets: &[u8]) -> Result<Self> {
+        let command: Self = decode(octets)?;
+        command.validate()?;
+        Ok(command)
+    }
+}
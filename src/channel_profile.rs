@@ -0,0 +1,239 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Channel assignments for transports with several channels per connection, e.g. ENet.
+//!
+//! A [`ChannelProfile`] routes every command type to a channel id and a [`CommandDelivery`].
+//! The Server sends it in a [`ChannelProfileCommand`] after its `HelloCommand`, so both sides
+//! send every command on the same channel.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{Command, CommandDelivery, ProtocolVersion, COMMAND_DESCRIPTORS, PROTOCOL_VERSION_6};
+
+pub const CHANNEL_PROFILE_COMMAND_TYPE_ID: u8 = 0x20;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelRoute {
+    pub channel_id: u8,
+    pub delivery: CommandDelivery,
+}
+
+impl ChannelRoute {
+    pub const OCTET_SIZE: usize = 1 + 1;
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u8(self.channel_id)?;
+        stream.write_u8(match self.delivery {
+            CommandDelivery::ReliableOrdered => 0,
+            CommandDelivery::Unreliable => 1,
+        })
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let channel_id = in_field(stream.read_u8(), "channel_id")?;
+        let delivery = match in_field(stream.read_u8(), "delivery")? {
+            0 => CommandDelivery::ReliableOrdered,
+            1 => CommandDelivery::Unreliable,
+            delivery => {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("unknown delivery {}", delivery),
+                    )),
+                    "delivery",
+                )
+            }
+        };
+        Ok(Self {
+            channel_id,
+            delivery,
+        })
+    }
+}
+
+/// The channel of every command type. Command types without a route of their own use the
+/// fallback route.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelProfile {
+    fallback: ChannelRoute,
+    routes: BTreeMap<u8, ChannelRoute>,
+}
+
+/// Everything on reliable ordered channel 0
+impl Default for ChannelProfile {
+    fn default() -> Self {
+        Self::new(ChannelRoute {
+            channel_id: 0,
+            delivery: CommandDelivery::ReliableOrdered,
+        })
+    }
+}
+
+impl ChannelProfile {
+    pub fn new(fallback: ChannelRoute) -> Self {
+        Self {
+            fallback,
+            routes: BTreeMap::new(),
+        }
+    }
+
+    /// Routes every known command type by the [`CommandDelivery`] of its descriptor. Unknown
+    /// command types use the reliable channel.
+    pub fn from_descriptors(reliable_channel_id: u8, unreliable_channel_id: u8) -> Self {
+        let mut profile = Self::new(ChannelRoute {
+            channel_id: reliable_channel_id,
+            delivery: CommandDelivery::ReliableOrdered,
+        });
+        for descriptor in COMMAND_DESCRIPTORS {
+            let channel_id = match descriptor.delivery {
+                CommandDelivery::ReliableOrdered => reliable_channel_id,
+                CommandDelivery::Unreliable => unreliable_channel_id,
+            };
+            profile.set_route(
+                descriptor.type_id,
+                ChannelRoute {
+                    channel_id,
+                    delivery: descriptor.delivery,
+                },
+            );
+        }
+        profile
+    }
+
+    pub fn with_route(mut self, command_type_id: u8, route: ChannelRoute) -> Self {
+        self.set_route(command_type_id, route);
+        self
+    }
+
+    pub fn set_route(&mut self, command_type_id: u8, route: ChannelRoute) {
+        self.routes.insert(command_type_id, route);
+    }
+
+    pub fn fallback(&self) -> ChannelRoute {
+        self.fallback
+    }
+
+    pub fn route(&self, command_type_id: u8) -> ChannelRoute {
+        self.routes
+            .get(&command_type_id)
+            .copied()
+            .unwrap_or(self.fallback)
+    }
+
+    /// The command types with a route of their own, in increasing command type id order
+    pub fn routes(&self) -> impl Iterator<Item = (u8, ChannelRoute)> + '_ {
+        self.routes
+            .iter()
+            .map(|(command_type_id, route)| (*command_type_id, *route))
+    }
+
+    /// One more than the highest channel id, e.g. the channel limit of an ENet host
+    pub fn channel_count(&self) -> usize {
+        self.routes
+            .values()
+            .chain([&self.fallback])
+            .map(|route| route.channel_id as usize + 1)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Encodes the command, and returns it with the route to send it on
+    pub fn encode_routed<C: Command>(
+        &self,
+        command: &C,
+        version: ProtocolVersion,
+    ) -> Result<(ChannelRoute, Vec<u8>)> {
+        let mut out_stream = OutOctetStream::new();
+        command.to_octets_with_version(&mut out_stream, version)?;
+        Ok((self.route(out_stream.data[0]), out_stream.data))
+    }
+
+    /// Writes the fallback route followed by the routes, prefixed with their count as a `u8`
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(self.fallback.to_octets(stream), "fallback")?;
+        if self.routes.len() > u8::MAX as usize {
+            return in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{} routes, at most {} supported",
+                        self.routes.len(),
+                        u8::MAX
+                    ),
+                )),
+                "routes",
+            );
+        }
+        stream.write_u8(self.routes.len() as u8)?;
+        for (command_type_id, route) in self.routes() {
+            stream.write_u8(command_type_id)?;
+            route.to_octets(stream)?;
+        }
+        Ok(())
+    }
+
+    /// The routes must be in increasing command type id order, so every profile has a single
+    /// encoding
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let fallback = in_field(ChannelRoute::from_cursor(stream), "fallback")?;
+        let count = in_field(stream.read_u8(), "routes.len")?;
+        let mut profile = Self::new(fallback);
+        let mut previous_command_type_id = None;
+        for index in 0..count {
+            let command_type_id = in_field(stream.read_u8(), format_args!("routes[{}]", index))?;
+            if previous_command_type_id.is_some_and(|previous| command_type_id <= previous) {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("route for 0x{:x} is out of order", command_type_id),
+                    )),
+                    format_args!("routes[{}]", index),
+                );
+            }
+            previous_command_type_id = Some(command_type_id);
+            let route = in_field(
+                ChannelRoute::from_cursor(stream),
+                format_args!("routes[{}]", index),
+            )?;
+            profile.set_route(command_type_id, route);
+        }
+        Ok(profile)
+    }
+}
+
+/// Sent by the Server during the handshake, the channels both sides use for the rest of the
+/// session
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelProfileCommand {
+    pub profile: ChannelProfile,
+}
+
+impl ChannelProfileCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "channel_profile";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        ChannelRoute::OCTET_SIZE + 1
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(self.profile.to_octets(stream), "profile")
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            profile: in_field(ChannelProfile::from_cursor(stream), "profile")?,
+        })
+    }
+}
@@ -0,0 +1,130 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::{ProtocolVersion, UserId, PROTOCOL_VERSION_4};
+
+pub const CLIENT_IDENTITY_U64: u8 = 0x00;
+pub const CLIENT_IDENTITY_UUID: u8 = 0x01;
+pub const CLIENT_IDENTITY_STRING: u8 = 0x02;
+
+/// How a backend identifies a client.
+///
+/// From [`PROTOCOL_VERSION_4`] on it is serialized as a discriminant octet followed by the value,
+/// strings are prefixed with their length in octets. Earlier versions serialize it as a [`UserId`]
+/// and can not represent [`ClientIdentity::String`].
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClientIdentity {
+    U64(u64),
+    Uuid(u128),
+    String(String),
+}
+
+impl Default for ClientIdentity {
+    fn default() -> Self {
+        Self::U64(0)
+    }
+}
+
+impl ClientIdentity {
+    /// The smallest serialized identity, an empty string from [`PROTOCOL_VERSION_4`] on
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        if version >= PROTOCOL_VERSION_4 {
+            1 + 1
+        } else {
+            UserId::octet_size(version)
+        }
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        if version < PROTOCOL_VERSION_4 {
+            return match self {
+                Self::U64(id) => UserId::from(*id).to_octets_with_version(stream, version),
+                Self::Uuid(id) => UserId(*id).to_octets_with_version(stream, version),
+                Self::String(_) => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "string identities need protocol version {}",
+                        PROTOCOL_VERSION_4
+                    ),
+                )),
+            };
+        }
+
+        match self {
+            Self::U64(id) => {
+                stream.write_u8(CLIENT_IDENTITY_U64)?;
+                stream.write_u64(*id)?;
+            }
+            Self::Uuid(id) => {
+                stream.write_u8(CLIENT_IDENTITY_UUID)?;
+                stream.write_u64((*id >> 64) as u64)?;
+                stream.write_u64(*id as u64)?;
+            }
+            Self::String(id) => {
+                if id.len() > u8::MAX as usize {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("string identity is too long ({} octets)", id.len()),
+                    ));
+                }
+                stream.write_u8(CLIENT_IDENTITY_STRING)?;
+                stream.write_u8(id.len() as u8)?;
+                stream.write(id.as_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        if version < PROTOCOL_VERSION_4 {
+            let user_id = UserId::from_cursor_with_version(stream, version)?;
+            return Ok(user_id.into());
+        }
+
+        let discriminant = stream.read_u8()?;
+        match discriminant {
+            CLIENT_IDENTITY_U64 => Ok(Self::U64(stream.read_u64()?)),
+            CLIENT_IDENTITY_UUID => {
+                let high = stream.read_u64()? as u128;
+                let low = stream.read_u64()? as u128;
+                Ok(Self::Uuid((high << 64) | low))
+            }
+            CLIENT_IDENTITY_STRING => {
+                let length = stream.read_u8()? as usize;
+                let mut octets = vec![0u8; length];
+                stream.read(&mut octets)?;
+                String::from_utf8(octets)
+                    .map(Self::String)
+                    .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown client identity 0x{:x}", discriminant),
+            )),
+        }
+    }
+}
+
+impl From<UserId> for ClientIdentity {
+    fn from(user_id: UserId) -> Self {
+        if user_id.is_wide() {
+            Self::Uuid(user_id.0)
+        } else {
+            Self::U64(user_id.0 as u64)
+        }
+    }
+}
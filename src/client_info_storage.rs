@@ -0,0 +1,98 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Storages for the client infos of a [`crate::RoomInfoCommand`].
+//!
+//! The storage is a type parameter of the command, so enabling a feature only adds storages and
+//! never changes the type of `client_infos` for other crates in the dependency graph.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::io::Result;
+use std::ops::{Deref, DerefMut};
+
+use crate::ClientInfo;
+#[cfg(feature = "arrayvec")]
+use crate::ProtocolError;
+
+/// Storage of the client infos of a [`crate::RoomInfoCommand`]
+pub trait ClientInfoStorage:
+    Default
+    + Debug
+    + Clone
+    + PartialEq
+    + Eq
+    + Hash
+    + Deref<Target = [ClientInfo]>
+    + DerefMut
+    + FromIterator<ClientInfo>
+{
+    fn clear_client_infos(&mut self);
+
+    /// Must only be called after [`Self::reserve_client_infos`] made room for it
+    fn push_client_info(&mut self, client_info: ClientInfo);
+
+    /// Makes room for `length` more client infos, fails with
+    /// [`crate::ProtocolError::TooManyClients`] if they would not fit
+    fn reserve_client_infos(&mut self, length: usize) -> Result<()>;
+}
+
+impl ClientInfoStorage for Vec<ClientInfo> {
+    fn clear_client_infos(&mut self) {
+        self.clear();
+    }
+
+    fn push_client_info(&mut self, client_info: ClientInfo) {
+        self.push(client_info);
+    }
+
+    fn reserve_client_infos(&mut self, length: usize) -> Result<()> {
+        self.reserve(length);
+        Ok(())
+    }
+}
+
+/// Stores the client infos inline, so decoding does not allocate (except for
+/// [`crate::ClientIdentity::String`]), and at most `N` can be decoded
+#[cfg(feature = "arrayvec")]
+impl<const N: usize> ClientInfoStorage for arrayvec::ArrayVec<ClientInfo, N> {
+    fn clear_client_infos(&mut self) {
+        self.clear();
+    }
+
+    fn push_client_info(&mut self, client_info: ClientInfo) {
+        self.push(client_info);
+    }
+
+    fn reserve_client_infos(&mut self, length: usize) -> Result<()> {
+        if self.len() + length > N {
+            return Err(ProtocolError::TooManyClients {
+                count: self.len() + length,
+                max: N,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Stores up to `N` client infos inline, larger rooms spill to the heap
+#[cfg(feature = "smallvec")]
+impl<const N: usize> ClientInfoStorage for smallvec::SmallVec<[ClientInfo; N]>
+where
+    [ClientInfo; N]: smallvec::Array<Item = ClientInfo>,
+{
+    fn clear_client_infos(&mut self) {
+        self.clear();
+    }
+
+    fn push_client_info(&mut self, client_info: ClientInfo) {
+        self.push(client_info);
+    }
+
+    fn reserve_client_infos(&mut self, length: usize) -> Result<()> {
+        self.reserve(length);
+        Ok(())
+    }
+}
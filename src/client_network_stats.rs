@@ -0,0 +1,90 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! The optional network stats section of a `RoomInfoCommand`.
+//!
+//! When [`ROOM_INFO_FLAG_NETWORK_STATS`] is set in the room info flags, the room info ends with
+//! a [`ClientNetworkStats`] for each client, in the order of the client infos.
+
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::ClientInfo;
+
+/// Set if the room info carries the network stats of its clients
+pub const ROOM_INFO_FLAG_NETWORK_STATS: u8 = 0x01;
+
+/// The connection quality of a client, as measured by the Server from its pings
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientNetworkStats {
+    /// The last round trip time in milliseconds
+    pub rtt_ms: u16,
+    /// The share of the recent pings that were lost, 0 to 100
+    pub loss_percent: u8,
+}
+
+impl ClientNetworkStats {
+    pub const OCTET_SIZE: usize = 2 + 1;
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u16(self.rtt_ms)?;
+        stream.write_u8(self.loss_percent)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            rtt_ms: in_field(stream.read_u16(), "rtt_ms")?,
+            loss_percent: in_field(stream.read_u8(), "loss_percent")?,
+        })
+    }
+}
+
+/// `true` if the room info needs the network stats section
+pub(crate) fn has_network_stats(client_infos: &[ClientInfo]) -> bool {
+    client_infos
+        .iter()
+        .any(|client_info| client_info.network_stats.is_some())
+}
+
+/// Writes the section, every client must have network stats
+pub(crate) fn write_network_stats(
+    client_infos: &[ClientInfo],
+    stream: &mut impl WriteOctetStream,
+) -> Result<()> {
+    for (index, client_info) in client_infos.iter().enumerate() {
+        match &client_info.network_stats {
+            Some(network_stats) => network_stats.to_octets(stream)?,
+            None => {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "connection {} has no network stats, but other clients do",
+                            client_info.connection_index
+                        ),
+                    )),
+                    format_args!("client_infos[{}].network_stats", index),
+                )
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the section into the already decoded `client_infos`
+pub(crate) fn read_network_stats(
+    client_infos: &mut [ClientInfo],
+    stream: &mut impl ReadOctetStream,
+) -> Result<()> {
+    for (index, client_info) in client_infos.iter_mut().enumerate() {
+        client_info.network_stats = Some(in_field(
+            ClientNetworkStats::from_cursor(stream),
+            format_args!("network_stats[{}]", index),
+        )?);
+    }
+    Ok(())
+}
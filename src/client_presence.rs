@@ -0,0 +1,78 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{ProtocolVersion, PROTOCOL_VERSION_8};
+
+pub const SET_PRESENCE_COMMAND_TYPE_ID: u8 = 0x24;
+
+/// If a client is at the keyboard, as shown in lobbies
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PresenceState {
+    #[default]
+    Active = 0,
+    Away = 1,
+    Busy = 2,
+}
+
+impl PresenceState {
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u8(*self as u8)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let value = stream.read_u8()?;
+        Self::try_from(value)
+    }
+}
+
+impl TryFrom<u8> for PresenceState {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Active),
+            1 => Ok(Self::Away),
+            2 => Ok(Self::Busy),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown presence state {}", value),
+            )),
+        }
+    }
+}
+
+/// Sent from Client to Server when the user becomes idle or returns. The Server updates the
+/// `ClientInfo` of the sender, so the other Clients see it in the next `RoomInfoCommand`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetPresenceCommand {
+    pub presence: PresenceState,
+}
+
+impl SetPresenceCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "set_presence";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_8;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.presence.to_octets(stream)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            presence: in_field(PresenceState::from_cursor(stream), "presence")?,
+        })
+    }
+}
@@ -0,0 +1,138 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Fields shared by all commands, written once before the command instead of in every payload.
+//!
+//! The header is the term as a u16, followed by a u8 presence mask and the optional fields, see
+//! [`crate::PresenceWriter`]. In the header framing, each frame is the length of the header and
+//! command as a u16, followed by the header and the command.
+
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+
+use conclave_room::Term;
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::{at_offset, in_field};
+use crate::{
+    Command, CorrelationId, PresenceMaskWidth, PresenceReader, PresenceWriter, ProtocolError,
+    ProtocolVersion, RoomId, SliceReader,
+};
+
+const ROOM_ID_BIT: u32 = 0;
+const CORRELATION_ID_BIT: u32 = 1;
+const KNOWN_HEADER_MASK: u16 = (1 << ROOM_ID_BIT) | (1 << CORRELATION_ID_BIT);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandHeader {
+    pub term: Term,
+    pub room_id: Option<RoomId>,
+    pub correlation_id: Option<CorrelationId>,
+}
+
+impl CommandHeader {
+    /// The term and an empty presence mask
+    pub const MIN_OCTET_SIZE: usize = 2 + 1;
+
+    pub fn octet_size(&self) -> usize {
+        Self::MIN_OCTET_SIZE
+            + if self.room_id.is_some() { 4 } else { 0 }
+            + if self.correlation_id.is_some() { 4 } else { 0 }
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u16(self.term)?;
+        let mut writer = PresenceWriter::new(PresenceMaskWidth::U8);
+        writer.field(ROOM_ID_BIT, &self.room_id)?;
+        writer.field(CORRELATION_ID_BIT, &self.correlation_id)?;
+        writer.to_octets(stream)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let term = in_field(stream.read_u16(), "term")?;
+        let mut reader =
+            PresenceReader::from_cursor(stream, PresenceMaskWidth::U8, KNOWN_HEADER_MASK)?;
+        Ok(Self {
+            term,
+            room_id: in_field(reader.field(ROOM_ID_BIT, stream), "room_id")?,
+            correlation_id: in_field(reader.field(CORRELATION_ID_BIT, stream), "correlation_id")?,
+        })
+    }
+}
+
+/// Writes the header followed by the command
+pub fn encode_with_header<C: Command>(
+    header: &CommandHeader,
+    command: &C,
+    stream: &mut impl WriteOctetStream,
+    version: ProtocolVersion,
+) -> Result<()> {
+    in_field(header.to_octets(stream), "header")?;
+    command.to_octets_with_version(stream, version)
+}
+
+/// Decodes a header and a command that must use all of `octets`
+pub fn decode_with_header<C: Command>(
+    octets: &[u8],
+    version: ProtocolVersion,
+) -> Result<(CommandHeader, C)> {
+    decode_header_and_command(octets, 0..octets.len(), version)
+}
+
+/// Failures get their offset in `octets`
+fn decode_header_and_command<C: Command>(
+    octets: &[u8],
+    span: Range<usize>,
+    version: ProtocolVersion,
+) -> Result<(CommandHeader, C)> {
+    let mut reader = SliceReader::new(&octets[span.clone()]);
+    let result = in_field(CommandHeader::from_cursor(&mut reader), "header").and_then(|header| {
+        let command = C::from_octets_with_version(&mut reader, version)?;
+        if reader.remaining() != 0 {
+            return Err(ProtocolError::TrailingBytes {
+                count: reader.remaining(),
+            }
+            .into());
+        }
+        Ok((header, command))
+    });
+    at_offset(result, span.start + reader.last_read_position())
+}
+
+/// Writes a frame of the header framing
+pub fn write_frame_with_header<C: Command>(
+    header: &CommandHeader,
+    command: &C,
+    stream: &mut impl WriteOctetStream,
+    version: ProtocolVersion,
+) -> Result<()> {
+    let mut frame_stream = OutOctetStream::new();
+    encode_with_header(header, command, &mut frame_stream, version)?;
+    if frame_stream.data.len() > u16::MAX as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "command is too large for a frame ({} octets)",
+                frame_stream.data.len()
+            ),
+        ));
+    }
+    stream.write_u16(frame_stream.data.len() as u16)?;
+    stream.write(&frame_stream.data)
+}
+
+/// Decodes the first frame of the header framing in `octets`. Also returns the number of
+/// octets the frame used, i.e. where the next frame starts.
+pub fn read_frame_with_header<C: Command>(
+    octets: &[u8],
+    version: ProtocolVersion,
+) -> Result<(CommandHeader, C, usize)> {
+    let mut reader = SliceReader::new(octets);
+    let length = at_offset(reader.read_u16(), 0)? as usize;
+    at_offset(reader.require(length), 0)?;
+    let frame_end = 2 + length;
+    let (header, command) = decode_header_and_command(octets, 2..frame_end, version)?;
+    Ok((header, command, frame_end))
+}
@@ -0,0 +1,137 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Typed commands over a datagram transport that is a `futures` [`Sink`] or [`Stream`] of
+//! [`Bytes`], where every datagram is one command.
+
+use std::io::{Error, Result};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use flood_rs::OutOctetStream;
+use futures_util::{Sink, Stream};
+
+use crate::framing::decode_payload;
+use crate::{Command, ProtocolVersion, DEFAULT_PROTOCOL_VERSION};
+
+/// Encodes every command sent to it into a datagram for the inner sink
+pub struct CommandSink<S, C> {
+    inner: S,
+    version: ProtocolVersion,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<S, C> CommandSink<S, C> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            version: DEFAULT_PROTOCOL_VERSION,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn with_version(mut self, version: ProtocolVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, C> Sink<C> for CommandSink<S, C>
+where
+    S: Sink<Bytes> + Unpin,
+    S::Error: Into<Error>,
+    C: Command,
+{
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_ready(cx).map_err(Into::into)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, command: C) -> Result<()> {
+        let mut out_stream = OutOctetStream::new();
+        command.to_octets_with_version(&mut out_stream, self.version)?;
+        Pin::new(&mut self.inner)
+            .start_send(Bytes::from(out_stream.data))
+            .map_err(Into::into)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx).map_err(Into::into)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx).map_err(Into::into)
+    }
+}
+
+/// Decodes every datagram of the inner stream into a command.
+///
+/// A datagram that fails to decode, or has octets after the command, is returned as an error
+/// item. The stream continues with the next datagram.
+pub struct CommandStream<S, C> {
+    inner: S,
+    version: ProtocolVersion,
+    marker: PhantomData<fn() -> C>,
+}
+
+impl<S, C> CommandStream<S, C> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            version: DEFAULT_PROTOCOL_VERSION,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn with_version(mut self, version: ProtocolVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, C> Stream for CommandStream<S, C>
+where
+    S: Stream<Item = Bytes> + Unpin,
+    C: Command,
+{
+    type Item = Result<C>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<C>>> {
+        let version = self.version;
+        Pin::new(&mut self.inner).poll_next(cx).map(|datagram| {
+            datagram.map(|octets| decode_payload(&octets, 0..octets.len(), version))
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
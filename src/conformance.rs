@@ -0,0 +1,230 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Runs a directory of golden files through the decoders and reports where the result differs
+//! from the expected JSON, so every implementation of the protocol can share one suite.
+//!
+//! The directory has the layout written by `conclave-test-vectors`: a `manifest.json` with a
+//! `vectors` array, where each entry names the octets `file`, the `direction`, the `version`
+//! and the expected `command`.
+
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::{ClientReceiveCommand, ProtocolVersion, ServerReceiveCommand};
+
+/// More differences in one sample than this are left out of the report
+const MAX_REPORTED_DIFFERENCES: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonDifference {
+    /// JSON pointer to the differing value, `""` for the whole command
+    pub path: String,
+    /// `None` if the value is missing
+    pub expected: Option<Value>,
+    pub actual: Option<Value>,
+}
+
+impl fmt::Display for JsonDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "(missing)".to_string(),
+        };
+        write!(
+            f,
+            "{}: expected {}, decoded {}",
+            if self.path.is_empty() {
+                "/"
+            } else {
+                &self.path
+            },
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConformanceFailure {
+    /// The octets did not decode
+    Decode(String),
+    /// The octets decoded to another command than expected
+    Mismatch(Vec<JsonDifference>),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConformanceReport {
+    pub passed: usize,
+    /// The name of each failed sample and why it failed
+    pub failures: Vec<(String, ConformanceFailure)>,
+}
+
+impl ConformanceReport {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, failure) in &self.failures {
+            match failure {
+                ConformanceFailure::Decode(err) => writeln!(f, "{}: decode failed: {}", name, err)?,
+                ConformanceFailure::Mismatch(differences) => {
+                    writeln!(f, "{}: mismatch", name)?;
+                    for difference in differences {
+                        writeln!(f, "    {}", difference)?;
+                    }
+                }
+            }
+        }
+        write!(f, "{} passed, {} failed", self.passed, self.failures.len())
+    }
+}
+
+fn push_difference(
+    differences: &mut Vec<JsonDifference>,
+    path: &str,
+    expected: Option<&Value>,
+    actual: Option<&Value>,
+) {
+    if differences.len() < MAX_REPORTED_DIFFERENCES {
+        differences.push(JsonDifference {
+            path: path.to_string(),
+            expected: expected.cloned(),
+            actual: actual.cloned(),
+        });
+    }
+}
+
+fn pointer_child(path: &str, key: &str) -> String {
+    format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Collects the differences between `expected` and `actual`, descending into objects and arrays
+pub fn json_differences(expected: &Value, actual: &Value) -> Vec<JsonDifference> {
+    let mut differences = Vec::new();
+    collect_differences("", expected, actual, &mut differences);
+    differences
+}
+
+fn collect_differences(
+    path: &str,
+    expected: &Value,
+    actual: &Value,
+    differences: &mut Vec<JsonDifference>,
+) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected_value) in expected {
+                let child = pointer_child(path, key);
+                match actual.get(key) {
+                    Some(actual_value) => {
+                        collect_differences(&child, expected_value, actual_value, differences)
+                    }
+                    None => push_difference(differences, &child, Some(expected_value), None),
+                }
+            }
+            for (key, actual_value) in actual {
+                if !expected.contains_key(key) {
+                    let child = pointer_child(path, key);
+                    push_difference(differences, &child, None, Some(actual_value));
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for index in 0..expected.len().max(actual.len()) {
+                let child = format!("{}/{}", path, index);
+                match (expected.get(index), actual.get(index)) {
+                    (Some(expected_value), Some(actual_value)) => {
+                        collect_differences(&child, expected_value, actual_value, differences)
+                    }
+                    (expected_value, actual_value) => {
+                        push_difference(differences, &child, expected_value, actual_value)
+                    }
+                }
+            }
+        }
+        _ if expected == actual => {}
+        _ => push_difference(differences, path, Some(expected), Some(actual)),
+    }
+}
+
+fn field<'a>(entry: &'a Value, name: &str) -> Result<&'a Value> {
+    entry.get(name).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("manifest entry is missing '{}'", name),
+        )
+    })
+}
+
+fn str_field<'a>(entry: &'a Value, name: &str) -> Result<&'a str> {
+    field(entry, name)?.as_str().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("manifest entry '{}' is not a string", name),
+        )
+    })
+}
+
+/// Decodes `octets` in `direction` and returns the command as JSON
+fn decode_to_json(octets: &[u8], direction: &str, version: ProtocolVersion) -> Result<Value> {
+    let value = match direction {
+        "client_to_server" => {
+            serde_json::to_value(ServerReceiveCommand::decode_checked(octets, version)?)
+        }
+        "server_to_client" => {
+            serde_json::to_value(ClientReceiveCommand::decode_checked(octets, version)?)
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown direction '{}'", direction),
+            ))
+        }
+    };
+    Ok(value?)
+}
+
+fn check_sample(directory: &Path, entry: &Value) -> Result<Option<ConformanceFailure>> {
+    let octets = std::fs::read(directory.join(str_field(entry, "file")?))?;
+    let direction = str_field(entry, "direction")?;
+    let version = field(entry, "version")?
+        .as_u64()
+        .and_then(|version| ProtocolVersion::try_from(version).ok())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid version in manifest"))?;
+    let expected = field(entry, "command")?;
+
+    let actual = match decode_to_json(&octets, direction, version) {
+        Ok(actual) => actual,
+        Err(err) => return Ok(Some(ConformanceFailure::Decode(err.to_string()))),
+    };
+    let differences = json_differences(expected, &actual);
+    Ok((!differences.is_empty()).then_some(ConformanceFailure::Mismatch(differences)))
+}
+
+/// Decodes every sample listed in `directory/manifest.json` and compares it to the expected
+/// command. Fails only if the manifest or a sample file can not be read.
+pub fn run_conformance(directory: &Path) -> Result<ConformanceReport> {
+    let manifest: Value = serde_json::from_slice(&std::fs::read(directory.join("manifest.json"))?)?;
+    let entries = manifest
+        .get("vectors")
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "manifest has no 'vectors' array"))?;
+
+    let mut report = ConformanceReport::default();
+    for entry in entries {
+        let name = str_field(entry, "name")?;
+        match check_sample(directory, entry)? {
+            Some(failure) => report.failures.push((name.to_string(), failure)),
+            None => report.passed += 1,
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,351 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Time-limited room access tokens in the style of netcode.io: a backend issues the token to
+//! the client, which presents it to one of the listed servers with a `ConnectWithTokenCommand`.
+//!
+//! The private section is encrypted by the backend with a key it shares with the servers,
+//! so it is opaque to the client. With the `secure` feature, `ConnectToken::seal` encrypts it
+//! with XChaCha20-Poly1305, with the public fields as associated data, so a client can not
+//! change them, e.g. to extend the expiry. Servers check the expiry of the decrypted copy with
+//! `ConnectToken::validate`.
+//!
+//! Servers accept a `ConnectWithTokenCommand` with `ConnectWithTokenCommand::accept`, which also
+//! needs the `secure` feature. Without it tokens can only be encoded and decoded, e.g. by a
+//! Client, and a server has no way to trust one, so it must refuse the command.
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+#[cfg(feature = "secure")]
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+#[cfg(feature = "secure")]
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+#[cfg(feature = "secure")]
+use flood_rs::OutOctetStream;
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::payload::{read_payload, write_payload};
+#[cfg(feature = "secure")]
+use crate::{ProtocolError, SliceReader};
+use crate::{ProtocolVersion, PROTOCOL_VERSION_6};
+
+pub const MAX_CONNECT_TOKEN_SERVER_ADDRESSES: usize = 32;
+pub const MAX_CONNECT_TOKEN_PRIVATE_OCTETS: usize = 1024;
+pub const CONNECT_WITH_TOKEN_COMMAND_TYPE_ID: u8 = 0x1A;
+/// The key the backend shares with the servers
+#[cfg(feature = "secure")]
+pub const CONNECT_TOKEN_KEY_OCTETS: usize = 32;
+/// The nonce that starts a sealed private section
+#[cfg(feature = "secure")]
+pub const CONNECT_TOKEN_NONCE_OCTETS: usize = 24;
+/// What sealing adds to the user data: the nonce, the copies of the expiry and client id, and
+/// the tag
+#[cfg(feature = "secure")]
+pub const CONNECT_TOKEN_SEAL_OVERHEAD_OCTETS: usize =
+    CONNECT_TOKEN_NONCE_OCTETS + 8 + 8 + CONNECT_TOKEN_TAG_OCTETS;
+
+#[cfg(feature = "secure")]
+const CONNECT_TOKEN_TAG_OCTETS: usize = 16;
+
+const ADDRESS_TYPE_IPV4: u8 = 1;
+const ADDRESS_TYPE_IPV6: u8 = 2;
+
+pub(crate) fn write_socket_addr(
+    stream: &mut impl WriteOctetStream,
+    address: &SocketAddr,
+) -> Result<()> {
+    match address.ip() {
+        IpAddr::V4(ip) => {
+            stream.write_u8(ADDRESS_TYPE_IPV4)?;
+            stream.write(&ip.octets())?;
+        }
+        IpAddr::V6(ip) => {
+            stream.write_u8(ADDRESS_TYPE_IPV6)?;
+            stream.write(&ip.octets())?;
+        }
+    }
+    stream.write_u16(address.port())
+}
+
+fn read_socket_addr(stream: &mut impl ReadOctetStream) -> Result<SocketAddr> {
+    let ip = match in_field(stream.read_u8(), "type")? {
+        ADDRESS_TYPE_IPV4 => {
+            let mut octets = [0u8; 4];
+            in_field(stream.read(&mut octets), "ip")?;
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        ADDRESS_TYPE_IPV6 => {
+            let mut octets = [0u8; 16];
+            in_field(stream.read(&mut octets), "ip")?;
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        address_type => {
+            return in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown address type {}", address_type),
+                )),
+                "type",
+            )
+        }
+    };
+    Ok(SocketAddr::new(ip, in_field(stream.read_u16(), "port")?))
+}
+
+/// The private section of a sealed token, as decrypted by a server
+#[cfg(feature = "secure")]
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct ConnectTokenPrivate {
+    /// The copy of the expiry that servers trust
+    pub expires_at_s: u64,
+    pub client_id: u64,
+    /// Opaque to this crate, e.g. the user id or session keys
+    pub user_data: Vec<u8>,
+}
+
+#[cfg(feature = "secure")]
+impl ConnectTokenPrivate {
+    /// Fails if the token has expired at `now_s`, in seconds since the Unix epoch
+    pub fn validate(&self, now_s: u64) -> std::result::Result<(), ProtocolError> {
+        if now_s >= self.expires_at_s {
+            return Err(ProtocolError::ConnectTokenExpired {
+                expires_at_s: self.expires_at_s,
+                now_s,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectToken {
+    /// Seconds since the Unix epoch after which servers refuse the token. Servers check the
+    /// sealed copy of it.
+    pub expires_at_s: u64,
+    pub client_id: u64,
+    /// The servers the token is valid for, 1 to [`MAX_CONNECT_TOKEN_SERVER_ADDRESSES`]
+    pub server_addresses: Vec<SocketAddr>,
+    /// Encrypted by the backend, at most [`MAX_CONNECT_TOKEN_PRIVATE_OCTETS`]
+    pub private_data: Vec<u8>,
+}
+
+impl ConnectToken {
+    /// The smallest serialized token, with a single IPv4 address
+    pub const MIN_OCTET_SIZE: usize = 8 + 8 + 1 + (1 + 4 + 2) + 2;
+
+    fn check_server_address_count(count: usize, kind: ErrorKind) -> Result<()> {
+        if count == 0 || count > MAX_CONNECT_TOKEN_SERVER_ADDRESSES {
+            return Err(Error::new(
+                kind,
+                format!(
+                    "server addresses must be 1 to {}, was {}",
+                    MAX_CONNECT_TOKEN_SERVER_ADDRESSES, count
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// `true` if the public expiry has passed at `now_s`, e.g. for the Client to know when to
+    /// fetch a new token. Servers must not trust it, see `ConnectToken::validate`.
+    pub fn is_expired(&self, now_s: u64) -> bool {
+        now_s >= self.expires_at_s
+    }
+
+    /// `true` if a server listening on `address` may accept the token
+    pub fn is_valid_for(&self, address: &SocketAddr) -> bool {
+        self.server_addresses.contains(address)
+    }
+
+    /// Encrypts the expiry, the client id and `user_data` into `private_data`, bound to the
+    /// public fields, which must not change afterwards. `nonce` must never repeat for `key`,
+    /// e.g. be random.
+    #[cfg(feature = "secure")]
+    pub fn seal(
+        &mut self,
+        user_data: &[u8],
+        key: &[u8; CONNECT_TOKEN_KEY_OCTETS],
+        nonce: &[u8; CONNECT_TOKEN_NONCE_OCTETS],
+    ) -> Result<()> {
+        let max_user_data = MAX_CONNECT_TOKEN_PRIVATE_OCTETS - CONNECT_TOKEN_SEAL_OVERHEAD_OCTETS;
+        if user_data.len() > max_user_data {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "user data is too large ({} octets, max {})",
+                    user_data.len(),
+                    max_user_data
+                ),
+            ));
+        }
+        let associated_data = self.associated_data()?;
+        let mut plain = OutOctetStream::new();
+        plain.write_u64(self.expires_at_s)?;
+        plain.write_u64(self.client_id)?;
+        plain.write(user_data)?;
+        let sealed = XChaCha20Poly1305::new(key.into())
+            .encrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: &plain.data,
+                    aad: &associated_data,
+                },
+            )
+            .map_err(|_| ProtocolError::EncryptionFailed)?;
+        self.private_data = nonce.iter().copied().chain(sealed).collect();
+        Ok(())
+    }
+
+    /// Decrypts the private section sealed by [`ConnectToken::seal`]. Fails with
+    /// [`ProtocolError::DecryptionFailed`] if it was sealed with another key, or if the private
+    /// section or any public field has changed since.
+    #[cfg(feature = "secure")]
+    pub fn open(&self, key: &[u8; CONNECT_TOKEN_KEY_OCTETS]) -> Result<ConnectTokenPrivate> {
+        if self.private_data.len() < CONNECT_TOKEN_SEAL_OVERHEAD_OCTETS {
+            return Err(ProtocolError::DecryptionFailed.into());
+        }
+        let (nonce, sealed) = self.private_data.split_at(CONNECT_TOKEN_NONCE_OCTETS);
+        let associated_data = self.associated_data()?;
+        let plain = XChaCha20Poly1305::new(key.into())
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: &associated_data,
+                },
+            )
+            .map_err(|_| ProtocolError::DecryptionFailed)?;
+        let mut reader = SliceReader::new(&plain);
+        let expires_at_s = reader.read_u64()?;
+        let client_id = reader.read_u64()?;
+        Ok(ConnectTokenPrivate {
+            expires_at_s,
+            client_id,
+            user_data: plain[reader.position()..].to_vec(),
+        })
+    }
+
+    /// Opens the token and checks the expiry of the decrypted copy at `now_s`, in seconds
+    /// since the Unix epoch. The public `expires_at_s` is not trusted.
+    #[cfg(feature = "secure")]
+    pub fn validate(
+        &self,
+        key: &[u8; CONNECT_TOKEN_KEY_OCTETS],
+        now_s: u64,
+    ) -> Result<ConnectTokenPrivate> {
+        let private = self.open(key)?;
+        private.validate(now_s)?;
+        Ok(private)
+    }
+
+    /// The public fields, which the seal authenticates
+    #[cfg(feature = "secure")]
+    fn associated_data(&self) -> Result<Vec<u8>> {
+        let mut out_stream = OutOctetStream::new();
+        self.public_to_octets(&mut out_stream)?;
+        Ok(out_stream.data)
+    }
+
+    fn public_to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(
+            Self::check_server_address_count(self.server_addresses.len(), ErrorKind::InvalidInput),
+            "server_addresses.len",
+        )?;
+        stream.write_u64(self.expires_at_s)?;
+        stream.write_u64(self.client_id)?;
+        stream.write_u8(self.server_addresses.len() as u8)?;
+        for (index, address) in self.server_addresses.iter().enumerate() {
+            in_field(
+                write_socket_addr(stream, address),
+                format_args!("server_addresses[{}]", index),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.public_to_octets(stream)?;
+        in_field(
+            write_payload(stream, &self.private_data, MAX_CONNECT_TOKEN_PRIVATE_OCTETS),
+            "private_data",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let expires_at_s = in_field(stream.read_u64(), "expires_at_s")?;
+        let client_id = in_field(stream.read_u64(), "client_id")?;
+        let count = in_field(stream.read_u8(), "server_addresses.len")? as usize;
+        in_field(
+            Self::check_server_address_count(count, ErrorKind::InvalidData),
+            "server_addresses.len",
+        )?;
+        let mut server_addresses = Vec::with_capacity(count);
+        for index in 0..count {
+            server_addresses.push(in_field(
+                read_socket_addr(stream),
+                format_args!("server_addresses[{}]", index),
+            )?);
+        }
+        Ok(Self {
+            expires_at_s,
+            client_id,
+            server_addresses,
+            private_data: in_field(
+                read_payload(stream, MAX_CONNECT_TOKEN_PRIVATE_OCTETS),
+                "private_data",
+            )?,
+        })
+    }
+}
+
+/// Sent from Client to Server to join with a token issued by the backend. Only servers built
+/// with the `secure` feature can accept it, with `ConnectWithTokenCommand::accept`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectWithTokenCommand {
+    pub token: ConnectToken,
+}
+
+impl ConnectWithTokenCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "connect_with_token";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        ConnectToken::MIN_OCTET_SIZE
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(self.token.to_octets(stream), "token")
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            token: in_field(ConnectToken::from_cursor(stream), "token")?,
+        })
+    }
+
+    /// Checks that the token lists `server_address`, was sealed with `key` and has not expired
+    /// at `now_s`, in seconds since the Unix epoch. The only way for a server to accept the
+    /// command.
+    #[cfg(feature = "secure")]
+    pub fn accept(
+        &self,
+        key: &[u8; CONNECT_TOKEN_KEY_OCTETS],
+        server_address: &SocketAddr,
+        now_s: u64,
+    ) -> Result<ConnectTokenPrivate> {
+        if !self.token.is_valid_for(server_address) {
+            return Err(ProtocolError::ConnectTokenWrongServer {
+                address: *server_address,
+            }
+            .into());
+        }
+        self.token.validate(key, now_s)
+    }
+}
@@ -0,0 +1,45 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::fmt;
+use std::io::Result;
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+/// Index of a connection in the room, e.g. a client or the leader.
+///
+/// Serialized as a single octet, the same as a plain `u8`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionIndex(pub u8);
+
+impl ConnectionIndex {
+    pub const OCTET_SIZE: usize = 1;
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u8(self.0)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self(stream.read_u8()?))
+    }
+}
+
+impl fmt::Display for ConnectionIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u8> for ConnectionIndex {
+    fn from(value: u8) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ConnectionIndex> for u8 {
+    fn from(value: ConnectionIndex) -> Self {
+        value.0
+    }
+}
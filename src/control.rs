@@ -0,0 +1,232 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Control and diagnostic commands for operational tooling, kept apart from the room traffic.
+//!
+//! They use the command type ids in [`CONTROL_COMMAND_TYPE_ID_RANGE`], which the room commands
+//! never use, so a receiver can tell them apart from the first octet with
+//! [`is_control_command`].
+//! They are not part of a protocol version, and ids in the range without a command decode as
+//! [`ControlCommand::Unknown`].
+
+use std::io::{Error, ErrorKind, Result};
+use std::ops::RangeInclusive;
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::{at_offset, in_field};
+use crate::payload::{read_payload, write_payload, MAX_PAYLOAD_OCTETS};
+use crate::raw_command::read_to_end;
+use crate::slice_reader::reject_trailing;
+use crate::{Command, ProtocolVersion, RawCommand, SliceReader};
+
+pub const CONTROL_COMMAND_TYPE_ID_RANGE: RangeInclusive<u8> = 0xF0..=0xFF;
+
+pub const ECHO_COMMAND_TYPE_ID: u8 = 0xF0;
+pub const STATS_REQUEST_COMMAND_TYPE_ID: u8 = 0xF1;
+pub const DEBUG_DUMP_COMMAND_TYPE_ID: u8 = 0xF2;
+
+/// If the datagram starts with a control command type id
+pub fn is_control_command(octets: &[u8]) -> bool {
+    octets
+        .first()
+        .is_some_and(|type_id| CONTROL_COMMAND_TYPE_ID_RANGE.contains(type_id))
+}
+
+/// Answered with the same command, to check that a peer is reachable
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EchoCommand {
+    /// At most [`MAX_PAYLOAD_OCTETS`]
+    pub payload: Vec<u8>,
+}
+
+impl EchoCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "echo";
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(
+            write_payload(stream, &self.payload, MAX_PAYLOAD_OCTETS),
+            "payload",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            payload: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "payload")?,
+        })
+    }
+}
+
+/// Asks the peer for its statistics, answered with a [`DebugDumpCommand`] with the same
+/// `request_id`
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsRequestCommand {
+    pub request_id: u32,
+}
+
+impl StatsRequestCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "stats_request";
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.request_id)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            request_id: in_field(stream.read_u32(), "request_id")?,
+        })
+    }
+}
+
+/// Diagnostic state in a format chosen by the sender, e.g. JSON. A `request_id` of zero is an
+/// unsolicited dump.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DebugDumpCommand {
+    pub request_id: u32,
+    /// At most [`MAX_PAYLOAD_OCTETS`]
+    pub dump: Vec<u8>,
+}
+
+impl DebugDumpCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "debug_dump";
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.request_id)?;
+        in_field(
+            write_payload(stream, &self.dump, MAX_PAYLOAD_OCTETS),
+            "dump",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            request_id: in_field(stream.read_u32(), "request_id")?,
+            dump: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "dump")?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ControlCommand {
+    EchoType(EchoCommand),
+    StatsRequestType(StatsRequestCommand),
+    DebugDumpType(DebugDumpCommand),
+    /// A control command type id this build does not know
+    Unknown(RawCommand),
+}
+
+impl ControlCommand {
+    /// The command type id this command is serialized with
+    pub fn command_type_id(&self) -> u8 {
+        match self {
+            Self::EchoType(_) => ECHO_COMMAND_TYPE_ID,
+            Self::StatsRequestType(_) => STATS_REQUEST_COMMAND_TYPE_ID,
+            Self::DebugDumpType(_) => DEBUG_DUMP_COMMAND_TYPE_ID,
+            Self::Unknown(raw_command) => raw_command.type_id,
+        }
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        match self {
+            Self::EchoType(echo) => {
+                stream.write_u8(ECHO_COMMAND_TYPE_ID)?;
+                echo.to_octets(stream)
+            }
+            Self::StatsRequestType(stats_request) => {
+                stream.write_u8(STATS_REQUEST_COMMAND_TYPE_ID)?;
+                stats_request.to_octets(stream)
+            }
+            Self::DebugDumpType(debug_dump) => {
+                stream.write_u8(DEBUG_DUMP_COMMAND_TYPE_ID)?;
+                debug_dump.to_octets(stream)
+            }
+            Self::Unknown(raw_command) => {
+                if !CONTROL_COMMAND_TYPE_ID_RANGE.contains(&raw_command.type_id) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("0x{:x} is not a control command", raw_command.type_id),
+                    ));
+                }
+                raw_command.to_octets(stream)
+            }
+        }
+    }
+
+    /// Fails for command type ids outside [`CONTROL_COMMAND_TYPE_ID_RANGE`]
+    pub fn from_octets(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let command_type_id = in_field(stream.read_u8(), "command_type_id")?;
+        match command_type_id {
+            ECHO_COMMAND_TYPE_ID => Ok(Self::EchoType(in_field(
+                EchoCommand::from_cursor(stream),
+                EchoCommand::NAME,
+            )?)),
+            STATS_REQUEST_COMMAND_TYPE_ID => Ok(Self::StatsRequestType(in_field(
+                StatsRequestCommand::from_cursor(stream),
+                StatsRequestCommand::NAME,
+            )?)),
+            DEBUG_DUMP_COMMAND_TYPE_ID => Ok(Self::DebugDumpType(in_field(
+                DebugDumpCommand::from_cursor(stream),
+                DebugDumpCommand::NAME,
+            )?)),
+            _ if CONTROL_COMMAND_TYPE_ID_RANGE.contains(&command_type_id) => {
+                Ok(Self::Unknown(RawCommand {
+                    type_id: command_type_id,
+                    payload: in_field(read_to_end(stream), "payload")?,
+                }))
+            }
+            _ => in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("0x{:x} is not a control command", command_type_id),
+                )),
+                "command_type_id",
+            ),
+        }
+    }
+
+    /// Decodes a command that must use all of `octets`
+    pub fn from_slice(octets: &[u8]) -> Result<Self> {
+        let mut reader = SliceReader::new(octets);
+        let result = Self::from_octets(&mut reader);
+        let command = at_offset(result, reader.last_read_position())?;
+        reject_trailing(octets, reader.position())?;
+        Ok(command)
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut out_stream = OutOctetStream::new();
+        self.to_octets(&mut out_stream)?;
+        Ok(out_stream.data)
+    }
+}
+
+/// The version is ignored, control commands have a single layout
+impl Command for ControlCommand {
+    fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        _version: ProtocolVersion,
+    ) -> Result<()> {
+        ControlCommand::to_octets(self, stream)
+    }
+
+    fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        _version: ProtocolVersion,
+    ) -> Result<Self> {
+        Self::from_octets(stream)
+    }
+
+    fn runs_to_end(&self) -> bool {
+        matches!(self, Self::Unknown(_))
+    }
+}
@@ -0,0 +1,308 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Envelope with an optional correlation id, so asynchronous clients can match replies
+//! with their requests.
+//!
+//! The envelope is a flags octet, followed by the correlation id if
+//! [`ENVELOPE_FLAG_CORRELATION_ID`] is set, written before the command type id.
+//!
+//! If [`ENVELOPE_FLAG_PADDING`] is set, a u16 octet count follows and that many zero octets
+//! follow the command. Servers pad handshake responses up to the size of the request with
+//! `encode_with_padding`, so they can not be used to amplify reflection attacks.
+//!
+//! If [`ENVELOPE_FLAG_EXTENSIONS`] is set, an [`Extensions`] area follows the command, before
+//! any padding.
+//!
+//! Neither can follow an unknown command, as its payload runs to the end of the stream.
+
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::raw_command::check_nothing_follows;
+use crate::{ClientReceiveCommand, Command, Extensions, ProtocolVersion, ServerReceiveCommand};
+
+pub type CorrelationId = u32;
+
+pub const ENVELOPE_FLAG_CORRELATION_ID: u8 = 0x01;
+pub const ENVELOPE_FLAG_PADDING: u8 = 0x02;
+pub const ENVELOPE_FLAG_EXTENSIONS: u8 = 0x04;
+
+const ALL_ENVELOPE_FLAGS: u8 =
+    ENVELOPE_FLAG_CORRELATION_ID | ENVELOPE_FLAG_PADDING | ENVELOPE_FLAG_EXTENSIONS;
+
+struct Envelope {
+    correlation_id: Option<CorrelationId>,
+    /// Zero octets after the command
+    padding_octets: usize,
+    has_extensions: bool,
+}
+
+pub(crate) fn write_envelope(
+    correlation_id: Option<CorrelationId>,
+    stream: &mut impl WriteOctetStream,
+) -> Result<()> {
+    match correlation_id {
+        Some(id) => {
+            stream.write_u8(ENVELOPE_FLAG_CORRELATION_ID)?;
+            stream.write_u32(id)
+        }
+        None => stream.write_u8(0x00),
+    }
+}
+
+pub(crate) fn read_envelope(stream: &mut impl ReadOctetStream) -> Result<Option<CorrelationId>> {
+    read_envelope_with_flags(stream, ENVELOPE_FLAG_CORRELATION_ID)
+        .map(|envelope| envelope.correlation_id)
+}
+
+fn read_envelope_with_flags(
+    stream: &mut impl ReadOctetStream,
+    known_flags: u8,
+) -> Result<Envelope> {
+    let flags = stream.read_u8()?;
+    if flags & !known_flags != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unknown envelope flags 0x{:x}", flags),
+        ));
+    }
+    let correlation_id = if flags & ENVELOPE_FLAG_CORRELATION_ID != 0 {
+        Some(stream.read_u32()?)
+    } else {
+        None
+    };
+    let padding_octets = if flags & ENVELOPE_FLAG_PADDING != 0 {
+        in_field(stream.read_u16(), "padding.len")? as usize
+    } else {
+        0
+    };
+    Ok(Envelope {
+        correlation_id,
+        padding_octets,
+        has_extensions: flags & ENVELOPE_FLAG_EXTENSIONS != 0,
+    })
+}
+
+fn write_extensions_envelope(
+    correlation_id: Option<CorrelationId>,
+    extensions: &Extensions,
+    stream: &mut impl WriteOctetStream,
+) -> Result<()> {
+    if extensions.is_empty() {
+        return write_envelope(correlation_id, stream);
+    }
+    stream.write_u8(
+        ENVELOPE_FLAG_EXTENSIONS
+            | if correlation_id.is_some() {
+                ENVELOPE_FLAG_CORRELATION_ID
+            } else {
+                0
+            },
+    )?;
+    if let Some(id) = correlation_id {
+        stream.write_u32(id)?;
+    }
+    Ok(())
+}
+
+/// Reads what follows the command: the extensions, if flagged, and the padding
+fn read_trailer(stream: &mut impl ReadOctetStream, envelope: &Envelope) -> Result<Extensions> {
+    let extensions = if envelope.has_extensions {
+        Extensions::from_cursor(stream)?
+    } else {
+        Extensions::new()
+    };
+    read_padding(stream, envelope.padding_octets)?;
+    Ok(extensions)
+}
+
+fn envelope_octet_size(correlation_id: Option<CorrelationId>, padded: bool) -> usize {
+    1 + if correlation_id.is_some() { 4 } else { 0 } + if padded { 2 } else { 0 }
+}
+
+/// Writes the envelope, `command` and enough zero octets to make it at least `pad_to_octets`
+fn write_padded<C: Command>(
+    correlation_id: Option<CorrelationId>,
+    command: &C,
+    pad_to_octets: usize,
+    stream: &mut impl WriteOctetStream,
+    version: ProtocolVersion,
+) -> Result<()> {
+    let mut out_stream = OutOctetStream::new();
+    command.to_octets_with_version(&mut out_stream, version)?;
+    let octets = out_stream.data;
+    let unpadded = envelope_octet_size(correlation_id, false) + octets.len();
+    if pad_to_octets <= unpadded {
+        write_envelope(correlation_id, stream)?;
+        return stream.write(&octets);
+    }
+    check_nothing_follows(command, true, ErrorKind::InvalidInput)?;
+    let padding_octets = pad_to_octets
+        .saturating_sub(envelope_octet_size(correlation_id, true) + octets.len())
+        .min(u16::MAX as usize);
+    let mut flags = ENVELOPE_FLAG_PADDING;
+    if correlation_id.is_some() {
+        flags |= ENVELOPE_FLAG_CORRELATION_ID;
+    }
+    stream.write_u8(flags)?;
+    if let Some(id) = correlation_id {
+        stream.write_u32(id)?;
+    }
+    stream.write_u16(padding_octets as u16)?;
+    stream.write(&octets)?;
+    stream.write(&vec![0u8; padding_octets])
+}
+
+/// Padding is read in chunks of this size, so the untrusted length is never allocated
+const PADDING_CHUNK_OCTETS: usize = 64;
+
+fn read_padding(stream: &mut impl ReadOctetStream, padding_octets: usize) -> Result<()> {
+    let mut chunk = [0u8; PADDING_CHUNK_OCTETS];
+    let mut remaining = padding_octets;
+    while remaining > 0 {
+        let padding = &mut chunk[..remaining.min(PADDING_CHUNK_OCTETS)];
+        in_field(stream.read(padding), "padding")?;
+        if padding.iter().any(|&octet| octet != 0) {
+            return in_field(
+                Err(Error::new(ErrorKind::InvalidData, "padding must be zero")),
+                "padding",
+            );
+        }
+        remaining -= padding.len();
+    }
+    Ok(())
+}
+
+impl ServerReceiveCommand {
+    pub fn encode_with_correlation(
+        &self,
+        correlation_id: Option<CorrelationId>,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        write_envelope(correlation_id, stream)?;
+        self.to_octets_with_version(stream, version)
+    }
+
+    /// Writes the command padded to at least `pad_to_octets`, e.g. the size of the request
+    /// it answers
+    pub fn encode_with_padding(
+        &self,
+        correlation_id: Option<CorrelationId>,
+        pad_to_octets: usize,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        write_padded(correlation_id, self, pad_to_octets, stream, version)
+    }
+
+    /// Also accepts commands written with `encode_with_padding`
+    pub fn decode_with_correlation(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<(Option<CorrelationId>, Self)> {
+        let envelope =
+            read_envelope_with_flags(stream, ENVELOPE_FLAG_CORRELATION_ID | ENVELOPE_FLAG_PADDING)?;
+        let command = Self::from_cursor_with_version(stream, version)?;
+        read_padding(stream, envelope.padding_octets)?;
+        Ok((envelope.correlation_id, command))
+    }
+
+    /// Writes `extensions` after the command. Without extensions this is the same as
+    /// [`Self::encode_with_correlation`].
+    pub fn encode_with_extensions(
+        &self,
+        correlation_id: Option<CorrelationId>,
+        extensions: &Extensions,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        check_nothing_follows(self, !extensions.is_empty(), ErrorKind::InvalidInput)?;
+        write_extensions_envelope(correlation_id, extensions, stream)?;
+        self.to_octets_with_version(stream, version)?;
+        if !extensions.is_empty() {
+            extensions.to_octets(stream)?;
+        }
+        Ok(())
+    }
+
+    /// Accepts every envelope, returning empty extensions if there are none
+    pub fn decode_with_extensions(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<(Option<CorrelationId>, Self, Extensions)> {
+        let envelope = read_envelope_with_flags(stream, ALL_ENVELOPE_FLAGS)?;
+        let command = Self::from_cursor_with_version(stream, version)?;
+        let extensions = read_trailer(stream, &envelope)?;
+        Ok((envelope.correlation_id, command, extensions))
+    }
+}
+
+impl ClientReceiveCommand {
+    pub fn encode_with_correlation(
+        &self,
+        correlation_id: Option<CorrelationId>,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        write_envelope(correlation_id, stream)?;
+        self.to_octets_with_version(stream, version)
+    }
+
+    /// Writes the command padded to at least `pad_to_octets`, e.g. the size of the request
+    /// it answers
+    pub fn encode_with_padding(
+        &self,
+        correlation_id: Option<CorrelationId>,
+        pad_to_octets: usize,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        write_padded(correlation_id, self, pad_to_octets, stream, version)
+    }
+
+    /// Also accepts commands written with `encode_with_padding`
+    pub fn decode_with_correlation(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<(Option<CorrelationId>, Self)> {
+        let envelope =
+            read_envelope_with_flags(stream, ENVELOPE_FLAG_CORRELATION_ID | ENVELOPE_FLAG_PADDING)?;
+        let command = Self::from_octets_with_version(stream, version)?;
+        read_padding(stream, envelope.padding_octets)?;
+        Ok((envelope.correlation_id, command))
+    }
+
+    /// Writes `extensions` after the command. Without extensions this is the same as
+    /// [`Self::encode_with_correlation`].
+    pub fn encode_with_extensions(
+        &self,
+        correlation_id: Option<CorrelationId>,
+        extensions: &Extensions,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        check_nothing_follows(self, !extensions.is_empty(), ErrorKind::InvalidInput)?;
+        write_extensions_envelope(correlation_id, extensions, stream)?;
+        self.to_octets_with_version(stream, version)?;
+        if !extensions.is_empty() {
+            extensions.to_octets(stream)?;
+        }
+        Ok(())
+    }
+
+    /// Accepts every envelope, returning empty extensions if there are none
+    pub fn decode_with_extensions(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<(Option<CorrelationId>, Self, Extensions)> {
+        let envelope = read_envelope_with_flags(stream, ALL_ENVELOPE_FLAGS)?;
+        let command = Self::from_octets_with_version(stream, version)?;
+        let extensions = read_trailer(stream, &envelope)?;
+        Ok((envelope.correlation_id, command, extensions))
+    }
+}
@@ -0,0 +1,62 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Optional wire mode where every datagram starts with a two octet magic, so stray traffic
+//! hitting the port is discarded before it is decoded.
+//!
+//! Decoding verifies and strips the magic when [`DecodeOptions::datagram_magic`] is set.
+
+use std::io::Result;
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{
+    ClientReceiveCommand, DecodeOptions, ProtocolError, ProtocolVersion, ServerReceiveCommand,
+};
+
+/// A suggested magic, any two octets agreed upon by both ends work
+pub const DATAGRAM_MAGIC: [u8; 2] = [0xC3, 0x52];
+
+pub(crate) fn read_datagram_magic(
+    stream: &mut impl ReadOctetStream,
+    options: &DecodeOptions,
+) -> Result<()> {
+    let Some(expected) = options.datagram_magic else {
+        return Ok(());
+    };
+    let mut found = [0u8; 2];
+    in_field(stream.read(&mut found), "datagram_magic")?;
+    if found != expected {
+        return in_field(
+            Err(ProtocolError::BadMagic { expected, found }.into()),
+            "datagram_magic",
+        );
+    }
+    Ok(())
+}
+
+impl ServerReceiveCommand {
+    pub fn encode_with_magic(
+        &self,
+        magic: [u8; 2],
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        stream.write(&magic)?;
+        self.to_octets_with_version(stream, version)
+    }
+}
+
+impl ClientReceiveCommand {
+    pub fn encode_with_magic(
+        &self,
+        magic: [u8; 2],
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        stream.write(&magic)?;
+        self.to_octets_with_version(stream, version)
+    }
+}
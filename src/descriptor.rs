@@ -0,0 +1,1255 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! A table of every command, so tools such as dissector generators, fuzzers and dashboards can
+//! enumerate the protocol.
+
+use std::io::Result;
+
+use crate::{
+    BanClientCommand, BandwidthReportCommand, BroadcastPayloadCommand, BroadcastedPayloadCommand,
+    ChallengeCommand, ChannelProfileCommand, ClientIdentity, ConnectWithCookieCommand,
+    ConnectWithTokenCommand, DebugDumpCommand, EchoCommand, HelloCommand, JoinAcceptedCommand,
+    JoinDeniedCommand, JoinRequestCommand, KnowledgeAckCommand, LatencyReportCommand,
+    MaintenanceNoticeCommand, MembershipSyncRequestCommand, MembershipSyncResponseCommand,
+    MuteStateCommand, PingCommand, PingResponseCommand, PreVoteRequestCommand,
+    PreVoteResponseCommand, ProtocolError, ProtocolVersion, PskAuthCommand, RedirectCommand,
+    RelayToClientCommand, RelayedFromClientCommand, ReplicateAckCommand, ReplicateCommand,
+    ResyncDataCommand, ResyncRequestCommand, RetryCookieCommand, RoomClosedCommand,
+    RoomInfoCommand, RoomInfoPageCommand, ServerLoadCommand, SetPresenceCommand,
+    SnapshotChunkCommand, SnapshotRequestCommand, StatsRequestCommand, TermAdvancedCommand,
+    ThrottledCommand, TimeoutConfigCommand, TransferChunkCommand, TransferResendRequestCommand,
+    UnbanClientCommand, UpdateRoomSettingsCommand, UserId, BANDWIDTH_REPORT_COMMAND_TYPE_ID,
+    BAN_CLIENT_COMMAND_TYPE_ID, BROADCASTED_PAYLOAD_COMMAND_TYPE_ID,
+    BROADCAST_PAYLOAD_COMMAND_TYPE_ID, CHALLENGE_COMMAND_TYPE_ID, CHALLENGE_SERVER_LOAD_BIT,
+    CHANNEL_PROFILE_COMMAND_TYPE_ID, CONNECT_WITH_COOKIE_COMMAND_TYPE_ID,
+    CONNECT_WITH_TOKEN_COMMAND_TYPE_ID, DEBUG_DUMP_COMMAND_TYPE_ID, ECHO_COMMAND_TYPE_ID,
+    HELLO_COMMAND_TYPE_ID, HELLO_MAGIC, JOIN_ACCEPTED_COMMAND_TYPE_ID, JOIN_DENIED_COMMAND_TYPE_ID,
+    JOIN_REQUEST_COMMAND_TYPE_ID, KNOWLEDGE_ACK_COMMAND_TYPE_ID, LATENCY_BUCKET_COUNT,
+    LATENCY_REPORT_COMMAND_TYPE_ID, MAINTENANCE_NOTICE_COMMAND_TYPE_ID,
+    MEMBERSHIP_SYNC_REQUEST_COMMAND_TYPE_ID, MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID,
+    MUTE_STATE_COMMAND_TYPE_ID, PING_COMMAND_TYPE_ID, PING_KNOWLEDGE_VECTOR_BIT,
+    PING_RESPONSE_COMMAND_TYPE_ID, PRE_VOTE_REQUEST_COMMAND_TYPE_ID,
+    PRE_VOTE_RESPONSE_COMMAND_TYPE_ID, PROTOCOL_VERSION, PROTOCOL_VERSION_1, PROTOCOL_VERSION_10,
+    PROTOCOL_VERSION_11, PROTOCOL_VERSION_12, PROTOCOL_VERSION_13, PROTOCOL_VERSION_14,
+    PROTOCOL_VERSION_2, PROTOCOL_VERSION_3, PROTOCOL_VERSION_4, PROTOCOL_VERSION_5,
+    PROTOCOL_VERSION_6, PROTOCOL_VERSION_7, PROTOCOL_VERSION_8, PROTOCOL_VERSION_9,
+    PSK_AUTH_COMMAND_TYPE_ID, PSK_CHALLENGE_OCTETS, PSK_PROOF_OCTETS, REDIRECT_COMMAND_TYPE_ID,
+    RELAYED_FROM_CLIENT_COMMAND_TYPE_ID, RELAY_TO_CLIENT_COMMAND_TYPE_ID,
+    REPLICATE_ACK_COMMAND_TYPE_ID, REPLICATE_COMMAND_TYPE_ID, RESYNC_DATA_COMMAND_TYPE_ID,
+    RESYNC_REQUEST_COMMAND_TYPE_ID, RETRY_COOKIE_COMMAND_TYPE_ID, RETRY_COOKIE_OCTETS,
+    ROOM_CLOSED_COMMAND_TYPE_ID, ROOM_INFO_COMMAND_TYPE_ID, ROOM_INFO_FLAG_KNOWLEDGE_VECTOR,
+    ROOM_INFO_PAGE_COMMAND_TYPE_ID, SERVER_LOAD_COMMAND_TYPE_ID, SET_PRESENCE_COMMAND_TYPE_ID,
+    SNAPSHOT_CHUNK_COMMAND_TYPE_ID, SNAPSHOT_REQUEST_COMMAND_TYPE_ID,
+    STATS_REQUEST_COMMAND_TYPE_ID, TERM_ADVANCED_COMMAND_TYPE_ID, THROTTLED_COMMAND_TYPE_ID,
+    TIMEOUT_CONFIG_COMMAND_TYPE_ID, TRANSFER_CHUNK_COMMAND_TYPE_ID,
+    TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID, UNBAN_CLIENT_COMMAND_TYPE_ID,
+    UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CommandDirection {
+    /// Decoded by [`crate::ServerReceiveCommand`]
+    ClientToServer,
+    /// Decoded by [`crate::ClientReceiveCommand`]
+    ServerToClient,
+    /// Decoded by both enums
+    Both,
+}
+
+impl CommandDirection {
+    pub fn is_server_receive(self) -> bool {
+        self != Self::ServerToClient
+    }
+
+    pub fn is_client_receive(self) -> bool {
+        self != Self::ClientToServer
+    }
+}
+
+/// The delivery guarantee a transport with several channels should give the command
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CommandDelivery {
+    /// The command is sent again until acknowledged, and arrives in order
+    ReliableOrdered,
+    /// The command can be lost or arrive out of order, e.g. measurements that the next one
+    /// replaces
+    Unreliable,
+}
+
+/// Who sent a command, as known to the receiver
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum SenderRole {
+    Server,
+    /// A Client that is not the leader of the room
+    Client,
+    /// The Client that is the leader of the room
+    Leader,
+}
+
+/// Who may send a command
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum SenderAuthorization {
+    AnyClient,
+    LeaderOnly,
+    ServerOnly,
+    AnyClientOrServer,
+    LeaderOrServer,
+}
+
+impl SenderAuthorization {
+    pub fn allowed_from(self, role: SenderRole) -> bool {
+        match self {
+            Self::AnyClient => role != SenderRole::Server,
+            Self::LeaderOnly => role == SenderRole::Leader,
+            Self::ServerOnly => role == SenderRole::Server,
+            Self::AnyClientOrServer => true,
+            Self::LeaderOrServer => role != SenderRole::Client,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CommandDescriptor {
+    pub type_id: u8,
+    /// The name used in decode error paths
+    pub name: &'static str,
+    pub direction: CommandDirection,
+    /// Who may send the command, checked when decoding with [`crate::DecodeOptions::with_sender`]
+    pub sender: SenderAuthorization,
+    pub delivery: CommandDelivery,
+    pub min_version: ProtocolVersion,
+    /// The smallest serialized payload for a version, not counting the command type id
+    pub min_octet_size: fn(ProtocolVersion) -> usize,
+    /// The serialized fields in order, after the command type id
+    pub fields: &'static [FieldDescriptor],
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LengthPrefix {
+    U8,
+    U16,
+}
+
+impl LengthPrefix {
+    pub fn octet_size(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+        }
+    }
+}
+
+/// How a field is serialized. All integers are big-endian.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FieldKind {
+    U8,
+    U16,
+    U32,
+    U64,
+    I16,
+    /// A `U8`, non-zero for `true`
+    Bool,
+    /// Seven bits per octet, least significant group first
+    Varint,
+    /// Constant octets
+    Magic(&'static [u8]),
+    FixedOctets(usize),
+    /// Octets prefixed with their count, at least `min` of them
+    Octets {
+        prefix: LengthPrefix,
+        min: usize,
+    },
+    /// UTF-8 prefixed with its length in octets, at least `min` of them
+    Text {
+        prefix: LengthPrefix,
+        min: usize,
+    },
+    /// `count` elements without a prefix
+    Array {
+        count: usize,
+        element: &'static FieldKind,
+    },
+    /// Elements prefixed with their count, at least `min` of them
+    List {
+        prefix: LengthPrefix,
+        min: usize,
+        element: &'static FieldKind,
+    },
+    Struct(&'static StructDescriptor),
+    /// See [`ClientIdentity`], its layout depends on the version
+    ClientIdentity,
+    /// See [`UserId`], its layout depends on the version
+    UserId,
+    /// An address type octet (1 for IPv4, 2 for IPv6), the address and a `U16` port
+    SocketAddr,
+    /// Only present if `mask` is set in the earlier field named `flags`
+    Flagged {
+        flags: &'static str,
+        mask: u8,
+        kind: &'static FieldKind,
+    },
+}
+
+impl FieldKind {
+    /// The fewest octets the field occupies in `version`
+    pub fn min_octet_size(&self, version: ProtocolVersion) -> usize {
+        match self {
+            Self::U8 | Self::Bool | Self::Varint => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 => 4,
+            Self::U64 => 8,
+            Self::Magic(octets) => octets.len(),
+            Self::FixedOctets(count) => *count,
+            Self::Octets { prefix, min } | Self::Text { prefix, min } => prefix.octet_size() + min,
+            Self::Array { count, element } => count * element.min_octet_size(version),
+            Self::List {
+                prefix,
+                min,
+                element,
+            } => prefix.octet_size() + min * element.min_octet_size(version),
+            Self::Struct(descriptor) => fields_min_octet_size(descriptor.fields, version),
+            Self::ClientIdentity => ClientIdentity::min_octet_size(version),
+            Self::UserId => UserId::octet_size(version),
+            Self::SocketAddr => 1 + 4 + 2,
+            Self::Flagged { .. } => 0,
+        }
+    }
+
+    /// Adds the protocol versions that changed the layout of the field
+    fn push_layout_versions(&self, versions: &mut Vec<ProtocolVersion>) {
+        match self {
+            Self::Array { element, .. } | Self::List { element, .. } => {
+                element.push_layout_versions(versions)
+            }
+            Self::Struct(descriptor) => push_fields_layout_versions(descriptor.fields, versions),
+            Self::Flagged { kind, .. } => kind.push_layout_versions(versions),
+            Self::ClientIdentity => versions.extend([PROTOCOL_VERSION_3, PROTOCOL_VERSION_4]),
+            Self::UserId => versions.push(PROTOCOL_VERSION_3),
+            _ => {}
+        }
+    }
+}
+
+fn push_fields_layout_versions(fields: &[FieldDescriptor], versions: &mut Vec<ProtocolVersion>) {
+    for field in fields {
+        versions.push(field.since);
+        field.kind.push_layout_versions(versions);
+    }
+}
+
+impl CommandDescriptor {
+    pub fn allowed_from(&self, role: SenderRole) -> bool {
+        self.sender.allowed_from(role)
+    }
+
+    /// The protocol versions, up to [`PROTOCOL_VERSION`], that changed the layout of the command,
+    /// in increasing order and starting with `min_version`. The command is serialized the same
+    /// in every version from one of them up to the next.
+    pub fn layout_versions(&self) -> Vec<ProtocolVersion> {
+        let mut versions = vec![self.min_version];
+        push_fields_layout_versions(self.fields, &mut versions);
+        versions.retain(|version| (self.min_version..=PROTOCOL_VERSION).contains(version));
+        versions.sort_unstable();
+        versions.dedup();
+        versions
+    }
+
+    /// The layout version the command uses in `version`, `None` before `min_version`
+    pub fn layout_version(&self, version: ProtocolVersion) -> Option<ProtocolVersion> {
+        self.layout_versions()
+            .into_iter()
+            .take_while(|layout_version| *layout_version <= version)
+            .last()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FieldDescriptor {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    /// The protocol version that introduced the field, it is absent in earlier versions
+    pub since: ProtocolVersion,
+}
+
+/// A group of fields serialized inside commands
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StructDescriptor {
+    pub name: &'static str,
+    pub fields: &'static [FieldDescriptor],
+}
+
+/// The fewest octets the fields present in `version` occupy
+pub fn fields_min_octet_size(fields: &[FieldDescriptor], version: ProtocolVersion) -> usize {
+    fields
+        .iter()
+        .filter(|field| field.since <= version)
+        .map(|field| field.kind.min_octet_size(version))
+        .sum()
+}
+
+const fn field(name: &'static str, kind: FieldKind) -> FieldDescriptor {
+    FieldDescriptor {
+        name,
+        kind,
+        since: PROTOCOL_VERSION_1,
+    }
+}
+
+const fn field_since(
+    name: &'static str,
+    kind: FieldKind,
+    since: ProtocolVersion,
+) -> FieldDescriptor {
+    FieldDescriptor { name, kind, since }
+}
+
+const PAYLOAD: FieldKind = FieldKind::Octets {
+    prefix: LengthPrefix::U16,
+    min: 0,
+};
+
+const CLIENT_INFO: StructDescriptor = StructDescriptor {
+    name: "client_info",
+    fields: &[
+        field("connection_index", FieldKind::U8),
+        field("identity", FieldKind::ClientIdentity),
+        field_since("role_flags", FieldKind::U16, PROTOCOL_VERSION_2),
+        field_since("revision", FieldKind::U32, PROTOCOL_VERSION_7),
+        field_since("presence", FieldKind::U8, PROTOCOL_VERSION_8),
+    ],
+};
+
+const KNOWLEDGE_VECTOR_ENTRY: StructDescriptor = StructDescriptor {
+    name: "knowledge_vector_entry",
+    fields: &[
+        field("connection_index", FieldKind::U8),
+        field("knowledge", FieldKind::Varint),
+    ],
+};
+
+const LOG_ENTRY: StructDescriptor = StructDescriptor {
+    name: "log_entry",
+    fields: &[field("payload", PAYLOAD)],
+};
+
+const KNOWLEDGE_VECTOR: StructDescriptor = StructDescriptor {
+    name: "knowledge_vector",
+    fields: &[field(
+        "entries",
+        FieldKind::List {
+            prefix: LengthPrefix::U8,
+            min: 0,
+            element: &FieldKind::Struct(&KNOWLEDGE_VECTOR_ENTRY),
+        },
+    )],
+};
+
+const ROOM_INFO_FIELDS: &[FieldDescriptor] = &[
+    field("term", FieldKind::U16),
+    field(
+        "client_infos",
+        FieldKind::List {
+            prefix: LengthPrefix::U8,
+            min: 0,
+            element: &FieldKind::Struct(&CLIENT_INFO),
+        },
+    ),
+    field("leader_index", FieldKind::U8),
+    field_since("flags", FieldKind::U8, PROTOCOL_VERSION_9),
+    field_since(
+        "knowledge_vector",
+        FieldKind::Flagged {
+            flags: "flags",
+            mask: ROOM_INFO_FLAG_KNOWLEDGE_VECTOR,
+            kind: &FieldKind::Struct(&KNOWLEDGE_VECTOR),
+        },
+        PROTOCOL_VERSION_10,
+    ),
+    // After the knowledge vector, so the protobuf fields keep their numbers
+    field_since("quorum_size", FieldKind::U8, PROTOCOL_VERSION_11),
+    field_since("votes_for_leader", FieldKind::U8, PROTOCOL_VERSION_11),
+    field_since(
+        "leader_lease_expires_in_ms",
+        FieldKind::U32,
+        PROTOCOL_VERSION_12,
+    ),
+    // Followed by the network stats of the clients if `ROOM_INFO_FLAG_NETWORK_STATS` is set
+];
+
+const ROOM_INFO: StructDescriptor = StructDescriptor {
+    name: RoomInfoCommand::NAME,
+    fields: ROOM_INFO_FIELDS,
+};
+
+const JOIN_REQUEST_FIELDS: &[FieldDescriptor] = &[
+    field("identity", FieldKind::ClientIdentity),
+    field("requested_role_flags", FieldKind::U16),
+];
+
+const JOIN_REQUEST: StructDescriptor = StructDescriptor {
+    name: JoinRequestCommand::NAME,
+    fields: JOIN_REQUEST_FIELDS,
+};
+
+const CONNECT_TOKEN: StructDescriptor = StructDescriptor {
+    name: "connect_token",
+    fields: &[
+        field("expires_at_s", FieldKind::U64),
+        field("client_id", FieldKind::U64),
+        field(
+            "server_addresses",
+            FieldKind::List {
+                prefix: LengthPrefix::U8,
+                min: 1,
+                element: &FieldKind::SocketAddr,
+            },
+        ),
+        field("private_data", PAYLOAD),
+    ],
+};
+
+const PING_FIELDS: &[FieldDescriptor] = &[
+    field("term", FieldKind::U16),
+    field("knowledge", FieldKind::U64),
+    field("has_connection_to_leader", FieldKind::Bool),
+    field_since("connection_index", FieldKind::U8, PROTOCOL_VERSION_5),
+    field_since("clock_drift_ppm", FieldKind::I16, PROTOCOL_VERSION_6),
+    field_since("presence", FieldKind::U8, PROTOCOL_VERSION_10),
+    field_since(
+        "knowledge_vector",
+        FieldKind::Flagged {
+            flags: "presence",
+            mask: 1 << PING_KNOWLEDGE_VECTOR_BIT,
+            kind: &FieldKind::Struct(&KNOWLEDGE_VECTOR),
+        },
+        PROTOCOL_VERSION_10,
+    ),
+];
+
+const ROOM_CLOSED_FIELDS: &[FieldDescriptor] = &[
+    field("reason_code", FieldKind::U8),
+    field("closes_at_ms", FieldKind::U32),
+];
+
+const MUTE_STATE_FIELDS: &[FieldDescriptor] = &[
+    field("connection_index", FieldKind::U8),
+    field("flags", FieldKind::U8),
+];
+
+const PING_RESPONSE_FIELDS: &[FieldDescriptor] = &[
+    field("term", FieldKind::U16),
+    field("server_knowledge", FieldKind::U64),
+    field("your_knowledge_ack", FieldKind::U64),
+];
+
+const RELAY_TO_CLIENT_FIELDS: &[FieldDescriptor] = &[
+    field("target_connection_index", FieldKind::U8),
+    field("payload", PAYLOAD),
+];
+
+const RELAYED_FROM_CLIENT_FIELDS: &[FieldDescriptor] = &[
+    field("source_connection_index", FieldKind::U8),
+    field("payload", PAYLOAD),
+];
+
+const BROADCAST_PAYLOAD_FIELDS: &[FieldDescriptor] = &[field("payload", PAYLOAD)];
+
+const BROADCASTED_PAYLOAD_FIELDS: &[FieldDescriptor] = &[
+    field("source_connection_index", FieldKind::U8),
+    field("payload", PAYLOAD),
+];
+
+const JOIN_ACCEPTED_FIELDS: &[FieldDescriptor] = &[
+    field("connection_index", FieldKind::U8),
+    field("room_info", FieldKind::Struct(&ROOM_INFO)),
+];
+
+const JOIN_DENIED_FIELDS: &[FieldDescriptor] = &[field("reason_code", FieldKind::U8)];
+
+const RESYNC_REQUEST_FIELDS: &[FieldDescriptor] = &[
+    field("from_term", FieldKind::U16),
+    field("from_knowledge", FieldKind::U64),
+];
+
+const RESYNC_DATA_FIELDS: &[FieldDescriptor] = &[
+    field("term", FieldKind::U16),
+    field("knowledge", FieldKind::U64),
+    field("chunk_index", FieldKind::U16),
+    field("chunk_count", FieldKind::U16),
+    field("data", PAYLOAD),
+];
+
+const SNAPSHOT_REQUEST_FIELDS: &[FieldDescriptor] = &[field("snapshot_id", FieldKind::U32)];
+
+const SNAPSHOT_CHUNK_FIELDS: &[FieldDescriptor] = &[
+    field("snapshot_id", FieldKind::U32),
+    field("chunk_index", FieldKind::U16),
+    field("chunk_count", FieldKind::U16),
+    field("data", PAYLOAD),
+];
+
+const TRANSFER_CHUNK_FIELDS: &[FieldDescriptor] = &[
+    field("transfer_id", FieldKind::U32),
+    field("chunk_index", FieldKind::U16),
+    field("chunk_count", FieldKind::U16),
+    field("data", PAYLOAD),
+];
+
+const TRANSFER_RESEND_REQUEST_FIELDS: &[FieldDescriptor] = &[
+    field("transfer_id", FieldKind::U32),
+    field(
+        "missing_chunk_indices",
+        FieldKind::List {
+            prefix: LengthPrefix::U16,
+            min: 0,
+            element: &FieldKind::U16,
+        },
+    ),
+];
+
+const BANDWIDTH_REPORT_FIELDS: &[FieldDescriptor] = &[
+    field("bytes_sent", FieldKind::Varint),
+    field("bytes_received", FieldKind::Varint),
+    field("window_ms", FieldKind::Varint),
+];
+
+const LATENCY_REPORT_FIELDS: &[FieldDescriptor] = &[field(
+    "buckets",
+    FieldKind::Array {
+        count: LATENCY_BUCKET_COUNT,
+        element: &FieldKind::U16,
+    },
+)];
+
+const MAINTENANCE_NOTICE_FIELDS: &[FieldDescriptor] = &[
+    field("starts_in_s", FieldKind::U32),
+    field("expected_duration_s", FieldKind::U32),
+];
+
+const REDIRECT_FIELDS: &[FieldDescriptor] = &[
+    field(
+        "host",
+        FieldKind::Text {
+            prefix: LengthPrefix::U8,
+            min: 1,
+        },
+    ),
+    field("port", FieldKind::U16),
+    field("token", PAYLOAD),
+];
+
+const SERVER_LOAD_FIELDS: &[FieldDescriptor] = &[
+    field("cpu_permille", FieldKind::U16),
+    field("rooms", FieldKind::U16),
+    field("headroom", FieldKind::U8),
+];
+
+const PSK_AUTH_FIELDS: &[FieldDescriptor] = &[
+    field("key_id", FieldKind::U16),
+    field("proof", FieldKind::FixedOctets(PSK_PROOF_OCTETS)),
+];
+
+const SERVER_LOAD: StructDescriptor = StructDescriptor {
+    name: ServerLoadCommand::NAME,
+    fields: SERVER_LOAD_FIELDS,
+};
+
+const CHALLENGE_FIELDS: &[FieldDescriptor] = &[
+    field("challenge", FieldKind::FixedOctets(PSK_CHALLENGE_OCTETS)),
+    field_since("presence", FieldKind::U8, PROTOCOL_VERSION_14),
+    field_since(
+        "server_load",
+        FieldKind::Flagged {
+            flags: "presence",
+            mask: 1 << CHALLENGE_SERVER_LOAD_BIT,
+            kind: &FieldKind::Struct(&SERVER_LOAD),
+        },
+        PROTOCOL_VERSION_14,
+    ),
+];
+
+const CONNECT_WITH_TOKEN_FIELDS: &[FieldDescriptor] =
+    &[field("token", FieldKind::Struct(&CONNECT_TOKEN))];
+
+const RETRY_COOKIE_FIELDS: &[FieldDescriptor] =
+    &[field("cookie", FieldKind::FixedOctets(RETRY_COOKIE_OCTETS))];
+
+const CONNECT_WITH_COOKIE_FIELDS: &[FieldDescriptor] = &[
+    field("cookie", FieldKind::FixedOctets(RETRY_COOKIE_OCTETS)),
+    field("join_request", FieldKind::Struct(&JOIN_REQUEST)),
+];
+
+const THROTTLED_FIELDS: &[FieldDescriptor] = &[field("retry_after_ms", FieldKind::U16)];
+
+const TIMEOUT_CONFIG_FIELDS: &[FieldDescriptor] = &[
+    field("disconnect_after_ms", FieldKind::U32),
+    field("ping_interval_ms", FieldKind::U16),
+];
+
+/// The settings are an [`crate::Extensions`] area, described as the octets it spans
+const UPDATE_ROOM_SETTINGS_FIELDS: &[FieldDescriptor] = &[
+    field("settings_version", FieldKind::U32),
+    field("settings", PAYLOAD),
+];
+
+const CHANNEL_ROUTE: StructDescriptor = StructDescriptor {
+    name: "channel_route",
+    fields: &[
+        field("channel_id", FieldKind::U8),
+        field("delivery", FieldKind::U8),
+    ],
+};
+
+const CHANNEL_PROFILE_ROUTE: StructDescriptor = StructDescriptor {
+    name: "channel_profile_route",
+    fields: &[
+        field("command_type_id", FieldKind::U8),
+        field("channel_id", FieldKind::U8),
+        field("delivery", FieldKind::U8),
+    ],
+};
+
+const CHANNEL_PROFILE_FIELDS: &[FieldDescriptor] = &[
+    field("fallback", FieldKind::Struct(&CHANNEL_ROUTE)),
+    field(
+        "routes",
+        FieldKind::List {
+            prefix: LengthPrefix::U8,
+            min: 0,
+            element: &FieldKind::Struct(&CHANNEL_PROFILE_ROUTE),
+        },
+    ),
+];
+
+const ROOM_INFO_PAGE_FIELDS: &[FieldDescriptor] = &[
+    field("term", FieldKind::U16),
+    field("page_index", FieldKind::U8),
+    field("page_count", FieldKind::U8),
+    field(
+        "client_infos",
+        FieldKind::List {
+            prefix: LengthPrefix::U8,
+            min: 0,
+            element: &FieldKind::Struct(&CLIENT_INFO),
+        },
+    ),
+    field("leader_index", FieldKind::U8),
+    field_since("flags", FieldKind::U8, PROTOCOL_VERSION_13),
+    field_since(
+        "knowledge_vector",
+        FieldKind::Flagged {
+            flags: "flags",
+            mask: ROOM_INFO_FLAG_KNOWLEDGE_VECTOR,
+            kind: &FieldKind::Struct(&KNOWLEDGE_VECTOR),
+        },
+        PROTOCOL_VERSION_13,
+    ),
+    field_since("quorum_size", FieldKind::U8, PROTOCOL_VERSION_13),
+    field_since("votes_for_leader", FieldKind::U8, PROTOCOL_VERSION_13),
+    field_since(
+        "leader_lease_expires_in_ms",
+        FieldKind::U32,
+        PROTOCOL_VERSION_13,
+    ),
+];
+
+const KNOWN_REVISION: StructDescriptor = StructDescriptor {
+    name: "known_revision",
+    fields: &[
+        field("connection_index", FieldKind::U8),
+        field("revision", FieldKind::U32),
+    ],
+};
+
+const MEMBERSHIP_SYNC_REQUEST_FIELDS: &[FieldDescriptor] = &[field(
+    "known_revisions",
+    FieldKind::List {
+        prefix: LengthPrefix::U8,
+        min: 0,
+        element: &FieldKind::Struct(&KNOWN_REVISION),
+    },
+)];
+
+const MEMBERSHIP_SYNC_RESPONSE_FIELDS: &[FieldDescriptor] = &[
+    field("term", FieldKind::U16),
+    field("leader_connection_index", FieldKind::U8),
+    field(
+        "changed",
+        FieldKind::List {
+            prefix: LengthPrefix::U8,
+            min: 0,
+            element: &FieldKind::Struct(&CLIENT_INFO),
+        },
+    ),
+    field(
+        "removed",
+        FieldKind::List {
+            prefix: LengthPrefix::U8,
+            min: 0,
+            element: &FieldKind::U8,
+        },
+    ),
+];
+
+const SET_PRESENCE_FIELDS: &[FieldDescriptor] = &[field("presence", FieldKind::U8)];
+
+const KNOWLEDGE_ACK_FIELDS: &[FieldDescriptor] = &[field("acked_knowledge", FieldKind::U64)];
+
+const TERM_ADVANCED_FIELDS: &[FieldDescriptor] = &[
+    field("new_term", FieldKind::U16),
+    field("cause", FieldKind::U8),
+];
+
+const PRE_VOTE_REQUEST_FIELDS: &[FieldDescriptor] = &[
+    field("next_term", FieldKind::U16),
+    field("knowledge", FieldKind::U64),
+];
+
+const PRE_VOTE_RESPONSE_FIELDS: &[FieldDescriptor] = &[
+    field("next_term", FieldKind::U16),
+    field("granted", FieldKind::Bool),
+];
+
+const REPLICATE_FIELDS: &[FieldDescriptor] = &[
+    field("term", FieldKind::U16),
+    field("prev_knowledge", FieldKind::U64),
+    field(
+        "entries",
+        FieldKind::List {
+            prefix: LengthPrefix::U8,
+            min: 0,
+            element: &FieldKind::Struct(&LOG_ENTRY),
+        },
+    ),
+];
+
+const REPLICATE_ACK_FIELDS: &[FieldDescriptor] = &[
+    field("term", FieldKind::U16),
+    field("knowledge", FieldKind::U64),
+    field("success", FieldKind::Bool),
+];
+
+const BAN_CLIENT_FIELDS: &[FieldDescriptor] = &[
+    field("custom_user_id", FieldKind::UserId),
+    field("duration_s", FieldKind::U32),
+];
+
+const UNBAN_CLIENT_FIELDS: &[FieldDescriptor] = &[field("custom_user_id", FieldKind::UserId)];
+
+/// The rest of the magic follows the command type id, which is the first magic octet
+const HELLO_FIELDS: &[FieldDescriptor] = &[
+    field(
+        "magic",
+        FieldKind::Magic(&[HELLO_MAGIC[1], HELLO_MAGIC[2], HELLO_MAGIC[3]]),
+    ),
+    field("version", FieldKind::U8),
+    field("fingerprint", FieldKind::U64),
+];
+
+/// Every command, ordered by type id
+pub const COMMAND_DESCRIPTORS: &[CommandDescriptor] = &[
+    CommandDescriptor {
+        type_id: PING_COMMAND_TYPE_ID,
+        name: PingCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::Unreliable,
+        min_version: PingCommand::MIN_VERSION,
+        min_octet_size: PingCommand::min_octet_size,
+        fields: PING_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: ROOM_INFO_COMMAND_TYPE_ID,
+        name: RoomInfoCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: RoomInfoCommand::MIN_VERSION,
+        min_octet_size: RoomInfoCommand::min_octet_size,
+        fields: ROOM_INFO_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: ROOM_CLOSED_COMMAND_TYPE_ID,
+        name: RoomClosedCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: RoomClosedCommand::MIN_VERSION,
+        min_octet_size: RoomClosedCommand::min_octet_size,
+        fields: ROOM_CLOSED_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: MUTE_STATE_COMMAND_TYPE_ID,
+        name: MuteStateCommand::NAME,
+        direction: CommandDirection::Both,
+        sender: SenderAuthorization::AnyClientOrServer,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: MuteStateCommand::MIN_VERSION,
+        min_octet_size: MuteStateCommand::min_octet_size,
+        fields: MUTE_STATE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: PING_RESPONSE_COMMAND_TYPE_ID,
+        name: PingResponseCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::Unreliable,
+        min_version: PingResponseCommand::MIN_VERSION,
+        min_octet_size: PingResponseCommand::min_octet_size,
+        fields: PING_RESPONSE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: RELAY_TO_CLIENT_COMMAND_TYPE_ID,
+        name: RelayToClientCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: RelayToClientCommand::MIN_VERSION,
+        min_octet_size: RelayToClientCommand::min_octet_size,
+        fields: RELAY_TO_CLIENT_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: RELAYED_FROM_CLIENT_COMMAND_TYPE_ID,
+        name: RelayedFromClientCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: RelayedFromClientCommand::MIN_VERSION,
+        min_octet_size: RelayedFromClientCommand::min_octet_size,
+        fields: RELAYED_FROM_CLIENT_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: BROADCAST_PAYLOAD_COMMAND_TYPE_ID,
+        name: BroadcastPayloadCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: BroadcastPayloadCommand::MIN_VERSION,
+        min_octet_size: BroadcastPayloadCommand::min_octet_size,
+        fields: BROADCAST_PAYLOAD_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: BROADCASTED_PAYLOAD_COMMAND_TYPE_ID,
+        name: BroadcastedPayloadCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: BroadcastedPayloadCommand::MIN_VERSION,
+        min_octet_size: BroadcastedPayloadCommand::min_octet_size,
+        fields: BROADCASTED_PAYLOAD_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: JOIN_REQUEST_COMMAND_TYPE_ID,
+        name: JoinRequestCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: JoinRequestCommand::MIN_VERSION,
+        min_octet_size: JoinRequestCommand::min_octet_size,
+        fields: JOIN_REQUEST_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: JOIN_ACCEPTED_COMMAND_TYPE_ID,
+        name: JoinAcceptedCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: JoinAcceptedCommand::MIN_VERSION,
+        min_octet_size: JoinAcceptedCommand::min_octet_size,
+        fields: JOIN_ACCEPTED_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: JOIN_DENIED_COMMAND_TYPE_ID,
+        name: JoinDeniedCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: JoinDeniedCommand::MIN_VERSION,
+        min_octet_size: JoinDeniedCommand::min_octet_size,
+        fields: JOIN_DENIED_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: RESYNC_REQUEST_COMMAND_TYPE_ID,
+        name: ResyncRequestCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: ResyncRequestCommand::MIN_VERSION,
+        min_octet_size: ResyncRequestCommand::min_octet_size,
+        fields: RESYNC_REQUEST_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: RESYNC_DATA_COMMAND_TYPE_ID,
+        name: ResyncDataCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: ResyncDataCommand::MIN_VERSION,
+        min_octet_size: ResyncDataCommand::min_octet_size,
+        fields: RESYNC_DATA_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: SNAPSHOT_REQUEST_COMMAND_TYPE_ID,
+        name: SnapshotRequestCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: SnapshotRequestCommand::MIN_VERSION,
+        min_octet_size: SnapshotRequestCommand::min_octet_size,
+        fields: SNAPSHOT_REQUEST_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: SNAPSHOT_CHUNK_COMMAND_TYPE_ID,
+        name: SnapshotChunkCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: SnapshotChunkCommand::MIN_VERSION,
+        min_octet_size: SnapshotChunkCommand::min_octet_size,
+        fields: SNAPSHOT_CHUNK_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: TRANSFER_CHUNK_COMMAND_TYPE_ID,
+        name: TransferChunkCommand::NAME,
+        direction: CommandDirection::Both,
+        sender: SenderAuthorization::AnyClientOrServer,
+        delivery: CommandDelivery::Unreliable,
+        min_version: TransferChunkCommand::MIN_VERSION,
+        min_octet_size: TransferChunkCommand::min_octet_size,
+        fields: TRANSFER_CHUNK_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID,
+        name: TransferResendRequestCommand::NAME,
+        direction: CommandDirection::Both,
+        sender: SenderAuthorization::AnyClientOrServer,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: TransferResendRequestCommand::MIN_VERSION,
+        min_octet_size: TransferResendRequestCommand::min_octet_size,
+        fields: TRANSFER_RESEND_REQUEST_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: BANDWIDTH_REPORT_COMMAND_TYPE_ID,
+        name: BandwidthReportCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::Unreliable,
+        min_version: BandwidthReportCommand::MIN_VERSION,
+        min_octet_size: BandwidthReportCommand::min_octet_size,
+        fields: BANDWIDTH_REPORT_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: LATENCY_REPORT_COMMAND_TYPE_ID,
+        name: LatencyReportCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::Unreliable,
+        min_version: LatencyReportCommand::MIN_VERSION,
+        min_octet_size: LatencyReportCommand::min_octet_size,
+        fields: LATENCY_REPORT_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: MAINTENANCE_NOTICE_COMMAND_TYPE_ID,
+        name: MaintenanceNoticeCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: MaintenanceNoticeCommand::MIN_VERSION,
+        min_octet_size: MaintenanceNoticeCommand::min_octet_size,
+        fields: MAINTENANCE_NOTICE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: REDIRECT_COMMAND_TYPE_ID,
+        name: RedirectCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: RedirectCommand::MIN_VERSION,
+        min_octet_size: RedirectCommand::min_octet_size,
+        fields: REDIRECT_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: SERVER_LOAD_COMMAND_TYPE_ID,
+        name: ServerLoadCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::Unreliable,
+        min_version: ServerLoadCommand::MIN_VERSION,
+        min_octet_size: ServerLoadCommand::min_octet_size,
+        fields: SERVER_LOAD_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: PSK_AUTH_COMMAND_TYPE_ID,
+        name: PskAuthCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: PskAuthCommand::MIN_VERSION,
+        min_octet_size: PskAuthCommand::min_octet_size,
+        fields: PSK_AUTH_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: CHALLENGE_COMMAND_TYPE_ID,
+        name: ChallengeCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: ChallengeCommand::MIN_VERSION,
+        min_octet_size: ChallengeCommand::min_octet_size,
+        fields: CHALLENGE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: CONNECT_WITH_TOKEN_COMMAND_TYPE_ID,
+        name: ConnectWithTokenCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: ConnectWithTokenCommand::MIN_VERSION,
+        min_octet_size: ConnectWithTokenCommand::min_octet_size,
+        fields: CONNECT_WITH_TOKEN_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: RETRY_COOKIE_COMMAND_TYPE_ID,
+        name: RetryCookieCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: RetryCookieCommand::MIN_VERSION,
+        min_octet_size: RetryCookieCommand::min_octet_size,
+        fields: RETRY_COOKIE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: CONNECT_WITH_COOKIE_COMMAND_TYPE_ID,
+        name: ConnectWithCookieCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: ConnectWithCookieCommand::MIN_VERSION,
+        min_octet_size: ConnectWithCookieCommand::min_octet_size,
+        fields: CONNECT_WITH_COOKIE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: THROTTLED_COMMAND_TYPE_ID,
+        name: ThrottledCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: ThrottledCommand::MIN_VERSION,
+        min_octet_size: ThrottledCommand::min_octet_size,
+        fields: THROTTLED_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: TIMEOUT_CONFIG_COMMAND_TYPE_ID,
+        name: TimeoutConfigCommand::NAME,
+        direction: CommandDirection::Both,
+        sender: SenderAuthorization::AnyClientOrServer,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: TimeoutConfigCommand::MIN_VERSION,
+        min_octet_size: TimeoutConfigCommand::min_octet_size,
+        fields: TIMEOUT_CONFIG_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID,
+        name: UpdateRoomSettingsCommand::NAME,
+        direction: CommandDirection::Both,
+        sender: SenderAuthorization::LeaderOrServer,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: UpdateRoomSettingsCommand::MIN_VERSION,
+        min_octet_size: UpdateRoomSettingsCommand::min_octet_size,
+        fields: UPDATE_ROOM_SETTINGS_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: CHANNEL_PROFILE_COMMAND_TYPE_ID,
+        name: ChannelProfileCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: ChannelProfileCommand::MIN_VERSION,
+        min_octet_size: ChannelProfileCommand::min_octet_size,
+        fields: CHANNEL_PROFILE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: ROOM_INFO_PAGE_COMMAND_TYPE_ID,
+        name: RoomInfoPageCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: RoomInfoPageCommand::MIN_VERSION,
+        min_octet_size: RoomInfoPageCommand::min_octet_size,
+        fields: ROOM_INFO_PAGE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: MEMBERSHIP_SYNC_REQUEST_COMMAND_TYPE_ID,
+        name: MembershipSyncRequestCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: MembershipSyncRequestCommand::MIN_VERSION,
+        min_octet_size: MembershipSyncRequestCommand::min_octet_size,
+        fields: MEMBERSHIP_SYNC_REQUEST_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID,
+        name: MembershipSyncResponseCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: MembershipSyncResponseCommand::MIN_VERSION,
+        min_octet_size: MembershipSyncResponseCommand::min_octet_size,
+        fields: MEMBERSHIP_SYNC_RESPONSE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: SET_PRESENCE_COMMAND_TYPE_ID,
+        name: SetPresenceCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: SetPresenceCommand::MIN_VERSION,
+        min_octet_size: SetPresenceCommand::min_octet_size,
+        fields: SET_PRESENCE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: KNOWLEDGE_ACK_COMMAND_TYPE_ID,
+        name: KnowledgeAckCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::Unreliable,
+        min_version: KnowledgeAckCommand::MIN_VERSION,
+        min_octet_size: KnowledgeAckCommand::min_octet_size,
+        fields: KNOWLEDGE_ACK_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: TERM_ADVANCED_COMMAND_TYPE_ID,
+        name: TermAdvancedCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: TermAdvancedCommand::MIN_VERSION,
+        min_octet_size: TermAdvancedCommand::min_octet_size,
+        fields: TERM_ADVANCED_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: PRE_VOTE_REQUEST_COMMAND_TYPE_ID,
+        name: PreVoteRequestCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::AnyClient,
+        delivery: CommandDelivery::Unreliable,
+        min_version: PreVoteRequestCommand::MIN_VERSION,
+        min_octet_size: PreVoteRequestCommand::min_octet_size,
+        fields: PRE_VOTE_REQUEST_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: PRE_VOTE_RESPONSE_COMMAND_TYPE_ID,
+        name: PreVoteResponseCommand::NAME,
+        direction: CommandDirection::ServerToClient,
+        sender: SenderAuthorization::ServerOnly,
+        delivery: CommandDelivery::Unreliable,
+        min_version: PreVoteResponseCommand::MIN_VERSION,
+        min_octet_size: PreVoteResponseCommand::min_octet_size,
+        fields: PRE_VOTE_RESPONSE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: REPLICATE_COMMAND_TYPE_ID,
+        name: ReplicateCommand::NAME,
+        direction: CommandDirection::Both,
+        sender: SenderAuthorization::LeaderOrServer,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: ReplicateCommand::MIN_VERSION,
+        min_octet_size: ReplicateCommand::min_octet_size,
+        fields: REPLICATE_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: REPLICATE_ACK_COMMAND_TYPE_ID,
+        name: ReplicateAckCommand::NAME,
+        direction: CommandDirection::Both,
+        sender: SenderAuthorization::AnyClientOrServer,
+        delivery: CommandDelivery::Unreliable,
+        min_version: ReplicateAckCommand::MIN_VERSION,
+        min_octet_size: ReplicateAckCommand::min_octet_size,
+        fields: REPLICATE_ACK_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: BAN_CLIENT_COMMAND_TYPE_ID,
+        name: BanClientCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::LeaderOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: BanClientCommand::MIN_VERSION,
+        min_octet_size: BanClientCommand::min_octet_size,
+        fields: BAN_CLIENT_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: UNBAN_CLIENT_COMMAND_TYPE_ID,
+        name: UnbanClientCommand::NAME,
+        direction: CommandDirection::ClientToServer,
+        sender: SenderAuthorization::LeaderOnly,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: UnbanClientCommand::MIN_VERSION,
+        min_octet_size: UnbanClientCommand::min_octet_size,
+        fields: UNBAN_CLIENT_FIELDS,
+    },
+    CommandDescriptor {
+        type_id: HELLO_COMMAND_TYPE_ID,
+        name: HelloCommand::NAME,
+        direction: CommandDirection::Both,
+        sender: SenderAuthorization::AnyClientOrServer,
+        delivery: CommandDelivery::ReliableOrdered,
+        min_version: HelloCommand::MIN_VERSION,
+        min_octet_size: HelloCommand::min_octet_size,
+        fields: HELLO_FIELDS,
+    },
+];
+
+/// The control commands, ordered by type id. They are not room commands and have no protocol
+/// version, so only their layout is described.
+pub(crate) const CONTROL_COMMAND_LAYOUTS: &[(u8, StructDescriptor)] = &[
+    (
+        ECHO_COMMAND_TYPE_ID,
+        StructDescriptor {
+            name: EchoCommand::NAME,
+            fields: &[field("payload", PAYLOAD)],
+        },
+    ),
+    (
+        STATS_REQUEST_COMMAND_TYPE_ID,
+        StructDescriptor {
+            name: StatsRequestCommand::NAME,
+            fields: &[field("request_id", FieldKind::U32)],
+        },
+    ),
+    (
+        DEBUG_DUMP_COMMAND_TYPE_ID,
+        StructDescriptor {
+            name: DebugDumpCommand::NAME,
+            fields: &[field("request_id", FieldKind::U32), field("dump", PAYLOAD)],
+        },
+    ),
+];
+
+/// Finds the descriptor for `type_id`
+pub fn command_descriptor(type_id: u8) -> Option<&'static CommandDescriptor> {
+    COMMAND_DESCRIPTORS
+        .iter()
+        .find(|descriptor| descriptor.type_id == type_id)
+}
+
+/// Rejects commands the sender set in the decode options may not send. Unknown command type ids
+/// are left to the decoder.
+pub(crate) fn check_sender(command_type_id: u8, sender: Option<SenderRole>) -> Result<()> {
+    let Some(sender) = sender else {
+        return Ok(());
+    };
+    match command_descriptor(command_type_id) {
+        Some(descriptor) if !descriptor.allowed_from(sender) => {
+            Err(ProtocolError::UnauthorizedSender {
+                command_type_id,
+                sender,
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,155 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Marker traits for the direction a command is sent in, so that the send helpers only accept
+//! commands the receiving side can decode.
+//!
+//! A client sends [`ClientToServer`] commands, which the server decodes as a
+//! [`ServerReceiveCommand`], and the server sends [`ServerToClient`] commands. Commands that
+//! are sent in both directions, e.g. [`MuteStateCommand`], implement both.
+
+use std::io::Result;
+
+use flood_rs::{OutOctetStream, WriteOctetStream};
+
+use crate::{
+    BanClientCommand, BandwidthReportCommand, BroadcastPayloadCommand, BroadcastedPayloadCommand,
+    ChallengeCommand, ChannelProfileCommand, ClientReceiveCommand, ConnectWithCookieCommand,
+    ConnectWithTokenCommand, HelloCommand, JoinAcceptedCommand, JoinDeniedCommand,
+    JoinRequestCommand, KnowledgeAckCommand, LatencyReportCommand, MaintenanceNoticeCommand,
+    MembershipSyncRequestCommand, MembershipSyncResponseCommand, MuteStateCommand, PingCommand,
+    PingResponseCommand, PreVoteRequestCommand, PreVoteResponseCommand, ProtocolVersion,
+    PskAuthCommand, RedirectCommand, RelayToClientCommand, RelayedFromClientCommand,
+    ReplicateAckCommand, ReplicateCommand, ResyncDataCommand, ResyncRequestCommand,
+    RetryCookieCommand, RoomClosedCommand, RoomInfoCommand, RoomInfoPageCommand, ServerLoadCommand,
+    ServerReceiveCommand, SetPresenceCommand, SnapshotChunkCommand, SnapshotRequestCommand,
+    TermAdvancedCommand, ThrottledCommand, TimeoutConfigCommand, TransferChunkCommand,
+    TransferResendRequestCommand, UnbanClientCommand, UpdateRoomSettingsCommand,
+    DEFAULT_PROTOCOL_VERSION,
+};
+
+/// A command sent by a client and decoded by the server
+pub trait ClientToServer: Into<ServerReceiveCommand> {}
+
+/// A command sent by the server and decoded by a client
+pub trait ServerToClient: Into<ClientReceiveCommand> {}
+
+macro_rules! client_to_server {
+    ($($command:ident => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$command> for ServerReceiveCommand {
+                fn from(command: $command) -> Self {
+                    Self::$variant(command)
+                }
+            }
+
+            impl ClientToServer for $command {}
+        )*
+    };
+}
+
+macro_rules! server_to_client {
+    ($($command:ident => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$command> for ClientReceiveCommand {
+                fn from(command: $command) -> Self {
+                    Self::$variant(command)
+                }
+            }
+
+            impl ServerToClient for $command {}
+        )*
+    };
+}
+
+client_to_server! {
+    PingCommand => PingCommandType,
+    BanClientCommand => BanClientType,
+    UnbanClientCommand => UnbanClientType,
+    MuteStateCommand => MuteStateType,
+    RelayToClientCommand => RelayToClientType,
+    BroadcastPayloadCommand => BroadcastPayloadType,
+    JoinRequestCommand => JoinRequestType,
+    ResyncRequestCommand => ResyncRequestType,
+    SnapshotRequestCommand => SnapshotRequestType,
+    TransferChunkCommand => TransferChunkType,
+    TransferResendRequestCommand => TransferResendRequestType,
+    BandwidthReportCommand => BandwidthReportType,
+    LatencyReportCommand => LatencyReportType,
+    HelloCommand => HelloType,
+    PskAuthCommand => PskAuthType,
+    ConnectWithTokenCommand => ConnectWithTokenType,
+    ConnectWithCookieCommand => ConnectWithCookieType,
+    TimeoutConfigCommand => TimeoutConfigType,
+    UpdateRoomSettingsCommand => UpdateRoomSettingsType,
+    MembershipSyncRequestCommand => MembershipSyncRequestType,
+    SetPresenceCommand => SetPresenceType,
+    PreVoteRequestCommand => PreVoteRequestType,
+    ReplicateCommand => ReplicateType,
+    ReplicateAckCommand => ReplicateAckType,
+}
+
+server_to_client! {
+    RoomInfoCommand => RoomInfoType,
+    RoomClosedCommand => RoomClosedType,
+    MuteStateCommand => MuteStateType,
+    PingResponseCommand => PingResponseType,
+    RelayedFromClientCommand => RelayedFromClientType,
+    BroadcastedPayloadCommand => BroadcastedPayloadType,
+    JoinAcceptedCommand => JoinAcceptedType,
+    JoinDeniedCommand => JoinDeniedType,
+    ResyncDataCommand => ResyncDataType,
+    SnapshotChunkCommand => SnapshotChunkType,
+    TransferChunkCommand => TransferChunkType,
+    TransferResendRequestCommand => TransferResendRequestType,
+    MaintenanceNoticeCommand => MaintenanceNoticeType,
+    RedirectCommand => RedirectType,
+    ServerLoadCommand => ServerLoadType,
+    HelloCommand => HelloType,
+    ChallengeCommand => ChallengeType,
+    RetryCookieCommand => RetryCookieType,
+    ThrottledCommand => ThrottledType,
+    TimeoutConfigCommand => TimeoutConfigType,
+    UpdateRoomSettingsCommand => UpdateRoomSettingsType,
+    ChannelProfileCommand => ChannelProfileType,
+    RoomInfoPageCommand => RoomInfoPageType,
+    MembershipSyncResponseCommand => MembershipSyncResponseType,
+    KnowledgeAckCommand => KnowledgeAckType,
+    TermAdvancedCommand => TermAdvancedType,
+    PreVoteResponseCommand => PreVoteResponseType,
+    ReplicateCommand => ReplicateType,
+    ReplicateAckCommand => ReplicateAckType,
+}
+
+/// Writes a command for the server, with its command type id
+pub fn write_client_to_server(
+    command: impl ClientToServer,
+    stream: &mut impl WriteOctetStream,
+    version: ProtocolVersion,
+) -> Result<()> {
+    command.into().to_octets_with_version(stream, version)
+}
+
+/// Writes a command for a client, with its command type id
+pub fn write_server_to_client(
+    command: impl ServerToClient,
+    stream: &mut impl WriteOctetStream,
+    version: ProtocolVersion,
+) -> Result<()> {
+    command.into().to_octets_with_version(stream, version)
+}
+
+/// Encodes a command for the server with the [`DEFAULT_PROTOCOL_VERSION`]
+pub fn client_to_server_vec(command: impl ClientToServer) -> Result<Vec<u8>> {
+    let mut out_stream = OutOctetStream::new();
+    write_client_to_server(command, &mut out_stream, DEFAULT_PROTOCOL_VERSION)?;
+    Ok(out_stream.data)
+}
+
+/// Encodes a command for a client with the [`DEFAULT_PROTOCOL_VERSION`]
+pub fn server_to_client_vec(command: impl ServerToClient) -> Result<Vec<u8>> {
+    let mut out_stream = OutOctetStream::new();
+    write_server_to_client(command, &mut out_stream, DEFAULT_PROTOCOL_VERSION)?;
+    Ok(out_stream.data)
+}
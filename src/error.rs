@@ -0,0 +1,290 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+
+use crate::{ConnectionIndex, ProtocolVersion, SenderRole};
+
+/// Protocol specific failures.
+///
+/// They are returned wrapped in an [`std::io::Error`], use [`ProtocolError::from_io`] to get them back.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ProtocolError {
+    /// The command is newer than the protocol version of the session
+    UnsupportedCommand {
+        command_type_id: u8,
+        min_version: ProtocolVersion,
+        version: ProtocolVersion,
+    },
+    /// The peer picked a protocol version newer than the newest this build supports
+    UnsupportedVersion {
+        version: ProtocolVersion,
+        newest: ProtocolVersion,
+    },
+    /// The octets end before the command does. `needed` and `available` count from the start
+    /// of the octets, so the caller can wait for `needed - available` more octets.
+    UnexpectedEof { needed: usize, available: usize },
+    /// The command ends `count` octets before the octets do
+    TrailingBytes { count: usize },
+    /// The octets decode, but differ from the canonical encoding starting at `offset`
+    NonCanonical { offset: usize },
+    /// `leader_index` is not the connection index of any of the `client_count` client infos
+    LeaderIndexOutOfBounds {
+        leader_index: ConnectionIndex,
+        client_count: usize,
+    },
+    /// Two client infos have the same connection index
+    DuplicateConnectionIndex { connection_index: ConnectionIndex },
+    /// There are more client infos than the layout can carry
+    TooManyClients { count: usize, max: usize },
+    /// The term is a reserved wire value, e.g. `TERM_UNKNOWN` where an authoritative term is
+    /// needed
+    ReservedTerm { term: u16 },
+    /// The knowledge is lower than the floor set in the `DecodeOptions`
+    KnowledgeBelowFloor { knowledge: u64, floor: u64 },
+    /// The datagram does not start with the agreed upon magic
+    BadMagic { expected: [u8; 2], found: [u8; 2] },
+    /// The connect token is no longer valid
+    ConnectTokenExpired { expires_at_s: u64, now_s: u64 },
+    /// The connect token does not list the server it was presented to
+    ConnectTokenWrongServer { address: SocketAddr },
+    /// Sealing failed, e.g. because the sequence numbers of the key are used up
+    EncryptionFailed,
+    /// The datagram was not sealed with the key of the session, or was modified on the way
+    DecryptionFailed,
+    /// The sequence number has already been received
+    ReplayedSequence { sequence: u64 },
+    /// The sequence number is too far behind the highest received to tell if it is a replay
+    SequenceTooOld { sequence: u64, highest: u64 },
+    /// The command is not the one expected at this point of the handshake
+    UnexpectedHandshakeCommand { command_type_id: u8 },
+    /// The peer was built with different command layouts, see `HelloCommand::is_same_build`
+    FingerprintMismatch { expected: u64, found: u64 },
+    /// The proof for the pre-shared key `key_id` does not verify, or the key is unknown
+    AuthenticationFailed { key_id: u16 },
+    /// The sender is not allowed to send the command, e.g. a Client sending a `RoomInfoCommand`
+    UnauthorizedSender {
+        command_type_id: u8,
+        sender: SenderRole,
+    },
+}
+
+impl ProtocolError {
+    /// Finds the protocol error in `err`, also when it is wrapped in a [`DecodeError`].
+    pub fn from_io(err: &Error) -> Option<&ProtocolError> {
+        let inner = err.get_ref()?;
+        if let Some(decode_error) = inner.downcast_ref::<DecodeError>() {
+            return Self::from_io(&decode_error.source);
+        }
+        inner.downcast_ref::<ProtocolError>()
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::UnsupportedCommand { .. } | Self::UnsupportedVersion { .. } => {
+                ErrorKind::Unsupported
+            }
+            Self::UnexpectedEof { .. } => ErrorKind::UnexpectedEof,
+            Self::TrailingBytes { .. }
+            | Self::NonCanonical { .. }
+            | Self::LeaderIndexOutOfBounds { .. }
+            | Self::DuplicateConnectionIndex { .. }
+            | Self::TooManyClients { .. }
+            | Self::ReservedTerm { .. }
+            | Self::KnowledgeBelowFloor { .. }
+            | Self::BadMagic { .. }
+            | Self::UnexpectedHandshakeCommand { .. }
+            | Self::FingerprintMismatch { .. }
+            | Self::DecryptionFailed
+            | Self::ReplayedSequence { .. }
+            | Self::SequenceTooOld { .. } => ErrorKind::InvalidData,
+            Self::EncryptionFailed => ErrorKind::Other,
+            Self::ConnectTokenExpired { .. } | Self::ConnectTokenWrongServer { .. } => {
+                ErrorKind::PermissionDenied
+            }
+            Self::AuthenticationFailed { .. } => ErrorKind::PermissionDenied,
+            Self::UnauthorizedSender { .. } => ErrorKind::PermissionDenied,
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedCommand {
+                command_type_id,
+                min_version,
+                version,
+            } => write!(
+                f,
+                "command 0x{:x} needs protocol version {} but the session uses {}",
+                command_type_id, min_version, version
+            ),
+            Self::UnsupportedVersion { version, newest } => write!(
+                f,
+                "protocol version {} is newer than the supported {}",
+                version, newest
+            ),
+            Self::UnexpectedEof { needed, available } => write!(
+                f,
+                "unexpected end of octets, needed {} but only {} available",
+                needed, available
+            ),
+            Self::TrailingBytes { count } => write!(f, "{} trailing octets", count),
+            Self::NonCanonical { offset } => {
+                write!(f, "non-canonical encoding at offset {}", offset)
+            }
+            Self::LeaderIndexOutOfBounds {
+                leader_index,
+                client_count,
+            } => write!(
+                f,
+                "leader {} is not one of the {} clients",
+                leader_index, client_count
+            ),
+            Self::DuplicateConnectionIndex { connection_index } => {
+                write!(f, "duplicate connection index {}", connection_index)
+            }
+            Self::TooManyClients { count, max } => {
+                write!(f, "{} clients, at most {} supported", count, max)
+            }
+            Self::ReservedTerm { term } => write!(f, "term 0x{:x} is reserved", term),
+            Self::KnowledgeBelowFloor { knowledge, floor } => {
+                write!(f, "knowledge {} is below the floor {}", knowledge, floor)
+            }
+            Self::BadMagic { expected, found } => write!(
+                f,
+                "datagram magic {:02x?} does not match {:02x?}",
+                found, expected
+            ),
+            Self::ConnectTokenExpired {
+                expires_at_s,
+                now_s,
+            } => write!(
+                f,
+                "connect token expired at {} s, it is now {} s",
+                expires_at_s, now_s
+            ),
+            Self::ConnectTokenWrongServer { address } => {
+                write!(f, "connect token is not valid for server {}", address)
+            }
+            Self::EncryptionFailed => write!(f, "sealing the command failed"),
+            Self::DecryptionFailed => write!(f, "the datagram does not authenticate"),
+            Self::ReplayedSequence { sequence } => {
+                write!(f, "sequence {} has already been received", sequence)
+            }
+            Self::SequenceTooOld { sequence, highest } => write!(
+                f,
+                "sequence {} is too far behind the highest received {}",
+                sequence, highest
+            ),
+            Self::UnexpectedHandshakeCommand { command_type_id } => write!(
+                f,
+                "command 0x{:x} is out of order in the handshake",
+                command_type_id
+            ),
+            Self::FingerprintMismatch { expected, found } => write!(
+                f,
+                "protocol fingerprint {:016x} does not match {:016x}",
+                found, expected
+            ),
+            Self::AuthenticationFailed { key_id } => {
+                write!(f, "authentication with pre-shared key {} failed", key_id)
+            }
+            Self::UnauthorizedSender {
+                command_type_id,
+                sender,
+            } => write!(
+                f,
+                "command 0x{:x} may not be sent by {:?}",
+                command_type_id, sender
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<ProtocolError> for Error {
+    fn from(err: ProtocolError) -> Self {
+        Error::new(err.kind(), err)
+    }
+}
+
+/// A decode failure annotated with where in the octets it happened.
+///
+/// `path` names the field being decoded, e.g. `room_info.client_infos[3].identity`.
+/// `offset` is the absolute octet offset of that field, it is only known when decoding
+/// from a slice, e.g. with `decode_checked`.
+#[derive(Debug)]
+pub struct DecodeError {
+    pub offset: Option<usize>,
+    pub path: String,
+    pub source: Error,
+}
+
+impl DecodeError {
+    pub fn from_io(err: &Error) -> Option<&DecodeError> {
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<DecodeError>())
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{} (at offset {}): {}", self.path, offset, self.source),
+            None => write!(f, "{}: {}", self.path, self.source),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::new(err.source.kind(), err)
+    }
+}
+
+fn into_decode_error(err: Error) -> DecodeError {
+    if DecodeError::from_io(&err).is_none() {
+        return DecodeError {
+            offset: None,
+            path: String::new(),
+            source: err,
+        };
+    }
+    *err.into_inner()
+        .and_then(|inner| inner.downcast::<DecodeError>().ok())
+        .expect("checked to be a decode error")
+}
+
+/// Prefixes the field path of a failed decode with `field`.
+pub(crate) fn in_field<T>(result: Result<T>, field: impl fmt::Display) -> Result<T> {
+    result.map_err(|err| {
+        let mut decode_error = into_decode_error(err);
+        decode_error.path = if decode_error.path.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", field, decode_error.path)
+        };
+        decode_error.into()
+    })
+}
+
+/// Sets the offset of a failed decode, unless it is already known.
+pub(crate) fn at_offset<T>(result: Result<T>, offset: usize) -> Result<T> {
+    result.map_err(|err| {
+        let mut decode_error = into_decode_error(err);
+        decode_error.offset.get_or_insert(offset);
+        decode_error.into()
+    })
+}
@@ -0,0 +1,183 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Optional fields that are not part of a command layout, written as TLV (tag, length, value)
+//! entries after the command. Decoders skip tags they do not know, so new optional fields can
+//! be added without a protocol version.
+//!
+//! The area is a u16 octet count followed by the entries in increasing tag order. Each entry
+//! is a u8 tag, a u16 value length and the value.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::slice_reader::reject_trailing;
+use crate::SliceReader;
+
+pub type ExtensionTag = u8;
+
+/// The most octets of entries in an extension area
+pub const MAX_EXTENSIONS_OCTETS: usize = u16::MAX as usize;
+
+const ENTRY_HEADER_OCTETS: usize = 1 + 2;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Extensions {
+    entries: BTreeMap<ExtensionTag, Vec<u8>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, tag: ExtensionTag) -> bool {
+        self.entries.contains_key(&tag)
+    }
+
+    pub fn get(&self, tag: ExtensionTag) -> Option<&[u8]> {
+        self.entries.get(&tag).map(Vec::as_slice)
+    }
+
+    /// Returns the previous value of `tag`
+    pub fn insert(&mut self, tag: ExtensionTag, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.entries.insert(tag, value)
+    }
+
+    pub fn remove(&mut self, tag: ExtensionTag) -> Option<Vec<u8>> {
+        self.entries.remove(&tag)
+    }
+
+    /// In increasing tag order
+    pub fn iter(&self) -> impl Iterator<Item = (ExtensionTag, &[u8])> {
+        self.entries
+            .iter()
+            .map(|(tag, value)| (*tag, value.as_slice()))
+    }
+
+    /// Sets the value of `tag` to the octets `write` produces
+    pub fn insert_with(
+        &mut self,
+        tag: ExtensionTag,
+        write: impl FnOnce(&mut OutOctetStream) -> Result<()>,
+    ) -> Result<()> {
+        let mut out_stream = OutOctetStream::new();
+        write(&mut out_stream)?;
+        self.entries.insert(tag, out_stream.data);
+        Ok(())
+    }
+
+    /// Decodes the value of `tag` with `read`, which must use all of it. `None` if the tag is
+    /// missing.
+    pub fn read_with<T>(
+        &self,
+        tag: ExtensionTag,
+        read: impl FnOnce(&mut SliceReader) -> Result<T>,
+    ) -> Result<Option<T>> {
+        let Some(value) = self.entries.get(&tag) else {
+            return Ok(None);
+        };
+        let mut reader = SliceReader::new(value);
+        let result = read(&mut reader).and_then(|decoded| {
+            reject_trailing(value, reader.position())?;
+            Ok(decoded)
+        });
+        in_field(result, format_args!("extensions[0x{:02X}]", tag)).map(Some)
+    }
+
+    /// The octets of the entries, not counting the octet count in front of them
+    pub fn octet_size(&self) -> usize {
+        self.entries
+            .values()
+            .map(|value| ENTRY_HEADER_OCTETS + value.len())
+            .sum()
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        let octet_size = self.octet_size();
+        if octet_size > MAX_EXTENSIONS_OCTETS {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "extensions use {} octets, at most {} fit",
+                    octet_size, MAX_EXTENSIONS_OCTETS
+                ),
+            ));
+        }
+        stream.write_u16(octet_size as u16)?;
+        for (tag, value) in &self.entries {
+            stream.write_u8(*tag)?;
+            stream.write_u16(value.len() as u16)?;
+            stream.write(value)?;
+        }
+        Ok(())
+    }
+
+    /// Rejects entries that are not in increasing tag order, so each set of extensions has a
+    /// single encoding
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let mut remaining = in_field(stream.read_u16(), "extensions.len")? as usize;
+        let mut extensions = Self::new();
+        let mut previous_tag = None;
+        while remaining > 0 {
+            if remaining < ENTRY_HEADER_OCTETS {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{} octets left, too few for an entry", remaining),
+                    )),
+                    "extensions",
+                );
+            }
+            let tag = in_field(stream.read_u8(), "extensions.tag")?;
+            let field = format!("extensions[0x{:02X}]", tag);
+            if previous_tag.is_some_and(|previous| tag <= previous) {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "tags must be in increasing order",
+                    )),
+                    field,
+                );
+            }
+            previous_tag = Some(tag);
+            let length = in_field(stream.read_u16(), format_args!("{}.len", field))? as usize;
+            remaining -= ENTRY_HEADER_OCTETS;
+            if length > remaining {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{} octets do not fit in the {} left", length, remaining),
+                    )),
+                    field,
+                );
+            }
+            let mut value = vec![0u8; length];
+            in_field(stream.read(&mut value), &field)?;
+            remaining -= length;
+            extensions.entries.insert(tag, value);
+        }
+        Ok(extensions)
+    }
+}
+
+impl FromIterator<(ExtensionTag, Vec<u8>)> for Extensions {
+    fn from_iter<I: IntoIterator<Item = (ExtensionTag, Vec<u8>)>>(iter: I) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
@@ -0,0 +1,264 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! A hash over the command layouts, exchanged in the [`crate::HelloCommand`] so client and
+//! server builds with different layouts are detected at the handshake.
+//!
+//! [`PROTOCOL_LAYOUT`] is rendered at compile time from [`crate::COMMAND_DESCRIPTORS`] and the
+//! control command layouts, so it follows every change to them. The layouts that are only
+//! described in code, e.g. the envelope, are listed by hand at the end and must be updated
+//! together with them. A test encodes each of them and compares it with its line.
+
+use crate::descriptor::CONTROL_COMMAND_LAYOUTS;
+use crate::{
+    FieldDescriptor, FieldKind, LengthPrefix, COMMAND_DESCRIPTORS, ENVELOPE_FLAG_CORRELATION_ID,
+    ENVELOPE_FLAG_EXTENSIONS, ENVELOPE_FLAG_PADDING, PROTOCOL_VERSION_1,
+    ROOM_INFO_FLAG_NETWORK_STATS,
+};
+
+/// Collects the first `N` octets written, and counts all of them
+struct LayoutWriter<const N: usize> {
+    octets: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> LayoutWriter<N> {
+    const fn new() -> Self {
+        Self {
+            octets: [0; N],
+            len: 0,
+        }
+    }
+
+    const fn write(&mut self, text: &str) {
+        let octets = text.as_bytes();
+        let mut index = 0;
+        while index < octets.len() {
+            if self.len < N {
+                self.octets[self.len] = octets[index];
+            }
+            self.len += 1;
+            index += 1;
+        }
+    }
+
+    const fn write_digit(&mut self, digit: u8) {
+        let digits = b"0123456789ABCDEF";
+        if self.len < N {
+            self.octets[self.len] = digits[digit as usize];
+        }
+        self.len += 1;
+    }
+
+    const fn write_decimal(&mut self, value: usize) {
+        if value >= 10 {
+            self.write_decimal(value / 10);
+        }
+        self.write_digit((value % 10) as u8);
+    }
+
+    const fn write_hex(&mut self, value: u8) {
+        self.write("0x");
+        self.write_digit(value >> 4);
+        self.write_digit(value & 0x0F);
+    }
+}
+
+const fn write_prefix<const N: usize>(writer: &mut LayoutWriter<N>, prefix: LengthPrefix) {
+    writer.write(match prefix {
+        LengthPrefix::U8 => "u8-len",
+        LengthPrefix::U16 => "u16-len",
+    });
+}
+
+const fn write_min<const N: usize>(writer: &mut LayoutWriter<N>, min: usize) {
+    if min > 0 {
+        writer.write(" min ");
+        writer.write_decimal(min);
+    }
+}
+
+const fn write_kind<const N: usize>(writer: &mut LayoutWriter<N>, kind: &FieldKind) {
+    match kind {
+        FieldKind::U8 | FieldKind::Bool => writer.write("u8"),
+        FieldKind::U16 => writer.write("u16"),
+        FieldKind::U32 => writer.write("u32"),
+        FieldKind::U64 => writer.write("u64"),
+        FieldKind::I16 => writer.write("i16"),
+        FieldKind::Varint => writer.write("varint"),
+        FieldKind::Magic(octets) => {
+            writer.write("[u8; ");
+            writer.write_decimal(octets.len());
+            writer.write("] =");
+            let mut index = 0;
+            while index < octets.len() {
+                writer.write(" ");
+                writer.write_hex(octets[index]);
+                index += 1;
+            }
+        }
+        FieldKind::FixedOctets(count) => {
+            writer.write("[u8; ");
+            writer.write_decimal(*count);
+            writer.write("]");
+        }
+        FieldKind::Octets { prefix, min } => {
+            write_prefix(writer, *prefix);
+            write_min(writer, *min);
+        }
+        FieldKind::Text { prefix, min } => {
+            writer.write("utf8 ");
+            write_prefix(writer, *prefix);
+            write_min(writer, *min);
+        }
+        FieldKind::Array { count, element } => {
+            writer.write("[");
+            write_element(writer, element);
+            writer.write("; ");
+            writer.write_decimal(*count);
+            writer.write("]");
+        }
+        FieldKind::List {
+            prefix,
+            min,
+            element,
+        } => {
+            write_prefix(writer, *prefix);
+            write_min(writer, *min);
+            writer.write(" [");
+            write_element(writer, element);
+            writer.write("]");
+        }
+        FieldKind::Struct(descriptor) => {
+            writer.write("[");
+            write_fields(writer, descriptor.fields);
+            writer.write("]");
+        }
+        FieldKind::ClientIdentity => writer.write("client_identity"),
+        FieldKind::UserId => writer.write("user_id"),
+        FieldKind::SocketAddr => writer.write("socket_addr"),
+        FieldKind::Flagged { flags, mask, kind } => {
+            writer.write("if ");
+            writer.write(flags);
+            writer.write(" & ");
+            writer.write_hex(*mask);
+            writer.write(" ");
+            write_kind(writer, kind);
+        }
+    }
+}
+
+/// Like [`write_kind`], without the brackets around struct fields
+const fn write_element<const N: usize>(writer: &mut LayoutWriter<N>, element: &FieldKind) {
+    match element {
+        FieldKind::Struct(descriptor) => write_fields(writer, descriptor.fields),
+        _ => write_kind(writer, element),
+    }
+}
+
+const fn write_fields<const N: usize>(writer: &mut LayoutWriter<N>, fields: &[FieldDescriptor]) {
+    let mut index = 0;
+    while index < fields.len() {
+        let field = &fields[index];
+        if index > 0 {
+            writer.write(", ");
+        }
+        if field.since > PROTOCOL_VERSION_1 {
+            writer.write("v");
+            writer.write_decimal(field.since as usize);
+            writer.write(" ");
+        }
+        writer.write(field.name);
+        writer.write(" ");
+        write_kind(writer, &field.kind);
+        index += 1;
+    }
+}
+
+const fn write_command<const N: usize>(
+    writer: &mut LayoutWriter<N>,
+    type_id: u8,
+    name: &str,
+    fields: &[FieldDescriptor],
+) {
+    writer.write_hex(type_id);
+    writer.write(" ");
+    writer.write(name);
+    writer.write(": ");
+    write_fields(writer, fields);
+    writer.write("\n");
+}
+
+const fn write_layout<const N: usize>(writer: &mut LayoutWriter<N>) {
+    let mut index = 0;
+    while index < COMMAND_DESCRIPTORS.len() {
+        let descriptor = &COMMAND_DESCRIPTORS[index];
+        write_command(
+            writer,
+            descriptor.type_id,
+            descriptor.name,
+            descriptor.fields,
+        );
+        index += 1;
+    }
+    let mut index = 0;
+    while index < CONTROL_COMMAND_LAYOUTS.len() {
+        let (type_id, descriptor) = &CONTROL_COMMAND_LAYOUTS[index];
+        write_command(writer, *type_id, descriptor.name, descriptor.fields);
+        index += 1;
+    }
+
+    writer.write(
+        "client_identity: v4 discriminant u8 [u64 | u128 | utf8 u8-len], user_id before v4\n",
+    );
+    writer.write("user_id: u64, v3 u128\n");
+    writer.write("socket_addr: type u8, ip [u8; 4] | [u8; 16], port u16\n");
+    writer.write("network_stats: after room_info if flags & ");
+    writer.write_hex(ROOM_INFO_FLAG_NETWORK_STATS);
+    writer.write(", for each client_infos [rtt_ms u16, loss_percent u8]\n");
+    writer.write("envelope: flags u8, correlation_id if flags & ");
+    writer.write_hex(ENVELOPE_FLAG_CORRELATION_ID);
+    writer.write(" u32, padding.len if flags & ");
+    writer.write_hex(ENVELOPE_FLAG_PADDING);
+    writer.write(" u16, command, extensions if flags & ");
+    writer.write_hex(ENVELOPE_FLAG_EXTENSIONS);
+    writer.write(", padding [u8; padding.len]\n");
+    writer.write("extensions: u16 octet count [tag u8, value u16-len]\n");
+    writer.write("layout_version: command_type_id u8, layout_version u8, payload\n");
+}
+
+const PROTOCOL_LAYOUT_OCTET_COUNT: usize = {
+    let mut writer = LayoutWriter::<0>::new();
+    write_layout(&mut writer);
+    writer.len
+};
+
+const PROTOCOL_LAYOUT_OCTETS: [u8; PROTOCOL_LAYOUT_OCTET_COUNT] = {
+    let mut writer = LayoutWriter::<PROTOCOL_LAYOUT_OCTET_COUNT>::new();
+    write_layout(&mut writer);
+    writer.octets
+};
+
+/// One line per command: type id, name and the fields in serialization order, with the
+/// protocol version that introduced them. Followed by the layouts the commands refer to and
+/// the framing around them.
+pub const PROTOCOL_LAYOUT: &str = match std::str::from_utf8(&PROTOCOL_LAYOUT_OCTETS) {
+    Ok(layout) => layout,
+    Err(_) => panic!("protocol layout must be UTF-8"),
+};
+
+/// FNV-1a, evaluated at compile time
+const fn fnv1a_64(octets: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut index = 0;
+    while index < octets.len() {
+        hash ^= octets[index] as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        index += 1;
+    }
+    hash
+}
+
+/// Differs between builds whose [`PROTOCOL_LAYOUT`] differs
+pub const PROTOCOL_FINGERPRINT: u64 = fnv1a_64(PROTOCOL_LAYOUT.as_bytes());
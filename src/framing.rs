@@ -0,0 +1,140 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Length framing for several commands in one buffer.
+//!
+//! Each frame is the length of the command as a `u16`, followed by the command octets.
+//! Since the length of every frame is known, the [`FrameDecoder`] can skip a corrupt
+//! command and continue with the next one.
+
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::at_offset;
+use crate::{Command, ProtocolError, ProtocolVersion, SliceReader, DEFAULT_PROTOCOL_VERSION};
+
+pub fn write_frame<C: Command>(command: &C, stream: &mut impl WriteOctetStream) -> Result<()> {
+    write_frame_with_version(command, stream, DEFAULT_PROTOCOL_VERSION)
+}
+
+pub fn write_frame_with_version<C: Command>(
+    command: &C,
+    stream: &mut impl WriteOctetStream,
+    version: ProtocolVersion,
+) -> Result<()> {
+    let mut command_stream = OutOctetStream::new();
+    command.to_octets_with_version(&mut command_stream, version)?;
+    if command_stream.data.len() > u16::MAX as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "command is too large for a frame ({} octets)",
+                command_stream.data.len()
+            ),
+        ));
+    }
+    stream.write_u16(command_stream.data.len() as u16)?;
+    stream.write(&command_stream.data)
+}
+
+#[derive(Debug)]
+pub enum Frame<C> {
+    Command(C),
+    /// A frame that failed to decode in recovery mode. `span` is the range of the skipped
+    /// octets, including the length prefix.
+    Skipped {
+        span: Range<usize>,
+        error: Error,
+    },
+}
+
+/// Iterates over the framed commands in a buffer.
+///
+/// By default the first failure ends the iteration. In recovery mode a frame that fails to
+/// decode is reported as [`Frame::Skipped`] and decoding continues with the next frame.
+/// A truncated length prefix or frame always ends the iteration, since the next boundary is unknown.
+pub struct FrameDecoder<'a, C> {
+    octets: &'a [u8],
+    position: usize,
+    version: ProtocolVersion,
+    recover: bool,
+    done: bool,
+    marker: std::marker::PhantomData<C>,
+}
+
+impl<'a, C: Command> FrameDecoder<'a, C> {
+    pub fn new(octets: &'a [u8]) -> Self {
+        Self {
+            octets,
+            position: 0,
+            version: DEFAULT_PROTOCOL_VERSION,
+            recover: false,
+            done: false,
+            marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn with_version(mut self, version: ProtocolVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_recovery(mut self) -> Self {
+        self.recover = true;
+        self
+    }
+
+    fn read_frame(&mut self) -> Result<Frame<C>> {
+        let frame_start = self.position;
+        let mut reader = SliceReader::new(&self.octets[frame_start..]);
+        let length = at_offset(reader.read_u16(), frame_start)? as usize;
+        at_offset(reader.require(length), frame_start)?;
+        let payload_start = frame_start + 2;
+        let payload_end = payload_start + length;
+        self.position = payload_end;
+
+        match decode_payload(self.octets, payload_start..payload_end, self.version) {
+            Ok(command) => Ok(Frame::Command(command)),
+            Err(error) if self.recover => Ok(Frame::Skipped {
+                span: frame_start..payload_end,
+                error,
+            }),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Decodes the command that must fill the whole payload, failures get their absolute offset.
+pub(crate) fn decode_payload<C: Command>(
+    octets: &[u8],
+    payload: Range<usize>,
+    version: ProtocolVersion,
+) -> Result<C> {
+    let mut reader = SliceReader::new(&octets[payload.clone()]);
+    let result = C::from_octets_with_version(&mut reader, version).and_then(|command| {
+        if reader.remaining() != 0 {
+            return Err(ProtocolError::TrailingBytes {
+                count: reader.remaining(),
+            }
+            .into());
+        }
+        Ok(command)
+    });
+    at_offset(result, payload.start + reader.last_read_position())
+}
+
+impl<C: Command> Iterator for FrameDecoder<'_, C> {
+    type Item = Result<Frame<C>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.position == self.octets.len() {
+            return None;
+        }
+        let result = self.read_frame();
+        self.done = result.is_err();
+        Some(result)
+    }
+}
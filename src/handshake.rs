@@ -0,0 +1,273 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! State machines for the pre-shared-key login, so the commands are produced and accepted in
+//! order:
+//!
+//! 1. Client sends `HelloCommand`
+//! 2. Server answers with `HelloCommand` for the negotiated version and a `ChallengeCommand`,
+//!    which from [`PROTOCOL_VERSION_14`] carries the server load, if the server has one
+//! 3. Client answers with a `PskAuthCommand`
+//!
+//! Both sides then derive the same [`PskSessionKeys`] with [`derive_psk_session_keys`], one key
+//! for each direction. A hello from a build with other command layouts fails with
+//! [`ProtocolError::FingerprintMismatch`].
+//!
+//! Only the client proves that it knows the pre-shared key, the server is not authenticated by
+//! the handshake. The client learns that the server knows the key only when the first command
+//! sealed with `server_to_client` opens.
+
+use std::io::Result;
+
+use crate::psk::derive_psk_session_keys;
+use crate::{
+    ChallengeCommand, ClientReceiveCommand, HelloCommand, ProtocolError, ProtocolVersion,
+    PskAuthCommand, PskSessionKeys, ServerLoadCommand, ServerReceiveCommand, PROTOCOL_FINGERPRINT,
+    PROTOCOL_VERSION, PROTOCOL_VERSION_14, PSK_AUTH_COMMAND_TYPE_ID, PSK_CHALLENGE_OCTETS,
+};
+
+fn check_version(version: ProtocolVersion) -> Result<()> {
+    if version < PskAuthCommand::MIN_VERSION {
+        return Err(ProtocolError::UnsupportedCommand {
+            command_type_id: PSK_AUTH_COMMAND_TYPE_ID,
+            min_version: PskAuthCommand::MIN_VERSION,
+            version,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn check_fingerprint(hello: &HelloCommand) -> Result<()> {
+    if !hello.is_same_build() {
+        return Err(ProtocolError::FingerprintMismatch {
+            expected: PROTOCOL_FINGERPRINT,
+            found: hello.fingerprint,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+enum ClientState {
+    AwaitingHello,
+    AwaitingChallenge {
+        version: ProtocolVersion,
+    },
+    Done {
+        version: ProtocolVersion,
+        session_keys: PskSessionKeys,
+        server_load: Option<ServerLoadCommand>,
+    },
+}
+
+/// The client side of the handshake
+#[derive(Debug)]
+pub struct ClientHandshake {
+    key_id: u16,
+    key: Vec<u8>,
+    state: ClientState,
+}
+
+impl ClientHandshake {
+    /// Starts the handshake, the returned hello must be sent to the server
+    pub fn start(key_id: u16, key: &[u8]) -> (Self, ServerReceiveCommand) {
+        let handshake = Self {
+            key_id,
+            key: key.to_vec(),
+            state: ClientState::AwaitingHello,
+        };
+        let hello = ServerReceiveCommand::HelloType(HelloCommand::new(PROTOCOL_VERSION));
+        (handshake, hello)
+    }
+
+    /// Handles a command from the server. Returns the command to send back, if any.
+    pub fn receive(
+        &mut self,
+        command: &ClientReceiveCommand,
+    ) -> Result<Option<ServerReceiveCommand>> {
+        match (&self.state, command) {
+            (ClientState::AwaitingHello, ClientReceiveCommand::HelloType(hello)) => {
+                check_fingerprint(hello)?;
+                if hello.version > PROTOCOL_VERSION {
+                    return Err(ProtocolError::UnsupportedVersion {
+                        version: hello.version,
+                        newest: PROTOCOL_VERSION,
+                    }
+                    .into());
+                }
+                check_version(hello.version)?;
+                self.state = ClientState::AwaitingChallenge {
+                    version: hello.version,
+                };
+                Ok(None)
+            }
+            (
+                ClientState::AwaitingChallenge { version },
+                ClientReceiveCommand::ChallengeType(challenge),
+            ) => {
+                let psk_auth = PskAuthCommand::new(self.key_id, &self.key, &challenge.challenge);
+                self.state = ClientState::Done {
+                    version: *version,
+                    session_keys: derive_psk_session_keys(&self.key, &challenge.challenge),
+                    server_load: challenge.server_load,
+                };
+                Ok(Some(ServerReceiveCommand::PskAuthType(psk_auth)))
+            }
+            _ => Err(ProtocolError::UnexpectedHandshakeCommand {
+                command_type_id: command.command_type_id(),
+            }
+            .into()),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, ClientState::Done { .. })
+    }
+
+    /// The version the server accepted, once the handshake is done
+    pub fn version(&self) -> Option<ProtocolVersion> {
+        match self.state {
+            ClientState::Done { version, .. } => Some(version),
+            _ => None,
+        }
+    }
+
+    /// The keys derived from the pre-shared key, once the handshake is done. The client seals
+    /// with `client_to_server` and opens with `server_to_client`.
+    pub fn session_keys(&self) -> Option<&PskSessionKeys> {
+        match &self.state {
+            ClientState::Done { session_keys, .. } => Some(session_keys),
+            _ => None,
+        }
+    }
+
+    /// The load the server sent with its challenge, once the handshake is done
+    pub fn server_load(&self) -> Option<ServerLoadCommand> {
+        match self.state {
+            ClientState::Done { server_load, .. } => server_load,
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ServerState {
+    AwaitingHello,
+    AwaitingAuth {
+        version: ProtocolVersion,
+    },
+    Done {
+        version: ProtocolVersion,
+        key_id: u16,
+        session_keys: PskSessionKeys,
+    },
+    /// Authentication failed. The challenge is not reused, a new handshake is needed.
+    Failed,
+}
+
+/// The server side of the handshake
+#[derive(Debug)]
+pub struct ServerHandshake {
+    challenge: [u8; PSK_CHALLENGE_OCTETS],
+    server_load: Option<ServerLoadCommand>,
+    state: ServerState,
+}
+
+impl ServerHandshake {
+    /// `challenge` must be random and unique for each handshake
+    pub fn new(challenge: [u8; PSK_CHALLENGE_OCTETS]) -> Self {
+        Self {
+            challenge,
+            server_load: None,
+            state: ServerState::AwaitingHello,
+        }
+    }
+
+    /// Sends `server_load` with the challenge, if the negotiated version is at least
+    /// [`PROTOCOL_VERSION_14`]
+    pub fn with_server_load(mut self, server_load: ServerLoadCommand) -> Self {
+        self.server_load = Some(server_load);
+        self
+    }
+
+    /// Handles a command from the client and returns the commands to send back.
+    /// `key_for` looks up the pre-shared key for a key id.
+    pub fn receive<K: AsRef<[u8]>>(
+        &mut self,
+        command: &ServerReceiveCommand,
+        key_for: impl FnOnce(u16) -> Option<K>,
+    ) -> Result<Vec<ClientReceiveCommand>> {
+        match (&self.state, command) {
+            (ServerState::AwaitingHello, ServerReceiveCommand::HelloType(hello)) => {
+                check_fingerprint(hello)?;
+                let version = hello.version.min(PROTOCOL_VERSION);
+                check_version(version)?;
+                self.state = ServerState::AwaitingAuth { version };
+                Ok(vec![
+                    ClientReceiveCommand::HelloType(HelloCommand::new(version)),
+                    ClientReceiveCommand::ChallengeType(ChallengeCommand {
+                        challenge: self.challenge,
+                        server_load: self.server_load.filter(|_| version >= PROTOCOL_VERSION_14),
+                    }),
+                ])
+            }
+            (
+                ServerState::AwaitingAuth { version },
+                ServerReceiveCommand::PskAuthType(psk_auth),
+            ) => {
+                let version = *version;
+                let key = key_for(psk_auth.key_id)
+                    .filter(|key| psk_auth.verify(key.as_ref(), &self.challenge));
+                let Some(key) = key else {
+                    self.state = ServerState::Failed;
+                    return Err(ProtocolError::AuthenticationFailed {
+                        key_id: psk_auth.key_id,
+                    }
+                    .into());
+                };
+                self.state = ServerState::Done {
+                    version,
+                    key_id: psk_auth.key_id,
+                    session_keys: derive_psk_session_keys(key.as_ref(), &self.challenge),
+                };
+                Ok(Vec::new())
+            }
+            _ => Err(ProtocolError::UnexpectedHandshakeCommand {
+                command_type_id: command.command_type_id(),
+            }
+            .into()),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, ServerState::Done { .. })
+    }
+
+    /// The negotiated version, once the handshake is done
+    pub fn version(&self) -> Option<ProtocolVersion> {
+        match self.state {
+            ServerState::Done { version, .. } => Some(version),
+            _ => None,
+        }
+    }
+
+    /// The pre-shared key the client authenticated with, once the handshake is done
+    pub fn key_id(&self) -> Option<u16> {
+        match self.state {
+            ServerState::Done { key_id, .. } => Some(key_id),
+            _ => None,
+        }
+    }
+
+    /// The keys derived from the pre-shared key, once the handshake is done. The server seals
+    /// with `server_to_client` and opens with `client_to_server`.
+    pub fn session_keys(&self) -> Option<&PskSessionKeys> {
+        match &self.state {
+            ServerState::Done { session_keys, .. } => Some(session_keys),
+            _ => None,
+        }
+    }
+}
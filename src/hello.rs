@@ -0,0 +1,108 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{
+    ClientReceiveCommand, ProtocolVersion, ServerReceiveCommand, PROTOCOL_FINGERPRINT,
+    PROTOCOL_VERSION, PROTOCOL_VERSION_1,
+};
+
+/// Every hello datagram starts with these octets. The first one doubles as the command type id.
+pub const HELLO_MAGIC: [u8; 4] = [0xC7, 0x52, 0x4F, 0x4F];
+pub const HELLO_COMMAND_TYPE_ID: u8 = HELLO_MAGIC[0];
+
+/// The first command of the handshake, sent by the Client and answered by the Server with the
+/// version it accepts. Its layout never changes, so it can be decoded before the version is
+/// negotiated.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HelloCommand {
+    pub version: ProtocolVersion,
+    /// The [`PROTOCOL_FINGERPRINT`] of the sender's build
+    pub fingerprint: u64,
+}
+
+impl HelloCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "hello";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_1;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        HELLO_MAGIC.len() - 1 + 1 + 8
+    }
+
+    /// A hello for `version` from this build
+    pub fn new(version: ProtocolVersion) -> Self {
+        Self {
+            version,
+            fingerprint: PROTOCOL_FINGERPRINT,
+        }
+    }
+
+    /// `false` if the sender was built with different command layouts than this build
+    pub fn is_same_build(&self) -> bool {
+        self.fingerprint == PROTOCOL_FINGERPRINT
+    }
+
+    /// Writes the magic after the command type id, followed by the version and fingerprint.
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write(&HELLO_MAGIC[1..])?;
+        stream.write_u8(self.version)?;
+        stream.write_u64(self.fingerprint)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let mut magic = [0u8; 3];
+        in_field(stream.read(&mut magic), "magic")?;
+        if magic != HELLO_MAGIC[1..] {
+            return in_field(
+                Err(Error::new(ErrorKind::InvalidData, "hello magic mismatch")),
+                "magic",
+            );
+        }
+        Ok(Self {
+            version: in_field(stream.read_u8(), "version")?,
+            fingerprint: in_field(stream.read_u64(), "fingerprint")?,
+        })
+    }
+}
+
+fn starts_with_hello_magic(octets: &[u8]) -> bool {
+    octets.len() >= HELLO_MAGIC.len() && octets[..HELLO_MAGIC.len()] == HELLO_MAGIC
+}
+
+impl ServerReceiveCommand {
+    /// Cheap check to drop garbage before decoding: the datagram must start with the
+    /// [`HELLO_MAGIC`] or a known command type id.
+    pub fn is_plausible_datagram(octets: &[u8]) -> bool {
+        match octets.first() {
+            Some(&HELLO_COMMAND_TYPE_ID) => starts_with_hello_magic(octets),
+            Some(command_type_id) => {
+                Self::min_octet_size(*command_type_id, PROTOCOL_VERSION).is_some()
+            }
+            None => false,
+        }
+    }
+}
+
+impl ClientReceiveCommand {
+    /// Cheap check to drop garbage before decoding: the datagram must start with the
+    /// [`HELLO_MAGIC`] or a known command type id.
+    pub fn is_plausible_datagram(octets: &[u8]) -> bool {
+        match octets.first() {
+            Some(&HELLO_COMMAND_TYPE_ID) => starts_with_hello_magic(octets),
+            Some(command_type_id) => {
+                Self::min_octet_size(*command_type_id, PROTOCOL_VERSION).is_some()
+            }
+            None => false,
+        }
+    }
+}
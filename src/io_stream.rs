@@ -0,0 +1,146 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Octet streams over [`std::io`], so commands can be written to and read from files, sockets
+//! or compression streams directly, without collecting them in an `OutOctetStream` first.
+//!
+//! Every field is a separate call to the inner writer or reader, wrap unbuffered ones in a
+//! [`std::io::BufWriter`] or [`std::io::BufReader`].
+
+use std::io::{Read, Result, Write};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+/// A [`WriteOctetStream`] that writes to `W`
+pub struct IoWriteStream<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> IoWriteStream<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> WriteOctetStream for IoWriteStream<W> {
+    fn write(&mut self, v: &[u8]) -> Result<()> {
+        self.writer.write_all(v)
+    }
+
+    fn write_u64(&mut self, v: u64) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn write_i64(&mut self, v: i64) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn write_u32(&mut self, v: u32) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn write_i32(&mut self, v: i32) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn write_u16(&mut self, v: u16) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn write_i16(&mut self, v: i16) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn write_u8(&mut self, v: u8) -> Result<()> {
+        self.write(&[v])
+    }
+
+    fn write_i8(&mut self, v: i8) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+}
+
+/// A [`ReadOctetStream`] that reads from `R`. Running out of octets fails with
+/// [`std::io::ErrorKind::UnexpectedEof`].
+pub struct IoReadStream<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> IoReadStream<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl<R: Read> ReadOctetStream for IoReadStream<R> {
+    fn read(&mut self, v: &mut [u8]) -> Result<()> {
+        self.reader.read_exact(v)
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+
+    fn read_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.take()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take()?))
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take()?))
+    }
+
+    fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.take()?))
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn read_i8(&mut self) -> Result<i8> {
+        Ok(self.take::<1>()?[0] as i8)
+    }
+}
@@ -0,0 +1,290 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Generates a [Kaitai Struct](https://kaitai.io) definition of the protocol from
+//! [`COMMAND_DESCRIPTORS`], so captures can be parsed and visualized with the standard tools.
+//!
+//! The definition describes a single command, for one protocol version.
+
+use std::fmt::Write;
+
+use crate::descriptor::{FieldDescriptor, FieldKind, LengthPrefix, StructDescriptor};
+use crate::{
+    ProtocolVersion, COMMAND_DESCRIPTORS, PROTOCOL_FINGERPRINT, PROTOCOL_VERSION_3,
+    PROTOCOL_VERSION_4,
+};
+
+#[derive(Default)]
+struct Generator {
+    version: ProtocolVersion,
+    types: String,
+    /// Structs referenced by fields that still need a type
+    pending: Vec<&'static StructDescriptor>,
+    emitted: Vec<&'static str>,
+    uses_varint: bool,
+    uses_client_identity: bool,
+    uses_user_id: bool,
+    uses_socket_addr: bool,
+    /// The `if` of the attributes of a flagged field
+    condition: Option<String>,
+}
+
+fn prefix_type(prefix: LengthPrefix) -> &'static str {
+    match prefix {
+        LengthPrefix::U8 => "u1",
+        LengthPrefix::U16 => "u2",
+    }
+}
+
+impl Generator {
+    fn type_name(&mut self, kind: &FieldKind) -> String {
+        match kind {
+            FieldKind::U8 | FieldKind::Bool => "u1".to_string(),
+            FieldKind::U16 => "u2".to_string(),
+            FieldKind::U32 => "u4".to_string(),
+            FieldKind::U64 => "u8".to_string(),
+            FieldKind::I16 => "s2".to_string(),
+            FieldKind::Varint => {
+                self.uses_varint = true;
+                "vlq_base128_le".to_string()
+            }
+            FieldKind::Struct(descriptor) => {
+                self.pending.push(descriptor);
+                descriptor.name.to_string()
+            }
+            FieldKind::ClientIdentity => {
+                self.uses_client_identity = true;
+                "client_identity".to_string()
+            }
+            FieldKind::UserId => {
+                self.uses_user_id = true;
+                "user_id".to_string()
+            }
+            FieldKind::SocketAddr => {
+                self.uses_socket_addr = true;
+                "socket_addr".to_string()
+            }
+            FieldKind::Magic(_)
+            | FieldKind::FixedOctets(_)
+            | FieldKind::Octets { .. }
+            | FieldKind::Text { .. }
+            | FieldKind::Array { .. }
+            | FieldKind::List { .. }
+            | FieldKind::Flagged { .. } => {
+                unreachable!("{:?} can not be an element", kind)
+            }
+        }
+    }
+
+    fn attribute(&mut self, id: &str, properties: &[(&str, String)]) {
+        let _ = writeln!(self.types, "      - id: {}", id);
+        for (key, value) in properties {
+            let _ = writeln!(self.types, "        {}: {}", key, value);
+        }
+        if let Some(condition) = &self.condition {
+            let _ = writeln!(self.types, "        if: {}", condition);
+        }
+    }
+
+    fn field(&mut self, field: &FieldDescriptor) {
+        let name = field.name;
+        match &field.kind {
+            FieldKind::Magic(octets) => {
+                let octets: Vec<String> = octets.iter().map(|o| format!("0x{:02x}", o)).collect();
+                self.attribute(name, &[("contents", format!("[{}]", octets.join(", ")))]);
+            }
+            FieldKind::FixedOctets(count) => self.attribute(name, &[("size", count.to_string())]),
+            FieldKind::Octets { prefix, .. } => {
+                let length = format!("{}_len", name);
+                self.attribute(&length, &[("type", prefix_type(*prefix).to_string())]);
+                self.attribute(name, &[("size", length)]);
+            }
+            FieldKind::Text { prefix, .. } => {
+                let length = format!("{}_len", name);
+                self.attribute(&length, &[("type", prefix_type(*prefix).to_string())]);
+                self.attribute(
+                    name,
+                    &[
+                        ("size", length),
+                        ("type", "str".to_string()),
+                        ("encoding", "UTF-8".to_string()),
+                    ],
+                );
+            }
+            FieldKind::Array { count, element } => {
+                let element = self.type_name(element);
+                self.attribute(
+                    name,
+                    &[
+                        ("type", element),
+                        ("repeat", "expr".to_string()),
+                        ("repeat-expr", count.to_string()),
+                    ],
+                );
+            }
+            FieldKind::List {
+                prefix, element, ..
+            } => {
+                let count = format!("{}_count", name);
+                self.attribute(&count, &[("type", prefix_type(*prefix).to_string())]);
+                let element = self.type_name(element);
+                self.attribute(
+                    name,
+                    &[
+                        ("type", element),
+                        ("repeat", "expr".to_string()),
+                        ("repeat-expr", count),
+                    ],
+                );
+            }
+            FieldKind::Flagged { flags, mask, kind } => {
+                self.condition = Some(format!("({} & 0x{:02x}) != 0", flags, mask));
+                self.field(&FieldDescriptor {
+                    kind: **kind,
+                    ..*field
+                });
+                self.condition = None;
+            }
+            kind => {
+                let type_name = self.type_name(kind);
+                self.attribute(name, &[("type", type_name)]);
+            }
+        }
+    }
+
+    fn struct_type(&mut self, name: &'static str, fields: &[FieldDescriptor]) {
+        if self.emitted.contains(&name) {
+            return;
+        }
+        self.emitted.push(name);
+        let _ = writeln!(self.types, "  {}:\n    seq:", name);
+        let version = self.version;
+        for field in fields.iter().filter(|field| field.since <= version) {
+            self.field(field);
+        }
+    }
+
+    fn helper_types(&mut self) {
+        if self.uses_client_identity {
+            if self.version >= PROTOCOL_VERSION_4 {
+                self.types.push_str(concat!(
+                    "  client_identity:\n",
+                    "    seq:\n",
+                    "      - id: discriminant\n",
+                    "        type: u1\n",
+                    "      - id: value\n",
+                    "        type:\n",
+                    "          switch-on: discriminant\n",
+                    "          cases:\n",
+                    "            0: u8\n",
+                    "            1: uuid\n",
+                    "            2: identity_string\n",
+                    "  uuid:\n",
+                    "    seq:\n",
+                    "      - id: high\n",
+                    "        type: u8\n",
+                    "      - id: low\n",
+                    "        type: u8\n",
+                    "  identity_string:\n",
+                    "    seq:\n",
+                    "      - id: value_len\n",
+                    "        type: u1\n",
+                    "      - id: value\n",
+                    "        size: value_len\n",
+                    "        type: str\n",
+                    "        encoding: UTF-8\n",
+                ));
+            } else {
+                self.uses_user_id = true;
+                self.types.push_str(concat!(
+                    "  client_identity:\n",
+                    "    seq:\n",
+                    "      - id: user_id\n",
+                    "        type: user_id\n",
+                ));
+            }
+        }
+        if self.uses_user_id {
+            self.types.push_str(if self.version >= PROTOCOL_VERSION_3 {
+                concat!(
+                    "  user_id:\n",
+                    "    seq:\n",
+                    "      - id: high\n",
+                    "        type: u8\n",
+                    "      - id: low\n",
+                    "        type: u8\n",
+                )
+            } else {
+                concat!(
+                    "  user_id:\n",
+                    "    seq:\n",
+                    "      - id: value\n",
+                    "        type: u8\n",
+                )
+            });
+        }
+        if self.uses_socket_addr {
+            self.types.push_str(concat!(
+                "  socket_addr:\n",
+                "    seq:\n",
+                "      - id: address_type\n",
+                "        type: u1\n",
+                "      - id: ip\n",
+                "        size: 'address_type == 1 ? 4 : 16'\n",
+                "      - id: port\n",
+                "        type: u2\n",
+            ));
+        }
+    }
+}
+
+/// The `.ksy` definition of a command in `version`, starting at the command type id
+pub fn kaitai_struct(version: ProtocolVersion) -> String {
+    let mut generator = Generator {
+        version,
+        ..Default::default()
+    };
+    let descriptors: Vec<_> = COMMAND_DESCRIPTORS
+        .iter()
+        .filter(|descriptor| descriptor.min_version <= version)
+        .collect();
+    for descriptor in &descriptors {
+        generator.struct_type(descriptor.name, descriptor.fields);
+    }
+    while let Some(descriptor) = generator.pending.pop() {
+        generator.struct_type(descriptor.name, descriptor.fields);
+    }
+    generator.helper_types();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "meta:");
+    let _ = writeln!(out, "  id: conclave_room_v{}", version);
+    let _ = writeln!(out, "  title: Conclave room protocol version {}", version);
+    let _ = writeln!(out, "  endian: be");
+    if generator.uses_varint {
+        let _ = writeln!(out, "  imports:\n    - /common/vlq_base128_le");
+    }
+    let _ = writeln!(
+        out,
+        "doc: Generated by conclave-room-serialize, layout fingerprint {:016x}",
+        PROTOCOL_FINGERPRINT
+    );
+    let _ = writeln!(out, "seq:");
+    let _ = writeln!(out, "  - id: command_type_id\n    type: u1");
+    let _ = writeln!(out, "  - id: command");
+    let _ = writeln!(
+        out,
+        "    type:\n      switch-on: command_type_id\n      cases:"
+    );
+    for descriptor in &descriptors {
+        let _ = writeln!(
+            out,
+            "        0x{:02x}: {}",
+            descriptor.type_id, descriptor.name
+        );
+    }
+    let _ = writeln!(out, "types:");
+    out.push_str(&generator.types);
+    out
+}
@@ -0,0 +1,25 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Sanity checks of decoded [`Knowledge`] values.
+//!
+//! `Knowledge` is defined by `conclave_room`, so the checks are hooked into decoding through
+//! [`crate::DecodeOptions::with_knowledge_floor`] instead of a newtype.
+
+use conclave_room::Knowledge;
+
+use crate::ProtocolError;
+
+/// Fails if `knowledge` regressed below `floor`
+pub(crate) fn check_knowledge_floor(
+    knowledge: Knowledge,
+    floor: Option<Knowledge>,
+) -> Result<(), ProtocolError> {
+    match floor {
+        Some(floor) if knowledge < floor => {
+            Err(ProtocolError::KnowledgeBelowFloor { knowledge, floor })
+        }
+        _ => Ok(()),
+    }
+}
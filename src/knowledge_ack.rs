@@ -0,0 +1,48 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::Result;
+
+use conclave_room::Knowledge;
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{ProtocolVersion, PROTOCOL_VERSION_10};
+
+pub const KNOWLEDGE_ACK_COMMAND_TYPE_ID: u8 = 0x25;
+
+/// Sent from Server to Client once it has durably observed the knowledge of the Client, so the
+/// Client can drop whatever it keeps around until then. Acks are not resent, and an ack lower
+/// than one received before is stale.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KnowledgeAckCommand {
+    pub acked_knowledge: Knowledge,
+}
+
+impl KnowledgeAckCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "knowledge_ack";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_10;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        8
+    }
+
+    /// `true` if the Server has observed `knowledge`, so it can be pruned
+    pub fn acknowledges(&self, knowledge: Knowledge) -> bool {
+        knowledge <= self.acked_knowledge
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u64(self.acked_knowledge)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            acked_knowledge: in_field(stream.read_u64(), "acked_knowledge")?,
+        })
+    }
+}
@@ -0,0 +1,168 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! The knowledge of every member of a room, for rooms where a single [`Knowledge`] is not
+//! enough, e.g. to tell which member is behind.
+//!
+//! Serialized as a u8 entry count, followed by the entries in increasing connection index
+//! order. Each entry is the connection index and the knowledge as a varint.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+
+use conclave_room::Knowledge;
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::varint::{read_varint, write_varint};
+use crate::{ConnectionIndex, PresenceField};
+
+/// Set if the room info carries a knowledge vector, right after the flags
+pub const ROOM_INFO_FLAG_KNOWLEDGE_VECTOR: u8 = 0x02;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KnowledgeVector {
+    entries: BTreeMap<ConnectionIndex, Knowledge>,
+}
+
+impl KnowledgeVector {
+    /// The entry count of an empty vector
+    pub const MIN_OCTET_SIZE: usize = 1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Zero for members without an entry
+    pub fn get(&self, connection_index: ConnectionIndex) -> Knowledge {
+        self.entries
+            .get(&connection_index)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Raises the knowledge of the member, it never goes down
+    pub fn observe(&mut self, connection_index: ConnectionIndex, knowledge: Knowledge) {
+        let entry = self.entries.entry(connection_index).or_default();
+        *entry = (*entry).max(knowledge);
+    }
+
+    /// Takes the highest knowledge of each member in either vector
+    pub fn merge(&mut self, other: &Self) {
+        for (connection_index, knowledge) in other.iter() {
+            self.observe(connection_index, knowledge);
+        }
+    }
+
+    /// `true` if every member has at least the knowledge it has in `other`
+    pub fn dominates(&self, other: &Self) -> bool {
+        other
+            .iter()
+            .all(|(connection_index, knowledge)| self.get(connection_index) >= knowledge)
+    }
+
+    /// The knowledge all members have reached, `None` if there are no members
+    pub fn common_knowledge(&self) -> Option<Knowledge> {
+        self.entries.values().min().copied()
+    }
+
+    /// Drops the entry of a member that has left
+    pub fn remove(&mut self, connection_index: ConnectionIndex) -> Option<Knowledge> {
+        self.entries.remove(&connection_index)
+    }
+
+    /// In increasing connection index order
+    pub fn iter(&self) -> impl Iterator<Item = (ConnectionIndex, Knowledge)> + '_ {
+        self.entries
+            .iter()
+            .map(|(connection_index, knowledge)| (*connection_index, *knowledge))
+    }
+
+    /// Fails for more than `u8::MAX` entries
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        if self.entries.len() > u8::MAX as usize {
+            return in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{} entries, at most {} supported",
+                        self.entries.len(),
+                        u8::MAX
+                    ),
+                )),
+                "entries.len",
+            );
+        }
+        stream.write_u8(self.entries.len() as u8)?;
+        for (connection_index, knowledge) in self.iter() {
+            connection_index.to_octets(stream)?;
+            write_varint(stream, knowledge)?;
+        }
+        Ok(())
+    }
+
+    /// The entries must be in increasing connection index order, so an encoding has a single
+    /// meaning
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let length = in_field(stream.read_u8(), "entries.len")?;
+        let mut entries = BTreeMap::new();
+        for index in 0..length {
+            let connection_index = in_field(
+                ConnectionIndex::from_cursor(stream),
+                format_args!("entries[{}].connection_index", index),
+            )?;
+            if let Some((&previous, _)) = entries
+                .last_key_value()
+                .filter(|(previous, _)| connection_index <= **previous)
+            {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "connection index {} must come after {}",
+                            connection_index, previous
+                        ),
+                    )),
+                    format_args!("entries[{}].connection_index", index),
+                );
+            }
+            let knowledge = in_field(
+                read_varint(stream),
+                format_args!("entries[{}].knowledge", index),
+            )?;
+            entries.insert(connection_index, knowledge);
+        }
+        Ok(Self { entries })
+    }
+}
+
+impl FromIterator<(ConnectionIndex, Knowledge)> for KnowledgeVector {
+    /// Keeps the highest knowledge of a member that is given more than once
+    fn from_iter<I: IntoIterator<Item = (ConnectionIndex, Knowledge)>>(iter: I) -> Self {
+        let mut vector = Self::new();
+        for (connection_index, knowledge) in iter {
+            vector.observe(connection_index, knowledge);
+        }
+        vector
+    }
+}
+
+impl PresenceField for KnowledgeVector {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets(stream)
+    }
+
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor(stream)
+    }
+}
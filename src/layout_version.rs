@@ -0,0 +1,140 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Commands that name their own layout, so one process can handle peers on different protocol
+//! versions, picked per packet instead of per connection.
+//!
+//! The layout version octet follows the command type id. It is one of the
+//! [`CommandDescriptor::layout_versions`] of the command, the version in which its current
+//! layout was introduced, so every layout has a single encoding.
+
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::{at_offset, in_field};
+use crate::slice_reader::reject_trailing;
+use crate::{
+    command_descriptor, ClientReceiveCommand, CommandDescriptor, DecodeOptions, ProtocolVersion,
+    ServerReceiveCommand, SliceReader,
+};
+
+fn unknown_command(command_type_id: u8) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("unknown command 0x{:x}", command_type_id),
+    )
+}
+
+fn descriptor_for(command_type_id: u8, server_receive: bool) -> Option<&'static CommandDescriptor> {
+    command_descriptor(command_type_id).filter(|descriptor| {
+        if server_receive {
+            descriptor.direction.is_server_receive()
+        } else {
+            descriptor.direction.is_client_receive()
+        }
+    })
+}
+
+/// Writes `encoded`, a command type id and payload, with the layout version after the id
+fn write_with_layout_version(
+    encoded: &[u8],
+    version: ProtocolVersion,
+    server_receive: bool,
+    stream: &mut impl WriteOctetStream,
+) -> Result<()> {
+    let command_type_id = encoded[0];
+    let layout_version = descriptor_for(command_type_id, server_receive)
+        .and_then(|descriptor| descriptor.layout_version(version))
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("command 0x{:x} has no layout version", command_type_id),
+            )
+        })?;
+    stream.write_u8(command_type_id)?;
+    stream.write_u8(layout_version)?;
+    stream.write(&encoded[1..])
+}
+
+/// Reads the command type id and the layout version, and checks that the payload can fit
+fn read_layout_version(
+    reader: &mut SliceReader,
+    server_receive: bool,
+) -> Result<(u8, ProtocolVersion)> {
+    let command_type_id = in_field(reader.read_u8(), "command_type_id")?;
+    let descriptor = in_field(
+        descriptor_for(command_type_id, server_receive)
+            .ok_or_else(|| unknown_command(command_type_id)),
+        "command_type_id",
+    )?;
+    let layout_version = in_field(reader.read_u8(), "layout_version")?;
+    let layout_versions = descriptor.layout_versions();
+    if !layout_versions.contains(&layout_version) {
+        return in_field(
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} has no layout version {}, only {:?}",
+                    descriptor.name, layout_version, layout_versions
+                ),
+            )),
+            "layout_version",
+        );
+    }
+    reader.require((descriptor.min_octet_size)(layout_version))?;
+    Ok((command_type_id, layout_version))
+}
+
+impl ServerReceiveCommand {
+    /// Writes the command in the layout it has in `version`, preceded by that layout version
+    pub fn to_octets_with_layout_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        let mut encoded = OutOctetStream::new();
+        self.to_octets_with_version(&mut encoded, version)?;
+        write_with_layout_version(&encoded.data, version, true, stream)
+    }
+
+    /// Decodes a command written with [`Self::to_octets_with_layout_version`], which must use
+    /// all of `octets`. Also returns the layout version, e.g. for answering in the same one.
+    pub fn decode_with_layout_version(octets: &[u8]) -> Result<(Self, ProtocolVersion)> {
+        let mut reader = SliceReader::new(octets);
+        let result = read_layout_version(&mut reader, true);
+        let (command_type_id, layout_version) = at_offset(result, reader.last_read_position())?;
+        let options = DecodeOptions::new(layout_version);
+        let result = Self::payload_from_cursor(command_type_id, &mut reader, &options);
+        let command = at_offset(result, reader.last_read_position())?;
+        reject_trailing(octets, reader.position())?;
+        Ok((command, layout_version))
+    }
+}
+
+impl ClientReceiveCommand {
+    /// Writes the command in the layout it has in `version`, preceded by that layout version
+    pub fn to_octets_with_layout_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        let mut encoded = OutOctetStream::new();
+        self.to_octets_with_version(&mut encoded, version)?;
+        write_with_layout_version(&encoded.data, version, false, stream)
+    }
+
+    /// Decodes a command written with [`Self::to_octets_with_layout_version`], which must use
+    /// all of `octets`. Also returns the layout version, e.g. for answering in the same one.
+    pub fn decode_with_layout_version(octets: &[u8]) -> Result<(Self, ProtocolVersion)> {
+        let mut reader = SliceReader::new(octets);
+        let result = read_layout_version(&mut reader, false);
+        let (command_type_id, layout_version) = at_offset(result, reader.last_read_position())?;
+        let options = DecodeOptions::new(layout_version);
+        let result = Self::payload_from_cursor(command_type_id, &mut reader, &options);
+        let command = at_offset(result, reader.last_read_position())?;
+        reject_trailing(octets, reader.position())?;
+        Ok((command, layout_version))
+    }
+}
@@ -9,19 +9,403 @@ use std::io::{Error, ErrorKind, Result};
 use conclave_room::{Knowledge, Term};
 use flood_rs::{ReadOctetStream, WriteOctetStream};
 
-use crate::ClientReceiveCommand::RoomInfoType;
-use crate::ServerReceiveCommand::PingCommandType;
+use crate::client_network_stats::{has_network_stats, read_network_stats, write_network_stats};
+use crate::datagram_magic::read_datagram_magic;
+use crate::descriptor::check_sender;
+use crate::error::in_field;
+use crate::knowledge::check_knowledge_floor;
+use crate::payload::{read_payload, write_payload};
+use crate::raw_command::read_to_end;
+use crate::term::check_known_term;
+use crate::transfer::{check_chunk_index, split_chunks};
+use crate::varint::{read_varint, write_varint};
+
+#[cfg(feature = "futures-io")]
+pub use async_io::{read_frame_async, write_frame_async};
+pub use budget::{encode_budgeted, BudgetedEncoding};
+pub use canonical::{verify_canonical, verify_canonical_with_version};
+pub use channel_profile::{
+    ChannelProfile, ChannelProfileCommand, ChannelRoute, CHANNEL_PROFILE_COMMAND_TYPE_ID,
+};
+pub use client_identity::ClientIdentity;
+pub use client_info_storage::ClientInfoStorage;
+pub use client_network_stats::{ClientNetworkStats, ROOM_INFO_FLAG_NETWORK_STATS};
+pub use client_presence::{PresenceState, SetPresenceCommand, SET_PRESENCE_COMMAND_TYPE_ID};
+pub use command_header::{
+    decode_with_header, encode_with_header, read_frame_with_header, write_frame_with_header,
+    CommandHeader,
+};
+#[cfg(feature = "futures")]
+pub use command_sink::{CommandSink, CommandStream};
+#[cfg(feature = "json")]
+pub use conformance::{
+    json_differences, run_conformance, ConformanceFailure, ConformanceReport, JsonDifference,
+};
+pub use connect_token::{
+    ConnectToken, ConnectWithTokenCommand, CONNECT_WITH_TOKEN_COMMAND_TYPE_ID,
+    MAX_CONNECT_TOKEN_PRIVATE_OCTETS, MAX_CONNECT_TOKEN_SERVER_ADDRESSES,
+};
+#[cfg(feature = "secure")]
+pub use connect_token::{
+    ConnectTokenPrivate, CONNECT_TOKEN_KEY_OCTETS, CONNECT_TOKEN_NONCE_OCTETS,
+    CONNECT_TOKEN_SEAL_OVERHEAD_OCTETS,
+};
+pub use connection_index::ConnectionIndex;
+pub use control::{
+    is_control_command, ControlCommand, DebugDumpCommand, EchoCommand, StatsRequestCommand,
+    CONTROL_COMMAND_TYPE_ID_RANGE, DEBUG_DUMP_COMMAND_TYPE_ID, ECHO_COMMAND_TYPE_ID,
+    STATS_REQUEST_COMMAND_TYPE_ID,
+};
+pub use correlation::{
+    CorrelationId, ENVELOPE_FLAG_CORRELATION_ID, ENVELOPE_FLAG_EXTENSIONS, ENVELOPE_FLAG_PADDING,
+};
+pub use datagram_magic::DATAGRAM_MAGIC;
+pub use descriptor::{
+    command_descriptor, fields_min_octet_size, CommandDelivery, CommandDescriptor,
+    CommandDirection, FieldDescriptor, FieldKind, LengthPrefix, SenderAuthorization, SenderRole,
+    StructDescriptor, COMMAND_DESCRIPTORS,
+};
+pub use direction::{
+    client_to_server_vec, server_to_client_vec, write_client_to_server, write_server_to_client,
+    ClientToServer, ServerToClient,
+};
+pub use error::{DecodeError, ProtocolError};
+pub use extensions::{ExtensionTag, Extensions, MAX_EXTENSIONS_OCTETS};
+pub use fingerprint::{PROTOCOL_FINGERPRINT, PROTOCOL_LAYOUT};
+pub use framing::{write_frame, write_frame_with_version, Frame, FrameDecoder};
+#[cfg(feature = "psk")]
+pub use handshake::{ClientHandshake, ServerHandshake};
+pub use hello::{HelloCommand, HELLO_COMMAND_TYPE_ID, HELLO_MAGIC};
+pub use io_stream::{IoReadStream, IoWriteStream};
+pub use kaitai::kaitai_struct;
+pub use knowledge_ack::{KnowledgeAckCommand, KNOWLEDGE_ACK_COMMAND_TYPE_ID};
+pub use knowledge_vector::{KnowledgeVector, ROOM_INFO_FLAG_KNOWLEDGE_VECTOR};
+pub use membership_sync::{
+    ClientRevision, KnownRevision, MembershipSyncRequestCommand, MembershipSyncResponseCommand,
+    MEMBERSHIP_SYNC_REQUEST_COMMAND_TYPE_ID, MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID,
+};
+pub use multiplex::{MultiplexedDatagram, MultiplexedDecoder};
+pub use payload::{MAX_BROADCAST_PAYLOAD_OCTETS, MAX_PAYLOAD_OCTETS};
+#[cfg(feature = "pcap")]
+pub use pcap::{CapturedCommand, PcapReader, PcapRecord};
+pub use pre_vote::{
+    PreVoteRequestCommand, PreVoteResponseCommand, PRE_VOTE_REQUEST_COMMAND_TYPE_ID,
+    PRE_VOTE_RESPONSE_COMMAND_TYPE_ID,
+};
+pub use presence::{PresenceField, PresenceMaskWidth, PresenceReader, PresenceWriter};
+#[cfg(feature = "protobuf")]
+pub use protobuf::{proto, proto_definition, PROTO_PACKAGE};
+#[cfg(feature = "psk")]
+pub use psk::{compute_psk_proof, derive_psk_session_keys};
+pub use psk::{
+    ChallengeCommand, PskAuthCommand, PskSessionKeys, CHALLENGE_COMMAND_TYPE_ID,
+    CHALLENGE_SERVER_LOAD_BIT, PSK_AUTH_COMMAND_TYPE_ID, PSK_CHALLENGE_OCTETS, PSK_PROOF_OCTETS,
+    PSK_SESSION_KEY_OCTETS,
+};
+pub use raw_command::RawCommand;
+#[cfg(feature = "renet")]
+pub use renet_channel::{
+    encode_renet_message, receive_from_renet_client, receive_from_renet_server,
+    renet_channel_configs, renet_channel_id, renet_connection_config, send_to_renet_client,
+    send_to_renet_server, RENET_RELIABLE_ORDERED_CHANNEL_ID, RENET_UNRELIABLE_CHANNEL_ID,
+};
+pub use replicate::{
+    LogEntry, ReplicateAckCommand, ReplicateCommand, REPLICATE_ACK_COMMAND_TYPE_ID,
+    REPLICATE_COMMAND_TYPE_ID,
+};
+pub use request::{Request, Response, ResponseMatcher};
+pub use retry_cookie::{
+    ConnectWithCookieCommand, RetryCookieCommand, CONNECT_WITH_COOKIE_COMMAND_TYPE_ID,
+    RETRY_COOKIE_COMMAND_TYPE_ID, RETRY_COOKIE_OCTETS, RETRY_COOKIE_PERIOD_S,
+};
+pub use room_event::{
+    RoomEvent, ROOM_EVENT_CLIENT_JOINED, ROOM_EVENT_CLIENT_LOST, ROOM_EVENT_LEADER_ELECTED,
+    ROOM_EVENT_TERM_ADVANCED,
+};
+pub use room_info_builder::RoomInfoCommandBuilder;
+pub use room_info_page::{
+    RoomInfoPageAccumulator, RoomInfoPageCommand, ROOM_INFO_PAGE_COMMAND_TYPE_ID,
+};
+pub use room_table::format_room_table;
+pub use routing::RoomId;
+#[cfg(feature = "secure")]
+pub use secure::{
+    SecureSession, SECURE_KEY_OCTETS, SECURE_OVERHEAD_OCTETS, SECURE_SEQUENCE_OCTETS,
+    SECURE_TAG_OCTETS, SECURE_TRUNCATED_OVERHEAD_OCTETS, SECURE_TRUNCATED_SEQUENCE_OCTETS,
+};
+#[cfg(feature = "secure")]
+pub use sequence_window::{SequenceWindow, SEQUENCE_WINDOW_SIZE};
+pub use slice_reader::SliceReader;
+pub use snapshot::{SnapshotId, SnapshotReassembler};
+pub use split::{
+    split_command, split_command_with_version, MessageId, PartialMessageBuffer, SplitHeader,
+};
+pub use stats::{CommandStats, CommandTypeStats};
+#[cfg(feature = "steamworks")]
+pub use steam::{
+    decode_steam_message, encode_steam_message, SteamMessage, STEAM_LANE_PRIORITIES,
+    STEAM_LANE_WEIGHTS, STEAM_MAX_MESSAGE_OCTETS, STEAM_RELIABLE_LANE, STEAM_SEND_NO_NAGLE,
+    STEAM_SEND_RELIABLE, STEAM_SEND_UNRELIABLE, STEAM_UNRELIABLE_LANE,
+};
+pub use term::{TermExt, TERM_UNKNOWN};
+pub use term_advanced::{
+    TermAdvancedCommand, TERM_ADVANCED_CAUSE_ELECTION, TERM_ADVANCED_CAUSE_LEADER_LEFT,
+    TERM_ADVANCED_CAUSE_LEADER_TIMEOUT, TERM_ADVANCED_COMMAND_TYPE_ID,
+};
+#[cfg(feature = "json")]
+pub use test_vectors::{test_vector_manifest, test_vectors, TestVector, TestVectorCommand};
+pub use transfer::{
+    ChunkReceiver, ChunkSender, ReassemblyLimits, TransferChunkCommand, TransferId,
+    TransferResendRequestCommand,
+};
+pub use user_id::UserId;
+pub use varint::{varint_octet_size, MAX_VARINT_OCTETS};
+#[cfg(feature = "webrtc")]
+pub use webrtc::{DataChannelReceiver, DataChannelSender, WEBRTC_MAX_MESSAGE_OCTETS};
+
+#[cfg(feature = "futures-io")]
+mod async_io;
+mod budget;
+mod canonical;
+#[cfg(feature = "cbor")]
+mod cbor;
+mod channel_profile;
+mod client_identity;
+mod client_info_storage;
+mod client_network_stats;
+mod client_presence;
+mod command_header;
+#[cfg(feature = "futures")]
+mod command_sink;
+#[cfg(feature = "json")]
+mod conformance;
+mod connect_token;
+mod connection_index;
+mod control;
+mod correlation;
+mod datagram_magic;
+mod descriptor;
+mod direction;
+mod error;
+mod extensions;
+mod fingerprint;
+mod framing;
+#[cfg(feature = "psk")]
+mod handshake;
+mod hello;
+mod io_stream;
+mod kaitai;
+mod knowledge;
+mod knowledge_ack;
+mod knowledge_vector;
+mod layout_version;
+mod membership_sync;
+pub mod migrate;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod multiplex;
+mod payload;
+#[cfg(feature = "pcap")]
+mod pcap;
+mod pre_vote;
+mod presence;
+#[cfg(feature = "protobuf")]
+mod protobuf;
+mod psk;
+mod raw_command;
+#[cfg(feature = "renet")]
+mod renet_channel;
+pub mod replay;
+mod replicate;
+mod request;
+mod retry_cookie;
+mod room_event;
+mod room_info_builder;
+mod room_info_page;
+mod room_table;
+mod routing;
+#[cfg(feature = "secure")]
+mod secure;
+#[cfg(feature = "secure")]
+mod sequence_window;
+mod slice_reader;
+mod snapshot;
+mod split;
+mod stats;
+#[cfg(feature = "steamworks")]
+mod steam;
+mod term;
+mod term_advanced;
+#[cfg(feature = "json")]
+mod test_vectors;
+mod transfer;
+mod user_id;
+mod varint;
+#[cfg(feature = "webrtc")]
+mod webrtc;
+
+/// Version of the serialized command layouts
+pub type ProtocolVersion = u8;
+
+/// The original layouts
+pub const PROTOCOL_VERSION_1: ProtocolVersion = 1;
+/// `ClientInfo` carries `role_flags`. Adds the room closed, ban, unban and mute state commands.
+pub const PROTOCOL_VERSION_2: ProtocolVersion = 2;
+/// [`UserId`] is serialized as 16 octets
+pub const PROTOCOL_VERSION_3: ProtocolVersion = 3;
+/// `ClientInfo` identifies the client with a [`ClientIdentity`]
+pub const PROTOCOL_VERSION_4: ProtocolVersion = 4;
+/// `PingCommand` carries the connection index of the sender. Commands introduced alongside it, such
+/// as `PingResponseCommand`, the relay, broadcast, join, resync, snapshot, transfer and report
+/// commands, require at least this version
+pub const PROTOCOL_VERSION_5: ProtocolVersion = 5;
+/// `PingCommand` carries the estimated clock drift of the sender. Commands introduced alongside
+/// it, such as `MaintenanceNoticeCommand`, require at least this version
+pub const PROTOCOL_VERSION_6: ProtocolVersion = 6;
+/// `ClientInfo` carries a revision. Adds the membership sync commands.
+pub const PROTOCOL_VERSION_7: ProtocolVersion = 7;
+/// `ClientInfo` carries a [`PresenceState`]. Adds `SetPresenceCommand`.
+pub const PROTOCOL_VERSION_8: ProtocolVersion = 8;
+/// `RoomInfoCommand` has flags, and can carry the [`ClientNetworkStats`] of its clients.
+pub const PROTOCOL_VERSION_9: ProtocolVersion = 9;
+/// `PingCommand` and `RoomInfoCommand` can carry a [`KnowledgeVector`]. Adds
+/// `KnowledgeAckCommand` and `TermAdvancedCommand`.
+pub const PROTOCOL_VERSION_10: ProtocolVersion = 10;
+/// `RoomInfoCommand` carries the quorum size and the votes for the leader.
+pub const PROTOCOL_VERSION_11: ProtocolVersion = 11;
+/// `RoomInfoCommand` carries when the lease of the leader expires. Adds the pre-vote and
+/// replication commands.
+pub const PROTOCOL_VERSION_12: ProtocolVersion = 12;
+/// `RoomInfoPageCommand` carries the knowledge vector, quorum size, votes for the leader and
+/// leader lease of the room info.
+pub const PROTOCOL_VERSION_13: ProtocolVersion = 13;
+/// `ChallengeCommand` can carry the [`ServerLoadCommand`] of the server.
+pub const PROTOCOL_VERSION_14: ProtocolVersion = 14;
+
+/// The newest version, offered in the handshake
+pub const PROTOCOL_VERSION: ProtocolVersion = PROTOCOL_VERSION_14;
+
+/// The version used by `to_octets`, `from_cursor`, `from_octets` and the other calls that do not
+/// take one. It stays on the original layouts, so peers built before the protocol versions keep
+/// understanding them. Use the `_with_version` calls with the negotiated version for anything
+/// newer.
+pub const DEFAULT_PROTOCOL_VERSION: ProtocolVersion = PROTOCOL_VERSION_1;
+
+/// How commands are decoded
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DecodeOptions {
+    pub version: ProtocolVersion,
+    /// Accept commands that decode but are semantically invalid,
+    /// e.g. a `RoomInfoCommand` with duplicate connection indices
+    pub lenient: bool,
+    /// Every datagram starts with this magic, which is verified and stripped
+    pub datagram_magic: Option<[u8; 2]>,
+    /// Reject knowledge claims of the room below this, e.g. the knowledge already applied in
+    /// the current term. Checked even when lenient.
+    pub knowledge_floor: Option<Knowledge>,
+    /// Reject commands this sender may not send, see [`CommandDescriptor::sender`]. Checked
+    /// even when lenient.
+    pub sender: Option<SenderRole>,
+}
+
+impl DecodeOptions {
+    pub fn new(version: ProtocolVersion) -> Self {
+        Self {
+            version,
+            lenient: false,
+            datagram_magic: None,
+            knowledge_floor: None,
+            sender: None,
+        }
+    }
+
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    pub fn with_datagram_magic(mut self, magic: [u8; 2]) -> Self {
+        self.datagram_magic = Some(magic);
+        self
+    }
+
+    pub fn with_knowledge_floor(mut self, floor: Knowledge) -> Self {
+        self.knowledge_floor = Some(floor);
+        self
+    }
+
+    pub fn with_sender(mut self, sender: SenderRole) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROTOCOL_VERSION)
+    }
+}
+
+/// Clock drift is measured in parts per million, i.e. microseconds gained per second
+pub const CLOCK_DRIFT_PARTS_PER: u32 = 1_000_000;
+/// The fastest clock drift that can be reported, faster clocks saturate to it
+pub const CLOCK_DRIFT_PPM_MAX: i16 = i16::MAX;
+/// The slowest clock drift that can be reported, slower clocks saturate to it
+pub const CLOCK_DRIFT_PPM_MIN: i16 = i16::MIN;
+
+/// Saturates an estimated drift in parts per million to the serialized range
+pub fn saturate_clock_drift_ppm(drift_ppm: i64) -> i16 {
+    drift_ppm.clamp(CLOCK_DRIFT_PPM_MIN as i64, CLOCK_DRIFT_PPM_MAX as i64) as i16
+}
+
+/// The presence mask bit of [`PingCommand::knowledge_vector`]
+pub const PING_KNOWLEDGE_VECTOR_BIT: u32 = 0;
 
 /// Sent from Client to Server
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PingCommand {
     pub term: Term,
     pub knowledge: Knowledge,
     pub has_connection_to_leader: bool,
+    /// For relays that aggregate many clients onto one socket. Not serialized before
+    /// [`PROTOCOL_VERSION_5`].
+    pub connection_index: ConnectionIndex,
+    /// The estimated drift of the sender's clock compared to the server's, see
+    /// [`CLOCK_DRIFT_PARTS_PER`]. Not serialized before [`PROTOCOL_VERSION_6`].
+    pub clock_drift_ppm: i16,
+    /// The knowledge of each member as seen by the sender, for rooms that track it. Behind a
+    /// presence mask from [`PROTOCOL_VERSION_10`], not serialized before.
+    pub knowledge_vector: Option<KnowledgeVector>,
 }
 
 impl PingCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "ping";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_1;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        if version >= PROTOCOL_VERSION_10 {
+            2 + 8 + 1 + 1 + 2 + 1
+        } else if version >= PROTOCOL_VERSION_6 {
+            2 + 8 + 1 + 1 + 2
+        } else if version >= PROTOCOL_VERSION_5 {
+            2 + 8 + 1 + 1
+        } else {
+            2 + 8 + 1
+        }
+    }
+
     pub fn to_octets<T: WriteOctetStream>(&self, stream: &mut T) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version<T: WriteOctetStream>(
+        &self,
+        stream: &mut T,
+        version: ProtocolVersion,
+    ) -> Result<()> {
         stream.write_u16(self.term)?;
         stream.write_u64(self.knowledge)?;
         stream.write_u8(if self.has_connection_to_leader {
@@ -29,224 +413,8467 @@ impl PingCommand {
         } else {
             0x00
         })?;
+        if version >= PROTOCOL_VERSION_5 {
+            self.connection_index.to_octets(stream)?;
+        }
+        if version >= PROTOCOL_VERSION_6 {
+            stream.write_i16(self.clock_drift_ppm)?;
+        }
+        if version >= PROTOCOL_VERSION_10 {
+            let mut writer = PresenceWriter::new(PresenceMaskWidth::U8);
+            in_field(
+                writer.field(PING_KNOWLEDGE_VECTOR_BIT, &self.knowledge_vector),
+                "knowledge_vector",
+            )?;
+            writer.to_octets(stream)?;
+        }
 
         Ok(())
     }
 
     pub fn from_cursor<T: ReadOctetStream>(stream: &mut T) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version<T: ReadOctetStream>(
+        stream: &mut T,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Self::from_cursor_with_options(stream, &DecodeOptions::new(version))
+    }
+
+    /// The knowledge must not be below the floor of the options
+    pub fn from_cursor_with_options<T: ReadOctetStream>(
+        stream: &mut T,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let version = options.version;
+        let term = in_field(stream.read_u16(), "term")?;
+        let knowledge = in_field(stream.read_u64(), "knowledge")?;
+        in_field(
+            check_knowledge_floor(knowledge, options.knowledge_floor).map_err(Error::from),
+            "knowledge",
+        )?;
         Ok(Self {
-            term: stream.read_u16()?,
-            knowledge: stream.read_u64()?,
-            has_connection_to_leader: stream.read_u8()? != 0,
+            term,
+            knowledge,
+            has_connection_to_leader: in_field(stream.read_u8(), "has_connection_to_leader")? != 0,
+            connection_index: if version >= PROTOCOL_VERSION_5 {
+                in_field(ConnectionIndex::from_cursor(stream), "connection_index")?
+            } else {
+                ConnectionIndex(0)
+            },
+            clock_drift_ppm: if version >= PROTOCOL_VERSION_6 {
+                in_field(stream.read_i16(), "clock_drift_ppm")?
+            } else {
+                0
+            },
+            knowledge_vector: if version >= PROTOCOL_VERSION_10 {
+                let mut reader = PresenceReader::from_cursor(
+                    stream,
+                    PresenceMaskWidth::U8,
+                    1 << PING_KNOWLEDGE_VECTOR_BIT,
+                )?;
+                in_field(
+                    reader.field(PING_KNOWLEDGE_VECTOR_BIT, stream),
+                    "knowledge_vector",
+                )?
+            } else {
+                None
+            },
         })
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+pub const ROLE_FLAG_HOST: u16 = 0x0001;
+pub const ROLE_FLAG_MODERATOR: u16 = 0x0002;
+pub const ROLE_FLAG_PLAYER: u16 = 0x0004;
+pub const ROLE_FLAG_SPECTATOR: u16 = 0x0008;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClientInfo {
-    pub custom_user_id: u64,
-    pub connection_index: u8,
+    pub identity: ClientIdentity,
+    pub connection_index: ConnectionIndex,
+    /// Combination of the `ROLE_FLAG_` bits. Not serialized in [`PROTOCOL_VERSION_1`].
+    pub role_flags: u16,
+    /// Changed by the Server whenever the other fields change, see
+    /// `MembershipSyncRequestCommand`. Not serialized before [`PROTOCOL_VERSION_7`].
+    pub revision: ClientRevision,
+    /// Not serialized before [`PROTOCOL_VERSION_8`]
+    pub presence: PresenceState,
+    /// Only serialized by `RoomInfoCommand`, from [`PROTOCOL_VERSION_9`]. Either all or none
+    /// of the clients of a room have network stats.
+    pub network_stats: Option<ClientNetworkStats>,
+}
+
+impl ClientInfo {
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        self.connection_index.to_octets(stream)?;
+        self.identity.to_octets_with_version(stream, version)?;
+        if version >= PROTOCOL_VERSION_2 {
+            stream.write_u16(self.role_flags)?;
+        }
+        if version >= PROTOCOL_VERSION_7 {
+            stream.write_u32(self.revision)?;
+        }
+        if version >= PROTOCOL_VERSION_8 {
+            self.presence.to_octets(stream)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Ok(Self {
+            connection_index: in_field(ConnectionIndex::from_cursor(stream), "connection_index")?,
+            identity: in_field(
+                ClientIdentity::from_cursor_with_version(stream, version),
+                "identity",
+            )?,
+            role_flags: if version >= PROTOCOL_VERSION_2 {
+                in_field(stream.read_u16(), "role_flags")?
+            } else {
+                0
+            },
+            revision: if version >= PROTOCOL_VERSION_7 {
+                in_field(stream.read_u32(), "revision")?
+            } else {
+                0
+            },
+            presence: if version >= PROTOCOL_VERSION_8 {
+                in_field(PresenceState::from_cursor(stream), "presence")?
+            } else {
+                PresenceState::Active
+            },
+            network_stats: None,
+        })
+    }
+}
+
+/// The default storage of the client infos in [`RoomInfoCommand`]
+pub type ClientInfos = Vec<ClientInfo>;
+
+/// The client infos of a [`RoomInfoCommand`] with up to [`INLINE_CLIENTS`] stored inline,
+/// which covers most rooms, e.g. `RoomInfoCommand<SmallClientInfos>`. Larger rooms spill to
+/// the heap.
+#[cfg(feature = "smallvec")]
+pub type SmallClientInfos = smallvec::SmallVec<[ClientInfo; INLINE_CLIENTS]>;
+
+/// The inline capacity of [`SmallClientInfos`]
+#[cfg(feature = "smallvec")]
+pub const INLINE_CLIENTS: usize = 8;
+
+/// The client infos of a [`RoomInfoCommand`] stored inline, so decoding does not allocate
+/// (except for [`ClientIdentity::String`]), e.g. `RoomInfoCommand<ArrayClientInfos>`.
+/// At most [`MAX_CLIENTS`] can be decoded.
+#[cfg(feature = "arrayvec")]
+pub type ArrayClientInfos = arrayvec::ArrayVec<ClientInfo, MAX_CLIENTS>;
+
+/// The capacity of [`ArrayClientInfos`]
+#[cfg(feature = "arrayvec")]
+pub const MAX_CLIENTS: usize = 32;
+
+/// The most client infos a [`RoomInfoCommand`] can carry, limited by the octet count on the wire
+pub const MAX_CLIENT_INFOS: usize = u8::MAX as usize;
+
+/// Makes room for `length` more client infos
+fn reserve_client_infos<C: ClientInfoStorage>(client_infos: &mut C, length: usize) -> Result<()> {
+    client_infos.reserve_client_infos(length)
 }
 
 /// Sent from Server to Client
-#[derive(Debug, PartialEq)]
-pub struct RoomInfoCommand {
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoomInfoCommand<C = ClientInfos> {
     pub term: Term,
-    pub leader_index: u8,
-    pub client_infos: Vec<ClientInfo>,
+    /// The connection index of the leader, as in `conclave_room`, not its position in
+    /// `client_infos`
+    pub leader_index: ConnectionIndex,
+    /// Another [`ClientInfoStorage`] than the default can be picked with the type parameter,
+    /// e.g. to decode without allocating
+    pub client_infos: C,
+    /// The knowledge of each member, for rooms that track it. Not serialized before
+    /// [`PROTOCOL_VERSION_10`].
+    pub knowledge_vector: Option<KnowledgeVector>,
+    /// The members needed to elect a leader, `0` if unknown. Not serialized before
+    /// [`PROTOCOL_VERSION_11`].
+    pub quorum_size: u8,
+    /// The members that voted for the current leader, `0` if unknown. Not serialized before
+    /// [`PROTOCOL_VERSION_11`].
+    pub votes_for_leader: u8,
+    /// How long the leader stays leader without renewing its lease, as of sending, `0` if
+    /// unknown. Not serialized before [`PROTOCOL_VERSION_12`].
+    pub leader_lease_expires_in_ms: u32,
 }
 
 impl RoomInfoCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "room_info";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_1;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        if version >= PROTOCOL_VERSION_12 {
+            2 + 1 + 1 + 1 + 1 + 1 + 4
+        } else if version >= PROTOCOL_VERSION_11 {
+            2 + 1 + 1 + 1 + 1 + 1
+        } else if version >= PROTOCOL_VERSION_9 {
+            2 + 1 + 1 + 1
+        } else {
+            2 + 1 + 1
+        }
+    }
+
+    pub fn builder() -> RoomInfoCommandBuilder {
+        RoomInfoCommandBuilder::default()
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Self::from_cursor_with_options(stream, &DecodeOptions::new(version))
+    }
+}
+
+impl<C: ClientInfoStorage> RoomInfoCommand<C> {
+    /// `true` if the votes for the leader reach the quorum, `false` if the quorum is unknown
+    pub fn leader_has_quorum(&self) -> bool {
+        self.quorum_size != 0 && self.votes_for_leader >= self.quorum_size
+    }
+
+    pub fn leader_lease_expires_in(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.leader_lease_expires_in_ms as u64)
+    }
+
+    /// Checks that the leader index is the connection index of one of the client infos, that
+    /// the connection indices are unique and that the client infos fit in the layout.
+    /// A room without clients has no leader, so its leader index is not checked. The term is
+    /// not checked, peers may send [`TERM_UNKNOWN`] before they know the term.
+    pub fn validate(&self) -> std::result::Result<(), ProtocolError> {
+        if self.client_infos.len() > MAX_CLIENT_INFOS {
+            return Err(ProtocolError::TooManyClients {
+                count: self.client_infos.len(),
+                max: MAX_CLIENT_INFOS,
+            });
+        }
+        if !self.client_infos.is_empty()
+            && !self
+                .client_infos
+                .iter()
+                .any(|client_info| client_info.connection_index == self.leader_index)
+        {
+            return Err(ProtocolError::LeaderIndexOutOfBounds {
+                leader_index: self.leader_index,
+                client_count: self.client_infos.len(),
+            });
+        }
+        for (index, client_info) in self.client_infos.iter().enumerate() {
+            if self.client_infos[..index]
+                .iter()
+                .any(|other| other.connection_index == client_info.connection_index)
+            {
+                return Err(ProtocolError::DuplicateConnectionIndex {
+                    connection_index: client_info.connection_index,
+                });
+            }
+        }
+        Ok(())
+    }
+
     pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    /// Expects the command to be valid, as guaranteed by [`RoomInfoCommand::builder`].
+    /// See [`RoomInfoCommand::validate`]. Only more client infos than [`MAX_CLIENT_INFOS`] are
+    /// checked, as their count would not fit.
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        if self.client_infos.len() > MAX_CLIENT_INFOS {
+            return Err(ProtocolError::TooManyClients {
+                count: self.client_infos.len(),
+                max: MAX_CLIENT_INFOS,
+            }
+            .into());
+        }
         stream.write_u16(self.term)?;
         stream.write_u8(self.client_infos.len() as u8)?;
         for client_info in self.client_infos.iter() {
-            stream.write_u8(client_info.connection_index)?;
-            stream.write_u64(client_info.custom_user_id)?;
+            client_info.to_octets_with_version(stream, version)?;
+        }
+        self.leader_index.to_octets(stream)?;
+        if version >= PROTOCOL_VERSION_9 {
+            let network_stats = has_network_stats(&self.client_infos);
+            let knowledge_vector = self
+                .knowledge_vector
+                .as_ref()
+                .filter(|_| version >= PROTOCOL_VERSION_10);
+            let mut flags = 0;
+            if network_stats {
+                flags |= ROOM_INFO_FLAG_NETWORK_STATS;
+            }
+            if knowledge_vector.is_some() {
+                flags |= ROOM_INFO_FLAG_KNOWLEDGE_VECTOR;
+            }
+            stream.write_u8(flags)?;
+            if let Some(knowledge_vector) = knowledge_vector {
+                in_field(knowledge_vector.to_octets(stream), "knowledge_vector")?;
+            }
+            if version >= PROTOCOL_VERSION_11 {
+                stream.write_u8(self.quorum_size)?;
+                stream.write_u8(self.votes_for_leader)?;
+            }
+            if version >= PROTOCOL_VERSION_12 {
+                stream.write_u32(self.leader_lease_expires_in_ms)?;
+            }
+            if network_stats {
+                write_network_stats(&self.client_infos, stream)?;
+            }
         }
-        stream.write_u8(self.leader_index)?;
 
         Ok(())
     }
 
-    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
-        let term = stream.read_u16()?;
-        let length = stream.read_u8()? as usize;
-        let slice = &mut vec![ClientInfo {
-            custom_user_id: 0,
-            connection_index: 0,
-        }][..length];
-        for client_info in slice.iter_mut().take(length) {
-            *client_info = ClientInfo {
-                connection_index: stream.read_u8()?,
-                custom_user_id: stream.read_u64()?,
+    /// Unless the options are lenient, the decoded command must pass [`RoomInfoCommand::validate`].
+    pub fn from_cursor_with_options(
+        stream: &mut impl ReadOctetStream,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let mut room_info = Self::default();
+        room_info.decode_into_with_options(stream, options)?;
+        Ok(room_info)
+    }
+
+    /// Decodes over `self` with the [`DEFAULT_PROTOCOL_VERSION`], reusing the storage of
+    /// `client_infos`, so decoding at high frequency does not allocate per command (except for
+    /// [`ClientIdentity::String`]). After a failure `self` holds a partially decoded command.
+    pub fn decode_into(&mut self, stream: &mut impl ReadOctetStream) -> Result<()> {
+        self.decode_into_with_options(stream, &DecodeOptions::new(DEFAULT_PROTOCOL_VERSION))
+    }
+
+    pub fn decode_into_with_options(
+        &mut self,
+        stream: &mut impl ReadOctetStream,
+        options: &DecodeOptions,
+    ) -> Result<()> {
+        let version = options.version;
+        self.term = in_field(stream.read_u16(), "term")?;
+        let length = in_field(stream.read_u8(), "client_infos.len")? as usize;
+        self.client_infos.clear_client_infos();
+        in_field(
+            reserve_client_infos(&mut self.client_infos, length),
+            "client_infos.len",
+        )?;
+        for index in 0..length {
+            self.client_infos.push_client_info(in_field(
+                ClientInfo::from_cursor_with_version(stream, version),
+                format_args!("client_infos[{}]", index),
+            )?);
+        }
+        self.leader_index = in_field(ConnectionIndex::from_cursor(stream), "leader_index")?;
+        self.knowledge_vector = None;
+        self.quorum_size = 0;
+        self.votes_for_leader = 0;
+        self.leader_lease_expires_in_ms = 0;
+        if version >= PROTOCOL_VERSION_9 {
+            let flags = in_field(stream.read_u8(), "flags")?;
+            let known_flags = if version >= PROTOCOL_VERSION_10 {
+                ROOM_INFO_FLAG_NETWORK_STATS | ROOM_INFO_FLAG_KNOWLEDGE_VECTOR
+            } else {
+                ROOM_INFO_FLAG_NETWORK_STATS
+            };
+            if flags & !known_flags != 0 {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("unknown room info flags 0x{:02x}", flags & !known_flags),
+                    )),
+                    "flags",
+                );
+            }
+            if flags & ROOM_INFO_FLAG_KNOWLEDGE_VECTOR != 0 {
+                self.knowledge_vector = Some(in_field(
+                    KnowledgeVector::from_cursor(stream),
+                    "knowledge_vector",
+                )?);
+            }
+            if version >= PROTOCOL_VERSION_11 {
+                self.quorum_size = in_field(stream.read_u8(), "quorum_size")?;
+                self.votes_for_leader = in_field(stream.read_u8(), "votes_for_leader")?;
+            }
+            if version >= PROTOCOL_VERSION_12 {
+                self.leader_lease_expires_in_ms =
+                    in_field(stream.read_u32(), "leader_lease_expires_in_ms")?;
+            }
+            if flags & ROOM_INFO_FLAG_NETWORK_STATS != 0 {
+                read_network_stats(&mut self.client_infos, stream)?;
             }
         }
+        if !options.lenient {
+            self.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Sent from Server to Client
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoomClosedCommand {
+    pub reason_code: u8,
+    /// Milliseconds until the room is closed, zero if it is closed immediately
+    pub closes_at_ms: u32,
+}
+
+impl RoomClosedCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "room_closed";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_2;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1 + 4
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u8(self.reason_code)?;
+        stream.write_u32(self.closes_at_ms)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
         Ok(Self {
-            term,
-            leader_index: stream.read_u8()?,
-            client_infos: slice.to_vec(),
+            reason_code: in_field(stream.read_u8(), "reason_code")?,
+            closes_at_ms: in_field(stream.read_u32(), "closes_at_ms")?,
         })
     }
 }
 
-#[derive(Debug)]
-pub enum ServerReceiveCommand {
-    PingCommandType(PingCommand),
+/// Sent from an administrating Client to Server
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BanClientCommand {
+    pub custom_user_id: UserId,
+    /// Duration of the ban in seconds, zero bans the user permanently
+    pub duration_s: u32,
 }
 
-impl ServerReceiveCommand {
-    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
-        let command_type_id = match self {
-            PingCommandType(_) => PING_COMMAND_TYPE_ID,
-            // _ => return Err(format!("unsupported command {:?}", self)),
-        };
+impl BanClientCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "ban_client";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_2;
 
-        stream.write_u8(command_type_id)?;
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        UserId::octet_size(version) + 4
+    }
 
-        match self {
-            PingCommandType(ping_command) => {
-                ping_command.to_octets(stream)?;
-            } // _ => return Err(format!("unknown command enum {:?}", self)),
-        }
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        self.custom_user_id
+            .to_octets_with_version(stream, version)?;
+        stream.write_u32(self.duration_s)?;
 
         Ok(())
     }
 
-    pub fn from_cursor<T: ReadOctetStream>(stream: &mut T) -> Result<ServerReceiveCommand> {
-        let command_type_id = stream.read_u8()?;
-        match command_type_id {
-            PING_COMMAND_TYPE_ID => Ok(PingCommandType(PingCommand::from_cursor(stream)?)),
-            _ => Err(Error::new(
-                ErrorKind::Other,
-                format!("unknown command 0x{:x}", command_type_id),
-            )),
-        }
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
     }
-}
 
-pub const PING_COMMAND_TYPE_ID: u8 = 0x01;
-pub const ROOM_INFO_COMMAND_TYPE_ID: u8 = 0x02;
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Ok(Self {
+            custom_user_id: in_field(
+                UserId::from_cursor_with_version(stream, version),
+                "custom_user_id",
+            )?,
+            duration_s: in_field(stream.read_u32(), "duration_s")?,
+        })
+    }
+}
 
-#[derive(Debug)]
-pub enum ClientReceiveCommand {
-    RoomInfoType(RoomInfoCommand),
+/// Sent from an administrating Client to Server
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnbanClientCommand {
+    pub custom_user_id: UserId,
 }
 
-impl ClientReceiveCommand {
-    pub fn to_octets<T: WriteOctetStream>(&self, stream: &mut T) -> Result<()> {
-        let command_type_id = match self {
-            RoomInfoType(_) => ROOM_INFO_COMMAND_TYPE_ID,
-            // _ => return Err(format!("unsupported command {:?}", self)),
-        };
+impl UnbanClientCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "unban_client";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_2;
 
-        stream.write_u8(command_type_id)?;
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        UserId::octet_size(version)
+    }
 
-        match self {
-            RoomInfoType(room_info_command) => room_info_command.to_octets(stream)?, // _ => return Err(format!("unknown command enum {:?}", self)),
-        }
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        self.custom_user_id
+            .to_octets_with_version(stream, version)?;
 
         Ok(())
     }
 
-    pub fn from_octets<T: ReadOctetStream>(stream: &mut T) -> Result<ClientReceiveCommand> {
-        let command_type_id = stream.read_u8()?;
-        match command_type_id {
-            ROOM_INFO_COMMAND_TYPE_ID => Ok(RoomInfoType(RoomInfoCommand::from_cursor(stream)?)),
-            _ => Err(Error::new(
-                ErrorKind::Other,
-                format!("unknown command 0x{:x}", command_type_id),
-            )),
-        }
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Ok(Self {
+            custom_user_id: in_field(
+                UserId::from_cursor_with_version(stream, version),
+                "custom_user_id",
+            )?,
+        })
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use flood_rs::{InOctetStream, OutOctetStream};
+pub const MUTE_STATE_MUTED_FLAG: u8 = 0x01;
 
-    use crate::ClientReceiveCommand::RoomInfoType;
-    use crate::ServerReceiveCommand::PingCommandType;
-    use crate::{
-        ClientReceiveCommand, PingCommand, ServerReceiveCommand, PING_COMMAND_TYPE_ID,
-        ROOM_INFO_COMMAND_TYPE_ID,
-    };
+/// Sent from Client to Server, and broadcast from Server to all Clients in the room
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MuteStateCommand {
+    pub connection_index: ConnectionIndex,
+    pub muted: bool,
+}
 
-    #[test]
-    fn check_serializer() {
-        let ping_command = PingCommand {
-            term: 32,
-            knowledge: 444441,
-            has_connection_to_leader: false,
-        };
+impl MuteStateCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "mute_state";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_2;
 
-        let mut out_stream = OutOctetStream::new();
-        ping_command.to_octets(&mut out_stream).unwrap();
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1 + 1
+    }
 
-        let mut in_stream = InOctetStream::new(out_stream.data);
-        let in_stream_ref = &mut in_stream;
-        let deserialized_ping_command = PingCommand::from_cursor(in_stream_ref).unwrap();
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.connection_index.to_octets(stream)?;
+        stream.write_u8(if self.muted {
+            MUTE_STATE_MUTED_FLAG
+        } else {
+            0x00
+        })?;
 
-        println!("before {:?}", &ping_command);
-        println!("after {:?}", &deserialized_ping_command);
-        assert_eq!(ping_command, deserialized_ping_command);
+        Ok(())
     }
 
-    #[test]
-    fn check_server_receive_message() {
-        const EXPECTED_KNOWLEDGE_VALUE: u64 = 17718865395771014920;
-
-        let octets = [
-            PING_COMMAND_TYPE_ID,
-            0x00,
-            0x20, // Term
-            0xF5,
-            0xE6,
-            0x0E,
-            0x32,
-            0xE9,
-            0xE4,
-            0x7F,
-            0x08, // Knowledge
-            0x01, // Has Connection
-        ];
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let connection_index = in_field(ConnectionIndex::from_cursor(stream), "connection_index")?;
+        let flags = in_field(stream.read_u8(), "muted")?;
+        Ok(Self {
+            connection_index,
+            muted: flags & MUTE_STATE_MUTED_FLAG != 0,
+        })
+    }
+}
 
-        let mut in_stream = InOctetStream::new(Vec::from(octets));
+/// Sent from Server to Client as a reply to a `PingCommand`
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PingResponseCommand {
+    pub term: Term,
+    /// The authoritative knowledge of the room
+    pub server_knowledge: Knowledge,
+    /// The knowledge the client reported in its latest `PingCommand`
+    pub your_knowledge_ack: Knowledge,
+}
 
-        let message = &ServerReceiveCommand::from_cursor(&mut in_stream).unwrap();
+impl PingResponseCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "ping_response";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
 
-        match message {
-            PingCommandType(ping_command) => {
-                println!("received {:?}", &ping_command);
-                assert_eq!(ping_command.term, 0x20);
-                assert_eq!(ping_command.knowledge, EXPECTED_KNOWLEDGE_VALUE);
-                assert_eq!(ping_command.has_connection_to_leader, true);
-            } // _ => assert!(false, "should be ping command"),
-        }
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 8 + 8
     }
 
-    #[test]
-    fn check_client_receive_message() {
-        const EXPECTED_LEADER_INDEX: u8 = 1;
+    /// How far the acknowledged knowledge of the client is behind the room
+    pub fn knowledge_behind(&self) -> Knowledge {
+        self.server_knowledge
+            .saturating_sub(self.your_knowledge_ack)
+    }
 
-        let octets = [
-            ROOM_INFO_COMMAND_TYPE_ID,
-            0x00,                  // Term
-            0x4A,                  // Term (lower)
-            0x00,                  // Number of client infos that follows
-            EXPECTED_LEADER_INDEX, // Leader index
-        ];
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(check_known_term(self.term).map_err(Error::from), "term")?;
+        stream.write_u16(self.term)?;
+        stream.write_u64(self.server_knowledge)?;
+        stream.write_u64(self.your_knowledge_ack)?;
 
-        let mut in_stream = InOctetStream::new(Vec::from(octets));
+        Ok(())
+    }
 
-        let message = &ClientReceiveCommand::from_octets(&mut in_stream).unwrap();
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_options(stream, &DecodeOptions::default())
+    }
 
-        match message {
-            RoomInfoType(room_info) => {
-                println!("received {:?}", &room_info);
+    /// The server knowledge must not be below the floor of the options
+    pub fn from_cursor_with_options(
+        stream: &mut impl ReadOctetStream,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let term = in_field(stream.read_u16(), "term")?;
+        in_field(check_known_term(term).map_err(Error::from), "term")?;
+        let server_knowledge = in_field(stream.read_u64(), "server_knowledge")?;
+        in_field(
+            check_knowledge_floor(server_knowledge, options.knowledge_floor).map_err(Error::from),
+            "server_knowledge",
+        )?;
+        Ok(Self {
+            term,
+            server_knowledge,
+            your_knowledge_ack: in_field(stream.read_u64(), "your_knowledge_ack")?,
+        })
+    }
+}
+
+/// Sent from Client to Server, which relays the `payload` to the target Client
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelayToClientCommand {
+    pub target_connection_index: ConnectionIndex,
+    /// Opaque to the room server, at most [`MAX_PAYLOAD_OCTETS`]
+    pub payload: Vec<u8>,
+}
+
+impl RelayToClientCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "relay_to_client";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1 + 2
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.target_connection_index.to_octets(stream)?;
+        in_field(
+            write_payload(stream, &self.payload, MAX_PAYLOAD_OCTETS),
+            "payload",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            target_connection_index: in_field(
+                ConnectionIndex::from_cursor(stream),
+                "target_connection_index",
+            )?,
+            payload: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "payload")?,
+        })
+    }
+}
+
+/// Sent from Server to Client with a payload relayed from another Client
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelayedFromClientCommand {
+    pub source_connection_index: ConnectionIndex,
+    /// Opaque to the room server, at most [`MAX_PAYLOAD_OCTETS`]
+    pub payload: Vec<u8>,
+}
+
+impl RelayedFromClientCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "relayed_from_client";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1 + 2
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.source_connection_index.to_octets(stream)?;
+        in_field(
+            write_payload(stream, &self.payload, MAX_PAYLOAD_OCTETS),
+            "payload",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            source_connection_index: in_field(
+                ConnectionIndex::from_cursor(stream),
+                "source_connection_index",
+            )?,
+            payload: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "payload")?,
+        })
+    }
+}
+
+/// Sent from Client to Server, which fans out the `payload` to all other Clients in the room
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BroadcastPayloadCommand {
+    /// Opaque to the room server, at most [`MAX_BROADCAST_PAYLOAD_OCTETS`]
+    pub payload: Vec<u8>,
+}
+
+impl BroadcastPayloadCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "broadcast_payload";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(
+            write_payload(stream, &self.payload, MAX_BROADCAST_PAYLOAD_OCTETS),
+            "payload",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            payload: in_field(
+                read_payload(stream, MAX_BROADCAST_PAYLOAD_OCTETS),
+                "payload",
+            )?,
+        })
+    }
+}
+
+/// Sent from Server to every Client in the room with a payload broadcast by a Client
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BroadcastedPayloadCommand {
+    pub source_connection_index: ConnectionIndex,
+    /// Opaque to the room server, at most [`MAX_BROADCAST_PAYLOAD_OCTETS`]
+    pub payload: Vec<u8>,
+}
+
+impl BroadcastedPayloadCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "broadcasted_payload";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1 + 2
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.source_connection_index.to_octets(stream)?;
+        in_field(
+            write_payload(stream, &self.payload, MAX_BROADCAST_PAYLOAD_OCTETS),
+            "payload",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            source_connection_index: in_field(
+                ConnectionIndex::from_cursor(stream),
+                "source_connection_index",
+            )?,
+            payload: in_field(
+                read_payload(stream, MAX_BROADCAST_PAYLOAD_OCTETS),
+                "payload",
+            )?,
+        })
+    }
+}
+
+/// Sent from Client to Server to ask for admission to the room
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JoinRequestCommand {
+    pub identity: ClientIdentity,
+    /// The `ROLE_FLAG_*` roles the client asks for
+    pub requested_role_flags: u16,
+}
+
+impl JoinRequestCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "join_request";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        ClientIdentity::min_octet_size(version) + 2
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        self.identity.to_octets_with_version(stream, version)?;
+        stream.write_u16(self.requested_role_flags)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Ok(Self {
+            identity: in_field(
+                ClientIdentity::from_cursor_with_version(stream, version),
+                "identity",
+            )?,
+            requested_role_flags: in_field(stream.read_u16(), "requested_role_flags")?,
+        })
+    }
+}
+
+/// Sent from Server to Client when the `JoinRequestCommand` is accepted
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JoinAcceptedCommand {
+    /// The connection index assigned to the joining client
+    pub connection_index: ConnectionIndex,
+    pub room_info: RoomInfoCommand,
+}
+
+impl JoinAcceptedCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "join_accepted";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        1 + RoomInfoCommand::min_octet_size(version)
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        self.connection_index.to_octets(stream)?;
+        self.room_info.to_octets_with_version(stream, version)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Self::from_cursor_with_options(stream, &DecodeOptions::new(version))
+    }
+
+    /// The embedded room info is validated like a [`RoomInfoCommand`].
+    pub fn from_cursor_with_options(
+        stream: &mut impl ReadOctetStream,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        Ok(Self {
+            connection_index: in_field(ConnectionIndex::from_cursor(stream), "connection_index")?,
+            room_info: in_field(
+                RoomInfoCommand::from_cursor_with_options(stream, options),
+                "room_info",
+            )?,
+        })
+    }
+}
+
+pub const JOIN_DENIED_REASON_ROOM_FULL: u8 = 0x01;
+pub const JOIN_DENIED_REASON_BANNED: u8 = 0x02;
+pub const JOIN_DENIED_REASON_ROOM_CLOSED: u8 = 0x03;
+pub const JOIN_DENIED_REASON_ROLE_NOT_ALLOWED: u8 = 0x04;
+
+/// Sent from Server to Client when the `JoinRequestCommand` is denied
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JoinDeniedCommand {
+    /// One of the `JOIN_DENIED_REASON_*` constants, or an application specific code
+    pub reason_code: u8,
+}
+
+impl JoinDeniedCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "join_denied";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u8(self.reason_code)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            reason_code: in_field(stream.read_u8(), "reason_code")?,
+        })
+    }
+}
+
+/// Sent from a reconnecting Client to Server, asking for the state after the last applied
+/// term and knowledge
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResyncRequestCommand {
+    pub from_term: Term,
+    pub from_knowledge: Knowledge,
+}
+
+impl ResyncRequestCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "resync_request";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 8
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u16(self.from_term)?;
+        stream.write_u64(self.from_knowledge)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            from_term: in_field(stream.read_u16(), "from_term")?,
+            from_knowledge: in_field(stream.read_u64(), "from_knowledge")?,
+        })
+    }
+}
+
+/// Sent from Server to Client as one of `chunk_count` responses to a `ResyncRequestCommand`
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResyncDataCommand {
+    /// The term and knowledge the client has caught up to once all chunks are applied
+    pub term: Term,
+    pub knowledge: Knowledge,
+    pub chunk_index: u16,
+    pub chunk_count: u16,
+    /// At most [`MAX_PAYLOAD_OCTETS`]
+    pub data: Vec<u8>,
+}
+
+impl ResyncDataCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "resync_data";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 8 + 2 + 2 + 2
+    }
+
+    /// Splits `data` into commands carrying at most `max_chunk_octets` each. Empty `data` still
+    /// results in a single chunk, so the client learns the term and knowledge it is at.
+    pub fn chunks(
+        term: Term,
+        knowledge: Knowledge,
+        data: &[u8],
+        max_chunk_octets: usize,
+    ) -> Result<Vec<Self>> {
+        let parts = split_chunks(data, max_chunk_octets)?;
+        let chunk_count = parts.len() as u16;
+        Ok(parts
+            .into_iter()
+            .enumerate()
+            .map(|(chunk_index, part)| Self {
+                term,
+                knowledge,
+                chunk_index: chunk_index as u16,
+                chunk_count,
+                data: part.to_vec(),
+            })
+            .collect())
+    }
+
+    pub fn is_last_chunk(&self) -> bool {
+        self.chunk_index.checked_add(1) == Some(self.chunk_count)
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(check_known_term(self.term).map_err(Error::from), "term")?;
+        stream.write_u16(self.term)?;
+        stream.write_u64(self.knowledge)?;
+        stream.write_u16(self.chunk_index)?;
+        stream.write_u16(self.chunk_count)?;
+        in_field(
+            write_payload(stream, &self.data, MAX_PAYLOAD_OCTETS),
+            "data",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_options(stream, &DecodeOptions::default())
+    }
+
+    /// The knowledge must not be below the floor of the options
+    pub fn from_cursor_with_options(
+        stream: &mut impl ReadOctetStream,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let term = in_field(stream.read_u16(), "term")?;
+        in_field(check_known_term(term).map_err(Error::from), "term")?;
+        let knowledge = in_field(stream.read_u64(), "knowledge")?;
+        in_field(
+            check_knowledge_floor(knowledge, options.knowledge_floor).map_err(Error::from),
+            "knowledge",
+        )?;
+        let chunk_index = in_field(stream.read_u16(), "chunk_index")?;
+        let chunk_count = in_field(stream.read_u16(), "chunk_count")?;
+        in_field(check_chunk_index(chunk_index, chunk_count), "chunk_index")?;
+        Ok(Self {
+            term,
+            knowledge,
+            chunk_index,
+            chunk_count,
+            data: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "data")?,
+        })
+    }
+}
+
+/// Sent from Client to Server to ask for the application state, answered with
+/// `SnapshotChunkCommand`s
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotRequestCommand {
+    pub snapshot_id: SnapshotId,
+}
+
+impl SnapshotRequestCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "snapshot_request";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        4
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.snapshot_id)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            snapshot_id: in_field(stream.read_u32(), "snapshot_id")?,
+        })
+    }
+}
+
+/// Sent from Server to Client, one part of the snapshot. Use a [`SnapshotReassembler`] to
+/// collect the parts.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotChunkCommand {
+    pub snapshot_id: SnapshotId,
+    pub chunk_index: u16,
+    pub chunk_count: u16,
+    /// At most [`MAX_PAYLOAD_OCTETS`]
+    pub data: Vec<u8>,
+}
+
+impl SnapshotChunkCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "snapshot_chunk";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        4 + 2 + 2 + 2
+    }
+
+    /// Splits `data` into commands carrying at most `max_chunk_octets` each. Empty `data` still
+    /// results in a single chunk.
+    pub fn chunks(
+        snapshot_id: SnapshotId,
+        data: &[u8],
+        max_chunk_octets: usize,
+    ) -> Result<Vec<Self>> {
+        let parts = split_chunks(data, max_chunk_octets)?;
+        let chunk_count = parts.len() as u16;
+        Ok(parts
+            .into_iter()
+            .enumerate()
+            .map(|(chunk_index, part)| Self {
+                snapshot_id,
+                chunk_index: chunk_index as u16,
+                chunk_count,
+                data: part.to_vec(),
+            })
+            .collect())
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.snapshot_id)?;
+        stream.write_u16(self.chunk_index)?;
+        stream.write_u16(self.chunk_count)?;
+        in_field(
+            write_payload(stream, &self.data, MAX_PAYLOAD_OCTETS),
+            "data",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let snapshot_id = in_field(stream.read_u32(), "snapshot_id")?;
+        let chunk_index = in_field(stream.read_u16(), "chunk_index")?;
+        let chunk_count = in_field(stream.read_u16(), "chunk_count")?;
+        in_field(check_chunk_index(chunk_index, chunk_count), "chunk_index")?;
+        Ok(Self {
+            snapshot_id,
+            chunk_index,
+            chunk_count,
+            data: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "data")?,
+        })
+    }
+}
+
+/// Sent from Client to Server, so the server can adapt how often it sends `RoomInfoCommand`s
+/// to the client. All fields are varints.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BandwidthReportCommand {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// The duration the byte counts were measured over
+    pub window_ms: u32,
+}
+
+impl BandwidthReportCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "bandwidth_report";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1 + 1 + 1
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        write_varint(stream, self.bytes_sent)?;
+        write_varint(stream, self.bytes_received)?;
+        write_varint(stream, self.window_ms as u64)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let bytes_sent = in_field(read_varint(stream), "bytes_sent")?;
+        let bytes_received = in_field(read_varint(stream), "bytes_received")?;
+        let window_ms = in_field(
+            read_varint(stream).and_then(|value| {
+                u32::try_from(value).map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{} does not fit in u32", value),
+                    )
+                })
+            }),
+            "window_ms",
+        )?;
+        Ok(Self {
+            bytes_sent,
+            bytes_received,
+            window_ms,
+        })
+    }
+}
+
+pub const LATENCY_BUCKET_COUNT: usize = 8;
+
+/// Inclusive upper bound in milliseconds of the round trip times counted in each bucket. The last
+/// bucket counts everything above the previous bound.
+pub const LATENCY_BUCKET_UPPER_BOUNDS_MS: [u16; LATENCY_BUCKET_COUNT] =
+    [10, 20, 40, 80, 160, 320, 640, u16::MAX];
+
+/// Sent from Client to Server with a histogram of the observed round trip times, so the
+/// server can select a leader by the latency distribution and not only the last sample
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyReportCommand {
+    /// The number of samples in each of the [`LATENCY_BUCKET_UPPER_BOUNDS_MS`] buckets
+    pub buckets: [u16; LATENCY_BUCKET_COUNT],
+}
+
+impl LatencyReportCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "latency_report";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 * LATENCY_BUCKET_COUNT
+    }
+
+    /// Packs the round trip times in milliseconds into buckets
+    pub fn from_samples(rtts_ms: impl IntoIterator<Item = u32>) -> Self {
+        let mut report = Self::default();
+        for rtt_ms in rtts_ms {
+            report.record(rtt_ms);
+        }
+        report
+    }
+
+    pub fn bucket_index(rtt_ms: u32) -> usize {
+        LATENCY_BUCKET_UPPER_BOUNDS_MS
+            .iter()
+            .position(|upper_bound| rtt_ms <= *upper_bound as u32)
+            .unwrap_or(LATENCY_BUCKET_COUNT - 1)
+    }
+
+    /// Counts the sample, saturating the bucket at `u16::MAX`
+    pub fn record(&mut self, rtt_ms: u32) {
+        let bucket = &mut self.buckets[Self::bucket_index(rtt_ms)];
+        *bucket = bucket.saturating_add(1);
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.buckets.iter().map(|count| *count as u32).sum()
+    }
+
+    /// Unpacks the upper bound of the bucket holding the `permille` percentile, `None` if there
+    /// are no samples
+    pub fn percentile_upper_bound_ms(&self, permille: u16) -> Option<u16> {
+        let sample_count = self.sample_count();
+        if sample_count == 0 {
+            return None;
+        }
+        let target = (sample_count as u64 * permille.min(1000) as u64)
+            .div_ceil(1000)
+            .max(1);
+        let mut accumulated = 0u64;
+        for (count, upper_bound) in self.buckets.iter().zip(LATENCY_BUCKET_UPPER_BOUNDS_MS) {
+            accumulated += *count as u64;
+            if accumulated >= target {
+                return Some(upper_bound);
+            }
+        }
+        None
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        for count in self.buckets {
+            stream.write_u16(count)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let mut buckets = [0u16; LATENCY_BUCKET_COUNT];
+        for (index, count) in buckets.iter_mut().enumerate() {
+            *count = in_field(stream.read_u16(), format_args!("buckets[{}]", index))?;
+        }
+        Ok(Self { buckets })
+    }
+}
+
+/// Sent from Server to all Clients in the room ahead of a server restart, so they can save
+/// their state
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaintenanceNoticeCommand {
+    /// Seconds until the maintenance starts
+    pub starts_in_s: u32,
+    /// How long the server is expected to be unavailable, zero if unknown
+    pub expected_duration_s: u32,
+}
+
+impl MaintenanceNoticeCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "maintenance_notice";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        4 + 4
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.starts_in_s)?;
+        stream.write_u32(self.expected_duration_s)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            starts_in_s: in_field(stream.read_u32(), "starts_in_s")?,
+            expected_duration_s: in_field(stream.read_u32(), "expected_duration_s")?,
+        })
+    }
+}
+
+/// The longest host name, as limited by DNS
+pub const MAX_REDIRECT_HOST_OCTETS: usize = 253;
+
+/// Sent from Server to Client when the room is handed off to another server. The client
+/// connects to `host` and `port` and presents the `token`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RedirectCommand {
+    /// A host name or an IP address, at most [`MAX_REDIRECT_HOST_OCTETS`]
+    pub host: String,
+    pub port: u16,
+    /// Opaque to the client, at most [`MAX_PAYLOAD_OCTETS`]
+    pub token: Vec<u8>,
+}
+
+impl RedirectCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "redirect";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1 + 1 + 2 + 2
+    }
+
+    fn check_host_length(length: usize, kind: ErrorKind) -> Result<()> {
+        if length == 0 || length > MAX_REDIRECT_HOST_OCTETS {
+            return Err(Error::new(
+                kind,
+                format!(
+                    "host must be 1 to {} octets, was {}",
+                    MAX_REDIRECT_HOST_OCTETS, length
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(
+            Self::check_host_length(self.host.len(), ErrorKind::InvalidInput),
+            "host",
+        )?;
+        stream.write_u8(self.host.len() as u8)?;
+        stream.write(self.host.as_bytes())?;
+        stream.write_u16(self.port)?;
+        in_field(
+            write_payload(stream, &self.token, MAX_PAYLOAD_OCTETS),
+            "token",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let length = in_field(stream.read_u8(), "host.len")? as usize;
+        in_field(
+            Self::check_host_length(length, ErrorKind::InvalidData),
+            "host.len",
+        )?;
+        let mut host = vec![0u8; length];
+        in_field(stream.read(&mut host), "host")?;
+        let host = in_field(
+            String::from_utf8(host).map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+            "host",
+        )?;
+        Ok(Self {
+            host,
+            port: in_field(stream.read_u16(), "port")?,
+            token: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "token")?,
+        })
+    }
+}
+
+/// Sent from Server to Client, so matchmaking clients can prefer less loaded hosts. From
+/// [`PROTOCOL_VERSION_14`] it can also be part of the handshake response, see
+/// `ChallengeCommand::server_load`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerLoadCommand {
+    /// CPU usage of the server, where 1000 is fully loaded
+    pub cpu_permille: u16,
+    /// The number of rooms hosted by the server
+    pub rooms: u16,
+    /// How many more rooms the server is willing to host, saturated at `u8::MAX`
+    pub headroom: u8,
+}
+
+impl ServerLoadCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "server_load";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 2 + 1
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u16(self.cpu_permille)?;
+        stream.write_u16(self.rooms)?;
+        stream.write_u8(self.headroom)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            cpu_permille: in_field(stream.read_u16(), "cpu_permille")?,
+            rooms: in_field(stream.read_u16(), "rooms")?,
+            headroom: in_field(stream.read_u8(), "headroom")?,
+        })
+    }
+}
+
+impl PresenceField for ServerLoadCommand {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets(stream)
+    }
+
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor(stream)
+    }
+}
+
+/// Sent from Server to Client instead of handling a command, when the client sends faster
+/// than the server allows. Not an error: the client should resend after the delay.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThrottledCommand {
+    /// Wait at least this long before sending again, saturated at `u16::MAX`
+    pub retry_after_ms: u16,
+}
+
+impl ThrottledCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "throttled";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2
+    }
+
+    /// Saturates `retry_after` to the serialized range
+    pub fn new(retry_after: std::time::Duration) -> Self {
+        Self {
+            retry_after_ms: retry_after.as_millis().min(u16::MAX as u128) as u16,
+        }
+    }
+
+    pub fn retry_after(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.retry_after_ms as u64)
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u16(self.retry_after_ms)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            retry_after_ms: in_field(stream.read_u16(), "retry_after_ms")?,
+        })
+    }
+}
+
+/// Sent in both directions after connecting, so both sides use the same liveness parameters.
+/// Each side sends its preferences and uses the [`TimeoutConfigCommand::negotiate`]d result.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeoutConfigCommand {
+    /// Consider the peer gone after this long without receiving from it, zero if the sender
+    /// has no preference
+    pub disconnect_after_ms: u32,
+    /// How often to ping the peer, zero if the sender has no preference
+    pub ping_interval_ms: u16,
+}
+
+impl TimeoutConfigCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "timeout_config";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        4 + 2
+    }
+
+    /// Saturates both durations to the serialized range
+    pub fn new(disconnect_after: std::time::Duration, ping_interval: std::time::Duration) -> Self {
+        Self {
+            disconnect_after_ms: disconnect_after.as_millis().min(u32::MAX as u128) as u32,
+            ping_interval_ms: ping_interval.as_millis().min(u16::MAX as u128) as u16,
+        }
+    }
+
+    pub fn disconnect_after(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.disconnect_after_ms as u64)
+    }
+
+    pub fn ping_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.ping_interval_ms as u64)
+    }
+
+    /// The parameters both sides use: the longer disconnect timeout and the shorter ping
+    /// interval, so neither side gives up on or pings the other less than it asked for. A zero
+    /// field is the preference of the other side.
+    pub fn negotiate(&self, peer: &Self) -> Self {
+        let ping_interval_ms = match (self.ping_interval_ms, peer.ping_interval_ms) {
+            (0, other) | (other, 0) => other,
+            (own, other) => own.min(other),
+        };
+        Self {
+            disconnect_after_ms: self.disconnect_after_ms.max(peer.disconnect_after_ms),
+            ping_interval_ms,
+        }
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.disconnect_after_ms)?;
+        stream.write_u16(self.ping_interval_ms)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            disconnect_after_ms: in_field(stream.read_u32(), "disconnect_after_ms")?,
+            ping_interval_ms: in_field(stream.read_u16(), "ping_interval_ms")?,
+        })
+    }
+}
+
+/// Sent from the leader Client to the Server to change the room settings, and forwarded by the
+/// Server to all Clients in the room. Carries all settings, not only the changed ones, so
+/// every Client ends up with the settings of the highest version it received.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UpdateRoomSettingsCommand {
+    /// Increased by the leader for every change
+    pub settings_version: u32,
+    /// The settings, with tags chosen by the application
+    pub settings: Extensions,
+}
+
+impl UpdateRoomSettingsCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "update_room_settings";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        4 + 2
+    }
+
+    /// If the settings should replace those of `settings_version`. Older and repeated updates
+    /// are ignored, so they can not revert a newer change.
+    pub fn supersedes(&self, settings_version: u32) -> bool {
+        self.settings_version > settings_version
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.settings_version)?;
+        in_field(self.settings.to_octets(stream), "settings")
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            settings_version: in_field(stream.read_u32(), "settings_version")?,
+            settings: in_field(Extensions::from_cursor(stream), "settings")?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ServerReceiveCommand {
+    PingCommandType(PingCommand),
+    BanClientType(BanClientCommand),
+    UnbanClientType(UnbanClientCommand),
+    MuteStateType(MuteStateCommand),
+    RelayToClientType(RelayToClientCommand),
+    BroadcastPayloadType(BroadcastPayloadCommand),
+    JoinRequestType(JoinRequestCommand),
+    ResyncRequestType(ResyncRequestCommand),
+    SnapshotRequestType(SnapshotRequestCommand),
+    TransferChunkType(TransferChunkCommand),
+    TransferResendRequestType(TransferResendRequestCommand),
+    BandwidthReportType(BandwidthReportCommand),
+    LatencyReportType(LatencyReportCommand),
+    HelloType(HelloCommand),
+    PskAuthType(PskAuthCommand),
+    ConnectWithTokenType(ConnectWithTokenCommand),
+    ConnectWithCookieType(ConnectWithCookieCommand),
+    TimeoutConfigType(TimeoutConfigCommand),
+    UpdateRoomSettingsType(UpdateRoomSettingsCommand),
+    MembershipSyncRequestType(MembershipSyncRequestCommand),
+    SetPresenceType(SetPresenceCommand),
+    PreVoteRequestType(PreVoteRequestCommand),
+    ReplicateType(ReplicateCommand),
+    ReplicateAckType(ReplicateAckCommand),
+    /// A command type id this build does not know, only produced when decoding leniently
+    Unknown(RawCommand),
+}
+
+impl ServerReceiveCommand {
+    /// The first protocol version that can carry the command
+    pub fn min_version(&self) -> ProtocolVersion {
+        match self {
+            Self::PingCommandType(_) => PingCommand::MIN_VERSION,
+            Self::BanClientType(_) => BanClientCommand::MIN_VERSION,
+            Self::UnbanClientType(_) => UnbanClientCommand::MIN_VERSION,
+            Self::MuteStateType(_) => MuteStateCommand::MIN_VERSION,
+            Self::RelayToClientType(_) => RelayToClientCommand::MIN_VERSION,
+            Self::BroadcastPayloadType(_) => BroadcastPayloadCommand::MIN_VERSION,
+            Self::JoinRequestType(_) => JoinRequestCommand::MIN_VERSION,
+            Self::ResyncRequestType(_) => ResyncRequestCommand::MIN_VERSION,
+            Self::SnapshotRequestType(_) => SnapshotRequestCommand::MIN_VERSION,
+            Self::TransferChunkType(_) => TransferChunkCommand::MIN_VERSION,
+            Self::TransferResendRequestType(_) => TransferResendRequestCommand::MIN_VERSION,
+            Self::BandwidthReportType(_) => BandwidthReportCommand::MIN_VERSION,
+            Self::LatencyReportType(_) => LatencyReportCommand::MIN_VERSION,
+            Self::HelloType(_) => HelloCommand::MIN_VERSION,
+            Self::PskAuthType(_) => PskAuthCommand::MIN_VERSION,
+            Self::ConnectWithTokenType(_) => ConnectWithTokenCommand::MIN_VERSION,
+            Self::ConnectWithCookieType(_) => ConnectWithCookieCommand::MIN_VERSION,
+            Self::TimeoutConfigType(_) => TimeoutConfigCommand::MIN_VERSION,
+            Self::UpdateRoomSettingsType(_) => UpdateRoomSettingsCommand::MIN_VERSION,
+            Self::MembershipSyncRequestType(_) => MembershipSyncRequestCommand::MIN_VERSION,
+            Self::SetPresenceType(_) => SetPresenceCommand::MIN_VERSION,
+            Self::PreVoteRequestType(_) => PreVoteRequestCommand::MIN_VERSION,
+            Self::ReplicateType(_) => ReplicateCommand::MIN_VERSION,
+            Self::ReplicateAckType(_) => ReplicateAckCommand::MIN_VERSION,
+            Self::Unknown(_) => PROTOCOL_VERSION_1,
+        }
+    }
+
+    /// The smallest serialized payload of the command type, `None` for unknown type ids
+    pub fn min_octet_size(command_type_id: u8, version: ProtocolVersion) -> Option<usize> {
+        match command_type_id {
+            PING_COMMAND_TYPE_ID => Some(PingCommand::min_octet_size(version)),
+            BAN_CLIENT_COMMAND_TYPE_ID => Some(BanClientCommand::min_octet_size(version)),
+            UNBAN_CLIENT_COMMAND_TYPE_ID => Some(UnbanClientCommand::min_octet_size(version)),
+            MUTE_STATE_COMMAND_TYPE_ID => Some(MuteStateCommand::min_octet_size(version)),
+            RELAY_TO_CLIENT_COMMAND_TYPE_ID => Some(RelayToClientCommand::min_octet_size(version)),
+            BROADCAST_PAYLOAD_COMMAND_TYPE_ID => {
+                Some(BroadcastPayloadCommand::min_octet_size(version))
+            }
+            JOIN_REQUEST_COMMAND_TYPE_ID => Some(JoinRequestCommand::min_octet_size(version)),
+            RESYNC_REQUEST_COMMAND_TYPE_ID => Some(ResyncRequestCommand::min_octet_size(version)),
+            SNAPSHOT_REQUEST_COMMAND_TYPE_ID => {
+                Some(SnapshotRequestCommand::min_octet_size(version))
+            }
+            TRANSFER_CHUNK_COMMAND_TYPE_ID => Some(TransferChunkCommand::min_octet_size(version)),
+            TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID => {
+                Some(TransferResendRequestCommand::min_octet_size(version))
+            }
+            BANDWIDTH_REPORT_COMMAND_TYPE_ID => {
+                Some(BandwidthReportCommand::min_octet_size(version))
+            }
+            LATENCY_REPORT_COMMAND_TYPE_ID => Some(LatencyReportCommand::min_octet_size(version)),
+            HELLO_COMMAND_TYPE_ID => Some(HelloCommand::min_octet_size(version)),
+            PSK_AUTH_COMMAND_TYPE_ID => Some(PskAuthCommand::min_octet_size(version)),
+            CONNECT_WITH_TOKEN_COMMAND_TYPE_ID => {
+                Some(ConnectWithTokenCommand::min_octet_size(version))
+            }
+            CONNECT_WITH_COOKIE_COMMAND_TYPE_ID => {
+                Some(ConnectWithCookieCommand::min_octet_size(version))
+            }
+            TIMEOUT_CONFIG_COMMAND_TYPE_ID => Some(TimeoutConfigCommand::min_octet_size(version)),
+            UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID => {
+                Some(UpdateRoomSettingsCommand::min_octet_size(version))
+            }
+            MEMBERSHIP_SYNC_REQUEST_COMMAND_TYPE_ID => {
+                Some(MembershipSyncRequestCommand::min_octet_size(version))
+            }
+            SET_PRESENCE_COMMAND_TYPE_ID => Some(SetPresenceCommand::min_octet_size(version)),
+            PRE_VOTE_REQUEST_COMMAND_TYPE_ID => {
+                Some(PreVoteRequestCommand::min_octet_size(version))
+            }
+            REPLICATE_COMMAND_TYPE_ID => Some(ReplicateCommand::min_octet_size(version)),
+            REPLICATE_ACK_COMMAND_TYPE_ID => Some(ReplicateAckCommand::min_octet_size(version)),
+            _ => None,
+        }
+    }
+
+    /// The command type id this command is serialized with
+    pub fn command_type_id(&self) -> u8 {
+        match self {
+            Self::PingCommandType(_) => PING_COMMAND_TYPE_ID,
+            Self::BanClientType(_) => BAN_CLIENT_COMMAND_TYPE_ID,
+            Self::UnbanClientType(_) => UNBAN_CLIENT_COMMAND_TYPE_ID,
+            Self::MuteStateType(_) => MUTE_STATE_COMMAND_TYPE_ID,
+            Self::RelayToClientType(_) => RELAY_TO_CLIENT_COMMAND_TYPE_ID,
+            Self::BroadcastPayloadType(_) => BROADCAST_PAYLOAD_COMMAND_TYPE_ID,
+            Self::JoinRequestType(_) => JOIN_REQUEST_COMMAND_TYPE_ID,
+            Self::ResyncRequestType(_) => RESYNC_REQUEST_COMMAND_TYPE_ID,
+            Self::SnapshotRequestType(_) => SNAPSHOT_REQUEST_COMMAND_TYPE_ID,
+            Self::TransferChunkType(_) => TRANSFER_CHUNK_COMMAND_TYPE_ID,
+            Self::TransferResendRequestType(_) => TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID,
+            Self::BandwidthReportType(_) => BANDWIDTH_REPORT_COMMAND_TYPE_ID,
+            Self::LatencyReportType(_) => LATENCY_REPORT_COMMAND_TYPE_ID,
+            Self::HelloType(_) => HELLO_COMMAND_TYPE_ID,
+            Self::PskAuthType(_) => PSK_AUTH_COMMAND_TYPE_ID,
+            Self::ConnectWithTokenType(_) => CONNECT_WITH_TOKEN_COMMAND_TYPE_ID,
+            Self::ConnectWithCookieType(_) => CONNECT_WITH_COOKIE_COMMAND_TYPE_ID,
+            Self::TimeoutConfigType(_) => TIMEOUT_CONFIG_COMMAND_TYPE_ID,
+            Self::UpdateRoomSettingsType(_) => UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID,
+            Self::MembershipSyncRequestType(_) => MEMBERSHIP_SYNC_REQUEST_COMMAND_TYPE_ID,
+            Self::SetPresenceType(_) => SET_PRESENCE_COMMAND_TYPE_ID,
+            Self::PreVoteRequestType(_) => PRE_VOTE_REQUEST_COMMAND_TYPE_ID,
+            Self::ReplicateType(_) => REPLICATE_COMMAND_TYPE_ID,
+            Self::ReplicateAckType(_) => REPLICATE_ACK_COMMAND_TYPE_ID,
+            Self::Unknown(raw_command) => raw_command.type_id,
+            // _ => return Err(format!("unsupported command {:?}", self)),
+        }
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        let command_type_id = self.command_type_id();
+
+        let min_version = self.min_version();
+        if version < min_version {
+            return Err(ProtocolError::UnsupportedCommand {
+                command_type_id,
+                min_version,
+                version,
+            }
+            .into());
+        }
+
+        stream.write_u8(command_type_id)?;
+
+        match self {
+            Self::PingCommandType(ping_command) => {
+                ping_command.to_octets_with_version(stream, version)?;
+            }
+            Self::BanClientType(ban_client_command) => {
+                ban_client_command.to_octets_with_version(stream, version)?;
+            }
+            Self::UnbanClientType(unban_client_command) => {
+                unban_client_command.to_octets_with_version(stream, version)?;
+            }
+            Self::MuteStateType(mute_state_command) => {
+                mute_state_command.to_octets(stream)?;
+            }
+            Self::RelayToClientType(relay_to_client_command) => {
+                relay_to_client_command.to_octets(stream)?;
+            }
+            Self::BroadcastPayloadType(broadcast_payload_command) => {
+                broadcast_payload_command.to_octets(stream)?;
+            }
+            Self::JoinRequestType(join_request_command) => {
+                join_request_command.to_octets_with_version(stream, version)?;
+            }
+            Self::ResyncRequestType(resync_request_command) => {
+                resync_request_command.to_octets(stream)?;
+            }
+            Self::SnapshotRequestType(snapshot_request_command) => {
+                snapshot_request_command.to_octets(stream)?;
+            }
+            Self::TransferChunkType(transfer_chunk_command) => {
+                transfer_chunk_command.to_octets(stream)?;
+            }
+            Self::TransferResendRequestType(transfer_resend_request_command) => {
+                transfer_resend_request_command.to_octets(stream)?;
+            }
+            Self::BandwidthReportType(bandwidth_report_command) => {
+                bandwidth_report_command.to_octets(stream)?;
+            }
+            Self::LatencyReportType(latency_report_command) => {
+                latency_report_command.to_octets(stream)?;
+            }
+            Self::HelloType(hello_command) => {
+                hello_command.to_octets(stream)?;
+            }
+            Self::PskAuthType(psk_auth_command) => {
+                psk_auth_command.to_octets(stream)?;
+            }
+            Self::ConnectWithTokenType(connect_with_token_command) => {
+                connect_with_token_command.to_octets(stream)?;
+            }
+            Self::ConnectWithCookieType(connect_with_cookie_command) => {
+                connect_with_cookie_command.to_octets_with_version(stream, version)?;
+            }
+            Self::TimeoutConfigType(timeout_config_command) => {
+                timeout_config_command.to_octets(stream)?;
+            }
+            Self::UpdateRoomSettingsType(update_room_settings_command) => {
+                update_room_settings_command.to_octets(stream)?;
+            }
+            Self::MembershipSyncRequestType(membership_sync_request_command) => {
+                membership_sync_request_command.to_octets(stream)?;
+            }
+            Self::SetPresenceType(set_presence_command) => {
+                set_presence_command.to_octets(stream)?;
+            }
+            Self::PreVoteRequestType(pre_vote_request_command) => {
+                pre_vote_request_command.to_octets(stream)?;
+            }
+            Self::ReplicateType(replicate_command) => {
+                replicate_command.to_octets(stream)?;
+            }
+            Self::ReplicateAckType(replicate_ack_command) => {
+                replicate_ack_command.to_octets(stream)?;
+            }
+            Self::Unknown(raw_command) => {
+                stream.write(&raw_command.payload)?;
+            } // _ => return Err(format!("unknown command enum {:?}", self)),
+        }
+
+        Ok(())
+    }
+
+    pub fn from_cursor<T: ReadOctetStream>(stream: &mut T) -> Result<ServerReceiveCommand> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version<T: ReadOctetStream>(
+        stream: &mut T,
+        version: ProtocolVersion,
+    ) -> Result<ServerReceiveCommand> {
+        Self::from_cursor_with_options(stream, &DecodeOptions::new(version))
+    }
+
+    pub fn from_cursor_with_options<T: ReadOctetStream>(
+        stream: &mut T,
+        options: &DecodeOptions,
+    ) -> Result<ServerReceiveCommand> {
+        read_datagram_magic(stream, options)?;
+        let command_type_id = in_field(stream.read_u8(), "command_type_id")?;
+        Self::payload_from_cursor(command_type_id, stream, options)
+    }
+
+    /// Decodes what follows the command type id
+    pub(crate) fn payload_from_cursor<T: ReadOctetStream>(
+        command_type_id: u8,
+        stream: &mut T,
+        options: &DecodeOptions,
+    ) -> Result<ServerReceiveCommand> {
+        in_field(
+            check_sender(command_type_id, options.sender),
+            "command_type_id",
+        )?;
+        let version = options.version;
+        match command_type_id {
+            PING_COMMAND_TYPE_ID => Ok(Self::PingCommandType(in_field(
+                PingCommand::from_cursor_with_options(stream, options),
+                PingCommand::NAME,
+            )?)),
+            BAN_CLIENT_COMMAND_TYPE_ID => Ok(Self::BanClientType(in_field(
+                BanClientCommand::from_cursor_with_version(stream, version),
+                BanClientCommand::NAME,
+            )?)),
+            UNBAN_CLIENT_COMMAND_TYPE_ID => Ok(Self::UnbanClientType(in_field(
+                UnbanClientCommand::from_cursor_with_version(stream, version),
+                UnbanClientCommand::NAME,
+            )?)),
+            MUTE_STATE_COMMAND_TYPE_ID => Ok(Self::MuteStateType(in_field(
+                MuteStateCommand::from_cursor(stream),
+                MuteStateCommand::NAME,
+            )?)),
+            RELAY_TO_CLIENT_COMMAND_TYPE_ID => Ok(Self::RelayToClientType(in_field(
+                RelayToClientCommand::from_cursor(stream),
+                RelayToClientCommand::NAME,
+            )?)),
+            BROADCAST_PAYLOAD_COMMAND_TYPE_ID => Ok(Self::BroadcastPayloadType(in_field(
+                BroadcastPayloadCommand::from_cursor(stream),
+                BroadcastPayloadCommand::NAME,
+            )?)),
+            JOIN_REQUEST_COMMAND_TYPE_ID => Ok(Self::JoinRequestType(in_field(
+                JoinRequestCommand::from_cursor_with_version(stream, version),
+                JoinRequestCommand::NAME,
+            )?)),
+            RESYNC_REQUEST_COMMAND_TYPE_ID => Ok(Self::ResyncRequestType(in_field(
+                ResyncRequestCommand::from_cursor(stream),
+                ResyncRequestCommand::NAME,
+            )?)),
+            SNAPSHOT_REQUEST_COMMAND_TYPE_ID => Ok(Self::SnapshotRequestType(in_field(
+                SnapshotRequestCommand::from_cursor(stream),
+                SnapshotRequestCommand::NAME,
+            )?)),
+            TRANSFER_CHUNK_COMMAND_TYPE_ID => Ok(Self::TransferChunkType(in_field(
+                TransferChunkCommand::from_cursor(stream),
+                TransferChunkCommand::NAME,
+            )?)),
+            TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID => {
+                Ok(Self::TransferResendRequestType(in_field(
+                    TransferResendRequestCommand::from_cursor(stream),
+                    TransferResendRequestCommand::NAME,
+                )?))
+            }
+            BANDWIDTH_REPORT_COMMAND_TYPE_ID => Ok(Self::BandwidthReportType(in_field(
+                BandwidthReportCommand::from_cursor(stream),
+                BandwidthReportCommand::NAME,
+            )?)),
+            LATENCY_REPORT_COMMAND_TYPE_ID => Ok(Self::LatencyReportType(in_field(
+                LatencyReportCommand::from_cursor(stream),
+                LatencyReportCommand::NAME,
+            )?)),
+            HELLO_COMMAND_TYPE_ID => Ok(Self::HelloType(in_field(
+                HelloCommand::from_cursor(stream),
+                HelloCommand::NAME,
+            )?)),
+            PSK_AUTH_COMMAND_TYPE_ID => Ok(Self::PskAuthType(in_field(
+                PskAuthCommand::from_cursor(stream),
+                PskAuthCommand::NAME,
+            )?)),
+            CONNECT_WITH_TOKEN_COMMAND_TYPE_ID => Ok(Self::ConnectWithTokenType(in_field(
+                ConnectWithTokenCommand::from_cursor(stream),
+                ConnectWithTokenCommand::NAME,
+            )?)),
+            CONNECT_WITH_COOKIE_COMMAND_TYPE_ID => Ok(Self::ConnectWithCookieType(in_field(
+                ConnectWithCookieCommand::from_cursor_with_version(stream, version),
+                ConnectWithCookieCommand::NAME,
+            )?)),
+            TIMEOUT_CONFIG_COMMAND_TYPE_ID => Ok(Self::TimeoutConfigType(in_field(
+                TimeoutConfigCommand::from_cursor(stream),
+                TimeoutConfigCommand::NAME,
+            )?)),
+            UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID => Ok(Self::UpdateRoomSettingsType(in_field(
+                UpdateRoomSettingsCommand::from_cursor(stream),
+                UpdateRoomSettingsCommand::NAME,
+            )?)),
+            MEMBERSHIP_SYNC_REQUEST_COMMAND_TYPE_ID => {
+                Ok(Self::MembershipSyncRequestType(in_field(
+                    MembershipSyncRequestCommand::from_cursor(stream),
+                    MembershipSyncRequestCommand::NAME,
+                )?))
+            }
+            SET_PRESENCE_COMMAND_TYPE_ID => Ok(Self::SetPresenceType(in_field(
+                SetPresenceCommand::from_cursor(stream),
+                SetPresenceCommand::NAME,
+            )?)),
+            PRE_VOTE_REQUEST_COMMAND_TYPE_ID => Ok(Self::PreVoteRequestType(in_field(
+                PreVoteRequestCommand::from_cursor(stream),
+                PreVoteRequestCommand::NAME,
+            )?)),
+            REPLICATE_COMMAND_TYPE_ID => Ok(Self::ReplicateType(in_field(
+                ReplicateCommand::from_cursor(stream),
+                ReplicateCommand::NAME,
+            )?)),
+            REPLICATE_ACK_COMMAND_TYPE_ID => Ok(Self::ReplicateAckType(in_field(
+                ReplicateAckCommand::from_cursor(stream),
+                ReplicateAckCommand::NAME,
+            )?)),
+            _ if options.lenient => Ok(Self::Unknown(RawCommand {
+                type_id: command_type_id,
+                payload: in_field(read_to_end(stream), "payload")?,
+            })),
+            _ => in_field(
+                Err(Error::other(format!(
+                    "unknown command 0x{:x}",
+                    command_type_id
+                ))),
+                "command_type_id",
+            ),
+        }
+    }
+}
+
+pub const PING_COMMAND_TYPE_ID: u8 = 0x01;
+pub const ROOM_INFO_COMMAND_TYPE_ID: u8 = 0x02;
+pub const ROOM_CLOSED_COMMAND_TYPE_ID: u8 = 0x03;
+pub const MUTE_STATE_COMMAND_TYPE_ID: u8 = 0x04;
+pub const PING_RESPONSE_COMMAND_TYPE_ID: u8 = 0x05;
+pub const RELAY_TO_CLIENT_COMMAND_TYPE_ID: u8 = 0x06;
+pub const RELAYED_FROM_CLIENT_COMMAND_TYPE_ID: u8 = 0x07;
+pub const BROADCAST_PAYLOAD_COMMAND_TYPE_ID: u8 = 0x08;
+pub const BROADCASTED_PAYLOAD_COMMAND_TYPE_ID: u8 = 0x09;
+pub const JOIN_REQUEST_COMMAND_TYPE_ID: u8 = 0x0A;
+pub const JOIN_ACCEPTED_COMMAND_TYPE_ID: u8 = 0x0B;
+pub const JOIN_DENIED_COMMAND_TYPE_ID: u8 = 0x0C;
+pub const RESYNC_REQUEST_COMMAND_TYPE_ID: u8 = 0x0D;
+pub const RESYNC_DATA_COMMAND_TYPE_ID: u8 = 0x0E;
+pub const SNAPSHOT_REQUEST_COMMAND_TYPE_ID: u8 = 0x0F;
+pub const SNAPSHOT_CHUNK_COMMAND_TYPE_ID: u8 = 0x10;
+pub const TRANSFER_CHUNK_COMMAND_TYPE_ID: u8 = 0x11;
+pub const TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID: u8 = 0x12;
+pub const BANDWIDTH_REPORT_COMMAND_TYPE_ID: u8 = 0x13;
+pub const LATENCY_REPORT_COMMAND_TYPE_ID: u8 = 0x14;
+pub const MAINTENANCE_NOTICE_COMMAND_TYPE_ID: u8 = 0x15;
+pub const REDIRECT_COMMAND_TYPE_ID: u8 = 0x16;
+pub const SERVER_LOAD_COMMAND_TYPE_ID: u8 = 0x17;
+pub const THROTTLED_COMMAND_TYPE_ID: u8 = 0x1D;
+pub const TIMEOUT_CONFIG_COMMAND_TYPE_ID: u8 = 0x1E;
+pub const UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID: u8 = 0x1F;
+
+/// Type ids reserved for administrative commands
+pub const ADMIN_COMMAND_TYPE_ID_RANGE: std::ops::RangeInclusive<u8> = 0x40..=0x4F;
+pub const BAN_CLIENT_COMMAND_TYPE_ID: u8 = 0x40;
+pub const UNBAN_CLIENT_COMMAND_TYPE_ID: u8 = 0x41;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ClientReceiveCommand {
+    RoomInfoType(RoomInfoCommand),
+    RoomClosedType(RoomClosedCommand),
+    MuteStateType(MuteStateCommand),
+    PingResponseType(PingResponseCommand),
+    RelayedFromClientType(RelayedFromClientCommand),
+    BroadcastedPayloadType(BroadcastedPayloadCommand),
+    JoinAcceptedType(JoinAcceptedCommand),
+    JoinDeniedType(JoinDeniedCommand),
+    ResyncDataType(ResyncDataCommand),
+    SnapshotChunkType(SnapshotChunkCommand),
+    TransferChunkType(TransferChunkCommand),
+    TransferResendRequestType(TransferResendRequestCommand),
+    MaintenanceNoticeType(MaintenanceNoticeCommand),
+    RedirectType(RedirectCommand),
+    ServerLoadType(ServerLoadCommand),
+    HelloType(HelloCommand),
+    ChallengeType(ChallengeCommand),
+    RetryCookieType(RetryCookieCommand),
+    ThrottledType(ThrottledCommand),
+    TimeoutConfigType(TimeoutConfigCommand),
+    UpdateRoomSettingsType(UpdateRoomSettingsCommand),
+    ChannelProfileType(ChannelProfileCommand),
+    RoomInfoPageType(RoomInfoPageCommand),
+    MembershipSyncResponseType(MembershipSyncResponseCommand),
+    KnowledgeAckType(KnowledgeAckCommand),
+    TermAdvancedType(TermAdvancedCommand),
+    PreVoteResponseType(PreVoteResponseCommand),
+    ReplicateType(ReplicateCommand),
+    ReplicateAckType(ReplicateAckCommand),
+    /// A command type id this build does not know, only produced when decoding leniently
+    Unknown(RawCommand),
+}
+
+impl ClientReceiveCommand {
+    /// The first protocol version that can carry the command
+    pub fn min_version(&self) -> ProtocolVersion {
+        match self {
+            Self::RoomInfoType(_) => RoomInfoCommand::MIN_VERSION,
+            Self::RoomClosedType(_) => RoomClosedCommand::MIN_VERSION,
+            Self::MuteStateType(_) => MuteStateCommand::MIN_VERSION,
+            Self::PingResponseType(_) => PingResponseCommand::MIN_VERSION,
+            Self::RelayedFromClientType(_) => RelayedFromClientCommand::MIN_VERSION,
+            Self::BroadcastedPayloadType(_) => BroadcastedPayloadCommand::MIN_VERSION,
+            Self::JoinAcceptedType(_) => JoinAcceptedCommand::MIN_VERSION,
+            Self::JoinDeniedType(_) => JoinDeniedCommand::MIN_VERSION,
+            Self::ResyncDataType(_) => ResyncDataCommand::MIN_VERSION,
+            Self::SnapshotChunkType(_) => SnapshotChunkCommand::MIN_VERSION,
+            Self::TransferChunkType(_) => TransferChunkCommand::MIN_VERSION,
+            Self::TransferResendRequestType(_) => TransferResendRequestCommand::MIN_VERSION,
+            Self::MaintenanceNoticeType(_) => MaintenanceNoticeCommand::MIN_VERSION,
+            Self::RedirectType(_) => RedirectCommand::MIN_VERSION,
+            Self::ServerLoadType(_) => ServerLoadCommand::MIN_VERSION,
+            Self::HelloType(_) => HelloCommand::MIN_VERSION,
+            Self::ChallengeType(_) => ChallengeCommand::MIN_VERSION,
+            Self::RetryCookieType(_) => RetryCookieCommand::MIN_VERSION,
+            Self::ThrottledType(_) => ThrottledCommand::MIN_VERSION,
+            Self::TimeoutConfigType(_) => TimeoutConfigCommand::MIN_VERSION,
+            Self::UpdateRoomSettingsType(_) => UpdateRoomSettingsCommand::MIN_VERSION,
+            Self::ChannelProfileType(_) => ChannelProfileCommand::MIN_VERSION,
+            Self::RoomInfoPageType(_) => RoomInfoPageCommand::MIN_VERSION,
+            Self::MembershipSyncResponseType(_) => MembershipSyncResponseCommand::MIN_VERSION,
+            Self::KnowledgeAckType(_) => KnowledgeAckCommand::MIN_VERSION,
+            Self::TermAdvancedType(_) => TermAdvancedCommand::MIN_VERSION,
+            Self::PreVoteResponseType(_) => PreVoteResponseCommand::MIN_VERSION,
+            Self::ReplicateType(_) => ReplicateCommand::MIN_VERSION,
+            Self::ReplicateAckType(_) => ReplicateAckCommand::MIN_VERSION,
+            Self::Unknown(_) => PROTOCOL_VERSION_1,
+        }
+    }
+
+    /// Checks the room info the command carries, if any, with [`RoomInfoCommand::validate`].
+    /// For commands that were not decoded from the binary format, which checks it already.
+    pub fn validate(&self) -> std::result::Result<(), ProtocolError> {
+        match self {
+            Self::RoomInfoType(room_info) => room_info.validate(),
+            Self::JoinAcceptedType(join_accepted) => join_accepted.room_info.validate(),
+            _ => Ok(()),
+        }
+    }
+
+    /// The smallest serialized payload of the command type, `None` for unknown type ids
+    pub fn min_octet_size(command_type_id: u8, version: ProtocolVersion) -> Option<usize> {
+        match command_type_id {
+            ROOM_INFO_COMMAND_TYPE_ID => Some(RoomInfoCommand::min_octet_size(version)),
+            ROOM_CLOSED_COMMAND_TYPE_ID => Some(RoomClosedCommand::min_octet_size(version)),
+            MUTE_STATE_COMMAND_TYPE_ID => Some(MuteStateCommand::min_octet_size(version)),
+            PING_RESPONSE_COMMAND_TYPE_ID => Some(PingResponseCommand::min_octet_size(version)),
+            RELAYED_FROM_CLIENT_COMMAND_TYPE_ID => {
+                Some(RelayedFromClientCommand::min_octet_size(version))
+            }
+            BROADCASTED_PAYLOAD_COMMAND_TYPE_ID => {
+                Some(BroadcastedPayloadCommand::min_octet_size(version))
+            }
+            JOIN_ACCEPTED_COMMAND_TYPE_ID => Some(JoinAcceptedCommand::min_octet_size(version)),
+            JOIN_DENIED_COMMAND_TYPE_ID => Some(JoinDeniedCommand::min_octet_size(version)),
+            RESYNC_DATA_COMMAND_TYPE_ID => Some(ResyncDataCommand::min_octet_size(version)),
+            SNAPSHOT_CHUNK_COMMAND_TYPE_ID => Some(SnapshotChunkCommand::min_octet_size(version)),
+            TRANSFER_CHUNK_COMMAND_TYPE_ID => Some(TransferChunkCommand::min_octet_size(version)),
+            TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID => {
+                Some(TransferResendRequestCommand::min_octet_size(version))
+            }
+            MAINTENANCE_NOTICE_COMMAND_TYPE_ID => {
+                Some(MaintenanceNoticeCommand::min_octet_size(version))
+            }
+            REDIRECT_COMMAND_TYPE_ID => Some(RedirectCommand::min_octet_size(version)),
+            SERVER_LOAD_COMMAND_TYPE_ID => Some(ServerLoadCommand::min_octet_size(version)),
+            HELLO_COMMAND_TYPE_ID => Some(HelloCommand::min_octet_size(version)),
+            CHALLENGE_COMMAND_TYPE_ID => Some(ChallengeCommand::min_octet_size(version)),
+            RETRY_COOKIE_COMMAND_TYPE_ID => Some(RetryCookieCommand::min_octet_size(version)),
+            THROTTLED_COMMAND_TYPE_ID => Some(ThrottledCommand::min_octet_size(version)),
+            TIMEOUT_CONFIG_COMMAND_TYPE_ID => Some(TimeoutConfigCommand::min_octet_size(version)),
+            UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID => {
+                Some(UpdateRoomSettingsCommand::min_octet_size(version))
+            }
+            CHANNEL_PROFILE_COMMAND_TYPE_ID => Some(ChannelProfileCommand::min_octet_size(version)),
+            ROOM_INFO_PAGE_COMMAND_TYPE_ID => Some(RoomInfoPageCommand::min_octet_size(version)),
+            MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID => {
+                Some(MembershipSyncResponseCommand::min_octet_size(version))
+            }
+            KNOWLEDGE_ACK_COMMAND_TYPE_ID => Some(KnowledgeAckCommand::min_octet_size(version)),
+            TERM_ADVANCED_COMMAND_TYPE_ID => Some(TermAdvancedCommand::min_octet_size(version)),
+            PRE_VOTE_RESPONSE_COMMAND_TYPE_ID => {
+                Some(PreVoteResponseCommand::min_octet_size(version))
+            }
+            REPLICATE_COMMAND_TYPE_ID => Some(ReplicateCommand::min_octet_size(version)),
+            REPLICATE_ACK_COMMAND_TYPE_ID => Some(ReplicateAckCommand::min_octet_size(version)),
+            _ => None,
+        }
+    }
+
+    /// The command type id this command is serialized with
+    pub fn command_type_id(&self) -> u8 {
+        match self {
+            Self::RoomInfoType(_) => ROOM_INFO_COMMAND_TYPE_ID,
+            Self::RoomClosedType(_) => ROOM_CLOSED_COMMAND_TYPE_ID,
+            Self::MuteStateType(_) => MUTE_STATE_COMMAND_TYPE_ID,
+            Self::PingResponseType(_) => PING_RESPONSE_COMMAND_TYPE_ID,
+            Self::RelayedFromClientType(_) => RELAYED_FROM_CLIENT_COMMAND_TYPE_ID,
+            Self::BroadcastedPayloadType(_) => BROADCASTED_PAYLOAD_COMMAND_TYPE_ID,
+            Self::JoinAcceptedType(_) => JOIN_ACCEPTED_COMMAND_TYPE_ID,
+            Self::JoinDeniedType(_) => JOIN_DENIED_COMMAND_TYPE_ID,
+            Self::ResyncDataType(_) => RESYNC_DATA_COMMAND_TYPE_ID,
+            Self::SnapshotChunkType(_) => SNAPSHOT_CHUNK_COMMAND_TYPE_ID,
+            Self::TransferChunkType(_) => TRANSFER_CHUNK_COMMAND_TYPE_ID,
+            Self::TransferResendRequestType(_) => TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID,
+            Self::MaintenanceNoticeType(_) => MAINTENANCE_NOTICE_COMMAND_TYPE_ID,
+            Self::RedirectType(_) => REDIRECT_COMMAND_TYPE_ID,
+            Self::ServerLoadType(_) => SERVER_LOAD_COMMAND_TYPE_ID,
+            Self::HelloType(_) => HELLO_COMMAND_TYPE_ID,
+            Self::ChallengeType(_) => CHALLENGE_COMMAND_TYPE_ID,
+            Self::RetryCookieType(_) => RETRY_COOKIE_COMMAND_TYPE_ID,
+            Self::ThrottledType(_) => THROTTLED_COMMAND_TYPE_ID,
+            Self::TimeoutConfigType(_) => TIMEOUT_CONFIG_COMMAND_TYPE_ID,
+            Self::UpdateRoomSettingsType(_) => UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID,
+            Self::ChannelProfileType(_) => CHANNEL_PROFILE_COMMAND_TYPE_ID,
+            Self::RoomInfoPageType(_) => ROOM_INFO_PAGE_COMMAND_TYPE_ID,
+            Self::MembershipSyncResponseType(_) => MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID,
+            Self::KnowledgeAckType(_) => KNOWLEDGE_ACK_COMMAND_TYPE_ID,
+            Self::TermAdvancedType(_) => TERM_ADVANCED_COMMAND_TYPE_ID,
+            Self::PreVoteResponseType(_) => PRE_VOTE_RESPONSE_COMMAND_TYPE_ID,
+            Self::ReplicateType(_) => REPLICATE_COMMAND_TYPE_ID,
+            Self::ReplicateAckType(_) => REPLICATE_ACK_COMMAND_TYPE_ID,
+            Self::Unknown(raw_command) => raw_command.type_id,
+            // _ => return Err(format!("unsupported command {:?}", self)),
+        }
+    }
+
+    pub fn to_octets<T: WriteOctetStream>(&self, stream: &mut T) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version<T: WriteOctetStream>(
+        &self,
+        stream: &mut T,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        let command_type_id = self.command_type_id();
+
+        let min_version = self.min_version();
+        if version < min_version {
+            return Err(ProtocolError::UnsupportedCommand {
+                command_type_id,
+                min_version,
+                version,
+            }
+            .into());
+        }
+
+        stream.write_u8(command_type_id)?;
+
+        match self {
+            Self::RoomInfoType(room_info_command) => {
+                room_info_command.to_octets_with_version(stream, version)?;
+            }
+            Self::RoomClosedType(room_closed_command) => {
+                room_closed_command.to_octets(stream)?;
+            }
+            Self::MuteStateType(mute_state_command) => {
+                mute_state_command.to_octets(stream)?;
+            }
+            Self::PingResponseType(ping_response_command) => {
+                ping_response_command.to_octets(stream)?;
+            }
+            Self::RelayedFromClientType(relayed_from_client_command) => {
+                relayed_from_client_command.to_octets(stream)?;
+            }
+            Self::BroadcastedPayloadType(broadcasted_payload_command) => {
+                broadcasted_payload_command.to_octets(stream)?;
+            }
+            Self::JoinAcceptedType(join_accepted_command) => {
+                join_accepted_command.to_octets_with_version(stream, version)?;
+            }
+            Self::JoinDeniedType(join_denied_command) => {
+                join_denied_command.to_octets(stream)?;
+            }
+            Self::ResyncDataType(resync_data_command) => {
+                resync_data_command.to_octets(stream)?;
+            }
+            Self::SnapshotChunkType(snapshot_chunk_command) => {
+                snapshot_chunk_command.to_octets(stream)?;
+            }
+            Self::TransferChunkType(transfer_chunk_command) => {
+                transfer_chunk_command.to_octets(stream)?;
+            }
+            Self::TransferResendRequestType(transfer_resend_request_command) => {
+                transfer_resend_request_command.to_octets(stream)?;
+            }
+            Self::MaintenanceNoticeType(maintenance_notice_command) => {
+                maintenance_notice_command.to_octets(stream)?;
+            }
+            Self::RedirectType(redirect_command) => {
+                redirect_command.to_octets(stream)?;
+            }
+            Self::ServerLoadType(server_load_command) => {
+                server_load_command.to_octets(stream)?;
+            }
+            Self::HelloType(hello_command) => {
+                hello_command.to_octets(stream)?;
+            }
+            Self::ChallengeType(challenge_command) => {
+                challenge_command.to_octets_with_version(stream, version)?;
+            }
+            Self::RetryCookieType(retry_cookie_command) => {
+                retry_cookie_command.to_octets(stream)?;
+            }
+            Self::ThrottledType(throttled_command) => {
+                throttled_command.to_octets(stream)?;
+            }
+            Self::TimeoutConfigType(timeout_config_command) => {
+                timeout_config_command.to_octets(stream)?;
+            }
+            Self::UpdateRoomSettingsType(update_room_settings_command) => {
+                update_room_settings_command.to_octets(stream)?;
+            }
+            Self::ChannelProfileType(channel_profile_command) => {
+                channel_profile_command.to_octets(stream)?;
+            }
+            Self::RoomInfoPageType(room_info_page_command) => {
+                room_info_page_command.to_octets_with_version(stream, version)?;
+            }
+            Self::MembershipSyncResponseType(membership_sync_response_command) => {
+                membership_sync_response_command.to_octets_with_version(stream, version)?;
+            }
+            Self::KnowledgeAckType(knowledge_ack_command) => {
+                knowledge_ack_command.to_octets(stream)?;
+            }
+            Self::TermAdvancedType(term_advanced_command) => {
+                term_advanced_command.to_octets(stream)?;
+            }
+            Self::PreVoteResponseType(pre_vote_response_command) => {
+                pre_vote_response_command.to_octets(stream)?;
+            }
+            Self::ReplicateType(replicate_command) => {
+                replicate_command.to_octets(stream)?;
+            }
+            Self::ReplicateAckType(replicate_ack_command) => {
+                replicate_ack_command.to_octets(stream)?;
+            }
+            Self::Unknown(raw_command) => {
+                stream.write(&raw_command.payload)?;
+            } // _ => return Err(format!("unknown command enum {:?}", self)),
+        }
+
+        Ok(())
+    }
+
+    pub fn from_octets<T: ReadOctetStream>(stream: &mut T) -> Result<ClientReceiveCommand> {
+        Self::from_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_octets_with_version<T: ReadOctetStream>(
+        stream: &mut T,
+        version: ProtocolVersion,
+    ) -> Result<ClientReceiveCommand> {
+        Self::from_octets_with_options(stream, &DecodeOptions::new(version))
+    }
+
+    pub fn from_octets_with_options<T: ReadOctetStream>(
+        stream: &mut T,
+        options: &DecodeOptions,
+    ) -> Result<ClientReceiveCommand> {
+        read_datagram_magic(stream, options)?;
+        let command_type_id = in_field(stream.read_u8(), "command_type_id")?;
+        Self::payload_from_cursor(command_type_id, stream, options)
+    }
+
+    /// Decodes what follows the command type id
+    pub(crate) fn payload_from_cursor<T: ReadOctetStream>(
+        command_type_id: u8,
+        stream: &mut T,
+        options: &DecodeOptions,
+    ) -> Result<ClientReceiveCommand> {
+        in_field(
+            check_sender(command_type_id, options.sender),
+            "command_type_id",
+        )?;
+        match command_type_id {
+            ROOM_INFO_COMMAND_TYPE_ID => Ok(Self::RoomInfoType(in_field(
+                RoomInfoCommand::from_cursor_with_options(stream, options),
+                RoomInfoCommand::NAME,
+            )?)),
+            ROOM_CLOSED_COMMAND_TYPE_ID => Ok(Self::RoomClosedType(in_field(
+                RoomClosedCommand::from_cursor(stream),
+                RoomClosedCommand::NAME,
+            )?)),
+            MUTE_STATE_COMMAND_TYPE_ID => Ok(Self::MuteStateType(in_field(
+                MuteStateCommand::from_cursor(stream),
+                MuteStateCommand::NAME,
+            )?)),
+            PING_RESPONSE_COMMAND_TYPE_ID => Ok(Self::PingResponseType(in_field(
+                PingResponseCommand::from_cursor_with_options(stream, options),
+                PingResponseCommand::NAME,
+            )?)),
+            RELAYED_FROM_CLIENT_COMMAND_TYPE_ID => Ok(Self::RelayedFromClientType(in_field(
+                RelayedFromClientCommand::from_cursor(stream),
+                RelayedFromClientCommand::NAME,
+            )?)),
+            BROADCASTED_PAYLOAD_COMMAND_TYPE_ID => Ok(Self::BroadcastedPayloadType(in_field(
+                BroadcastedPayloadCommand::from_cursor(stream),
+                BroadcastedPayloadCommand::NAME,
+            )?)),
+            JOIN_ACCEPTED_COMMAND_TYPE_ID => Ok(Self::JoinAcceptedType(in_field(
+                JoinAcceptedCommand::from_cursor_with_options(stream, options),
+                JoinAcceptedCommand::NAME,
+            )?)),
+            JOIN_DENIED_COMMAND_TYPE_ID => Ok(Self::JoinDeniedType(in_field(
+                JoinDeniedCommand::from_cursor(stream),
+                JoinDeniedCommand::NAME,
+            )?)),
+            RESYNC_DATA_COMMAND_TYPE_ID => Ok(Self::ResyncDataType(in_field(
+                ResyncDataCommand::from_cursor_with_options(stream, options),
+                ResyncDataCommand::NAME,
+            )?)),
+            SNAPSHOT_CHUNK_COMMAND_TYPE_ID => Ok(Self::SnapshotChunkType(in_field(
+                SnapshotChunkCommand::from_cursor(stream),
+                SnapshotChunkCommand::NAME,
+            )?)),
+            TRANSFER_CHUNK_COMMAND_TYPE_ID => Ok(Self::TransferChunkType(in_field(
+                TransferChunkCommand::from_cursor(stream),
+                TransferChunkCommand::NAME,
+            )?)),
+            TRANSFER_RESEND_REQUEST_COMMAND_TYPE_ID => {
+                Ok(Self::TransferResendRequestType(in_field(
+                    TransferResendRequestCommand::from_cursor(stream),
+                    TransferResendRequestCommand::NAME,
+                )?))
+            }
+            MAINTENANCE_NOTICE_COMMAND_TYPE_ID => Ok(Self::MaintenanceNoticeType(in_field(
+                MaintenanceNoticeCommand::from_cursor(stream),
+                MaintenanceNoticeCommand::NAME,
+            )?)),
+            REDIRECT_COMMAND_TYPE_ID => Ok(Self::RedirectType(in_field(
+                RedirectCommand::from_cursor(stream),
+                RedirectCommand::NAME,
+            )?)),
+            SERVER_LOAD_COMMAND_TYPE_ID => Ok(Self::ServerLoadType(in_field(
+                ServerLoadCommand::from_cursor(stream),
+                ServerLoadCommand::NAME,
+            )?)),
+            HELLO_COMMAND_TYPE_ID => Ok(Self::HelloType(in_field(
+                HelloCommand::from_cursor(stream),
+                HelloCommand::NAME,
+            )?)),
+            CHALLENGE_COMMAND_TYPE_ID => Ok(Self::ChallengeType(in_field(
+                ChallengeCommand::from_cursor_with_version(stream, options.version),
+                ChallengeCommand::NAME,
+            )?)),
+            RETRY_COOKIE_COMMAND_TYPE_ID => Ok(Self::RetryCookieType(in_field(
+                RetryCookieCommand::from_cursor(stream),
+                RetryCookieCommand::NAME,
+            )?)),
+            THROTTLED_COMMAND_TYPE_ID => Ok(Self::ThrottledType(in_field(
+                ThrottledCommand::from_cursor(stream),
+                ThrottledCommand::NAME,
+            )?)),
+            TIMEOUT_CONFIG_COMMAND_TYPE_ID => Ok(Self::TimeoutConfigType(in_field(
+                TimeoutConfigCommand::from_cursor(stream),
+                TimeoutConfigCommand::NAME,
+            )?)),
+            UPDATE_ROOM_SETTINGS_COMMAND_TYPE_ID => Ok(Self::UpdateRoomSettingsType(in_field(
+                UpdateRoomSettingsCommand::from_cursor(stream),
+                UpdateRoomSettingsCommand::NAME,
+            )?)),
+            CHANNEL_PROFILE_COMMAND_TYPE_ID => Ok(Self::ChannelProfileType(in_field(
+                ChannelProfileCommand::from_cursor(stream),
+                ChannelProfileCommand::NAME,
+            )?)),
+            ROOM_INFO_PAGE_COMMAND_TYPE_ID => Ok(Self::RoomInfoPageType(in_field(
+                RoomInfoPageCommand::from_cursor_with_version(stream, options.version),
+                RoomInfoPageCommand::NAME,
+            )?)),
+            MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID => {
+                Ok(Self::MembershipSyncResponseType(in_field(
+                    MembershipSyncResponseCommand::from_cursor_with_version(
+                        stream,
+                        options.version,
+                    ),
+                    MembershipSyncResponseCommand::NAME,
+                )?))
+            }
+            KNOWLEDGE_ACK_COMMAND_TYPE_ID => Ok(Self::KnowledgeAckType(in_field(
+                KnowledgeAckCommand::from_cursor(stream),
+                KnowledgeAckCommand::NAME,
+            )?)),
+            TERM_ADVANCED_COMMAND_TYPE_ID => Ok(Self::TermAdvancedType(in_field(
+                TermAdvancedCommand::from_cursor(stream),
+                TermAdvancedCommand::NAME,
+            )?)),
+            PRE_VOTE_RESPONSE_COMMAND_TYPE_ID => Ok(Self::PreVoteResponseType(in_field(
+                PreVoteResponseCommand::from_cursor(stream),
+                PreVoteResponseCommand::NAME,
+            )?)),
+            REPLICATE_COMMAND_TYPE_ID => Ok(Self::ReplicateType(in_field(
+                ReplicateCommand::from_cursor(stream),
+                ReplicateCommand::NAME,
+            )?)),
+            REPLICATE_ACK_COMMAND_TYPE_ID => Ok(Self::ReplicateAckType(in_field(
+                ReplicateAckCommand::from_cursor(stream),
+                ReplicateAckCommand::NAME,
+            )?)),
+            _ if options.lenient => Ok(Self::Unknown(RawCommand {
+                type_id: command_type_id,
+                payload: in_field(read_to_end(stream), "payload")?,
+            })),
+            _ => in_field(
+                Err(Error::other(format!(
+                    "unknown command 0x{:x}",
+                    command_type_id
+                ))),
+                "command_type_id",
+            ),
+        }
+    }
+}
+
+/// Common interface of the command enums, for containers that can carry either direction
+pub trait Command: Sized {
+    fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()>;
+
+    fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self>;
+
+    fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    fn from_octets(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    /// `true` if the payload has no length of its own and runs to the end of the stream, like a
+    /// [`RawCommand`]. Containers that do not know the length of each command only accept it as
+    /// the last thing in them.
+    fn runs_to_end(&self) -> bool {
+        false
+    }
+}
+
+impl Command for ServerReceiveCommand {
+    fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        ServerReceiveCommand::to_octets_with_version(self, stream, version)
+    }
+
+    fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        ServerReceiveCommand::from_cursor_with_version(stream, version)
+    }
+
+    fn runs_to_end(&self) -> bool {
+        matches!(self, Self::Unknown(_))
+    }
+}
+
+impl Command for ClientReceiveCommand {
+    fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        ClientReceiveCommand::to_octets_with_version(self, stream, version)
+    }
+
+    fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        ClientReceiveCommand::from_octets_with_version(stream, version)
+    }
+
+    fn runs_to_end(&self) -> bool {
+        matches!(self, Self::Unknown(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flood_rs::{InOctetStream, OutOctetStream, ReadOctetStream, WriteOctetStream};
+    use proptest::prelude::*;
+
+    use crate::ClientReceiveCommand::{RoomClosedType, RoomInfoType};
+    use crate::ServerReceiveCommand::{
+        BanClientType, MuteStateType, PingCommandType, UnbanClientType,
+    };
+    use crate::{
+        client_to_server_vec, command_descriptor, decode_with_header, encode_budgeted,
+        encode_with_header, format_room_table, is_control_command, read_frame_with_header,
+        saturate_clock_drift_ppm, server_to_client_vec, split_command, split_command_with_version,
+        varint_octet_size, verify_canonical_with_version, write_client_to_server,
+        write_frame_with_header, write_frame_with_version, write_server_to_client,
+        BanClientCommand, BandwidthReportCommand, BroadcastPayloadCommand,
+        BroadcastedPayloadCommand, ChallengeCommand, ChannelProfile, ChannelProfileCommand,
+        ChannelRoute, ChunkReceiver, ChunkSender, ClientIdentity, ClientInfo, ClientInfos,
+        ClientNetworkStats, ClientReceiveCommand, ClientToServer, Command, CommandDelivery,
+        CommandHeader, CommandStats, CommandTypeStats, ConnectToken, ConnectWithCookieCommand,
+        ConnectWithTokenCommand, ConnectionIndex, ControlCommand, DebugDumpCommand, DecodeError,
+        DecodeOptions, EchoCommand, Extensions, FieldDescriptor, FieldKind, Frame, FrameDecoder,
+        HelloCommand, IoReadStream, IoWriteStream, JoinAcceptedCommand, JoinDeniedCommand,
+        JoinRequestCommand, KnowledgeAckCommand, KnowledgeVector, KnownRevision,
+        LatencyReportCommand, LogEntry, MaintenanceNoticeCommand, MembershipSyncRequestCommand,
+        MembershipSyncResponseCommand, MultiplexedDatagram, MultiplexedDecoder, MuteStateCommand,
+        PartialMessageBuffer, PingCommand, PingResponseCommand, PreVoteRequestCommand,
+        PreVoteResponseCommand, PresenceMaskWidth, PresenceReader, PresenceState, PresenceWriter,
+        ProtocolError, PskAuthCommand, RawCommand, ReassemblyLimits, RedirectCommand,
+        RelayToClientCommand, RelayedFromClientCommand, ReplicateAckCommand, ReplicateCommand,
+        Request, Response, ResponseMatcher, ResyncDataCommand, ResyncRequestCommand,
+        RetryCookieCommand, RoomClosedCommand, RoomEvent, RoomInfoCommand, RoomInfoCommandBuilder,
+        RoomInfoPageAccumulator, RoomInfoPageCommand, SenderAuthorization, SenderRole,
+        ServerLoadCommand, ServerReceiveCommand, SetPresenceCommand, SliceReader,
+        SnapshotChunkCommand, SnapshotReassembler, SnapshotRequestCommand, SplitHeader,
+        StatsRequestCommand, TermAdvancedCommand, TermExt, ThrottledCommand, TimeoutConfigCommand,
+        TransferChunkCommand, TransferResendRequestCommand, UnbanClientCommand,
+        UpdateRoomSettingsCommand, UserId, ADMIN_COMMAND_TYPE_ID_RANGE,
+        BANDWIDTH_REPORT_COMMAND_TYPE_ID, BAN_CLIENT_COMMAND_TYPE_ID,
+        BROADCASTED_PAYLOAD_COMMAND_TYPE_ID, BROADCAST_PAYLOAD_COMMAND_TYPE_ID,
+        CHALLENGE_COMMAND_TYPE_ID, CHALLENGE_SERVER_LOAD_BIT, CHANNEL_PROFILE_COMMAND_TYPE_ID,
+        CLOCK_DRIFT_PPM_MAX, CLOCK_DRIFT_PPM_MIN, COMMAND_DESCRIPTORS,
+        CONNECT_WITH_COOKIE_COMMAND_TYPE_ID, CONNECT_WITH_TOKEN_COMMAND_TYPE_ID,
+        CONTROL_COMMAND_TYPE_ID_RANGE, DATAGRAM_MAGIC, ENVELOPE_FLAG_CORRELATION_ID,
+        ENVELOPE_FLAG_EXTENSIONS, ENVELOPE_FLAG_PADDING, HELLO_MAGIC, JOIN_DENIED_COMMAND_TYPE_ID,
+        JOIN_DENIED_REASON_ROOM_FULL, KNOWLEDGE_ACK_COMMAND_TYPE_ID, LATENCY_BUCKET_COUNT,
+        MAINTENANCE_NOTICE_COMMAND_TYPE_ID, MAX_BROADCAST_PAYLOAD_OCTETS,
+        MAX_CONNECT_TOKEN_SERVER_ADDRESSES, MAX_EXTENSIONS_OCTETS, MAX_PAYLOAD_OCTETS,
+        MAX_REDIRECT_HOST_OCTETS, MAX_VARINT_OCTETS, MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID,
+        MUTE_STATE_COMMAND_TYPE_ID, PING_COMMAND_TYPE_ID, PING_RESPONSE_COMMAND_TYPE_ID,
+        PRE_VOTE_REQUEST_COMMAND_TYPE_ID, PRE_VOTE_RESPONSE_COMMAND_TYPE_ID, PROTOCOL_FINGERPRINT,
+        PROTOCOL_LAYOUT, PROTOCOL_VERSION, PROTOCOL_VERSION_1, PROTOCOL_VERSION_10,
+        PROTOCOL_VERSION_11, PROTOCOL_VERSION_12, PROTOCOL_VERSION_13, PROTOCOL_VERSION_14,
+        PROTOCOL_VERSION_2, PROTOCOL_VERSION_3, PROTOCOL_VERSION_4, PROTOCOL_VERSION_5,
+        PROTOCOL_VERSION_6, PROTOCOL_VERSION_7, PROTOCOL_VERSION_8, PROTOCOL_VERSION_9,
+        PSK_AUTH_COMMAND_TYPE_ID, REDIRECT_COMMAND_TYPE_ID, RELAY_TO_CLIENT_COMMAND_TYPE_ID,
+        REPLICATE_ACK_COMMAND_TYPE_ID, REPLICATE_COMMAND_TYPE_ID, RESYNC_REQUEST_COMMAND_TYPE_ID,
+        RETRY_COOKIE_COMMAND_TYPE_ID, ROLE_FLAG_HOST, ROLE_FLAG_PLAYER,
+        ROOM_CLOSED_COMMAND_TYPE_ID, ROOM_EVENT_LEADER_ELECTED, ROOM_INFO_COMMAND_TYPE_ID,
+        ROOM_INFO_FLAG_KNOWLEDGE_VECTOR, ROOM_INFO_FLAG_NETWORK_STATS,
+        ROOM_INFO_PAGE_COMMAND_TYPE_ID, SERVER_LOAD_COMMAND_TYPE_ID, SET_PRESENCE_COMMAND_TYPE_ID,
+        TERM_ADVANCED_CAUSE_LEADER_TIMEOUT, TERM_ADVANCED_COMMAND_TYPE_ID, TERM_UNKNOWN,
+        THROTTLED_COMMAND_TYPE_ID, TIMEOUT_CONFIG_COMMAND_TYPE_ID, UNBAN_CLIENT_COMMAND_TYPE_ID,
+    };
+
+    #[test]
+    fn check_serializer() {
+        let ping_command = PingCommand {
+            term: 32,
+            knowledge: 444441,
+            has_connection_to_leader: false,
+            ..Default::default()
+        };
+
+        let mut out_stream = OutOctetStream::new();
+        ping_command.to_octets(&mut out_stream).unwrap();
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let in_stream_ref = &mut in_stream;
+        let deserialized_ping_command = PingCommand::from_cursor(in_stream_ref).unwrap();
+
+        println!("before {:?}", &ping_command);
+        println!("after {:?}", &deserialized_ping_command);
+        assert_eq!(ping_command, deserialized_ping_command);
+    }
+
+    #[test]
+    fn check_server_receive_message() {
+        const EXPECTED_KNOWLEDGE_VALUE: u64 = 17718865395771014920;
+
+        let octets = [
+            PING_COMMAND_TYPE_ID,
+            0x00,
+            0x20, // Term
+            0xF5,
+            0xE6,
+            0x0E,
+            0x32,
+            0xE9,
+            0xE4,
+            0x7F,
+            0x08, // Knowledge
+            0x01, // Has Connection
+        ];
+
+        let mut in_stream = InOctetStream::new(Vec::from(octets));
+
+        let message = &ServerReceiveCommand::from_cursor(&mut in_stream).unwrap();
+
+        match message {
+            PingCommandType(ping_command) => {
+                println!("received {:?}", &ping_command);
+                assert_eq!(ping_command.term, 0x20);
+                assert_eq!(ping_command.knowledge, EXPECTED_KNOWLEDGE_VALUE);
+                assert!(ping_command.has_connection_to_leader);
+            }
+            _ => panic!("should be ping command"),
+        }
+    }
+
+    #[test]
+    fn check_client_receive_message() {
+        const EXPECTED_LEADER_INDEX: u8 = 1;
+
+        let octets = [
+            ROOM_INFO_COMMAND_TYPE_ID,
+            0x00,                  // Term
+            0x4A,                  // Term (lower)
+            0x00,                  // Number of client infos that follows
+            EXPECTED_LEADER_INDEX, // Leader index
+        ];
+
+        let mut in_stream = InOctetStream::new(Vec::from(octets));
+
+        let message = &ClientReceiveCommand::from_octets(&mut in_stream).unwrap();
+
+        match message {
+            RoomInfoType(room_info) => {
+                println!("received {:?}", &room_info);
                 assert_eq!(room_info.term, 0x4A);
-                assert_eq!(room_info.leader_index, EXPECTED_LEADER_INDEX);
-            } // _ => assert!(false, "should be room info command"),
+                assert_eq!(
+                    room_info.leader_index,
+                    ConnectionIndex(EXPECTED_LEADER_INDEX)
+                );
+            }
+            _ => panic!("should be room info command"),
+        }
+    }
+
+    #[test]
+    fn check_room_closed() {
+        let room_closed_command = RoomClosedCommand {
+            reason_code: 0x02,
+            closes_at_ms: 30000,
+        };
+
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::RoomClosedType(room_closed_command)
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        assert_eq!(
+            out_stream.data,
+            [ROOM_CLOSED_COMMAND_TYPE_ID, 0x02, 0x00, 0x00, 0x75, 0x30]
+        );
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let message =
+            ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap();
+
+        match message {
+            RoomClosedType(room_closed) => {
+                assert_eq!(room_closed.reason_code, 0x02);
+                assert_eq!(room_closed.closes_at_ms, 30000);
+            }
+            _ => panic!("should be room closed command"),
+        }
+    }
+
+    #[test]
+    fn check_ban_and_unban() {
+        assert!(ADMIN_COMMAND_TYPE_ID_RANGE.contains(&BAN_CLIENT_COMMAND_TYPE_ID));
+        assert!(ADMIN_COMMAND_TYPE_ID_RANGE.contains(&UNBAN_CLIENT_COMMAND_TYPE_ID));
+
+        let mut out_stream = OutOctetStream::new();
+        BanClientType(BanClientCommand {
+            custom_user_id: UserId(0x0102030405060708),
+            duration_s: 3600,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        UnbanClientType(UnbanClientCommand {
+            custom_user_id: UserId(0x0102030405060708),
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            BanClientType(ban_client) => {
+                assert_eq!(ban_client.custom_user_id, UserId(0x0102030405060708));
+                assert_eq!(ban_client.duration_s, 3600);
+            }
+            _ => panic!("should be ban client command"),
+        }
+
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            UnbanClientType(unban_client) => {
+                assert_eq!(unban_client.custom_user_id, UserId(0x0102030405060708));
+            }
+            _ => panic!("should be unban client command"),
+        }
+    }
+
+    #[test]
+    fn check_mute_state() {
+        let mute_state_command = MuteStateCommand {
+            connection_index: ConnectionIndex(3),
+            muted: true,
+        };
+
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::MuteStateType(mute_state_command)
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        assert_eq!(out_stream.data, [MUTE_STATE_COMMAND_TYPE_ID, 0x03, 0x01]);
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let message =
+            ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap();
+
+        match message {
+            ClientReceiveCommand::MuteStateType(mute_state) => {
+                assert_eq!(mute_state.connection_index, ConnectionIndex(3));
+                assert!(mute_state.muted);
+            }
+            _ => panic!("should be mute state command"),
+        }
+    }
+
+    #[test]
+    fn check_room_info_role_flags() {
+        let room_info_command = RoomInfoCommand {
+            term: 3,
+            leader_index: ConnectionIndex(1),
+            client_infos: ClientInfos::from_iter([
+                ClientInfo {
+                    identity: ClientIdentity::U64(42),
+                    connection_index: ConnectionIndex(0),
+                    role_flags: ROLE_FLAG_PLAYER,
+                    revision: 0,
+                    presence: PresenceState::Active,
+                    network_stats: None,
+                },
+                ClientInfo {
+                    identity: ClientIdentity::String("player-99".to_string()),
+                    connection_index: ConnectionIndex(1),
+                    role_flags: ROLE_FLAG_HOST | ROLE_FLAG_PLAYER,
+                    revision: 0,
+                    presence: PresenceState::Active,
+                    network_stats: None,
+                },
+            ]),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+
+        let mut out_stream = OutOctetStream::new();
+        room_info_command
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let deserialized =
+            RoomInfoCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION).unwrap();
+
+        assert_eq!(room_info_command, deserialized);
+    }
+
+    #[test]
+    fn check_room_info_version_1_layout() {
+        let octets = [
+            ROOM_INFO_COMMAND_TYPE_ID,
+            0x00,
+            0x07, // Term
+            0x01, // Number of client infos that follows
+            0x02, // Connection index
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x2A, // Custom user id
+            0x02, // Leader index
+        ];
+
+        let mut in_stream = InOctetStream::new(Vec::from(octets));
+        let message =
+            ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION_1)
+                .unwrap();
+
+        match &message {
+            RoomInfoType(room_info) => {
+                assert_eq!(room_info.term, 7);
+                assert_eq!(room_info.leader_index, ConnectionIndex(2));
+                assert_eq!(
+                    room_info.client_infos[..],
+                    [ClientInfo {
+                        identity: ClientIdentity::U64(42),
+                        connection_index: ConnectionIndex(2),
+                        role_flags: 0,
+                        revision: 0,
+                        presence: PresenceState::Active,
+                        network_stats: None,
+                    }]
+                );
+            }
+            _ => panic!("should be room info command"),
+        }
+
+        let mut out_stream = OutOctetStream::new();
+        message
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_1)
+            .unwrap();
+        assert_eq!(out_stream.data, octets);
+    }
+
+    #[test]
+    fn check_wide_user_id() {
+        const UUID: u128 = 0x6BA7B8109DAD11D180B400C04FD430C8;
+
+        let ban_client = ServerReceiveCommand::BanClientType(BanClientCommand {
+            custom_user_id: UserId(UUID),
+            duration_s: 0,
+        });
+
+        let mut out_stream = OutOctetStream::new();
+        ban_client
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(out_stream.data.len(), 1 + 16 + 4);
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            BanClientType(ban_client) => assert_eq!(ban_client.custom_user_id, UserId(UUID)),
+            _ => panic!("should be ban client command"),
+        }
+
+        let mut narrow_stream = OutOctetStream::new();
+        assert!(ban_client
+            .to_octets_with_version(&mut narrow_stream, PROTOCOL_VERSION_2)
+            .is_err());
+    }
+
+    #[test]
+    fn check_client_identity() {
+        let identities = [
+            ClientIdentity::U64(0x0102030405060708),
+            ClientIdentity::Uuid(0x6BA7B8109DAD11D180B400C04FD430C8),
+            ClientIdentity::String("account-1234".to_string()),
+        ];
+
+        for identity in identities.iter() {
+            let mut out_stream = OutOctetStream::new();
+            identity
+                .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+                .unwrap();
+
+            let mut in_stream = InOctetStream::new(out_stream.data);
+            let deserialized =
+                ClientIdentity::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION).unwrap();
+            assert_eq!(identity, &deserialized);
+        }
+
+        let mut out_stream = OutOctetStream::new();
+        assert!(identities[2]
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_3)
+            .is_err());
+    }
+
+    #[test]
+    fn check_room_routing_header() {
+        let room_closed = ClientReceiveCommand::RoomClosedType(RoomClosedCommand {
+            reason_code: 0x01,
+            closes_at_ms: 0,
+        });
+
+        let mut raw_stream = OutOctetStream::new();
+        room_closed
+            .to_octets_with_version(&mut raw_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        let mut headered_stream = OutOctetStream::new();
+        room_closed
+            .encode_with_room(0x00C0FFEE, &mut headered_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        assert_eq!(headered_stream.data[..4], [0x00, 0xC0, 0xFF, 0xEE]);
+        assert_eq!(headered_stream.data[4..], raw_stream.data);
+
+        let mut in_stream = InOctetStream::new(headered_stream.data);
+        let (room_id, message) =
+            ClientReceiveCommand::decode_with_room(&mut in_stream, PROTOCOL_VERSION).unwrap();
+        assert_eq!(room_id, 0x00C0FFEE);
+        match message {
+            RoomClosedType(room_closed) => assert_eq!(room_closed.reason_code, 0x01),
+            _ => panic!("should be room closed command"),
+        }
+
+        let mut in_stream = InOctetStream::new(raw_stream.data);
+        match ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            RoomClosedType(room_closed) => assert_eq!(room_closed.reason_code, 0x01),
+            _ => panic!("should be room closed command"),
+        }
+    }
+
+    #[test]
+    fn check_multiplexed_datagram() {
+        let mut datagram = MultiplexedDatagram::new();
+        datagram.push(
+            10,
+            ClientReceiveCommand::MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(1),
+                muted: true,
+            }),
+        );
+        datagram.push(
+            20,
+            ClientReceiveCommand::RoomClosedType(RoomClosedCommand {
+                reason_code: 0,
+                closes_at_ms: 0,
+            }),
+        );
+        datagram.push(
+            10,
+            ClientReceiveCommand::MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(2),
+                muted: false,
+            }),
+        );
+        assert_eq!(datagram.rooms.len(), 2);
+
+        let mut out_stream = OutOctetStream::new();
+        datagram
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        let mut in_stream = InOctetStream::new(out_stream.data.clone());
+        let room_ids = MultiplexedDecoder::<_, ClientReceiveCommand>::new(&mut in_stream)
+            .unwrap()
+            .map(|result| result.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(room_ids, [10, 10, 20]);
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let deserialized = MultiplexedDatagram::<ClientReceiveCommand>::from_octets_with_version(
+            &mut in_stream,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert_eq!(deserialized.rooms.len(), 2);
+        assert_eq!(deserialized.rooms[0].0, 10);
+        assert_eq!(deserialized.rooms[0].1.len(), 2);
+        assert_eq!(deserialized.rooms[1].0, 20);
+    }
+
+    #[test]
+    fn check_correlation_envelope() {
+        let ban_client = ServerReceiveCommand::BanClientType(BanClientCommand {
+            custom_user_id: UserId(7),
+            duration_s: 60,
+        });
+
+        let mut out_stream = OutOctetStream::new();
+        ban_client
+            .encode_with_correlation(Some(0x11223344), &mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        ban_client
+            .encode_with_correlation(None, &mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        assert_eq!(
+            out_stream.data[..6],
+            [
+                ENVELOPE_FLAG_CORRELATION_ID,
+                0x11,
+                0x22,
+                0x33,
+                0x44,
+                BAN_CLIENT_COMMAND_TYPE_ID
+            ]
+        );
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let (correlation_id, _) =
+            ServerReceiveCommand::decode_with_correlation(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap();
+        assert_eq!(correlation_id, Some(0x11223344));
+        let (correlation_id, message) =
+            ServerReceiveCommand::decode_with_correlation(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap();
+        assert_eq!(correlation_id, None);
+        match message {
+            BanClientType(ban_client) => assert_eq!(ban_client.duration_s, 60),
+            _ => panic!("should be ban client command"),
+        }
+    }
+
+    #[test]
+    fn check_request_response_matching() {
+        let mut matcher = ResponseMatcher::new();
+        let request = matcher.request(
+            ServerReceiveCommand::UnbanClientType(UnbanClientCommand {
+                custom_user_id: UserId(5),
+            }),
+            "unban 5",
+        );
+        let other_request = matcher.request(
+            ServerReceiveCommand::UnbanClientType(UnbanClientCommand {
+                custom_user_id: UserId(6),
+            }),
+            "unban 6",
+        );
+        assert_ne!(request.correlation_id, other_request.correlation_id);
+
+        let mut out_stream = OutOctetStream::new();
+        request
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let received_request = Request::<ServerReceiveCommand>::from_octets_with_version(
+            &mut in_stream,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+
+        let response = Response::to(
+            &received_request,
+            ClientReceiveCommand::RoomClosedType(RoomClosedCommand {
+                reason_code: 0,
+                closes_at_ms: 0,
+            }),
+        );
+        let mut out_stream = OutOctetStream::new();
+        response
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let received_response = Response::<ClientReceiveCommand>::from_octets_with_version(
+            &mut in_stream,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+
+        assert_eq!(matcher.on_response(&received_response), Some("unban 5"));
+        assert_eq!(matcher.on_response(&received_response), None);
+        assert_eq!(matcher.pending_count(), 1);
+    }
+
+    #[test]
+    fn check_command_min_version() {
+        let mute_state = ClientReceiveCommand::MuteStateType(MuteStateCommand {
+            connection_index: ConnectionIndex(0),
+            muted: true,
+        });
+        assert_eq!(mute_state.min_version(), PROTOCOL_VERSION_2);
+
+        let mut out_stream = OutOctetStream::new();
+        let err = mute_state
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_1)
+            .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::UnsupportedCommand {
+                command_type_id: MUTE_STATE_COMMAND_TYPE_ID,
+                min_version: PROTOCOL_VERSION_2,
+                version: PROTOCOL_VERSION_1,
+            })
+        );
+        assert!(out_stream.data.is_empty());
+
+        mute_state
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_2)
+            .unwrap();
+    }
+
+    #[test]
+    fn check_unexpected_eof() {
+        let room_info = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(0),
+            client_infos: ClientInfos::from_iter([ClientInfo {
+                identity: ClientIdentity::U64(1),
+                connection_index: ConnectionIndex(0),
+                role_flags: 0,
+                revision: 0,
+                presence: PresenceState::Active,
+                network_stats: None,
+            }]),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        });
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        let octets = out_stream.data;
+
+        let err = ClientReceiveCommand::decode_checked(&octets[..3], PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::UnexpectedEof {
+                needed: 12,
+                available: 3
+            })
+        );
+
+        let err =
+            ClientReceiveCommand::decode_checked(&octets[..12], PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::UnexpectedEof {
+                needed: 14,
+                available: 12
+            })
+        );
+
+        assert!(ClientReceiveCommand::decode_checked(&octets, PROTOCOL_VERSION).is_ok());
+
+        let err = ServerReceiveCommand::decode_checked(&[], PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::UnexpectedEof {
+                needed: 1,
+                available: 0
+            })
+        );
+    }
+
+    #[test]
+    fn check_decode_error_path_and_offset() {
+        let octets = [
+            ROOM_INFO_COMMAND_TYPE_ID,
+            0x00,
+            0x01, // Term
+            0x02, // Number of client infos that follows
+            0x00, // Connection index
+            0x00, // ClientIdentity::U64
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x01, // Custom user id
+            0x00,
+            0x00, // Role flags
+            0x00,
+            0x00,
+            0x00,
+            0x00, // Revision
+            0x00, // PresenceState::Active
+            0x01, // Connection index
+            0x07, // Unknown identity discriminant
+        ];
+
+        let err = ClientReceiveCommand::decode_checked(&octets, PROTOCOL_VERSION).unwrap_err();
+        let decode_error = DecodeError::from_io(&err).unwrap();
+        assert_eq!(decode_error.path, "room_info.client_infos[1].identity");
+        assert_eq!(decode_error.offset, Some(22));
+
+        let err =
+            ClientReceiveCommand::decode_checked(&octets[..12], PROTOCOL_VERSION).unwrap_err();
+        let decode_error = DecodeError::from_io(&err).unwrap();
+        assert_eq!(decode_error.path, "room_info.client_infos[0].identity");
+        assert_eq!(decode_error.offset, Some(6));
+        assert!(matches!(
+            ProtocolError::from_io(&err),
+            Some(ProtocolError::UnexpectedEof { .. })
+        ));
+
+        let err = ClientReceiveCommand::decode_checked(&octets[..2], PROTOCOL_VERSION).unwrap_err();
+        let decode_error = DecodeError::from_io(&err).unwrap();
+        assert_eq!(decode_error.path, "room_info.term");
+        assert_eq!(decode_error.offset, Some(1));
+        assert!(matches!(
+            ProtocolError::from_io(&err),
+            Some(ProtocolError::UnexpectedEof { available: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn check_frame_recovery() {
+        let mute_state = ServerReceiveCommand::MuteStateType(MuteStateCommand {
+            connection_index: ConnectionIndex(4),
+            muted: true,
+        });
+
+        let mut out_stream = OutOctetStream::new();
+        write_frame_with_version(&mute_state, &mut out_stream, PROTOCOL_VERSION).unwrap();
+        out_stream.write_u16(3).unwrap();
+        out_stream.write(&[0xEE, 0x00, 0x00]).unwrap(); // Unknown command type id
+        write_frame_with_version(&mute_state, &mut out_stream, PROTOCOL_VERSION).unwrap();
+        let octets = out_stream.data;
+
+        let frames = FrameDecoder::<ServerReceiveCommand>::new(&octets)
+            .with_version(PROTOCOL_VERSION)
+            .map(|frame| frame.is_ok())
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [true, false]);
+
+        let frames = FrameDecoder::<ServerReceiveCommand>::new(&octets)
+            .with_version(PROTOCOL_VERSION)
+            .with_recovery()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(frames.len(), 3);
+        assert!(matches!(frames[0], Frame::Command(MuteStateType(_))));
+        match &frames[1] {
+            Frame::Skipped { span, error } => {
+                assert_eq!(*span, 5..10);
+                assert_eq!(DecodeError::from_io(error).unwrap().offset, Some(7));
+            }
+            _ => panic!("should be skipped"),
+        }
+        assert!(matches!(frames[2], Frame::Command(MuteStateType(_))));
+    }
+
+    #[test]
+    fn check_non_canonical_encodings() {
+        let octets = [
+            PING_COMMAND_TYPE_ID,
+            0x00,
+            0x20, // Term
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x08, // Knowledge
+            0x01, // Has Connection
+            0x05, // Connection index
+            0xFF,
+            0xF6, // Clock drift
+            0x00, // Presence
+        ];
+        verify_canonical_with_version::<ServerReceiveCommand>(&octets, PROTOCOL_VERSION).unwrap();
+
+        let mut boolean_as_two = octets;
+        boolean_as_two[11] = 0x02;
+        let err = verify_canonical_with_version::<ServerReceiveCommand>(
+            &boolean_as_two,
+            PROTOCOL_VERSION,
+        )
+        .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::NonCanonical { offset: 11 })
+        );
+
+        let mut trailing = octets.to_vec();
+        trailing.push(0x00);
+        let err =
+            verify_canonical_with_version::<ServerReceiveCommand>(&trailing, PROTOCOL_VERSION)
+                .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::NonCanonical { offset: 16 })
+        );
+    }
+
+    fn client_identity_strategy() -> impl Strategy<Value = ClientIdentity> {
+        prop_oneof![
+            any::<u64>().prop_map(ClientIdentity::U64),
+            any::<u128>().prop_map(ClientIdentity::Uuid),
+            "[a-z0-9-]{0,32}".prop_map(ClientIdentity::String),
+        ]
+    }
+
+    fn room_info_strategy() -> impl Strategy<Value = RoomInfoCommand> {
+        let client_info = (client_identity_strategy(), any::<u8>(), any::<u16>()).prop_map(
+            |(identity, connection_index, role_flags)| ClientInfo {
+                identity,
+                connection_index: ConnectionIndex(connection_index),
+                role_flags,
+                revision: 0,
+                presence: PresenceState::Active,
+                network_stats: None,
+            },
+        );
+        (
+            0..TERM_UNKNOWN,
+            any::<u8>(),
+            prop::collection::vec(client_info, 0..8),
+        )
+            .prop_map(|(term, leader_index, mut client_infos)| {
+                client_infos.sort_by_key(|client_info| client_info.connection_index);
+                client_infos.dedup_by_key(|client_info| client_info.connection_index);
+                let leader_index = client_infos
+                    .get(leader_index as usize % client_infos.len().max(1))
+                    .map_or(ConnectionIndex(0), |leader| leader.connection_index);
+                RoomInfoCommand {
+                    term,
+                    leader_index,
+                    client_infos: client_infos.into_iter().collect(),
+                    knowledge_vector: None,
+                    quorum_size: 0,
+                    votes_for_leader: 0,
+                    leader_lease_expires_in_ms: 0,
+                }
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn encoded_ping_is_canonical(term: u16, knowledge: u64, has_connection_to_leader: bool, connection_index: u8, clock_drift_ppm: i16) {
+            let mut out_stream = OutOctetStream::new();
+            PingCommandType(PingCommand {
+                term,
+                knowledge,
+                has_connection_to_leader,
+                connection_index: ConnectionIndex(connection_index),
+                clock_drift_ppm,
+                knowledge_vector: None,
+            })
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+            prop_assert!(verify_canonical_with_version::<ServerReceiveCommand>(&out_stream.data, PROTOCOL_VERSION).is_ok());
+        }
+
+        #[test]
+        fn encoded_room_info_is_canonical(room_info in room_info_strategy()) {
+            let mut out_stream = OutOctetStream::new();
+            RoomInfoType(room_info).to_octets_with_version(&mut out_stream, PROTOCOL_VERSION).unwrap();
+            prop_assert!(verify_canonical_with_version::<ClientReceiveCommand>(&out_stream.data, PROTOCOL_VERSION).is_ok());
+        }
+
+        #[test]
+        fn deterministic_room_info_ignores_client_order(
+            (room_info, shuffled) in room_info_strategy().prop_flat_map(|room_info| {
+                let client_infos = room_info.client_infos.to_vec();
+                (Just(room_info), Just(client_infos).prop_shuffle())
+            })
+        ) {
+            let shuffled_room_info = RoomInfoCommand {
+                term: room_info.term,
+                leader_index: room_info.leader_index,
+                client_infos: shuffled.into_iter().collect(),
+                knowledge_vector: None,
+                quorum_size: 0,
+                votes_for_leader: 0,
+                leader_lease_expires_in_ms: 0,
+            };
+            let octets = room_info.to_deterministic_vec(PROTOCOL_VERSION).unwrap();
+            prop_assert_eq!(&octets, &shuffled_room_info.to_deterministic_vec(PROTOCOL_VERSION).unwrap());
+            let command_octets = [&[ROOM_INFO_COMMAND_TYPE_ID][..], &octets].concat();
+            prop_assert!(verify_canonical_with_version::<ClientReceiveCommand>(&command_octets, PROTOCOL_VERSION).is_ok());
+            prop_assert_eq!(
+                ClientReceiveCommand::decode_exact(&command_octets, PROTOCOL_VERSION).unwrap(),
+                RoomInfoType(shuffled_room_info.sorted())
+            );
+        }
+
+        #[test]
+        fn decodable_octets_are_canonical_or_rejected(octets in prop::collection::vec(any::<u8>(), 0..32)) {
+            let mut in_stream = InOctetStream::new(octets.clone());
+            if let Ok(command) = ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION) {
+                let mut out_stream = OutOctetStream::new();
+                command.to_octets_with_version(&mut out_stream, PROTOCOL_VERSION).unwrap();
+                let canonical = verify_canonical_with_version::<ServerReceiveCommand>(&octets, PROTOCOL_VERSION).is_ok();
+                prop_assert_eq!(canonical, out_stream.data == octets);
+            }
+        }
+    }
+
+    #[cfg(feature = "arrayvec")]
+    #[test]
+    fn check_room_info_over_max_clients() {
+        use crate::{ArrayClientInfos, MAX_CLIENTS};
+
+        let room_info = RoomInfoCommand {
+            leader_index: ConnectionIndex(0),
+            client_infos: (0..=MAX_CLIENTS as u8)
+                .map(|index| ClientInfo {
+                    connection_index: ConnectionIndex(index),
+                    ..ClientInfo::default()
+                })
+                .collect(),
+            ..RoomInfoCommand::default()
+        };
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        // The default storage is not limited by the feature
+        let decoded = RoomInfoCommand::from_cursor_with_version(
+            &mut InOctetStream::new(out_stream.data.clone()),
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert_eq!(decoded, room_info);
+
+        let mut inline = RoomInfoCommand::<ArrayClientInfos>::default();
+        let err = inline
+            .decode_into_with_options(
+                &mut InOctetStream::new(out_stream.data[..out_stream.data.len() - 1].to_vec()),
+                &DecodeOptions::new(PROTOCOL_VERSION),
+            )
+            .unwrap_err();
+        assert_eq!(DecodeError::from_io(&err).unwrap().path, "client_infos.len");
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::TooManyClients {
+                count: MAX_CLIENTS + 1,
+                max: MAX_CLIENTS
+            })
+        );
+
+        let mut out_stream = OutOctetStream::new();
+        let fits = RoomInfoCommand::<ArrayClientInfos> {
+            client_infos: room_info.client_infos[..MAX_CLIENTS]
+                .iter()
+                .cloned()
+                .collect(),
+            ..RoomInfoCommand::default()
+        };
+        fits.to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        inline
+            .decode_into_with_options(
+                &mut InOctetStream::new(out_stream.data),
+                &DecodeOptions::new(PROTOCOL_VERSION),
+            )
+            .unwrap();
+        assert_eq!(inline, fits);
+    }
+
+    #[test]
+    fn check_room_info_builder() {
+        let client = |connection_index: u8| ClientInfo {
+            identity: ClientIdentity::U64(connection_index as u64),
+            connection_index: ConnectionIndex(connection_index),
+            role_flags: 0,
+            revision: 0,
+            presence: PresenceState::Active,
+            network_stats: None,
+        };
+
+        let room_info = RoomInfoCommand::builder()
+            .term(4)
+            .leader_index(ConnectionIndex(3))
+            .client(client(0))
+            .client(client(3))
+            .build()
+            .unwrap();
+        assert_eq!(room_info.term, 4);
+        assert_eq!(room_info.client_infos.len(), 2);
+
+        assert_eq!(
+            RoomInfoCommand::builder()
+                .leader_index(ConnectionIndex(2))
+                .client(client(0))
+                .client(client(1))
+                .build(),
+            Err(ProtocolError::LeaderIndexOutOfBounds {
+                leader_index: ConnectionIndex(2),
+                client_count: 2
+            })
+        );
+
+        // Connection indices do not have to be contiguous
+        let sparse = RoomInfoCommand::builder()
+            .term(1)
+            .leader_index(ConnectionIndex(7))
+            .clients([client(3), client(7)])
+            .build()
+            .unwrap();
+        let octets = ClientReceiveCommand::RoomInfoType(sparse.clone())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ClientReceiveCommand::RoomInfoType(sparse)
+        );
+        assert_eq!(
+            RoomInfoCommand::builder()
+                .leader_index(ConnectionIndex(0))
+                .clients([client(5), client(6)])
+                .build(),
+            Err(ProtocolError::LeaderIndexOutOfBounds {
+                leader_index: ConnectionIndex(0),
+                client_count: 2
+            })
+        );
+
+        assert_eq!(
+            RoomInfoCommandBuilder::default()
+                .leader_index(ConnectionIndex(5))
+                .client(client(5))
+                .client(client(5))
+                .build(),
+            Err(ProtocolError::DuplicateConnectionIndex {
+                connection_index: ConnectionIndex(5)
+            })
+        );
+    }
+
+    #[test]
+    fn check_room_info_too_many_clients_to_encode() {
+        use crate::MAX_CLIENT_INFOS;
+
+        let room_info: RoomInfoCommand = RoomInfoCommand {
+            client_infos: (0..=MAX_CLIENT_INFOS)
+                .map(|_| ClientInfo::default())
+                .collect(),
+            ..RoomInfoCommand::default()
+        };
+        let mut out_stream = OutOctetStream::new();
+        let err = room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::TooManyClients {
+                count: MAX_CLIENT_INFOS + 1,
+                max: MAX_CLIENT_INFOS
+            })
+        );
+        assert!(out_stream.data.is_empty());
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn check_room_info_spills_past_inline_clients() {
+        use crate::{SmallClientInfos, INLINE_CLIENTS};
+
+        let room_info: RoomInfoCommand = RoomInfoCommand {
+            client_infos: (0..=INLINE_CLIENTS as u8)
+                .map(|index| ClientInfo {
+                    connection_index: ConnectionIndex(index),
+                    ..ClientInfo::default()
+                })
+                .collect(),
+            ..RoomInfoCommand::default()
+        };
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+
+        let mut decoded = RoomInfoCommand::<SmallClientInfos>::default();
+        decoded
+            .decode_into_with_options(
+                &mut SliceReader::new(&out_stream.data),
+                &DecodeOptions::new(PROTOCOL_VERSION),
+            )
+            .unwrap();
+        assert_eq!(decoded.client_infos.len(), INLINE_CLIENTS + 1);
+        assert!(decoded.client_infos.spilled());
+        assert_eq!(decoded.client_infos[..], room_info.client_infos[..]);
+    }
+
+    #[test]
+    fn check_room_info_semantic_validation() {
+        let octets = [
+            ROOM_INFO_COMMAND_TYPE_ID,
+            0x00,
+            0x01, // Term
+            0x01, // Number of client infos that follows
+            0x00, // Connection index
+            0x00, // ClientIdentity::U64
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x01, // Custom user id
+            0x00,
+            0x00, // Role flags
+            0x00,
+            0x00,
+            0x00,
+            0x00, // Revision
+            0x00, // PresenceState::Active
+            0x03, // Leader index, past the client list
+            0x00, // Flags
+            0x00, // Quorum size
+            0x00, // Votes for leader
+            0x00,
+            0x00,
+            0x00,
+            0x00, // Leader lease expires in ms
+        ];
+
+        let mut in_stream = InOctetStream::new(Vec::from(octets));
+        let err = ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::LeaderIndexOutOfBounds {
+                leader_index: ConnectionIndex(3),
+                client_count: 1
+            })
+        );
+
+        let mut in_stream = InOctetStream::new(Vec::from(octets));
+        let message = ClientReceiveCommand::from_octets_with_options(
+            &mut in_stream,
+            &DecodeOptions::new(PROTOCOL_VERSION).lenient(),
+        )
+        .unwrap();
+        assert!(
+            matches!(message, RoomInfoType(room_info) if room_info.leader_index == ConnectionIndex(3))
+        );
+    }
+
+    #[test]
+    fn check_ping_layout_by_version() {
+        let ping = PingCommandType(PingCommand {
+            term: 1,
+            knowledge: 2,
+            has_connection_to_leader: true,
+            connection_index: ConnectionIndex(9),
+            clock_drift_ppm: 40,
+            knowledge_vector: None,
+        });
+
+        let mut out_stream = OutOctetStream::new();
+        ping.to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(out_stream.data.len(), 1 + 2 + 8 + 1 + 1 + 2 + 1);
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            PingCommandType(ping_command) => {
+                assert_eq!(ping_command.connection_index, ConnectionIndex(9));
+                assert_eq!(ping_command.clock_drift_ppm, 40);
+            }
+            _ => panic!("should be ping command"),
+        }
+
+        let mut out_stream = OutOctetStream::new();
+        ping.to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_5)
+            .unwrap();
+        assert_eq!(out_stream.data.len(), 1 + 2 + 8 + 1 + 1);
+        assert_eq!(out_stream.data[12], 9);
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION_5)
+            .unwrap()
+        {
+            PingCommandType(ping_command) => {
+                assert_eq!(ping_command.connection_index, ConnectionIndex(9));
+                assert_eq!(ping_command.clock_drift_ppm, 0);
+            }
+            _ => panic!("should be ping command"),
+        }
+
+        let mut out_stream = OutOctetStream::new();
+        ping.to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_4)
+            .unwrap();
+        assert_eq!(out_stream.data.len(), 1 + 2 + 8 + 1);
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION_4)
+            .unwrap()
+        {
+            PingCommandType(ping_command) => {
+                assert_eq!(ping_command.connection_index, ConnectionIndex(0))
+            }
+            _ => panic!("should be ping command"),
+        }
+
+        assert_eq!(saturate_clock_drift_ppm(-12), -12);
+        assert_eq!(saturate_clock_drift_ppm(100_000), CLOCK_DRIFT_PPM_MAX);
+        assert_eq!(saturate_clock_drift_ppm(-100_000), CLOCK_DRIFT_PPM_MIN);
+    }
+
+    #[test]
+    fn check_ping_response() {
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::PingResponseType(PingResponseCommand {
+            term: 0x0102,
+            server_knowledge: 40,
+            your_knowledge_ack: 32,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+
+        assert_eq!(out_stream.data[0], PING_RESPONSE_COMMAND_TYPE_ID);
+        assert_eq!(out_stream.data.len(), 1 + 2 + 8 + 8);
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ClientReceiveCommand::PingResponseType(ping_response) => {
+                assert_eq!(ping_response.term, 0x0102);
+                assert_eq!(ping_response.server_knowledge, 40);
+                assert_eq!(ping_response.your_knowledge_ack, 32);
+                assert_eq!(ping_response.knowledge_behind(), 8);
+            }
+            _ => panic!("should be ping response command"),
+        }
+    }
+
+    #[test]
+    fn check_relay_payloads() {
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::RelayToClientType(RelayToClientCommand {
+            target_connection_index: ConnectionIndex(3),
+            payload: vec![0xCA, 0xFE],
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+
+        assert_eq!(
+            out_stream.data,
+            [
+                RELAY_TO_CLIENT_COMMAND_TYPE_ID,
+                0x03,
+                0x00,
+                0x02,
+                0xCA,
+                0xFE
+            ]
+        );
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ServerReceiveCommand::RelayToClientType(relay) => {
+                assert_eq!(relay.target_connection_index, ConnectionIndex(3));
+                assert_eq!(relay.payload, [0xCA, 0xFE]);
+            }
+            _ => panic!("should be relay to client command"),
+        }
+
+        let relayed = RelayedFromClientCommand {
+            source_connection_index: ConnectionIndex(1),
+            payload: vec![],
+        };
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::RelayedFromClientType(relayed)
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ClientReceiveCommand::RelayedFromClientType(relayed) => {
+                assert_eq!(relayed.source_connection_index, ConnectionIndex(1));
+                assert!(relayed.payload.is_empty());
+            }
+            _ => panic!("should be relayed from client command"),
+        }
+
+        let too_large = RelayToClientCommand {
+            target_connection_index: ConnectionIndex(0),
+            payload: vec![0; MAX_PAYLOAD_OCTETS + 1],
+        };
+        assert!(too_large.to_octets(&mut OutOctetStream::new()).is_err());
+    }
+
+    #[test]
+    fn check_broadcast_payload() {
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::BroadcastPayloadType(BroadcastPayloadCommand {
+            payload: vec![0x10, 0x20, 0x30],
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ServerReceiveCommand::BroadcastPayloadType(broadcast) => {
+                assert_eq!(broadcast.payload, [0x10, 0x20, 0x30]);
+            }
+            _ => panic!("should be broadcast payload command"),
+        }
+
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::BroadcastedPayloadType(BroadcastedPayloadCommand {
+            source_connection_index: ConnectionIndex(4),
+            payload: vec![0x10],
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        assert_eq!(
+            out_stream.data,
+            [BROADCASTED_PAYLOAD_COMMAND_TYPE_ID, 0x04, 0x00, 0x01, 0x10]
+        );
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ClientReceiveCommand::BroadcastedPayloadType(broadcasted) => {
+                assert_eq!(broadcasted.source_connection_index, ConnectionIndex(4));
+                assert_eq!(broadcasted.payload, [0x10]);
+            }
+            _ => panic!("should be broadcasted payload command"),
+        }
+    }
+
+    #[test]
+    fn check_broadcast_payload_size_cap() {
+        let at_cap = BroadcastPayloadCommand {
+            payload: vec![0xAA; MAX_BROADCAST_PAYLOAD_OCTETS],
+        };
+        assert!(at_cap.to_octets(&mut OutOctetStream::new()).is_ok());
+
+        let over_cap = BroadcastPayloadCommand {
+            payload: vec![0xAA; MAX_BROADCAST_PAYLOAD_OCTETS + 1],
+        };
+        assert!(over_cap.to_octets(&mut OutOctetStream::new()).is_err());
+
+        let mut out_stream = OutOctetStream::new();
+        out_stream
+            .write_u16(MAX_BROADCAST_PAYLOAD_OCTETS as u16 + 1)
+            .unwrap();
+        out_stream
+            .write(&[0xAA; MAX_BROADCAST_PAYLOAD_OCTETS + 1])
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let err = BroadcastPayloadCommand::from_cursor(&mut in_stream).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn check_join_flow() {
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::JoinRequestType(JoinRequestCommand {
+            identity: ClientIdentity::String("player-one".to_string()),
+            requested_role_flags: ROLE_FLAG_PLAYER,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ServerReceiveCommand::JoinRequestType(join_request) => {
+                assert_eq!(
+                    join_request.identity,
+                    ClientIdentity::String("player-one".to_string())
+                );
+                assert_eq!(join_request.requested_role_flags, ROLE_FLAG_PLAYER);
+            }
+            _ => panic!("should be join request command"),
+        }
+
+        let room_info = RoomInfoCommand::builder()
+            .term(2)
+            .client(ClientInfo {
+                identity: ClientIdentity::U64(7),
+                connection_index: ConnectionIndex(0),
+                role_flags: ROLE_FLAG_PLAYER,
+                revision: 0,
+                presence: PresenceState::Active,
+                network_stats: None,
+            })
+            .build()
+            .unwrap();
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::JoinAcceptedType(JoinAcceptedCommand {
+            connection_index: ConnectionIndex(0),
+            room_info,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ClientReceiveCommand::JoinAcceptedType(join_accepted) => {
+                assert_eq!(join_accepted.connection_index, ConnectionIndex(0));
+                assert_eq!(join_accepted.room_info.term, 2);
+                assert_eq!(join_accepted.room_info.client_infos.len(), 1);
+            }
+            _ => panic!("should be join accepted command"),
+        }
+
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::JoinDeniedType(JoinDeniedCommand {
+            reason_code: JOIN_DENIED_REASON_ROOM_FULL,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        assert_eq!(
+            out_stream.data,
+            [JOIN_DENIED_COMMAND_TYPE_ID, JOIN_DENIED_REASON_ROOM_FULL]
+        );
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert!(matches!(
+            ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap(),
+            ClientReceiveCommand::JoinDeniedType(JoinDeniedCommand {
+                reason_code: JOIN_DENIED_REASON_ROOM_FULL
+            })
+        ));
+    }
+
+    #[test]
+    fn check_resync() {
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::ResyncRequestType(ResyncRequestCommand {
+            from_term: 3,
+            from_knowledge: 0x10,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        assert_eq!(
+            out_stream.data,
+            [
+                RESYNC_REQUEST_COMMAND_TYPE_ID,
+                0x00,
+                0x03,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                0x10
+            ]
+        );
+
+        let data: Vec<u8> = (0..10).collect();
+        let chunks = ResyncDataCommand::chunks(4, 0x20, &data, 4).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[2].is_last_chunk());
+        assert_eq!(chunks[2].data, [8, 9]);
+
+        let past_the_end = ResyncDataCommand {
+            chunk_index: u16::MAX,
+            chunk_count: u16::MAX,
+            ..chunks[2].clone()
+        };
+        assert!(!past_the_end.is_last_chunk());
+
+        let mut reassembled = Vec::new();
+        for chunk in chunks {
+            let mut out_stream = OutOctetStream::new();
+            ClientReceiveCommand::ResyncDataType(chunk)
+                .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+                .unwrap();
+            let mut in_stream = InOctetStream::new(out_stream.data);
+            match ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap()
+            {
+                ClientReceiveCommand::ResyncDataType(resync_data) => {
+                    assert_eq!(resync_data.chunk_count, 3);
+                    assert_eq!(resync_data.knowledge, 0x20);
+                    reassembled.extend_from_slice(&resync_data.data);
+                }
+                _ => panic!("should be resync data command"),
+            }
+        }
+        assert_eq!(reassembled, data);
+
+        assert_eq!(ResyncDataCommand::chunks(4, 0x20, &[], 4).unwrap().len(), 1);
+        assert!(ResyncDataCommand::chunks(4, 0x20, &data, 0).is_err());
+    }
+
+    #[test]
+    fn check_snapshot_reassembly() {
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::SnapshotRequestType(SnapshotRequestCommand { snapshot_id: 9 })
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert!(matches!(
+            ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap(),
+            ServerReceiveCommand::SnapshotRequestType(SnapshotRequestCommand { snapshot_id: 9 })
+        ));
+
+        let data: Vec<u8> = (0..=200).collect();
+        let mut chunks = SnapshotChunkCommand::chunks(9, &data, 64).unwrap();
+        assert_eq!(chunks.len(), 4);
+        chunks.reverse();
+
+        let mut reassembler = SnapshotReassembler::new();
+        let mut snapshot = None;
+        for chunk in chunks {
+            let mut out_stream = OutOctetStream::new();
+            ClientReceiveCommand::SnapshotChunkType(chunk)
+                .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+                .unwrap();
+            let mut in_stream = InOctetStream::new(out_stream.data);
+            let ClientReceiveCommand::SnapshotChunkType(chunk) =
+                ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+                    .unwrap()
+            else {
+                panic!("should be snapshot chunk command");
+            };
+            assert!(snapshot.is_none());
+            snapshot = reassembler.receive(chunk).unwrap();
+        }
+        assert_eq!(snapshot.unwrap(), data);
+        assert_eq!(reassembler.pending_count(), 0);
+
+        let first = SnapshotChunkCommand::chunks(10, &data, 64)
+            .unwrap()
+            .remove(0);
+        let duplicate = SnapshotChunkCommand::chunks(10, &data, 64)
+            .unwrap()
+            .remove(0);
+        assert_eq!(reassembler.receive(first).unwrap(), None);
+        assert_eq!(reassembler.receive(duplicate).unwrap(), None);
+        let resized = SnapshotChunkCommand::chunks(10, &data, 32)
+            .unwrap()
+            .remove(1);
+        assert!(reassembler.receive(resized).is_err());
+        assert!(reassembler.cancel(10));
+        assert_eq!(reassembler.pending_count(), 0);
+    }
+
+    #[test]
+    fn check_chunked_transfer() {
+        let data: Vec<u8> = (0..1000).map(|value| value as u8).collect();
+        let sender = ChunkSender::new(5, data.clone(), 300).unwrap();
+        assert_eq!(sender.chunk_count(), 4);
+
+        let mut receiver = ChunkReceiver::new();
+        for chunk in sender.chunks().filter(|chunk| chunk.chunk_index != 1) {
+            let mut out_stream = OutOctetStream::new();
+            ServerReceiveCommand::TransferChunkType(chunk)
+                .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+                .unwrap();
+            let mut in_stream = InOctetStream::new(out_stream.data);
+            let ServerReceiveCommand::TransferChunkType(chunk) =
+                ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+                    .unwrap()
+            else {
+                panic!("should be transfer chunk command");
+            };
+            assert_eq!(receiver.receive(chunk).unwrap(), None);
+        }
+
+        let resend_request = receiver.resend_request(5).unwrap();
+        assert_eq!(resend_request.missing_chunk_indices, [1]);
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::TransferResendRequestType(resend_request)
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let ClientReceiveCommand::TransferResendRequestType(resend_request) =
+            ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap()
+        else {
+            panic!("should be transfer resend request command");
+        };
+
+        let mut resent = sender.on_resend_request(&resend_request);
+        assert_eq!(resent.len(), 1);
+        assert_eq!(receiver.receive(resent.remove(0)).unwrap(), Some(data));
+        assert_eq!(receiver.pending_count(), 0);
+        assert!(receiver.resend_request(5).is_none());
+
+        let other_transfer = TransferResendRequestCommand {
+            transfer_id: 6,
+            missing_chunk_indices: vec![0],
+        };
+        assert!(sender.on_resend_request(&other_transfer).is_empty());
+
+        let mut in_stream = InOctetStream::new(vec![0, 0, 0, 5, 0x00, 0x02, 0x00, 0x02, 0, 0]);
+        assert!(TransferChunkCommand::from_cursor(&mut in_stream).is_err());
+    }
+
+    #[test]
+    fn check_bandwidth_report_varints() {
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::BandwidthReportType(BandwidthReportCommand {
+            bytes_sent: 0x7F,
+            bytes_received: 300,
+            window_ms: 1000,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        assert_eq!(
+            out_stream.data,
+            [
+                BANDWIDTH_REPORT_COMMAND_TYPE_ID,
+                0x7F,
+                0xAC,
+                0x02,
+                0xE8,
+                0x07
+            ]
+        );
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ServerReceiveCommand::BandwidthReportType(report) => {
+                assert_eq!(report.bytes_sent, 0x7F);
+                assert_eq!(report.bytes_received, 300);
+                assert_eq!(report.window_ms, 1000);
+            }
+            _ => panic!("should be bandwidth report command"),
+        }
+
+        let report = BandwidthReportCommand {
+            bytes_sent: u64::MAX,
+            bytes_received: 0,
+            window_ms: u32::MAX,
+        };
+        let mut out_stream = OutOctetStream::new();
+        report.to_octets(&mut out_stream).unwrap();
+        assert_eq!(out_stream.data.len(), MAX_VARINT_OCTETS + 1 + 5);
+        assert_eq!(varint_octet_size(u64::MAX), MAX_VARINT_OCTETS);
+        assert_eq!(varint_octet_size(0), 1);
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert_eq!(
+            BandwidthReportCommand::from_cursor(&mut in_stream).unwrap(),
+            report
+        );
+
+        // Overlong zero, and a window that does not fit in u32
+        let mut in_stream = InOctetStream::new(vec![0x80, 0x00, 0x00, 0x00]);
+        assert!(BandwidthReportCommand::from_cursor(&mut in_stream).is_err());
+        let mut in_stream = InOctetStream::new(vec![0x00, 0x00, 0x80, 0x80, 0x80, 0x80, 0x10]);
+        assert!(BandwidthReportCommand::from_cursor(&mut in_stream).is_err());
+    }
+
+    #[test]
+    fn check_latency_report() {
+        let report = LatencyReportCommand::from_samples([5, 10, 11, 35, 90, 90, 2000, 70000]);
+        assert_eq!(report.buckets, [2, 1, 1, 0, 2, 0, 0, 2]);
+        assert_eq!(report.sample_count(), 8);
+        assert_eq!(report.percentile_upper_bound_ms(500), Some(40));
+        assert_eq!(report.percentile_upper_bound_ms(1000), Some(u16::MAX));
+        assert_eq!(
+            LatencyReportCommand::default().percentile_upper_bound_ms(500),
+            None
+        );
+
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::LatencyReportType(report.clone())
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(out_stream.data.len(), 1 + 2 * LATENCY_BUCKET_COUNT);
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ServerReceiveCommand::LatencyReportType(decoded) => assert_eq!(decoded, report),
+            _ => panic!("should be latency report command"),
+        }
+
+        let mut saturated = LatencyReportCommand {
+            buckets: [u16::MAX; LATENCY_BUCKET_COUNT],
+        };
+        saturated.record(1);
+        assert_eq!(saturated.buckets[0], u16::MAX);
+    }
+
+    #[test]
+    fn check_maintenance_notice() {
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::MaintenanceNoticeType(MaintenanceNoticeCommand {
+            starts_in_s: 300,
+            expected_duration_s: 60,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        assert_eq!(
+            out_stream.data,
+            [
+                MAINTENANCE_NOTICE_COMMAND_TYPE_ID,
+                0x00,
+                0x00,
+                0x01,
+                0x2C,
+                0x00,
+                0x00,
+                0x00,
+                0x3C
+            ]
+        );
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert!(matches!(
+            ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap(),
+            ClientReceiveCommand::MaintenanceNoticeType(MaintenanceNoticeCommand {
+                starts_in_s: 300,
+                expected_duration_s: 60,
+            })
+        ));
+
+        let err = ClientReceiveCommand::MaintenanceNoticeType(MaintenanceNoticeCommand {
+            starts_in_s: 0,
+            expected_duration_s: 0,
+        })
+        .to_octets_with_version(&mut OutOctetStream::new(), PROTOCOL_VERSION_5)
+        .unwrap_err();
+        assert!(matches!(
+            ProtocolError::from_io(&err),
+            Some(ProtocolError::UnsupportedCommand { .. })
+        ));
+    }
+
+    #[test]
+    fn check_redirect() {
+        let redirect = RedirectCommand {
+            host: "eu-2.example.com".to_string(),
+            port: 27015,
+            token: vec![0xDE, 0xAD, 0xBE, 0xEF],
+        };
+        let mut out_stream = OutOctetStream::new();
+        redirect.to_octets(&mut out_stream).unwrap();
+        assert_eq!(out_stream.data[0], 16);
+        assert_eq!(out_stream.data.len(), 1 + 16 + 2 + 2 + 4);
+
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::RedirectType(redirect)
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(out_stream.data[0], REDIRECT_COMMAND_TYPE_ID);
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ClientReceiveCommand::RedirectType(redirect) => {
+                assert_eq!(redirect.host, "eu-2.example.com");
+                assert_eq!(redirect.port, 27015);
+                assert_eq!(redirect.token, [0xDE, 0xAD, 0xBE, 0xEF]);
+            }
+            _ => panic!("should be redirect command"),
+        }
+
+        for host in [String::new(), "a".repeat(MAX_REDIRECT_HOST_OCTETS + 1)] {
+            let redirect = RedirectCommand {
+                host,
+                port: 1,
+                token: vec![],
+            };
+            assert!(redirect.to_octets(&mut OutOctetStream::new()).is_err());
+        }
+
+        let mut in_stream = InOctetStream::new(vec![0x00, 0x00, 0x01, 0x00, 0x00]);
+        assert!(RedirectCommand::from_cursor(&mut in_stream).is_err());
+        let mut in_stream = InOctetStream::new(vec![0x01, 0xFF, 0x00, 0x01, 0x00, 0x00]);
+        assert!(RedirectCommand::from_cursor(&mut in_stream).is_err());
+    }
+
+    #[test]
+    fn check_server_load() {
+        let server_load = ServerLoadCommand {
+            cpu_permille: 750,
+            rooms: 12,
+            headroom: 4,
+        };
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::ServerLoadType(server_load)
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(
+            out_stream.data,
+            [SERVER_LOAD_COMMAND_TYPE_ID, 0x02, 0xEE, 0x00, 0x0C, 0x04]
+        );
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        match ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+            .unwrap()
+        {
+            ClientReceiveCommand::ServerLoadType(decoded) => assert_eq!(decoded, server_load),
+            _ => panic!("should be server load command"),
+        }
+    }
+
+    #[test]
+    fn check_hello_and_plausible_datagrams() {
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::HelloType(HelloCommand::new(PROTOCOL_VERSION))
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_1)
+            .unwrap();
+        assert_eq!(out_stream.data[..4], HELLO_MAGIC);
+        assert_eq!(out_stream.data[4], PROTOCOL_VERSION);
+        assert!(ServerReceiveCommand::is_plausible_datagram(
+            &out_stream.data
+        ));
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert!(matches!(
+            ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION_1)
+                .unwrap(),
+            ServerReceiveCommand::HelloType(hello) if hello.is_same_build()
+        ));
+
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::HelloType(HelloCommand {
+            version: PROTOCOL_VERSION_2,
+            fingerprint: PROTOCOL_FINGERPRINT ^ 1,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        assert!(ClientReceiveCommand::is_plausible_datagram(
+            &out_stream.data
+        ));
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert!(matches!(
+            ClientReceiveCommand::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION).unwrap(),
+            ClientReceiveCommand::HelloType(hello)
+                if hello.version == PROTOCOL_VERSION_2 && !hello.is_same_build()
+        ));
+
+        let bad_magic = [
+            HELLO_MAGIC[0],
+            HELLO_MAGIC[1],
+            0x00,
+            0x00,
+            0x06,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        assert!(!ServerReceiveCommand::is_plausible_datagram(&bad_magic));
+        let mut in_stream = InOctetStream::new(bad_magic.to_vec());
+        assert!(
+            ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .is_err()
+        );
+
+        assert!(!ServerReceiveCommand::is_plausible_datagram(&[]));
+        assert!(!ServerReceiveCommand::is_plausible_datagram(&[0xEE, 0x00]));
+        assert!(!ServerReceiveCommand::is_plausible_datagram(
+            &HELLO_MAGIC[..3]
+        ));
+        assert!(ServerReceiveCommand::is_plausible_datagram(&[
+            PING_COMMAND_TYPE_ID
+        ]));
+        assert!(!ClientReceiveCommand::is_plausible_datagram(&[
+            PING_COMMAND_TYPE_ID
+        ]));
+        assert!(ClientReceiveCommand::is_plausible_datagram(&[
+            ROOM_INFO_COMMAND_TYPE_ID
+        ]));
+    }
+
+    #[test]
+    fn check_datagram_magic() {
+        let options = DecodeOptions::new(PROTOCOL_VERSION).with_datagram_magic(DATAGRAM_MAGIC);
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::RoomClosedType(RoomClosedCommand {
+            reason_code: 1,
+            closes_at_ms: 0,
+        })
+        .encode_with_magic(DATAGRAM_MAGIC, &mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        assert_eq!(out_stream.data[..2], DATAGRAM_MAGIC);
+        assert_eq!(out_stream.data[2], ROOM_CLOSED_COMMAND_TYPE_ID);
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert!(matches!(
+            ClientReceiveCommand::from_octets_with_options(&mut in_stream, &options).unwrap(),
+            RoomClosedType(RoomClosedCommand { reason_code: 1, .. })
+        ));
+
+        let mut in_stream = InOctetStream::new(vec![0x00, 0x00, MUTE_STATE_COMMAND_TYPE_ID, 1, 1]);
+        let err =
+            ServerReceiveCommand::from_cursor_with_options(&mut in_stream, &options).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::BadMagic {
+                expected: DATAGRAM_MAGIC,
+                found: [0x00, 0x00],
+            })
+        );
+    }
+
+    #[test]
+    fn check_command_descriptors() {
+        let mut type_ids: Vec<u8> = COMMAND_DESCRIPTORS
+            .iter()
+            .map(|descriptor| descriptor.type_id)
+            .collect();
+        type_ids.sort();
+        type_ids.dedup();
+        assert_eq!(type_ids.len(), COMMAND_DESCRIPTORS.len());
+
+        for descriptor in COMMAND_DESCRIPTORS {
+            let min_octet_size = (descriptor.min_octet_size)(PROTOCOL_VERSION);
+            let server_receive =
+                ServerReceiveCommand::min_octet_size(descriptor.type_id, PROTOCOL_VERSION);
+            let client_receive =
+                ClientReceiveCommand::min_octet_size(descriptor.type_id, PROTOCOL_VERSION);
+            assert_eq!(
+                server_receive,
+                descriptor
+                    .direction
+                    .is_server_receive()
+                    .then_some(min_octet_size),
+                "{}",
+                descriptor.name
+            );
+            assert_eq!(
+                client_receive,
+                descriptor
+                    .direction
+                    .is_client_receive()
+                    .then_some(min_octet_size),
+                "{}",
+                descriptor.name
+            );
+        }
+
+        for type_id in 0..=u8::MAX {
+            let known = ServerReceiveCommand::min_octet_size(type_id, PROTOCOL_VERSION).is_some()
+                || ClientReceiveCommand::min_octet_size(type_id, PROTOCOL_VERSION).is_some();
+            assert_eq!(command_descriptor(type_id).is_some(), known);
+        }
+        assert_eq!(
+            command_descriptor(PING_COMMAND_TYPE_ID).unwrap().name,
+            "ping"
+        );
+    }
+
+    #[test]
+    fn check_unknown_command_when_lenient() {
+        let octets = vec![0x3E, 0x01, 0x02, 0x03];
+        let mut in_stream = InOctetStream::new(octets.clone());
+        assert!(
+            ServerReceiveCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .is_err()
+        );
+
+        let mut in_stream = InOctetStream::new(octets.clone());
+        let message = ServerReceiveCommand::from_cursor_with_options(
+            &mut in_stream,
+            &DecodeOptions::new(PROTOCOL_VERSION).lenient(),
+        )
+        .unwrap();
+        match &message {
+            ServerReceiveCommand::Unknown(raw_command) => {
+                assert_eq!(
+                    raw_command,
+                    &RawCommand {
+                        type_id: 0x3E,
+                        payload: vec![0x01, 0x02, 0x03],
+                    }
+                );
+            }
+            _ => panic!("should be unknown command"),
+        }
+        let mut out_stream = OutOctetStream::new();
+        message
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(out_stream.data, octets);
+
+        let mut in_stream = InOctetStream::new(vec![0x3F]);
+        assert!(matches!(
+            ClientReceiveCommand::from_octets_with_options(
+                &mut in_stream,
+                &DecodeOptions::new(PROTOCOL_VERSION).lenient(),
+            )
+            .unwrap(),
+            ClientReceiveCommand::Unknown(RawCommand { type_id: 0x3F, payload }) if payload.is_empty()
+        ));
+    }
+
+    #[test]
+    fn check_raw_command_pass_through() {
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand {
+            term: 3,
+            knowledge: 4,
+            has_connection_to_leader: true,
+            connection_index: ConnectionIndex(1),
+            clock_drift_ppm: 0,
+            knowledge_vector: None,
+        });
+        let mut original = OutOctetStream::new();
+        ping.to_octets_with_version(&mut original, PROTOCOL_VERSION)
+            .unwrap();
+
+        let mut in_stream = InOctetStream::new(original.data.clone());
+        let raw_command = RawCommand::from_cursor(&mut in_stream).unwrap();
+        assert_eq!(raw_command.type_id, PING_COMMAND_TYPE_ID);
+        assert_eq!(
+            raw_command,
+            RawCommand::from_command(&ping, PROTOCOL_VERSION).unwrap()
+        );
+
+        let mut forwarded = OutOctetStream::new();
+        raw_command
+            .to_octets_with_version(&mut forwarded, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(forwarded.data, original.data);
+        assert!(matches!(
+            raw_command
+                .decode::<ServerReceiveCommand>(PROTOCOL_VERSION)
+                .unwrap(),
+            PingCommandType(PingCommand { term: 3, .. })
+        ));
+
+        let mut framed = OutOctetStream::new();
+        write_frame_with_version(&raw_command, &mut framed, PROTOCOL_VERSION).unwrap();
+        write_frame_with_version(&raw_command, &mut framed, PROTOCOL_VERSION).unwrap();
+        let frames = FrameDecoder::<RawCommand>::new(&framed.data)
+            .with_version(PROTOCOL_VERSION)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert!(matches!(&frames[1], Frame::Command(forwarded) if *forwarded == raw_command));
+    }
+
+    #[test]
+    fn check_unknown_command_in_containers() {
+        let unknown = RawCommand {
+            type_id: 0x3E,
+            payload: vec![0x01, 0x02, 0x03],
+        };
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand {
+            term: 3,
+            ..PingCommand::default()
+        });
+        let mut ping_octets = OutOctetStream::new();
+        ping.to_octets_with_version(&mut ping_octets, PROTOCOL_VERSION)
+            .unwrap();
+
+        // One room with the unknown command followed by a ping
+        let mut octets = vec![0x01, 0x00, 0x00, 0x00, 0x07, 0x02, 0x3E, 0x01, 0x02, 0x03];
+        octets.extend_from_slice(&ping_octets.data);
+        let mut in_stream = InOctetStream::new(octets.clone());
+        assert!(MultiplexedDatagram::<RawCommand>::from_octets_with_version(
+            &mut in_stream,
+            PROTOCOL_VERSION
+        )
+        .is_err());
+        let mut in_stream = InOctetStream::new(octets);
+        assert!(
+            MultiplexedDatagram::<ServerReceiveCommand>::from_octets_with_version(
+                &mut in_stream,
+                PROTOCOL_VERSION
+            )
+            .is_err()
+        );
+
+        let mut datagram = MultiplexedDatagram::new();
+        datagram.push(7, ServerReceiveCommand::Unknown(unknown.clone()));
+        datagram.push(7, ping.clone());
+        let err = datagram
+            .to_octets_with_version(&mut OutOctetStream::new(), PROTOCOL_VERSION)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        // Last in the datagram, the unknown command ends where the datagram does
+        let mut datagram = MultiplexedDatagram::new();
+        datagram.push(7, ping.clone());
+        datagram.push(8, ServerReceiveCommand::Unknown(unknown.clone()));
+        datagram
+            .to_octets_with_version(&mut OutOctetStream::new(), PROTOCOL_VERSION)
+            .unwrap();
+        let mut datagram = MultiplexedDatagram::new();
+        datagram.push(8, unknown.clone());
+        let mut out_stream = OutOctetStream::new();
+        datagram
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert_eq!(
+            MultiplexedDatagram::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap(),
+            datagram
+        );
+
+        let unknown = ServerReceiveCommand::Unknown(unknown);
+        assert!(unknown
+            .encode_with_padding(None, 64, &mut OutOctetStream::new(), PROTOCOL_VERSION)
+            .is_err());
+        let mut extensions = Extensions::new();
+        extensions.insert(0x01, vec![8]);
+        assert!(unknown
+            .encode_with_extensions(
+                None,
+                &extensions,
+                &mut OutOctetStream::new(),
+                PROTOCOL_VERSION
+            )
+            .is_err());
+        unknown
+            .encode_with_extensions(
+                None,
+                &Extensions::new(),
+                &mut OutOctetStream::new(),
+                PROTOCOL_VERSION,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn check_decode_prefix_consumed_octets() {
+        let mut out_stream = OutOctetStream::new();
+        ClientReceiveCommand::RoomClosedType(RoomClosedCommand {
+            reason_code: 1,
+            closes_at_ms: 2,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        ClientReceiveCommand::MuteStateType(MuteStateCommand {
+            connection_index: ConnectionIndex(3),
+            muted: true,
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        let octets = out_stream.data;
+
+        let (first, consumed) =
+            ClientReceiveCommand::decode_prefix(&octets, PROTOCOL_VERSION).unwrap();
+        assert_eq!(consumed, 1 + 1 + 4);
+        assert!(matches!(first, RoomClosedType(_)));
+
+        let (second, consumed_second) =
+            ClientReceiveCommand::decode_prefix(&octets[consumed..], PROTOCOL_VERSION).unwrap();
+        assert_eq!(consumed + consumed_second, octets.len());
+        assert!(matches!(
+            second,
+            ClientReceiveCommand::MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(3),
+                muted: true
+            })
+        ));
+
+        let (_, consumed) =
+            ServerReceiveCommand::decode_prefix(&octets[consumed..], PROTOCOL_VERSION).unwrap();
+        assert_eq!(consumed, 3);
+    }
+
+    #[test]
+    fn check_from_slice_and_to_vec() {
+        let octets = ServerReceiveCommand::MuteStateType(MuteStateCommand {
+            connection_index: ConnectionIndex(2),
+            muted: false,
+        })
+        .to_vec_with_version(PROTOCOL_VERSION)
+        .unwrap();
+        assert_eq!(octets, [MUTE_STATE_COMMAND_TYPE_ID, 0x02, 0x00]);
+        assert!(matches!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(2),
+                muted: false
+            })
+        ));
+
+        let octets = ClientReceiveCommand::RoomClosedType(RoomClosedCommand {
+            reason_code: 0,
+            closes_at_ms: 0,
+        })
+        .to_vec_with_version(PROTOCOL_VERSION)
+        .unwrap();
+        assert!(ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).is_ok());
+
+        let mut trailing = octets;
+        trailing.push(0x00);
+        let err = ClientReceiveCommand::decode_exact(&trailing, PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(DecodeError::from_io(&err).unwrap().offset, Some(6));
+    }
+
+    #[test]
+    fn check_commands_are_hashable_values() {
+        let room_info = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(0),
+            client_infos: [ClientInfo::default()].into_iter().collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        });
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand::default());
+
+        let mut seen = std::collections::HashSet::new();
+        assert!(seen.insert(room_info.clone()));
+        assert!(!seen.insert(room_info.clone()));
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(
+                &room_info.to_vec_with_version(PROTOCOL_VERSION).unwrap(),
+                PROTOCOL_VERSION
+            )
+            .unwrap(),
+            room_info
+        );
+
+        let mut pings = std::collections::HashMap::new();
+        pings.insert(ping.clone(), "ping");
+        assert_eq!(pings.get(&ping), Some(&"ping"));
+        assert_ne!(
+            ping,
+            ServerReceiveCommand::PingCommandType(PingCommand {
+                term: 1,
+                ..PingCommand::default()
+            })
+        );
+    }
+
+    #[test]
+    fn check_reserved_term() {
+        assert_eq!(TERM_UNKNOWN, <u16 as TermExt>::UNKNOWN);
+        assert!(TERM_UNKNOWN.is_unknown());
+
+        let err = RoomInfoCommand::builder()
+            .term(TERM_UNKNOWN)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, ProtocolError::ReservedTerm { term: TERM_UNKNOWN });
+
+        // Decoding accepts it, as peers send it before they know the term
+        let mut octets = [0x00; 1 + 11];
+        octets[..3].copy_from_slice(&[ROOM_INFO_COMMAND_TYPE_ID, 0xFF, 0xFF]);
+        assert!(ClientReceiveCommand::decode_checked(&octets, PROTOCOL_VERSION).is_ok());
+
+        let response = PingResponseCommand {
+            term: TERM_UNKNOWN,
+            ..PingResponseCommand::default()
+        };
+        assert!(response.to_octets(&mut OutOctetStream::new()).is_err());
+
+        let mut out_stream = OutOctetStream::new();
+        ServerReceiveCommand::PingCommandType(PingCommand {
+            term: TERM_UNKNOWN,
+            ..PingCommand::default()
+        })
+        .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+        .unwrap();
+        assert!(ServerReceiveCommand::decode_exact(&out_stream.data, PROTOCOL_VERSION).is_ok());
+    }
+
+    #[test]
+    fn check_knowledge_floor() {
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand {
+            term: 2,
+            knowledge: 99,
+            ..PingCommand::default()
+        });
+        let octets = ping.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+
+        let options = DecodeOptions::new(PROTOCOL_VERSION).with_knowledge_floor(99);
+        let decoded = ServerReceiveCommand::from_cursor_with_options(
+            &mut InOctetStream::new(octets.clone()),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(decoded, ping);
+
+        let options = DecodeOptions::new(PROTOCOL_VERSION)
+            .with_knowledge_floor(100)
+            .lenient();
+        let err = ServerReceiveCommand::from_cursor_with_options(
+            &mut InOctetStream::new(octets.clone()),
+            &options,
+        )
+        .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::KnowledgeBelowFloor {
+                knowledge: 99,
+                floor: 100
+            })
+        );
+
+        let response = ClientReceiveCommand::PingResponseType(PingResponseCommand {
+            term: 2,
+            server_knowledge: 10,
+            your_knowledge_ack: 4,
+        });
+        let octets = response.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        let options = DecodeOptions::new(PROTOCOL_VERSION).with_knowledge_floor(10);
+        assert!(ClientReceiveCommand::from_octets_with_options(
+            &mut InOctetStream::new(octets.clone()),
+            &options
+        )
+        .is_ok());
+        let options = DecodeOptions::new(PROTOCOL_VERSION).with_knowledge_floor(11);
+        assert!(ClientReceiveCommand::from_octets_with_options(
+            &mut InOctetStream::new(octets.clone()),
+            &options
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn check_psk_auth() {
+        let psk_auth = PskAuthCommand {
+            key_id: 0x0102,
+            proof: [0xAB; 32],
+        };
+        let octets = ServerReceiveCommand::PskAuthType(psk_auth.clone())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(
+            octets.len(),
+            1 + PskAuthCommand::min_octet_size(PROTOCOL_VERSION)
+        );
+        assert_eq!(octets[..3], [PSK_AUTH_COMMAND_TYPE_ID, 0x01, 0x02]);
+        assert_eq!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ServerReceiveCommand::PskAuthType(psk_auth)
+        );
+        assert!(
+            ServerReceiveCommand::decode_exact(&octets[..octets.len() - 1], PROTOCOL_VERSION)
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "psk")]
+    #[test]
+    fn check_psk_proof() {
+        // RFC 4231 test case 2
+        let expected = [
+            0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e, 0x6a, 0x04, 0x24, 0x26, 0x08, 0x95,
+            0x75, 0xc7, 0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83, 0x9d, 0xec, 0x58, 0xb9,
+            0x64, 0xec, 0x38, 0x43,
+        ];
+        assert_eq!(
+            crate::compute_psk_proof(b"Jefe", b"what do ya want for nothing?"),
+            expected
+        );
+
+        let psk_auth = PskAuthCommand::new(7, b"secret", b"challenge");
+        assert_eq!(psk_auth.key_id, 7);
+        assert!(psk_auth.verify(b"secret", b"challenge"));
+        assert!(!psk_auth.verify(b"secret", b"other challenge"));
+        assert!(!psk_auth.verify(b"other secret", b"challenge"));
+    }
+
+    #[test]
+    fn check_challenge() {
+        let challenge = ChallengeCommand {
+            challenge: [0x5A; 32],
+            server_load: None,
+        };
+        let octets = ClientReceiveCommand::ChallengeType(challenge.clone())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(octets.len(), 1 + 32 + 1);
+        assert_eq!(octets[0], CHALLENGE_COMMAND_TYPE_ID);
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ClientReceiveCommand::ChallengeType(challenge.clone())
+        );
+
+        let loaded = ChallengeCommand {
+            server_load: Some(ServerLoadCommand {
+                cpu_permille: 750,
+                rooms: 12,
+                headroom: 4,
+            }),
+            ..challenge.clone()
+        };
+        let octets = ClientReceiveCommand::ChallengeType(loaded.clone())
+            .to_vec_with_version(PROTOCOL_VERSION_14)
+            .unwrap();
+        assert_eq!(
+            octets[1 + 32..],
+            [1 << CHALLENGE_SERVER_LOAD_BIT, 0x02, 0xEE, 0x00, 0x0C, 0x04]
+        );
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION_14).unwrap(),
+            ClientReceiveCommand::ChallengeType(loaded.clone())
+        );
+
+        // Not serialized before the version that added it
+        let octets = ClientReceiveCommand::ChallengeType(loaded)
+            .to_vec_with_version(PROTOCOL_VERSION_13)
+            .unwrap();
+        assert_eq!(octets.len(), 1 + 32);
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION_13).unwrap(),
+            ClientReceiveCommand::ChallengeType(challenge)
+        );
+
+        // Unknown presence bits are rejected
+        let mut octets = vec![CHALLENGE_COMMAND_TYPE_ID];
+        octets.extend([0x5A; 32]);
+        octets.push(0x02);
+        assert!(ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION_14).is_err());
+    }
+
+    #[cfg(feature = "psk")]
+    #[test]
+    fn check_psk_handshake() {
+        use crate::{ClientHandshake, ServerHandshake};
+
+        fn key_for(key_id: u16) -> Option<&'static [u8]> {
+            (key_id == 3).then_some(b"shared secret".as_slice())
+        }
+
+        let (mut client, hello) = ClientHandshake::start(3, b"shared secret");
+        let mut server = ServerHandshake::new([0x11; 32]);
+
+        // Out of order: the client must say hello first
+        let mut early = ServerHandshake::new([0x11; 32]);
+        let psk_auth = ServerReceiveCommand::PskAuthType(PskAuthCommand::default());
+        let err = early.receive(&psk_auth, key_for).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::UnexpectedHandshakeCommand {
+                command_type_id: PSK_AUTH_COMMAND_TYPE_ID
+            })
+        );
+
+        let replies = server.receive(&hello, key_for).unwrap();
+        assert_eq!(replies.len(), 2);
+        assert!(client.receive(&replies[0]).unwrap().is_none());
+        let psk_auth = client.receive(&replies[1]).unwrap().unwrap();
+        assert!(client.is_done());
+
+        assert!(server.receive(&psk_auth, key_for).unwrap().is_empty());
+        assert!(server.is_done());
+        assert_eq!(server.key_id(), Some(3));
+        assert_eq!(server.version(), Some(PROTOCOL_VERSION));
+        assert_eq!(client.version(), Some(PROTOCOL_VERSION));
+        assert_eq!(server.session_keys(), client.session_keys());
+        let session_keys = server.session_keys().unwrap();
+        assert_ne!(session_keys.client_to_server, session_keys.server_to_client);
+        assert_ne!(session_keys.client_to_server, [0x11; 32]);
+        assert_eq!(client.server_load(), None);
+
+        // The server load is part of the challenge
+        let server_load = ServerLoadCommand {
+            cpu_permille: 420,
+            rooms: 7,
+            headroom: 3,
+        };
+        let (mut client, hello) = ClientHandshake::start(3, b"shared secret");
+        let mut server = ServerHandshake::new([0x33; 32]).with_server_load(server_load);
+        let replies = server.receive(&hello, key_for).unwrap();
+        assert_eq!(replies.len(), 2);
+        let octets = replies[1].to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        let challenge = ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap();
+        client.receive(&replies[0]).unwrap();
+        let psk_auth = client.receive(&challenge).unwrap().unwrap();
+        assert_eq!(client.server_load(), Some(server_load));
+        assert!(server.receive(&psk_auth, key_for).unwrap().is_empty());
+        assert!(server.is_done());
+
+        // but only from the version that can carry it
+        let mut server = ServerHandshake::new([0x33; 32]).with_server_load(server_load);
+        let old_hello = ServerReceiveCommand::HelloType(HelloCommand::new(PROTOCOL_VERSION_13));
+        let replies = server.receive(&old_hello, key_for).unwrap();
+        let ClientReceiveCommand::ChallengeType(challenge) = &replies[1] else {
+            panic!("not a challenge");
+        };
+        assert_eq!(challenge.server_load, None);
+
+        // A wrong key fails and the challenge can not be retried
+        let (mut client, hello) = ClientHandshake::start(3, b"guess");
+        let mut server = ServerHandshake::new([0x22; 32]);
+        let replies = server.receive(&hello, key_for).unwrap();
+        client.receive(&replies[0]).unwrap();
+        let psk_auth = client.receive(&replies[1]).unwrap().unwrap();
+        let err = server.receive(&psk_auth, key_for).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::AuthenticationFailed { key_id: 3 })
+        );
+        assert!(server.receive(&psk_auth, key_for).is_err());
+        assert!(!server.is_done());
+    }
+
+    #[cfg(feature = "psk")]
+    #[test]
+    fn check_handshake_fingerprint() {
+        use crate::{ClientHandshake, ServerHandshake};
+
+        let other_build = HelloCommand {
+            version: PROTOCOL_VERSION,
+            fingerprint: PROTOCOL_FINGERPRINT ^ 1,
+        };
+        let expected = Some(&ProtocolError::FingerprintMismatch {
+            expected: PROTOCOL_FINGERPRINT,
+            found: PROTOCOL_FINGERPRINT ^ 1,
+        });
+
+        let mut server = ServerHandshake::new([0x11; 32]);
+        let err = server
+            .receive(&ServerReceiveCommand::HelloType(other_build), |_| {
+                None::<&[u8]>
+            })
+            .unwrap_err();
+        assert_eq!(ProtocolError::from_io(&err), expected);
+        assert!(!server.is_done());
+
+        let (mut client, _) = ClientHandshake::start(3, b"shared secret");
+        let err = client
+            .receive(&ClientReceiveCommand::HelloType(other_build))
+            .unwrap_err();
+        assert_eq!(ProtocolError::from_io(&err), expected);
+        assert!(!client.is_done());
+    }
+
+    #[cfg(feature = "psk")]
+    #[test]
+    fn check_handshake_rejects_newer_version() {
+        use crate::ClientHandshake;
+
+        let (mut client, _) = ClientHandshake::start(3, b"shared secret");
+        let newer = HelloCommand::new(PROTOCOL_VERSION + 1);
+        let err = client
+            .receive(&ClientReceiveCommand::HelloType(newer))
+            .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::UnsupportedVersion {
+                version: PROTOCOL_VERSION + 1,
+                newest: PROTOCOL_VERSION,
+            })
+        );
+
+        let current = HelloCommand::new(PROTOCOL_VERSION);
+        assert!(client
+            .receive(&ClientReceiveCommand::HelloType(current))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn check_connect_token() {
+        let token = ConnectToken {
+            expires_at_s: 1_700_000_000,
+            client_id: 42,
+            server_addresses: vec![
+                "10.0.0.1:4000".parse().unwrap(),
+                "[::1]:4001".parse().unwrap(),
+            ],
+            private_data: vec![0xEE; 16],
+        };
+        let command = ServerReceiveCommand::ConnectWithTokenType(ConnectWithTokenCommand {
+            token: token.clone(),
+        });
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(octets[0], CONNECT_WITH_TOKEN_COMMAND_TYPE_ID);
+        assert_eq!(octets.len(), 1 + 8 + 8 + 1 + 7 + 19 + 2 + 16);
+        assert_eq!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        assert!(!token.is_expired(1_699_999_999));
+        assert!(token.is_expired(1_700_000_000));
+        assert!(token.is_valid_for(&"[::1]:4001".parse().unwrap()));
+        assert!(!token.is_valid_for(&"10.0.0.1:4001".parse().unwrap()));
+
+        let no_servers = ConnectToken {
+            server_addresses: Vec::new(),
+            ..token.clone()
+        };
+        let mut out_stream = OutOctetStream::new();
+        assert!(no_servers.to_octets(&mut out_stream).is_err());
+        let too_many_servers = ConnectToken {
+            server_addresses: vec![
+                token.server_addresses[0];
+                MAX_CONNECT_TOKEN_SERVER_ADDRESSES + 1
+            ],
+            ..token
+        };
+        assert!(too_many_servers.to_octets(&mut out_stream).is_err());
+
+        let mut bad_address_type = octets.clone();
+        bad_address_type[1 + 8 + 8 + 1] = 0x03;
+        assert!(ServerReceiveCommand::decode_exact(&bad_address_type, PROTOCOL_VERSION).is_err());
+    }
+
+    #[cfg(feature = "secure")]
+    #[test]
+    fn check_connect_token_seal() {
+        use crate::{
+            ConnectTokenPrivate, CONNECT_TOKEN_SEAL_OVERHEAD_OCTETS,
+            MAX_CONNECT_TOKEN_PRIVATE_OCTETS,
+        };
+
+        let key = [0x01; 32];
+        let mut token = ConnectToken {
+            expires_at_s: 1_700_000_000,
+            client_id: 42,
+            server_addresses: vec!["10.0.0.1:4000".parse().unwrap()],
+            private_data: Vec::new(),
+        };
+        token.seal(&[0xAB, 0xCD], &key, &[0x02; 24]).unwrap();
+        assert_eq!(
+            token.private_data.len(),
+            CONNECT_TOKEN_SEAL_OVERHEAD_OCTETS + 2
+        );
+        let opened = token.validate(&key, 1_699_999_999).unwrap();
+        assert_eq!(
+            opened,
+            ConnectTokenPrivate {
+                expires_at_s: 1_700_000_000,
+                client_id: 42,
+                user_data: vec![0xAB, 0xCD],
+            }
+        );
+        let err = token.validate(&key, 1_700_000_000).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::ConnectTokenExpired {
+                expires_at_s: 1_700_000_000,
+                now_s: 1_700_000_000
+            })
+        );
+
+        // The Client can not extend the expiry, or change any other public field
+        let extended = ConnectToken {
+            expires_at_s: u64::MAX,
+            ..token.clone()
+        };
+        let err = extended.validate(&key, 1_700_000_000).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::DecryptionFailed)
+        );
+        let other_server = ConnectToken {
+            server_addresses: vec!["10.0.0.2:4000".parse().unwrap()],
+            ..token.clone()
+        };
+        assert!(other_server.open(&key).is_err());
+        assert!(token.open(&[0x03; 32]).is_err());
+        assert!(ConnectToken::default().open(&key).is_err());
+
+        let command = ConnectWithTokenCommand {
+            token: token.clone(),
+        };
+        let server_address = "10.0.0.1:4000".parse().unwrap();
+        assert_eq!(
+            command
+                .accept(&key, &server_address, 1_699_999_999)
+                .unwrap(),
+            opened
+        );
+        let other_address = "10.0.0.2:4000".parse().unwrap();
+        let err = command
+            .accept(&key, &other_address, 1_699_999_999)
+            .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::ConnectTokenWrongServer {
+                address: other_address
+            })
+        );
+        assert!(command
+            .accept(&key, &server_address, 1_700_000_000)
+            .is_err());
+
+        assert!(token
+            .seal(&[0; MAX_CONNECT_TOKEN_PRIVATE_OCTETS], &key, &[0x02; 24])
+            .is_err());
+    }
+
+    #[cfg(feature = "secure")]
+    #[test]
+    fn check_secure_session() {
+        use crate::{SecureSession, SECURE_OVERHEAD_OCTETS};
+
+        let client_to_server = [0x01; 32];
+        let server_to_client = [0x02; 32];
+        let mut client = SecureSession::new(&client_to_server, &server_to_client, PROTOCOL_VERSION);
+        let mut server = SecureSession::new(&server_to_client, &client_to_server, PROTOCOL_VERSION);
+
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand {
+            term: 3,
+            knowledge: 77,
+            ..PingCommand::default()
+        });
+        let first = client.seal(&ping).unwrap();
+        let second = client.seal(&ping).unwrap();
+        assert_eq!(client.next_send_sequence(), 2);
+        assert_eq!(
+            first.len(),
+            ping.to_vec_with_version(PROTOCOL_VERSION).unwrap().len() + SECURE_OVERHEAD_OCTETS
+        );
+        assert_ne!(first[8..], second[8..]);
+        assert_eq!(server.open::<ServerReceiveCommand>(&second).unwrap(), ping);
+        assert_eq!(server.open::<ServerReceiveCommand>(&first).unwrap(), ping);
+        let err = server.open::<ServerReceiveCommand>(&first).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::ReplayedSequence { sequence: 0 })
+        );
+
+        // Only the peer can open, and any modification is detected
+        assert!(client.open::<ServerReceiveCommand>(&first).is_err());
+        let mut tampered = first.clone();
+        tampered[0] ^= 0x01;
+        let err = server.open::<ServerReceiveCommand>(&tampered).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::DecryptionFailed)
+        );
+        assert!(server.open::<ServerReceiveCommand>(&first[..10]).is_err());
+
+        let closed = ClientReceiveCommand::RoomClosedType(RoomClosedCommand::default());
+        let sealed = server.seal(&closed).unwrap();
+        assert_eq!(
+            client.open::<ClientReceiveCommand>(&sealed).unwrap(),
+            closed
+        );
+    }
+
+    #[cfg(feature = "secure")]
+    #[test]
+    fn check_sequence_window() {
+        use crate::{SequenceWindow, SEQUENCE_WINDOW_SIZE};
+
+        let mut window = SequenceWindow::new();
+        assert_eq!(window.expand(5), 5);
+        for sequence in [3, 1, 2, 7] {
+            window.check(sequence).unwrap();
+            window.mark(sequence);
+        }
+        assert_eq!(window.highest(), Some(7));
+        assert_eq!(
+            window.check(2),
+            Err(ProtocolError::ReplayedSequence { sequence: 2 })
+        );
+        assert!(window.check(0).is_ok());
+        assert!(window.check(6).is_ok());
+
+        window.mark(7 + SEQUENCE_WINDOW_SIZE);
+        assert_eq!(
+            window.check(7),
+            Err(ProtocolError::SequenceTooOld {
+                sequence: 7,
+                highest: 7 + SEQUENCE_WINDOW_SIZE
+            })
+        );
+        assert!(window.check(8).is_ok());
+
+        // Reconstruction picks the candidate closest to the highest, across 16 bit wraps
+        let mut window = SequenceWindow::new();
+        window.mark(0x1_FFF0);
+        assert_eq!(window.expand(0xFFF8), 0x1_FFF8);
+        assert_eq!(window.expand(0x0004), 0x2_0004);
+        assert_eq!(window.expand(0xFF00), 0x1_FF00);
+        window.mark(0x2_0004);
+        assert_eq!(window.expand(0xFFF0), 0x1_FFF0);
+        let mut window = SequenceWindow::new();
+        window.mark(3);
+        assert_eq!(window.expand(0xFFFE), 0xFFFE);
+    }
+
+    #[cfg(feature = "secure")]
+    #[test]
+    fn check_secure_session_truncated_sequences() {
+        use crate::{SecureSession, SECURE_TRUNCATED_OVERHEAD_OCTETS};
+
+        let client_to_server = [0x03; 32];
+        let server_to_client = [0x04; 32];
+        let mut client = SecureSession::new(&client_to_server, &server_to_client, PROTOCOL_VERSION)
+            .with_truncated_sequences();
+        let mut server = SecureSession::new(&server_to_client, &client_to_server, PROTOCOL_VERSION)
+            .with_truncated_sequences();
+        assert_eq!(client.overhead_octets(), SECURE_TRUNCATED_OVERHEAD_OCTETS);
+
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand::default());
+        let sealed: Vec<Vec<u8>> = (0..0x60).map(|_| client.seal(&ping).unwrap()).collect();
+        assert_eq!(
+            sealed[0].len(),
+            ping.to_vec_with_version(PROTOCOL_VERSION).unwrap().len()
+                + SECURE_TRUNCATED_OVERHEAD_OCTETS
+        );
+
+        // Reordered, see check_sequence_window for the 16 bit wrap
+        for index in [0x10, 0x0F, 0x14, 0x11, 0x12, 0x13, 0x15, 0x5F, 0x20] {
+            assert_eq!(
+                server.open::<ServerReceiveCommand>(&sealed[index]).unwrap(),
+                ping
+            );
+        }
+        let err = server
+            .open::<ServerReceiveCommand>(&sealed[0x20])
+            .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::ReplayedSequence { sequence: 0x20 })
+        );
+        let err = server
+            .open::<ServerReceiveCommand>(&sealed[0x16])
+            .unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::SequenceTooOld {
+                sequence: 0x16,
+                highest: 0x5F
+            })
+        );
+    }
+
+    #[test]
+    fn check_padded_envelope() {
+        let hello = ServerReceiveCommand::HelloType(HelloCommand::new(PROTOCOL_VERSION));
+        let mut request = OutOctetStream::new();
+        hello
+            .encode_with_padding(None, 64, &mut request, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(request.data.len(), 64);
+        assert_eq!(request.data[0], ENVELOPE_FLAG_PADDING);
+
+        let response = ClientReceiveCommand::HelloType(HelloCommand::new(PROTOCOL_VERSION));
+        let mut out_stream = OutOctetStream::new();
+        response
+            .encode_with_padding(
+                Some(9),
+                request.data.len(),
+                &mut out_stream,
+                PROTOCOL_VERSION,
+            )
+            .unwrap();
+        assert_eq!(out_stream.data.len(), request.data.len());
+        let mut in_stream = InOctetStream::new(out_stream.data.clone());
+        let (correlation_id, decoded) =
+            ClientReceiveCommand::decode_with_correlation(&mut in_stream, PROTOCOL_VERSION)
+                .unwrap();
+        assert_eq!(correlation_id, Some(9));
+        assert_eq!(decoded, response);
+
+        // Already large enough, so no padding is added
+        let mut unpadded = OutOctetStream::new();
+        response
+            .encode_with_padding(None, 4, &mut unpadded, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(unpadded.data[0], 0x00);
+        assert_eq!(
+            unpadded.data.len(),
+            1 + 1 + HelloCommand::min_octet_size(PROTOCOL_VERSION)
+        );
+
+        let mut non_zero = out_stream.data;
+        *non_zero.last_mut().unwrap() = 0x01;
+        assert!(ClientReceiveCommand::decode_with_correlation(
+            &mut InOctetStream::new(non_zero),
+            PROTOCOL_VERSION
+        )
+        .is_err());
+
+        // Padding longer than the chunks it is checked in
+        let mut long = OutOctetStream::new();
+        response
+            .encode_with_padding(None, 300, &mut long, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(long.data.len(), 300);
+        let (_, decoded) = ClientReceiveCommand::decode_with_correlation(
+            &mut InOctetStream::new(long.data.clone()),
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert_eq!(decoded, response);
+        let mut non_zero = long.data;
+        non_zero[200] = 0x01;
+        assert!(ClientReceiveCommand::decode_with_correlation(
+            &mut InOctetStream::new(non_zero),
+            PROTOCOL_VERSION
+        )
+        .is_err());
+
+        // A padding length larger than the datagram fails without reading past it
+        let mut truncated = vec![ENVELOPE_FLAG_PADDING, 0xFF, 0xFF];
+        truncated.extend(response.to_vec_with_version(PROTOCOL_VERSION).unwrap());
+        truncated.extend([0x00; 16]);
+        assert!(ClientReceiveCommand::decode_with_correlation(
+            &mut InOctetStream::new(truncated),
+            PROTOCOL_VERSION
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn check_retry_cookie() {
+        let retry =
+            ClientReceiveCommand::RetryCookieType(RetryCookieCommand { cookie: [0x33; 16] });
+        let octets = retry.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(octets.len(), 1 + 16);
+        assert_eq!(octets[0], RETRY_COOKIE_COMMAND_TYPE_ID);
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            retry
+        );
+
+        let connect = ServerReceiveCommand::ConnectWithCookieType(ConnectWithCookieCommand {
+            cookie: [0x33; 16],
+            join_request: JoinRequestCommand {
+                identity: ClientIdentity::U64(8),
+                requested_role_flags: ROLE_FLAG_PLAYER,
+            },
+        });
+        let octets = connect.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(octets[0], CONNECT_WITH_COOKIE_COMMAND_TYPE_ID);
+        assert_eq!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            connect
+        );
+    }
+
+    #[cfg(feature = "cookie")]
+    #[test]
+    fn check_retry_cookie_mint_and_verify() {
+        use crate::RETRY_COOKIE_PERIOD_S;
+
+        let address = "192.0.2.7:5000".parse().unwrap();
+        let now_s = 1_700_000_000;
+        let retry = RetryCookieCommand::mint(b"server secret", &address, now_s);
+        let connect = ConnectWithCookieCommand {
+            cookie: retry.cookie,
+            join_request: JoinRequestCommand::default(),
+        };
+        assert!(connect.verify(b"server secret", &address, now_s));
+        assert!(connect.verify(b"server secret", &address, now_s + RETRY_COOKIE_PERIOD_S));
+        assert!(!connect.verify(
+            b"server secret",
+            &address,
+            now_s + 2 * RETRY_COOKIE_PERIOD_S
+        ));
+        assert!(!connect.verify(b"other secret", &address, now_s));
+        assert!(!connect.verify(b"server secret", &"192.0.2.7:5001".parse().unwrap(), now_s));
+    }
+
+    #[test]
+    fn check_throttled() {
+        let throttled = ThrottledCommand::new(std::time::Duration::from_millis(1500));
+        assert_eq!(throttled.retry_after_ms, 1500);
+        assert_eq!(
+            throttled.retry_after(),
+            std::time::Duration::from_millis(1500)
+        );
+        assert_eq!(
+            ThrottledCommand::new(std::time::Duration::from_secs(600)).retry_after_ms,
+            u16::MAX
+        );
+
+        let command = ClientReceiveCommand::ThrottledType(throttled);
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(octets, [THROTTLED_COMMAND_TYPE_ID, 0x05, 0xDC]);
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+    }
+
+    #[test]
+    fn check_timeout_config() {
+        let server = TimeoutConfigCommand::new(
+            std::time::Duration::from_secs(10),
+            std::time::Duration::from_millis(500),
+        );
+        assert_eq!(
+            server.disconnect_after(),
+            std::time::Duration::from_secs(10)
+        );
+        assert_eq!(
+            TimeoutConfigCommand::new(
+                std::time::Duration::from_secs(1 << 40),
+                std::time::Duration::from_secs(600)
+            ),
+            TimeoutConfigCommand {
+                disconnect_after_ms: u32::MAX,
+                ping_interval_ms: u16::MAX,
+            }
+        );
+
+        let client = TimeoutConfigCommand {
+            disconnect_after_ms: 15_000,
+            ping_interval_ms: 0,
+        };
+        let agreed = TimeoutConfigCommand {
+            disconnect_after_ms: 15_000,
+            ping_interval_ms: 500,
+        };
+        assert_eq!(server.negotiate(&client), agreed);
+        assert_eq!(client.negotiate(&server), agreed);
+
+        let octets = ServerReceiveCommand::TimeoutConfigType(client)
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(
+            octets,
+            [
+                TIMEOUT_CONFIG_COMMAND_TYPE_ID,
+                0x00,
+                0x00,
+                0x3A,
+                0x98,
+                0x00,
+                0x00
+            ]
+        );
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ClientReceiveCommand::TimeoutConfigType(client)
+        );
+        let mut out_stream = OutOctetStream::new();
+        assert!(ServerReceiveCommand::TimeoutConfigType(client)
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_5)
+            .is_err());
+    }
+
+    #[test]
+    fn check_update_room_settings() {
+        let mut settings = Extensions::new();
+        settings.insert(0x01, vec![8]);
+        let update = UpdateRoomSettingsCommand {
+            settings_version: 2,
+            settings,
+        };
+        assert!(update.supersedes(1));
+        assert!(!update.supersedes(2));
+
+        let octets = ServerReceiveCommand::UpdateRoomSettingsType(update.clone())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(
+            octets,
+            [0x1F, 0x00, 0x00, 0x00, 0x02, 0x00, 0x04, 0x01, 0x00, 0x01, 0x08]
+        );
+        // The Server forwards the same octets to the Clients
+        let ClientReceiveCommand::UpdateRoomSettingsType(forwarded) =
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap()
+        else {
+            panic!("should be update room settings command");
+        };
+        assert_eq!(forwarded, update);
+        assert_eq!(
+            forwarded
+                .settings
+                .read_with(0x01, |reader| reader.read_u8())
+                .unwrap(),
+            Some(8)
+        );
+
+        let truncated = [
+            0x1F, 0x00, 0x00, 0x00, 0x02, 0x00, 0x04, 0x01, 0x00, 0x02, 0x08,
+        ];
+        let err = ServerReceiveCommand::decode_exact(&truncated, PROTOCOL_VERSION).unwrap_err();
+        assert!(err.to_string().contains("settings"), "{}", err);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn check_test_vectors() {
+        use crate::{test_vector_manifest, test_vectors};
+
+        let vectors = test_vectors();
+        for vector in &vectors {
+            let octets = vector.to_vec().unwrap();
+            assert_eq!(octets[0], vector.command_type_id(), "{}", vector.name);
+            vector.check(&octets).unwrap();
+        }
+        for pair in vectors.iter().enumerate() {
+            assert!(
+                vectors[..pair.0]
+                    .iter()
+                    .all(|other| other.name != pair.1.name),
+                "duplicate {}",
+                pair.1.name
+            );
+        }
+
+        let manifest = test_vector_manifest(&vectors).unwrap();
+        assert_eq!(manifest.matches("\"file\":").count(), vectors.len());
+        assert!(manifest.contains("\"file\": \"ping_zero.bin\""));
+        assert!(manifest.contains(&u128::MAX.to_string()));
+
+        for name in [
+            "ping_boundary_min",
+            "ping_boundary_term_65535",
+            "bandwidth_report_boundary_bytes_sent_127",
+            "bandwidth_report_boundary_bytes_sent_128",
+            "bandwidth_report_boundary_bytes_sent_16383",
+            "bandwidth_report_boundary_bytes_sent_16384",
+            "redirect_boundary_token_255",
+            "redirect_boundary_token_256",
+            "relay_to_client_boundary_payload_65535",
+            "transfer_resend_request_boundary_missing_chunk_indices_0_65535",
+        ] {
+            assert!(vectors.iter().any(|vector| vector.name == name), "{}", name);
+        }
+        assert!(!vectors
+            .iter()
+            .any(|vector| vector.name == "broadcast_payload_boundary_payload_65535"));
+    }
+
+    #[cfg(feature = "pcap")]
+    #[test]
+    fn check_pcap_reader() {
+        use crate::{CapturedCommand, CommandDirection, PcapReader};
+
+        fn frame(source_port: u16, destination_port: u16, protocol: u8, payload: &[u8]) -> Vec<u8> {
+            let mut frame = vec![0u8; 12];
+            frame.extend_from_slice(&[0x08, 0x00]);
+            let total_length = (20 + 8 + payload.len()) as u16;
+            frame.extend_from_slice(&[0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, protocol, 0, 0]);
+            frame[16..18].copy_from_slice(&total_length.to_be_bytes());
+            frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+            frame.extend_from_slice(&source_port.to_be_bytes());
+            frame.extend_from_slice(&destination_port.to_be_bytes());
+            frame.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+            frame.extend_from_slice(&[0, 0]);
+            frame.extend_from_slice(payload);
+            // Ethernet pads short frames
+            frame.extend_from_slice(&[0; 4]);
+            frame
+        }
+
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand::default());
+        let room_info = ClientReceiveCommand::RoomInfoType(RoomInfoCommand::default());
+        let frames = [
+            frame(
+                50000,
+                27000,
+                17,
+                &ping.to_vec_with_version(PROTOCOL_VERSION).unwrap(),
+            ),
+            frame(50000, 27000, 6, &[1, 2, 3]),
+            frame(
+                27000,
+                50000,
+                17,
+                &room_info.to_vec_with_version(PROTOCOL_VERSION).unwrap(),
+            ),
+        ];
+
+        let mut capture = vec![0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0];
+        capture.extend_from_slice(&[0; 8]);
+        capture.extend_from_slice(&65535u32.to_le_bytes());
+        capture.extend_from_slice(&1u32.to_le_bytes());
+        for (index, frame) in frames.iter().enumerate() {
+            capture.extend_from_slice(&(1_700_000_000 + index as u32).to_le_bytes());
+            capture.extend_from_slice(&250_000u32.to_le_bytes());
+            capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            capture.extend_from_slice(frame);
+        }
+
+        let records: Vec<_> = PcapReader::new(capture.as_slice(), PROTOCOL_VERSION)
+            .unwrap()
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].timestamp,
+            std::time::Duration::from_millis(1_700_000_000_250)
+        );
+        assert_eq!(records[0].source, "10.0.0.1:50000".parse().unwrap());
+        assert_eq!(records[0].direction, CommandDirection::ClientToServer);
+        assert_eq!(
+            *records[0].command.as_ref().unwrap(),
+            CapturedCommand::Server(ping)
+        );
+        assert_eq!(records[1].direction, CommandDirection::ServerToClient);
+        assert_eq!(
+            *records[1].command.as_ref().unwrap(),
+            CapturedCommand::Client(room_info)
+        );
+
+        assert!(PcapReader::new(&[0x0A, 0x0D, 0x0D, 0x0A][..], PROTOCOL_VERSION).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn check_conformance() {
+        use crate::{run_conformance, test_vector_manifest, test_vectors, ConformanceFailure};
+
+        let directory =
+            std::env::temp_dir().join(format!("conclave-conformance-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let vectors = test_vectors();
+        for vector in &vectors {
+            std::fs::write(
+                directory.join(format!("{}.bin", vector.name)),
+                vector.to_vec().unwrap(),
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            directory.join("manifest.json"),
+            test_vector_manifest(&vectors).unwrap(),
+        )
+        .unwrap();
+
+        let report = run_conformance(&directory).unwrap();
+        assert!(report.is_success(), "{}", report);
+        assert_eq!(report.passed, vectors.len());
+
+        // term 0xFFFE becomes 0x7FFE
+        let mut octets = std::fs::read(directory.join("ping_max.bin")).unwrap();
+        octets[1] = 0x7F;
+        std::fs::write(directory.join("ping_max.bin"), octets).unwrap();
+        std::fs::write(directory.join("ping_zero.bin"), [PING_COMMAND_TYPE_ID]).unwrap();
+
+        let report = run_conformance(&directory).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(report.passed, vectors.len() - 2);
+        assert!(matches!(
+            report.failures[0],
+            (ref name, ConformanceFailure::Decode(_)) if name == "ping_zero"
+        ));
+        let ConformanceFailure::Mismatch(differences) = &report.failures[1].1 else {
+            panic!("expected a mismatch, got {:?}", report.failures[1]);
+        };
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].path, "/PingCommandType/term");
+        assert_eq!(
+            differences[0].to_string(),
+            "/PingCommandType/term: expected 65534, decoded 32766"
+        );
+    }
+
+    #[test]
+    fn check_descriptor_fields() {
+        use crate::{fields_min_octet_size, COMMAND_DESCRIPTORS};
+
+        for descriptor in COMMAND_DESCRIPTORS {
+            for version in descriptor.min_version..=PROTOCOL_VERSION {
+                assert_eq!(
+                    fields_min_octet_size(descriptor.fields, version),
+                    (descriptor.min_octet_size)(version),
+                    "{} in version {}",
+                    descriptor.name,
+                    version
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn check_kaitai_struct() {
+        use crate::kaitai_struct;
+
+        let ksy = kaitai_struct(PROTOCOL_VERSION);
+        assert!(ksy.contains("        0x01: ping\n"));
+        assert!(ksy.contains("        0xc7: hello\n"));
+        assert!(ksy.contains("      - id: clock_drift_ppm\n        type: s2\n"));
+        assert!(ksy.contains("    - /common/vlq_base128_le\n"));
+        assert!(ksy.contains(
+            "      - id: knowledge_vector\n        type: knowledge_vector\n        if: (flags & 0x02) != 0\n"
+        ));
+
+        // Every referenced type is built in, imported or defined
+        for version in PROTOCOL_VERSION_1..=PROTOCOL_VERSION {
+            let ksy = kaitai_struct(version);
+            let referenced = ksy
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("type: "))
+                .chain(ksy.lines().filter_map(|line| {
+                    let (key, value) = line.trim().split_once(": ")?;
+                    (key.starts_with("0x") || key.parse::<u8>().is_ok()).then_some(value)
+                }));
+            for type_name in referenced {
+                assert!(
+                    ["u1", "u2", "u4", "u8", "s2", "str", "vlq_base128_le"].contains(&type_name)
+                        || ksy.contains(&format!("\n  {}:\n    seq:\n", type_name)),
+                    "{} is not defined in version {}",
+                    type_name,
+                    version
+                );
+            }
+        }
+        assert!(!kaitai_struct(PROTOCOL_VERSION_1).contains("clock_drift_ppm"));
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn check_protobuf() {
+        use crate::{proto, proto_definition};
+
+        // The octets start with the key of the oneof field, which is numbered by type id
+        fn field_number(octets: &[u8]) -> u8 {
+            let key = if octets[0] & 0x80 != 0 {
+                (octets[0] & 0x7F) as u16 | (octets[1] as u16) << 7
+            } else {
+                octets[0] as u16
+            };
+            (key >> 3) as u8
+        }
+
+        let server_commands = [
+            ServerReceiveCommand::PingCommandType(PingCommand {
+                term: 0xFFFE,
+                knowledge: u64::MAX,
+                has_connection_to_leader: true,
+                connection_index: ConnectionIndex(7),
+                clock_drift_ppm: -300,
+                knowledge_vector: None,
+            }),
+            ServerReceiveCommand::HelloType(HelloCommand::default()),
+        ];
+        for command in &server_commands {
+            let octets = command.to_protobuf().unwrap();
+            assert_eq!(field_number(&octets), command.command_type_id());
+            assert_eq!(
+                &ServerReceiveCommand::from_protobuf(&octets).unwrap(),
+                command
+            );
+        }
+
+        let room_info = RoomInfoCommand {
+            term: 3,
+            leader_index: ConnectionIndex(9),
+            client_infos: [
+                ClientInfo {
+                    connection_index: ConnectionIndex(4),
+                    identity: ClientIdentity::U64(99),
+                    role_flags: 0,
+                    revision: 0,
+                    presence: PresenceState::Active,
+                    network_stats: None,
+                },
+                ClientInfo {
+                    connection_index: ConnectionIndex(9),
+                    identity: ClientIdentity::String("spectator".to_string()),
+                    role_flags: 1,
+                    revision: 0,
+                    presence: PresenceState::Active,
+                    network_stats: None,
+                },
+            ]
+            .into_iter()
+            .collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        let command = ClientReceiveCommand::RoomInfoType(room_info);
+        let octets = command.to_protobuf().unwrap();
+        assert_eq!(field_number(&octets), command.command_type_id());
+        assert_eq!(
+            ClientReceiveCommand::from_protobuf(&octets).unwrap(),
+            command
+        );
+
+        #[cfg(feature = "json")]
+        for vector in crate::test_vectors() {
+            use crate::TestVectorCommand;
+            match &vector.command {
+                TestVectorCommand::Server(command) => {
+                    let octets = command.to_protobuf().unwrap();
+                    assert_eq!(field_number(&octets), command.command_type_id());
+                    let decoded = ServerReceiveCommand::from_protobuf(&octets);
+                    assert_eq!(&decoded.unwrap(), command, "{}", vector.name);
+                }
+                TestVectorCommand::Client(command) => {
+                    let octets = command.to_protobuf().unwrap();
+                    assert_eq!(field_number(&octets), command.command_type_id());
+                    let decoded = ClientReceiveCommand::from_protobuf(&octets);
+                    assert_eq!(&decoded.unwrap(), command, "{}", vector.name);
+                }
+            }
+        }
+
+        let too_wide = proto::ServerReceiveCommand {
+            command: Some(proto::server_receive_command::Command::Ping(proto::Ping {
+                term: 0x1_0000,
+                ..Default::default()
+            })),
+        };
+        let err = ServerReceiveCommand::from_proto(too_wide).unwrap_err();
+        assert!(err.to_string().contains("term"), "{}", err);
+        assert!(ServerReceiveCommand::from_proto(proto::ServerReceiveCommand::default()).is_err());
+        let unknown = ServerReceiveCommand::Unknown(RawCommand {
+            type_id: 0xF0,
+            payload: vec![],
+        });
+        assert!(unknown.to_protobuf().is_err());
+
+        let definition = proto_definition();
+        assert!(definition.contains("package conclave.room;"));
+        assert!(definition.contains("\nmessage Ping {\n  uint32 term = 1;\n"));
+        assert!(definition.contains("  sint32 clock_drift_ppm = 5;\n"));
+        assert!(definition.contains("  repeated ClientInfo client_infos = 2;\n"));
+        assert!(definition.contains("\nmessage ClientInfo {\n"));
+        assert!(definition.contains("    Hello hello = 199;\n"));
+        // Every message used in a field is defined
+        for line in definition.lines() {
+            let words: Vec<&str> = line
+                .trim()
+                .trim_start_matches("repeated ")
+                .split(' ')
+                .collect();
+            if let [type_name, _, "=", _] = words.as_slice() {
+                if type_name.starts_with(char::is_uppercase) {
+                    assert!(
+                        definition.contains(&format!("\nmessage {} {{\n", type_name)),
+                        "{} is not defined",
+                        type_name
+                    );
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn check_cbor() {
+        let command = ServerReceiveCommand::BanClientType(BanClientCommand {
+            custom_user_id: UserId(u128::MAX),
+            duration_s: 600,
+        });
+        let octets = command.to_cbor().unwrap();
+        assert_eq!(ServerReceiveCommand::from_cbor(&octets).unwrap(), command);
+
+        let command = ClientReceiveCommand::RedirectType(RedirectCommand {
+            host: "eu-2.example.com".to_string(),
+            port: 27015,
+            token: vec![1, 2, 3],
+        });
+        let octets = command.to_cbor().unwrap();
+        assert_eq!(ClientReceiveCommand::from_cbor(&octets).unwrap(), command);
+
+        #[cfg(feature = "json")]
+        for vector in crate::test_vectors() {
+            use crate::TestVectorCommand;
+            match &vector.command {
+                TestVectorCommand::Server(command) => {
+                    let decoded = ServerReceiveCommand::from_cbor(&command.to_cbor().unwrap());
+                    assert_eq!(&decoded.unwrap(), command, "{}", vector.name);
+                }
+                TestVectorCommand::Client(command) => {
+                    let decoded = ClientReceiveCommand::from_cbor(&command.to_cbor().unwrap());
+                    assert_eq!(&decoded.unwrap(), command, "{}", vector.name);
+                }
+            }
+        }
+
+        let client_info = ClientInfo {
+            connection_index: ConnectionIndex(2),
+            identity: ClientIdentity::U64(1),
+            role_flags: 0,
+            revision: 0,
+            presence: PresenceState::Active,
+            network_stats: None,
+        };
+        let invalid = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(0),
+            client_infos: [client_info.clone(), client_info].into_iter().collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        });
+        let octets = invalid.to_cbor().unwrap();
+        assert!(ClientReceiveCommand::from_cbor(&octets).is_err());
+        assert!(ServerReceiveCommand::from_cbor(&octets[..octets.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn check_msgpack() {
+        let command = ServerReceiveCommand::BanClientType(BanClientCommand {
+            custom_user_id: UserId(u128::MAX),
+            duration_s: 600,
+        });
+        let octets = command.to_msgpack().unwrap();
+        // Structs are maps keyed by field name
+        assert!(octets.windows(10).any(|window| window == b"duration_s"));
+        assert_eq!(
+            ServerReceiveCommand::from_msgpack(&octets).unwrap(),
+            command
+        );
+
+        let command = ClientReceiveCommand::RedirectType(RedirectCommand {
+            host: "eu-2.example.com".to_string(),
+            port: 27015,
+            token: vec![1, 2, 3],
+        });
+        let octets = command.to_msgpack().unwrap();
+        assert_eq!(
+            ClientReceiveCommand::from_msgpack(&octets).unwrap(),
+            command
+        );
+
+        #[cfg(feature = "json")]
+        for vector in crate::test_vectors() {
+            use crate::TestVectorCommand;
+            match &vector.command {
+                TestVectorCommand::Server(command) => {
+                    let decoded =
+                        ServerReceiveCommand::from_msgpack(&command.to_msgpack().unwrap());
+                    assert_eq!(&decoded.unwrap(), command, "{}", vector.name);
+                }
+                TestVectorCommand::Client(command) => {
+                    let decoded =
+                        ClientReceiveCommand::from_msgpack(&command.to_msgpack().unwrap());
+                    assert_eq!(&decoded.unwrap(), command, "{}", vector.name);
+                }
+            }
+        }
+
+        let client_info = ClientInfo {
+            connection_index: ConnectionIndex(2),
+            identity: ClientIdentity::U64(1),
+            role_flags: 0,
+            revision: 0,
+            presence: PresenceState::Active,
+            network_stats: None,
+        };
+        let invalid = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(0),
+            client_infos: [client_info.clone(), client_info].into_iter().collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        });
+        let octets = invalid.to_msgpack().unwrap();
+        assert!(ClientReceiveCommand::from_msgpack(&octets).is_err());
+        assert!(ServerReceiveCommand::from_msgpack(&octets[..octets.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn check_extensions() {
+        let mut extensions = Extensions::new();
+        extensions
+            .insert_with(0x10, |stream| stream.write_u32(0xCAFE_F00D))
+            .unwrap();
+        extensions.insert(0x02, b"eu".to_vec());
+
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand::default());
+        let mut out_stream = OutOctetStream::new();
+        ping.encode_with_extensions(Some(9), &extensions, &mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        ping.encode_with_extensions(None, &Extensions::new(), &mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        ping.encode_with_padding(None, 64, &mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(
+            out_stream.data[0],
+            ENVELOPE_FLAG_EXTENSIONS | ENVELOPE_FLAG_CORRELATION_ID
+        );
+
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let (correlation_id, command, decoded) =
+            ServerReceiveCommand::decode_with_extensions(&mut in_stream, PROTOCOL_VERSION).unwrap();
+        assert_eq!((correlation_id, &command), (Some(9), &ping));
+        assert_eq!(decoded, extensions);
+        assert_eq!(
+            decoded.iter().map(|(tag, _)| tag).collect::<Vec<_>>(),
+            [2, 16]
+        );
+        assert_eq!(
+            decoded.read_with(0x10, |reader| reader.read_u32()).unwrap(),
+            Some(0xCAFE_F00D)
+        );
+        assert_eq!(
+            decoded.read_with(0x11, |reader| reader.read_u8()).unwrap(),
+            None
+        );
+        let err = decoded
+            .read_with(0x02, |reader| reader.read_u8())
+            .unwrap_err();
+        assert!(err.to_string().contains("extensions[0x02]"), "{}", err);
+
+        // Without the flag there are no extensions, also when padded
+        for _ in 0..2 {
+            let (_, command, decoded) =
+                ServerReceiveCommand::decode_with_extensions(&mut in_stream, PROTOCOL_VERSION)
+                    .unwrap();
+            assert_eq!(command, ping);
+            assert!(decoded.is_empty());
+        }
+
+        let out_of_order = [0x00, 0x08, 0x05, 0x00, 0x01, 0xAA, 0x04, 0x00, 0x01, 0xBB];
+        assert!(Extensions::from_cursor(&mut SliceReader::new(&out_of_order)).is_err());
+        let overrun = [0x00, 0x04, 0x05, 0x00, 0x02, 0xAA];
+        assert!(Extensions::from_cursor(&mut SliceReader::new(&overrun)).is_err());
+
+        let oversized: Extensions = [(1, vec![0; MAX_EXTENSIONS_OCTETS])].into_iter().collect();
+        assert!(oversized.to_octets(&mut OutOctetStream::new()).is_err());
+    }
+
+    #[test]
+    fn check_presence_mask() {
+        #[derive(Debug, PartialEq, Default)]
+        struct Optional {
+            rtt_ms: Option<u16>,
+            region: Option<u8>,
+            relayed: Option<bool>,
+        }
+
+        fn write(optional: &Optional) -> Vec<u8> {
+            let mut writer = PresenceWriter::new(PresenceMaskWidth::U8);
+            writer.field(0, &optional.rtt_ms).unwrap();
+            writer.field(1, &optional.region).unwrap();
+            writer.field(4, &optional.relayed).unwrap();
+            let mut out_stream = OutOctetStream::new();
+            writer.to_octets(&mut out_stream).unwrap();
+            assert_eq!(writer.octet_size(), out_stream.data.len());
+            out_stream.data
+        }
+
+        fn read(octets: &[u8]) -> std::io::Result<Optional> {
+            let mut reader = SliceReader::new(octets);
+            let mut presence =
+                PresenceReader::from_cursor(&mut reader, PresenceMaskWidth::U8, 0b1_0011)?;
+            Ok(Optional {
+                rtt_ms: presence.field(0, &mut reader)?,
+                region: presence.field(1, &mut reader)?,
+                relayed: presence.field(4, &mut reader)?,
+            })
+        }
+
+        assert_eq!(write(&Optional::default()), [0x00]);
+        let optional = Optional {
+            rtt_ms: Some(0x1234),
+            region: None,
+            relayed: Some(true),
+        };
+        let octets = write(&optional);
+        assert_eq!(octets, [0b1_0001, 0x12, 0x34, 0x01]);
+        assert_eq!(read(&octets).unwrap(), optional);
+
+        assert!(read(&[0b0100]).is_err());
+        assert!(read(&[0b1_0000, 0x02]).is_err());
+
+        let mut writer = PresenceWriter::new(PresenceMaskWidth::U8);
+        writer.field(2, &Some(1u8)).unwrap();
+        assert!(writer.field(1, &Some(1u8)).is_err());
+        assert!(writer.field(8, &Some(1u8)).is_err());
+        let mut writer = PresenceWriter::new(PresenceMaskWidth::U16);
+        writer.field(15, &Some(u64::MAX)).unwrap();
+        assert_eq!(writer.mask(), 0x8000);
+        assert_eq!(writer.octet_size(), 2 + 8);
+    }
+
+    #[test]
+    fn check_layout_version() {
+        use crate::command_descriptor;
+
+        let ping_descriptor = command_descriptor(PING_COMMAND_TYPE_ID).unwrap();
+        assert_eq!(ping_descriptor.layout_versions(), [1, 5, 6, 10]);
+        assert_eq!(ping_descriptor.layout_version(PROTOCOL_VERSION_4), Some(1));
+        let room_info_descriptor = command_descriptor(ROOM_INFO_COMMAND_TYPE_ID).unwrap();
+        assert_eq!(
+            room_info_descriptor.layout_versions(),
+            [1, 2, 3, 4, 7, 8, 9, 10, 11, 12]
+        );
+
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand {
+            term: 2,
+            knowledge: 3,
+            has_connection_to_leader: true,
+            connection_index: ConnectionIndex(0),
+            clock_drift_ppm: 0,
+            knowledge_vector: None,
+        });
+        // Peers on different versions, decoded side by side
+        for (version, layout_version) in [(PROTOCOL_VERSION_4, 1), (PROTOCOL_VERSION, 10)] {
+            let mut out_stream = OutOctetStream::new();
+            ping.to_octets_with_layout_version(&mut out_stream, version)
+                .unwrap();
+            assert_eq!(out_stream.data[..2], [PING_COMMAND_TYPE_ID, layout_version]);
+            let (decoded, decoded_layout_version) =
+                ServerReceiveCommand::decode_with_layout_version(&out_stream.data).unwrap();
+            assert_eq!(
+                (decoded, decoded_layout_version),
+                (ping.clone(), layout_version)
+            );
+        }
+
+        let room_info = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(3),
+            client_infos: [ClientInfo {
+                connection_index: ConnectionIndex(3),
+                identity: ClientIdentity::U64(8),
+                role_flags: 0,
+                revision: 0,
+                presence: PresenceState::Active,
+                network_stats: None,
+            }]
+            .into_iter()
+            .collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        });
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_layout_version(&mut out_stream, PROTOCOL_VERSION_2)
+            .unwrap();
+        let (decoded, layout_version) =
+            ClientReceiveCommand::decode_with_layout_version(&out_stream.data).unwrap();
+        assert_eq!((decoded, layout_version), (room_info, PROTOCOL_VERSION_2));
+
+        // Each version encodes like the layout version it maps to
+        #[cfg(feature = "json")]
+        for vector in crate::test_vectors() {
+            use crate::TestVectorCommand;
+            let descriptor = command_descriptor(vector.command_type_id()).unwrap();
+            for version in descriptor.min_version..=PROTOCOL_VERSION {
+                let layout_version = descriptor.layout_version(version).unwrap();
+                let encode = |version| {
+                    let mut out_stream = OutOctetStream::new();
+                    match &vector.command {
+                        TestVectorCommand::Server(command) => {
+                            command.to_octets_with_version(&mut out_stream, version)
+                        }
+                        TestVectorCommand::Client(command) => {
+                            command.to_octets_with_version(&mut out_stream, version)
+                        }
+                    }
+                    .ok()
+                    .map(|_| out_stream.data)
+                };
+                assert_eq!(encode(version), encode(layout_version), "{}", vector.name);
+            }
+        }
+
+        let err = ServerReceiveCommand::decode_with_layout_version(&[PING_COMMAND_TYPE_ID, 3])
+            .unwrap_err();
+        assert!(err.to_string().contains("layout_version"), "{}", err);
+        assert!(
+            ClientReceiveCommand::decode_with_layout_version(&[PING_COMMAND_TYPE_ID, 1]).is_err()
+        );
+    }
+
+    #[test]
+    fn check_migrate() {
+        use crate::migrate::{ClientInfoV1, PingCommandV1, RoomInfoCommandV1};
+
+        let ping = PingCommandV1 {
+            term: 4,
+            knowledge: 99,
+            has_connection_to_leader: true,
+        };
+        // The same as decoding the old layout
+        let mut out_stream = OutOctetStream::new();
+        PingCommand::from(ping.clone())
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_1)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert_eq!(
+            PingCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION_1).unwrap(),
+            PingCommand::from(ping)
+        );
+
+        let room_info = RoomInfoCommandV1 {
+            term: 2,
+            client_infos: vec![
+                ClientInfoV1 {
+                    custom_user_id: 1000,
+                    connection_index: ConnectionIndex(0),
+                },
+                ClientInfoV1 {
+                    custom_user_id: 1001,
+                    connection_index: ConnectionIndex(1),
+                },
+            ],
+            leader_index: ConnectionIndex(1),
+        };
+        let migrated = RoomInfoCommand::try_from(room_info.clone()).unwrap();
+        assert_eq!(migrated.client_infos[1].identity, ClientIdentity::U64(1001));
+        assert_eq!(migrated.client_infos[1].role_flags, 0);
+
+        let mut duplicate = room_info;
+        duplicate.client_infos[1].connection_index = ConnectionIndex(0);
+        assert!(RoomInfoCommand::try_from(duplicate).is_err());
+    }
+
+    #[test]
+    fn check_split_command() {
+        let client_count = crate::MAX_CLIENT_INFOS.min(200);
+        let room_info = RoomInfoCommand::builder()
+            .term(3)
+            .clients((0..client_count).map(|index| ClientInfo {
+                identity: ClientIdentity::U64(index as u64),
+                connection_index: ConnectionIndex(index as u8),
+                role_flags: 0,
+                revision: 0,
+                presence: PresenceState::Active,
+                network_stats: None,
+            }))
+            .build()
+            .unwrap();
+        let command = ClientReceiveCommand::RoomInfoType(room_info.clone());
+
+        let mut datagrams = split_command(&command, 7, 64).unwrap();
+        assert!(datagrams.len() > 1);
+        assert!(datagrams.iter().all(|datagram| datagram.len() <= 64));
+        datagrams.reverse();
+
+        let mut buffer = PartialMessageBuffer::<ClientReceiveCommand>::new();
+        let (last, rest) = datagrams.split_last().unwrap();
+        for datagram in rest {
+            assert_eq!(buffer.receive(datagram).unwrap(), None);
+        }
+        assert_eq!(buffer.receive(&rest[0]).unwrap(), None);
+        assert_eq!(buffer.missing_part_indices(7), Some(vec![0]));
+        assert_eq!(buffer.receive(last).unwrap(), Some(command.clone()));
+        assert_eq!(buffer.pending_count(), 0);
+
+        let single = split_command(&command, 8, 64 * 1024).unwrap();
+        assert_eq!(single.len(), 1);
+        let mut in_stream = InOctetStream::new(single[0].clone());
+        assert_eq!(
+            SplitHeader::from_cursor(&mut in_stream).unwrap(),
+            SplitHeader {
+                message_id: 8,
+                part_index: 0,
+                part_count: 1,
+            }
+        );
+        assert_eq!(buffer.receive(&single[0]).unwrap(), Some(command.clone()));
+
+        assert!(split_command(&command, 9, SplitHeader::OCTET_SIZE).is_err());
+        let mut trailing = single[0].clone();
+        trailing.push(0);
+        assert!(buffer.receive(&trailing).is_err());
+
+        // The negotiated version keeps what the default layouts drop
+        let elected = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            quorum_size: 2,
+            votes_for_leader: 2,
+            leader_lease_expires_in_ms: 1500,
+            ..room_info
+        });
+        let datagrams = split_command_with_version(&elected, 10, 64, PROTOCOL_VERSION).unwrap();
+        let mut buffer =
+            PartialMessageBuffer::<ClientReceiveCommand>::new().with_version(PROTOCOL_VERSION);
+        let mut received = None;
+        for datagram in &datagrams {
+            received = buffer.receive(datagram).unwrap();
+        }
+        assert_eq!(received, Some(elected));
+    }
+
+    #[test]
+    fn check_control_commands() {
+        assert!(COMMAND_DESCRIPTORS
+            .iter()
+            .all(|descriptor| !CONTROL_COMMAND_TYPE_ID_RANGE.contains(&descriptor.type_id)));
+
+        let commands = [
+            ControlCommand::EchoType(EchoCommand {
+                payload: vec![1, 2, 3],
+            }),
+            ControlCommand::StatsRequestType(StatsRequestCommand { request_id: 5 }),
+            ControlCommand::DebugDumpType(DebugDumpCommand {
+                request_id: 5,
+                dump: b"{}".to_vec(),
+            }),
+            ControlCommand::Unknown(RawCommand {
+                type_id: 0xFE,
+                payload: vec![9],
+            }),
+        ];
+        for command in commands {
+            let octets = command.to_vec().unwrap();
+            assert!(is_control_command(&octets));
+            assert_eq!(octets[0], command.command_type_id());
+            assert_eq!(ControlCommand::from_slice(&octets).unwrap(), command);
+        }
+        assert_eq!(
+            ControlCommand::StatsRequestType(StatsRequestCommand { request_id: 5 })
+                .to_vec()
+                .unwrap(),
+            [0xF1, 0x00, 0x00, 0x00, 0x05]
+        );
+
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand::default())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert!(!is_control_command(&ping));
+        assert!(!is_control_command(&[]));
+        assert!(ControlCommand::from_slice(&ping).is_err());
+        assert!(ControlCommand::Unknown(RawCommand {
+            type_id: 0x01,
+            payload: vec![],
+        })
+        .to_vec()
+        .is_err());
+        assert!(ControlCommand::from_slice(&[0xF1, 0x00, 0x00, 0x00, 0x05, 0x00]).is_err());
+    }
+
+    #[test]
+    fn check_sender_authorization() {
+        use SenderRole::{Client, Leader, Server};
+
+        assert!(SenderAuthorization::AnyClient.allowed_from(Leader));
+        assert!(!SenderAuthorization::AnyClient.allowed_from(Server));
+        assert!(!SenderAuthorization::LeaderOnly.allowed_from(Client));
+        assert!(!SenderAuthorization::LeaderOrServer.allowed_from(Client));
+        assert!(SenderAuthorization::LeaderOrServer.allowed_from(Server));
+        // Only commands the Server sends are decoded by the Clients
+        for descriptor in COMMAND_DESCRIPTORS {
+            assert_eq!(
+                descriptor.direction.is_client_receive(),
+                descriptor.allowed_from(Server),
+                "{}",
+                descriptor.name
+            );
+        }
+
+        let ban = ServerReceiveCommand::BanClientType(BanClientCommand {
+            custom_user_id: UserId(1),
+            duration_s: 60,
+        })
+        .to_vec_with_version(PROTOCOL_VERSION)
+        .unwrap();
+        let decode = |sender| {
+            ServerReceiveCommand::from_cursor_with_options(
+                &mut InOctetStream::new(ban.clone()),
+                &DecodeOptions::new(PROTOCOL_VERSION).with_sender(sender),
+            )
+        };
+        assert!(decode(Leader).is_ok());
+        let err = decode(Client).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::UnauthorizedSender {
+                command_type_id: BAN_CLIENT_COMMAND_TYPE_ID,
+                sender: Client,
+            })
+        );
+
+        let room_info = ClientReceiveCommand::RoomInfoType(RoomInfoCommand::default())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        let options = DecodeOptions::new(PROTOCOL_VERSION)
+            .with_sender(Client)
+            .lenient();
+        assert!(ClientReceiveCommand::from_octets_with_options(
+            &mut InOctetStream::new(room_info),
+            &options
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn check_direction_traits() {
+        fn decode_on_server(command: impl ClientToServer) -> ServerReceiveCommand {
+            let mut out_stream = OutOctetStream::new();
+            write_client_to_server(command, &mut out_stream, PROTOCOL_VERSION).unwrap();
+            ServerReceiveCommand::decode_exact(&out_stream.data, PROTOCOL_VERSION).unwrap()
+        }
+
+        let ping = PingCommand {
+            term: 4,
+            knowledge: 99,
+            has_connection_to_leader: true,
+            connection_index: ConnectionIndex(2),
+            clock_drift_ppm: -5,
+            knowledge_vector: None,
+        };
+        assert_eq!(
+            decode_on_server(ping.clone()),
+            ServerReceiveCommand::PingCommandType(ping)
+        );
+
+        let room_closed = RoomClosedCommand::default();
+        let mut out_stream = OutOctetStream::new();
+        write_server_to_client(room_closed.clone(), &mut out_stream, PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&out_stream.data, PROTOCOL_VERSION).unwrap(),
+            ClientReceiveCommand::RoomClosedType(room_closed)
+        );
+
+        let mute = MuteStateCommand::default();
+        let mut out_stream = OutOctetStream::new();
+        write_server_to_client(mute.clone(), &mut out_stream, PROTOCOL_VERSION).unwrap();
+        let mut client_stream = OutOctetStream::new();
+        write_client_to_server(mute.clone(), &mut client_stream, PROTOCOL_VERSION).unwrap();
+        assert_eq!(out_stream.data, client_stream.data);
+        assert_eq!(
+            ServerReceiveCommand::from(mute.clone()),
+            ServerReceiveCommand::MuteStateType(mute)
+        );
+
+        let baseline_ping = PingCommand {
+            term: 4,
+            knowledge: 99,
+            has_connection_to_leader: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            ServerReceiveCommand::from_slice(&client_to_server_vec(baseline_ping.clone()).unwrap())
+                .unwrap(),
+            ServerReceiveCommand::PingCommandType(baseline_ping)
+        );
+        let room_info = RoomInfoCommand::default();
+        assert_eq!(
+            ClientReceiveCommand::from_slice(&server_to_client_vec(room_info.clone()).unwrap())
+                .unwrap(),
+            ClientReceiveCommand::RoomInfoType(room_info)
+        );
+    }
+
+    #[test]
+    fn check_connection_index() {
+        let index = ConnectionIndex::from(7);
+        assert_eq!(u8::from(index), 7);
+        assert_eq!(index.to_string(), "7");
+
+        let mut out_stream = OutOctetStream::new();
+        index.to_octets(&mut out_stream).unwrap();
+        assert_eq!(out_stream.data, [7]);
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert_eq!(ConnectionIndex::from_cursor(&mut in_stream).unwrap(), index);
+
+        let relay = RelayToClientCommand {
+            target_connection_index: index,
+            payload: vec![1],
+        };
+        let mut out_stream = OutOctetStream::new();
+        relay.to_octets(&mut out_stream).unwrap();
+        assert_eq!(out_stream.data[0], 7);
+    }
+
+    #[test]
+    fn check_user_id_text() {
+        let user_id = UserId(0xdead_beef);
+        assert_eq!(user_id.to_string(), "0xdeadbeef");
+        assert_eq!("0xdeadbeef".parse::<UserId>().unwrap(), user_id);
+        assert_eq!("DEADBEEF".parse::<UserId>().unwrap(), user_id);
+        assert_eq!(
+            UserId(u128::MAX).to_string().parse::<UserId>().unwrap(),
+            UserId(u128::MAX)
+        );
+        assert!("0x".parse::<UserId>().is_err());
+        assert!("0xbeefy".parse::<UserId>().is_err());
+    }
+
+    #[test]
+    fn check_command_header() {
+        let header = CommandHeader {
+            term: 7,
+            room_id: None,
+            correlation_id: Some(0x0102_0304),
+        };
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand::default());
+        let mut out_stream = OutOctetStream::new();
+        encode_with_header(&header, &ping, &mut out_stream, PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            out_stream.data[..header.octet_size()],
+            [0, 7, 0b10, 1, 2, 3, 4]
+        );
+        assert_eq!(
+            decode_with_header::<ServerReceiveCommand>(&out_stream.data, PROTOCOL_VERSION).unwrap(),
+            (header, ping.clone())
+        );
+
+        let mut frames = OutOctetStream::new();
+        let routed = CommandHeader {
+            room_id: Some(99),
+            ..header
+        };
+        write_frame_with_header(&routed, &ping, &mut frames, PROTOCOL_VERSION).unwrap();
+        write_frame_with_header(&header, &ping, &mut frames, PROTOCOL_VERSION).unwrap();
+        let (first_header, command, consumed) =
+            read_frame_with_header::<ServerReceiveCommand>(&frames.data, PROTOCOL_VERSION).unwrap();
+        assert_eq!((first_header, command), (routed, ping.clone()));
+        let (second_header, _, _) = read_frame_with_header::<ServerReceiveCommand>(
+            &frames.data[consumed..],
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert_eq!(second_header, header);
+
+        let mut unknown_bits = out_stream.data.clone();
+        unknown_bits[2] = 0b100;
+        let err = decode_with_header::<ServerReceiveCommand>(&unknown_bits, PROTOCOL_VERSION)
+            .unwrap_err();
+        assert_eq!(DecodeError::from_io(&err).unwrap().path, "header.presence");
+    }
+
+    #[test]
+    fn check_room_events() {
+        let events = [
+            RoomEvent::TermAdvanced { term: 3 },
+            RoomEvent::ClientJoined {
+                connection_index: ConnectionIndex(1),
+                identity: ClientIdentity::String("player".to_string()),
+            },
+            RoomEvent::LeaderElected {
+                term: 3,
+                leader_index: ConnectionIndex(1),
+            },
+            RoomEvent::ClientLost {
+                connection_index: ConnectionIndex(1),
+            },
+        ];
+        let mut log = OutOctetStream::new();
+        for event in &events {
+            write_frame_with_version(event, &mut log, PROTOCOL_VERSION).unwrap();
+        }
+        let decoded = FrameDecoder::<RoomEvent>::new(&log.data)
+            .with_version(PROTOCOL_VERSION)
+            .map(|frame| match frame.unwrap() {
+                Frame::Command(event) => event,
+                Frame::Skipped { error, .. } => panic!("skipped: {}", error),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, events);
+
+        let mut out_stream = OutOctetStream::new();
+        events[2]
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(out_stream.data, [ROOM_EVENT_LEADER_ELECTED, 0, 3, 1]);
+
+        let mut in_stream = InOctetStream::new(vec![0x7f]);
+        assert!(RoomEvent::from_octets_with_version(&mut in_stream, PROTOCOL_VERSION).is_err());
+    }
+
+    #[test]
+    fn check_replay() {
+        use crate::replay::{ReplayCommand, ReplayReader, ReplayRecord, ReplayWriter};
+        use std::time::Duration;
+
+        let records = [
+            ReplayRecord {
+                timestamp: Duration::from_millis(5),
+                delay: Duration::from_millis(5),
+                command: ReplayCommand::ClientToServer(ServerReceiveCommand::PingCommandType(
+                    PingCommand::default(),
+                )),
+            },
+            ReplayRecord {
+                timestamp: Duration::from_millis(5),
+                delay: Duration::ZERO,
+                command: ReplayCommand::ServerToClient(ClientReceiveCommand::RoomClosedType(
+                    RoomClosedCommand::default(),
+                )),
+            },
+            ReplayRecord {
+                timestamp: Duration::from_secs(90),
+                delay: Duration::from_millis(89_995),
+                command: ReplayCommand::ServerToClient(ClientReceiveCommand::RoomInfoType(
+                    RoomInfoCommand::default(),
+                )),
+            },
+        ];
+        let mut writer = ReplayWriter::new(Vec::new(), PROTOCOL_VERSION_5).unwrap();
+        for record in &records {
+            writer.record(record.timestamp, &record.command).unwrap();
+        }
+        assert!(writer
+            .record(Duration::from_secs(1), &records[0].command)
+            .is_err());
+        let octets = writer.into_inner();
+
+        let reader = ReplayReader::new(&octets).unwrap();
+        assert_eq!(reader.header().version, PROTOCOL_VERSION_5);
+        assert!(reader.header().matches_build());
+        assert_eq!(
+            reader.collect::<std::io::Result<Vec<_>>>().unwrap(),
+            records
+        );
+
+        let truncated = ReplayReader::new(&octets[..octets.len() - 1])
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>();
+        assert!(truncated.is_err());
+        assert!(ReplayReader::new(b"PCAP").is_err());
+    }
+
+    #[test]
+    fn check_replay_filters() {
+        use crate::replay::{ReplayCommand, ReplayDirection, ReplayReader, ReplayWriter};
+        use std::time::Duration;
+
+        let ping = ReplayCommand::ClientToServer(ServerReceiveCommand::PingCommandType(
+            PingCommand::default(),
+        ));
+        let room_closed = ReplayCommand::ServerToClient(ClientReceiveCommand::RoomClosedType(
+            RoomClosedCommand::default(),
+        ));
+        let mut writer = ReplayWriter::new(Vec::new(), PROTOCOL_VERSION).unwrap();
+        for (millis, command) in [(10, &ping), (20, &room_closed), (35, &ping)] {
+            writer
+                .record(Duration::from_millis(millis), command)
+                .unwrap();
+        }
+        let mut octets = writer.into_inner();
+
+        let pings = ReplayReader::new(&octets)
+            .unwrap()
+            .with_direction(ReplayDirection::ClientToServer)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(pings.len(), 2);
+        assert_eq!(pings[1].timestamp, Duration::from_millis(35));
+        assert_eq!(pings[1].delay, Duration::from_millis(25));
+
+        // A command that is filtered out is never decoded
+        octets.extend([0, ReplayDirection::ClientToServer as u8, 1, 0xee]);
+        let closed = ReplayReader::new(&octets)
+            .unwrap()
+            .with_command_type_ids(&[room_closed.command_type_id()])
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].command, room_closed);
+        assert!(ReplayReader::new(&octets)
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .is_err());
+    }
+
+    #[test]
+    fn check_deterministic_room_info() {
+        let client = |connection_index: u8, identity| ClientInfo {
+            identity,
+            connection_index: ConnectionIndex(connection_index),
+            role_flags: 0,
+            revision: 0,
+            presence: PresenceState::Active,
+            network_stats: None,
+        };
+        let room_info = RoomInfoCommand {
+            term: 2,
+            leader_index: ConnectionIndex(9),
+            client_infos: [
+                client(9, ClientIdentity::U64(9)),
+                client(4, ClientIdentity::Uuid(4)),
+            ]
+            .into_iter()
+            .collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        let sorted = room_info.sorted();
+        assert_eq!(sorted.client_infos[0].connection_index, ConnectionIndex(4));
+        assert_eq!(sorted.leader_index, ConnectionIndex(9));
+        assert_eq!(
+            room_info.to_deterministic_vec(PROTOCOL_VERSION).unwrap(),
+            ClientReceiveCommand::RoomInfoType(sorted)
+                .to_vec_with_version(PROTOCOL_VERSION)
+                .unwrap()[1..]
+        );
+
+        assert!(room_info.to_deterministic_vec(PROTOCOL_VERSION_3).is_err());
+        let mut with_roles = room_info.clone();
+        with_roles.client_infos[0].identity = ClientIdentity::U64(4);
+        with_roles.client_infos[1].identity = ClientIdentity::U64(9);
+        assert!(with_roles.to_deterministic_vec(PROTOCOL_VERSION_1).is_ok());
+        with_roles.client_infos[0].role_flags = ROLE_FLAG_HOST;
+        assert!(with_roles.to_deterministic_vec(PROTOCOL_VERSION_1).is_err());
+    }
+
+    #[test]
+    fn check_command_stats() {
+        let mut stats = CommandStats::new();
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand::default())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        stats.record(&ping);
+        stats.record(&ping);
+        stats.record(&[0xee, 1, 2]);
+        stats.record(&[]);
+
+        let ping_stats = stats.get(PING_COMMAND_TYPE_ID).unwrap();
+        assert_eq!(ping_stats.count, 2);
+        assert_eq!(ping_stats.total_octets, 2 * ping.len() as u64);
+        assert_eq!(ping_stats.average_octets(), ping.len() as f64);
+        assert_eq!(
+            stats.total(),
+            CommandTypeStats {
+                count: 3,
+                total_octets: 2 * ping.len() as u64 + 3,
+            }
+        );
+        let report = stats.to_string();
+        assert!(report.starts_with("0x01 ping: 2 commands"));
+        assert!(report.contains("0xee unknown: 1 commands, 3 octets"));
+
+        stats.clear();
+        assert_eq!(stats.iter().count(), 0);
+    }
+
+    #[test]
+    fn check_format_room_table() {
+        let room_info = RoomInfoCommand {
+            term: 3,
+            leader_index: ConnectionIndex(12),
+            client_infos: [
+                ClientInfo {
+                    identity: ClientIdentity::String("ann".to_string()),
+                    connection_index: ConnectionIndex(2),
+                    role_flags: 0,
+                    revision: 0,
+                    presence: PresenceState::Active,
+                    network_stats: None,
+                },
+                ClientInfo {
+                    identity: ClientIdentity::U64(0xbeef),
+                    connection_index: ConnectionIndex(12),
+                    role_flags: ROLE_FLAG_HOST | ROLE_FLAG_PLAYER | 0x8000,
+                    revision: 0,
+                    presence: PresenceState::Active,
+                    network_stats: None,
+                },
+            ]
+            .into_iter()
+            .collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        assert_eq!(
+            format_room_table(&room_info),
+            "term 3, 2 clients\n\
+             \x20 | connection | identity   | roles\n\
+             --+------------+------------+-------------------\n\
+             \x20 | 2          | \"ann\"      | -\n\
+             * | 12         | u64 0xbeef | host,player,0x8000\n"
+        );
+    }
+
+    #[test]
+    fn check_room_info_decode_into() {
+        let client = |connection_index: u8| ClientInfo {
+            identity: ClientIdentity::U64(connection_index as u64),
+            connection_index: ConnectionIndex(connection_index),
+            role_flags: 0,
+            revision: 0,
+            presence: PresenceState::Active,
+            network_stats: None,
+        };
+        let large: RoomInfoCommand = RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(0),
+            client_infos: (0..8).map(client).collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        let small = RoomInfoCommand {
+            term: 2,
+            leader_index: ConnectionIndex(1),
+            client_infos: (0..2).map(client).collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+
+        let mut room_info = RoomInfoCommand::default();
+        let mut out_stream = OutOctetStream::new();
+        large
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        room_info
+            .decode_into_with_options(
+                &mut InOctetStream::new(out_stream.data),
+                &DecodeOptions::new(PROTOCOL_VERSION),
+            )
+            .unwrap();
+        assert_eq!(room_info, large);
+        let capacity = room_info.client_infos.capacity();
+
+        let mut out_stream = OutOctetStream::new();
+        small
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        room_info
+            .decode_into_with_options(
+                &mut InOctetStream::new(out_stream.data),
+                &DecodeOptions::new(PROTOCOL_VERSION),
+            )
+            .unwrap();
+        assert_eq!(room_info, small);
+        assert_eq!(room_info.client_infos.capacity(), capacity);
+    }
+
+    #[test]
+    fn check_decode_exact() {
+        let octets = ServerReceiveCommand::PingCommandType(PingCommand::default())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert!(matches!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ServerReceiveCommand::PingCommandType(_)
+        ));
+
+        let mut trailing = octets.clone();
+        trailing.extend_from_slice(&[0x00, 0x00]);
+        let err = ServerReceiveCommand::decode_exact(&trailing, PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::TrailingBytes { count: 2 })
+        );
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().offset,
+            Some(octets.len())
+        );
+        assert!(ServerReceiveCommand::decode_checked(&trailing, PROTOCOL_VERSION).is_ok());
+
+        let mut trailing = ClientReceiveCommand::RoomClosedType(RoomClosedCommand::default())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        trailing.push(0x00);
+        let err = ClientReceiveCommand::decode_exact(&trailing, PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&err),
+            Some(&ProtocolError::TrailingBytes { count: 1 })
+        );
+    }
+
+    #[test]
+    fn check_io_streams() {
+        let command = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            term: 3,
+            leader_index: ConnectionIndex(0),
+            client_infos: [ClientInfo::default()].into_iter().collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        });
+        let mut stream = IoWriteStream::new(Vec::new());
+        command
+            .to_octets_with_version(&mut stream, PROTOCOL_VERSION)
+            .unwrap();
+        let written = stream.into_inner();
+        assert_eq!(
+            written,
+            command.to_vec_with_version(PROTOCOL_VERSION).unwrap()
+        );
+
+        let mut stream = IoReadStream::new(written.as_slice());
+        assert_eq!(
+            ClientReceiveCommand::from_octets_with_version(&mut stream, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        let mut stream = IoReadStream::new(&written[..written.len() - 1]);
+        let err = ClientReceiveCommand::from_octets_with_version(&mut stream, PROTOCOL_VERSION)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    /// Polls a future that only waits on in-memory values, so one poll completes it
+    #[cfg(any(feature = "futures", feature = "futures-io"))]
+    fn complete<T>(future: impl std::future::Future<Output = T>) -> T {
+        use std::task::{Context, Poll, Waker};
+
+        match std::pin::pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("future is pending"),
+        }
+    }
+
+    #[cfg(feature = "futures-io")]
+    #[test]
+    fn check_async_frames() {
+        use futures_util::io::Cursor;
+
+        use crate::{read_frame_async, write_frame_async};
+
+        let commands = [
+            ServerReceiveCommand::PingCommandType(PingCommand::default()),
+            ServerReceiveCommand::MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(4),
+                muted: true,
+            }),
+        ];
+        let mut writer = Cursor::new(Vec::new());
+        for command in &commands {
+            complete(write_frame_async(command, &mut writer, PROTOCOL_VERSION)).unwrap();
+        }
+        let written = writer.into_inner();
+        let mut out_stream = OutOctetStream::new();
+        for command in &commands {
+            write_frame_with_version(command, &mut out_stream, PROTOCOL_VERSION).unwrap();
+        }
+        assert_eq!(written, out_stream.data);
+
+        let mut reader = Cursor::new(written.clone());
+        for command in &commands {
+            let read = complete(read_frame_async::<ServerReceiveCommand>(
+                &mut reader,
+                PROTOCOL_VERSION,
+            ));
+            assert_eq!(read.unwrap().as_ref(), Some(command));
+        }
+        let end = complete(read_frame_async::<ServerReceiveCommand>(
+            &mut reader,
+            PROTOCOL_VERSION,
+        ));
+        assert!(end.unwrap().is_none());
+
+        let mut reader = Cursor::new(&written[..written.len() - 1]);
+        complete(read_frame_async::<ServerReceiveCommand>(
+            &mut reader,
+            PROTOCOL_VERSION,
+        ))
+        .unwrap();
+        let err = complete(read_frame_async::<ServerReceiveCommand>(
+            &mut reader,
+            PROTOCOL_VERSION,
+        ))
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn check_command_sink_and_stream() {
+        use std::io::Result;
+        use std::pin::Pin;
+        use std::task::{Context, Poll};
+
+        use bytes::Bytes;
+        use futures_util::{stream, Sink, SinkExt, StreamExt};
+
+        use crate::{CommandSink, CommandStream};
+
+        struct DatagramSink(Vec<Bytes>);
+
+        impl Sink<Bytes> for DatagramSink {
+            type Error = std::io::Error;
+
+            fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn start_send(mut self: Pin<&mut Self>, datagram: Bytes) -> Result<()> {
+                self.0.push(datagram);
+                Ok(())
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let commands = [
+            ClientReceiveCommand::MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(1),
+                muted: true,
+            }),
+            ClientReceiveCommand::RoomClosedType(RoomClosedCommand {
+                reason_code: 2,
+                closes_at_ms: 500,
+            }),
+        ];
+        let mut sink = CommandSink::new(DatagramSink(Vec::new())).with_version(PROTOCOL_VERSION);
+        for command in commands.clone() {
+            complete(sink.send(command)).unwrap();
+        }
+        let mut datagrams = sink.into_inner().0;
+        assert_eq!(
+            datagrams[1],
+            commands[1].to_vec_with_version(PROTOCOL_VERSION).unwrap()
+        );
+
+        let mut trailing = datagrams[0].to_vec();
+        trailing.push(0);
+        datagrams.push(Bytes::from(trailing));
+        let decoded = complete(
+            CommandStream::<_, ClientReceiveCommand>::new(stream::iter(datagrams))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[0].as_ref().unwrap(), &commands[0]);
+        assert_eq!(decoded[1].as_ref().unwrap(), &commands[1]);
+        assert_eq!(
+            ProtocolError::from_io(decoded[2].as_ref().unwrap_err()),
+            Some(&ProtocolError::TrailingBytes { count: 1 })
+        );
+    }
+
+    #[cfg(feature = "renet")]
+    #[test]
+    fn check_renet_channels() {
+        use renet::{RenetClient, RenetServer};
+
+        use crate::{
+            receive_from_renet_client, receive_from_renet_server, renet_channel_id,
+            renet_connection_config, send_to_renet_client, send_to_renet_server,
+            RENET_RELIABLE_ORDERED_CHANNEL_ID, RENET_UNRELIABLE_CHANNEL_ID,
+        };
+
+        assert_eq!(
+            renet_channel_id(ROOM_INFO_COMMAND_TYPE_ID),
+            RENET_RELIABLE_ORDERED_CHANNEL_ID
+        );
+        assert_eq!(
+            renet_channel_id(PING_COMMAND_TYPE_ID),
+            RENET_UNRELIABLE_CHANNEL_ID
+        );
+        assert_eq!(renet_channel_id(0xff), RENET_RELIABLE_ORDERED_CHANNEL_ID);
+
+        let mut server = RenetServer::new(renet_connection_config());
+        let mut client = RenetClient::new(renet_connection_config());
+        let client_id = 7;
+        server.add_connection(client_id);
+
+        let room_info = RoomInfoCommand {
+            term: 2,
+            leader_index: ConnectionIndex(0),
+            client_infos: [ClientInfo::default()].into_iter().collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        send_to_renet_client(&mut server, client_id, room_info.clone(), PROTOCOL_VERSION).unwrap();
+        for packet in server.get_packets_to_send(client_id).unwrap() {
+            client.process_packet(&packet);
+        }
+        let received = receive_from_renet_server(&mut client, PROTOCOL_VERSION);
+        assert_eq!(received.len(), 1);
+        assert_eq!(
+            received[0].as_ref().unwrap(),
+            &ClientReceiveCommand::RoomInfoType(room_info)
+        );
+
+        client.set_connected();
+        send_to_renet_server(&mut client, PingCommand::default(), PROTOCOL_VERSION).unwrap();
+        for packet in client.get_packets_to_send() {
+            server.process_packet_from(&packet, client_id).unwrap();
+        }
+        let received = receive_from_renet_client(&mut server, client_id, PROTOCOL_VERSION);
+        assert!(matches!(
+            received.as_slice(),
+            [Ok(ServerReceiveCommand::PingCommandType(_))]
+        ));
+    }
+
+    #[cfg(feature = "webrtc")]
+    #[test]
+    fn check_webrtc_data_channel() {
+        use crate::{DataChannelReceiver, DataChannelSender};
+
+        let room_info = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            term: 9,
+            leader_index: ConnectionIndex(0),
+            client_infos: (0..20)
+                .map(|index| ClientInfo {
+                    connection_index: ConnectionIndex(index),
+                    ..ClientInfo::default()
+                })
+                .collect(),
+            knowledge_vector: None,
+            quorum_size: 2,
+            votes_for_leader: 2,
+            leader_lease_expires_in_ms: 1500,
+        });
+        let mut sender = DataChannelSender::new()
+            .with_max_message_octets(64)
+            .with_version(PROTOCOL_VERSION);
+        let messages = sender.encode(&room_info).unwrap();
+        assert!(messages.len() > 1);
+        assert!(messages.iter().all(|message| message.len() <= 64));
+
+        let mut received = Vec::new();
+        let mut receiver =
+            DataChannelReceiver::<ClientReceiveCommand, _>::new(|command| received.push(command))
+                .with_version(PROTOCOL_VERSION);
+        for message in messages.iter().rev() {
+            receiver.on_message(message);
+        }
+        receiver.on_message(&[0x00]);
+        assert_eq!(receiver.pending_count(), 0);
+        drop(receiver);
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].as_ref().unwrap(), &room_info);
+        assert!(received[1].is_err());
+
+        assert!(DataChannelSender::new()
+            .with_max_message_octets(4)
+            .encode(&room_info)
+            .is_err());
+    }
+
+    #[cfg(feature = "steamworks")]
+    #[test]
+    fn check_steam_messages() {
+        use crate::{
+            decode_steam_message, encode_steam_message, STEAM_RELIABLE_LANE, STEAM_SEND_NO_NAGLE,
+            STEAM_SEND_RELIABLE, STEAM_UNRELIABLE_LANE,
+        };
+
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand::default());
+        let message = encode_steam_message(&ping, PROTOCOL_VERSION).unwrap();
+        assert_eq!(message.lane, STEAM_UNRELIABLE_LANE);
+        assert_eq!(message.send_flags, STEAM_SEND_NO_NAGLE);
+        assert_eq!(
+            decode_steam_message::<ServerReceiveCommand>(&message.payload, PROTOCOL_VERSION)
+                .unwrap(),
+            ping
+        );
+
+        let room_closed = ClientReceiveCommand::RoomClosedType(RoomClosedCommand::default());
+        let message = encode_steam_message(&room_closed, PROTOCOL_VERSION).unwrap();
+        assert_eq!(message.lane, STEAM_RELIABLE_LANE);
+        assert_eq!(message.send_flags, STEAM_SEND_RELIABLE);
+
+        let mut payload = message.payload;
+        payload.push(0);
+        assert!(decode_steam_message::<ClientReceiveCommand>(&payload, PROTOCOL_VERSION).is_err());
+    }
+
+    #[test]
+    fn check_channel_profile() {
+        let reliable = ChannelRoute {
+            channel_id: 0,
+            delivery: CommandDelivery::ReliableOrdered,
+        };
+        let unreliable = ChannelRoute {
+            channel_id: 1,
+            delivery: CommandDelivery::Unreliable,
+        };
+        let profile = ChannelProfile::from_descriptors(0, 1);
+        assert_eq!(profile.route(ROOM_INFO_COMMAND_TYPE_ID), reliable);
+        assert_eq!(profile.route(PING_COMMAND_TYPE_ID), unreliable);
+        assert_eq!(profile.route(0xee), reliable);
+        assert_eq!(profile.channel_count(), 2);
+
+        let voice = ChannelRoute {
+            channel_id: 3,
+            delivery: CommandDelivery::Unreliable,
+        };
+        let profile = profile.with_route(BROADCAST_PAYLOAD_COMMAND_TYPE_ID, voice);
+        assert_eq!(profile.channel_count(), 4);
+        let (route, octets) = profile
+            .encode_routed(
+                &ServerReceiveCommand::BroadcastPayloadType(BroadcastPayloadCommand::default()),
+                PROTOCOL_VERSION,
+            )
+            .unwrap();
+        assert_eq!(route, voice);
+        assert_eq!(octets[0], BROADCAST_PAYLOAD_COMMAND_TYPE_ID);
+
+        let command = ClientReceiveCommand::ChannelProfileType(ChannelProfileCommand {
+            profile: ChannelProfile::new(reliable).with_route(PING_COMMAND_TYPE_ID, unreliable),
+        });
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            octets,
+            [
+                CHANNEL_PROFILE_COMMAND_TYPE_ID,
+                0x00,
+                0x00,
+                0x01,
+                PING_COMMAND_TYPE_ID,
+                0x01,
+                0x01
+            ]
+        );
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        let mut bad_delivery = octets.clone();
+        bad_delivery[6] = 0x02;
+        assert!(ClientReceiveCommand::decode_exact(&bad_delivery, PROTOCOL_VERSION).is_err());
+        let out_of_order = [CHANNEL_PROFILE_COMMAND_TYPE_ID, 0, 0, 2, 5, 0, 0, 4, 0, 0];
+        let err = ClientReceiveCommand::decode_exact(&out_of_order, PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().path,
+            "channel_profile.profile.routes[1]"
+        );
+    }
+
+    #[test]
+    fn check_encode_budgeted() {
+        let room_info = ClientReceiveCommand::RoomInfoType(RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(0),
+            client_infos: (0..8)
+                .map(|index| ClientInfo {
+                    connection_index: ConnectionIndex(index),
+                    ..ClientInfo::default()
+                })
+                .collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        });
+        let muted = |index| {
+            ClientReceiveCommand::MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(index),
+                muted: true,
+            })
+        };
+        let commands = [muted(1), room_info.clone(), muted(2), muted(3)];
+        let mute_frame_octets = 2 + muted(1)
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap()
+            .len();
+        let priority = |command: &ClientReceiveCommand| {
+            matches!(command, ClientReceiveCommand::RoomInfoType(_))
+        };
+
+        let encoding =
+            encode_budgeted(&commands, 2 * mute_frame_octets, PROTOCOL_VERSION, priority).unwrap();
+        assert_eq!(encoding.omitted, [1, 3]);
+        let decoded = FrameDecoder::<ClientReceiveCommand>::new(&encoding.octets)
+            .with_version(PROTOCOL_VERSION)
+            .map(|frame| match frame.unwrap() {
+                Frame::Command(command) => command,
+                Frame::Skipped { .. } => panic!("skipped frame"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, [muted(1), muted(2)]);
+
+        let room_info_frame_octets = 2 + room_info
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap()
+            .len();
+        let encoding = encode_budgeted(
+            &commands,
+            room_info_frame_octets + mute_frame_octets,
+            PROTOCOL_VERSION,
+            priority,
+        )
+        .unwrap();
+        assert_eq!(encoding.omitted, [2, 3]);
+        assert_eq!(
+            encoding.octets.len(),
+            room_info_frame_octets + mute_frame_octets
+        );
+
+        let encoding = encode_budgeted(&commands, 0, PROTOCOL_VERSION, priority).unwrap();
+        assert!(encoding.octets.is_empty());
+        assert_eq!(encoding.omitted, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn check_room_info_pages() {
+        let room_info = RoomInfoCommand {
+            term: 9,
+            leader_index: ConnectionIndex(17),
+            client_infos: (0..20)
+                .map(|index| ClientInfo {
+                    identity: ClientIdentity::U64(index as u64),
+                    connection_index: ConnectionIndex(index),
+                    role_flags: ROLE_FLAG_PLAYER,
+                    revision: 0,
+                    presence: PresenceState::Active,
+                    network_stats: None,
+                })
+                .collect(),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        let pages = RoomInfoPageCommand::pages(&room_info, 64, PROTOCOL_VERSION).unwrap();
+        assert_eq!(pages.len(), 10);
+        for page in &pages {
+            let command = ClientReceiveCommand::RoomInfoPageType(page.clone());
+            let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+            assert_eq!(octets[0], ROOM_INFO_PAGE_COMMAND_TYPE_ID);
+            assert!(octets.len() <= 64);
+            assert_eq!(
+                ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+                command
+            );
+        }
+
+        let mut accumulator = RoomInfoPageAccumulator::new();
+        for page in pages[1..].iter().rev() {
+            assert_eq!(accumulator.receive(page.clone()).unwrap(), None);
+        }
+        assert_eq!(accumulator.receive(pages[1].clone()).unwrap(), None);
+        assert_eq!(accumulator.pending_term(), Some(9));
+        assert_eq!(
+            accumulator.receive(pages[0].clone()).unwrap(),
+            Some(room_info.clone())
+        );
+        assert_eq!(accumulator.pending_term(), None);
+
+        // A newer term replaces the pages of the pending term
+        assert_eq!(accumulator.receive(pages[0].clone()).unwrap(), None);
+        let newer = RoomInfoCommand {
+            term: 10,
+            ..RoomInfoCommand::default()
+        };
+        let newer_pages = RoomInfoPageCommand::pages(&newer, 64, PROTOCOL_VERSION).unwrap();
+        assert_eq!(newer_pages.len(), 1);
+        assert_eq!(
+            accumulator.receive(newer_pages[0].clone()).unwrap(),
+            Some(newer)
+        );
+
+        assert_eq!(accumulator.receive(pages[0].clone()).unwrap(), None);
+        let changed_count = RoomInfoPageCommand {
+            page_count: 6,
+            ..pages[1].clone()
+        };
+        assert!(accumulator.receive(changed_count).is_err());
+
+        assert!(RoomInfoPageCommand::pages(&room_info, 8, PROTOCOL_VERSION).is_err());
+        let beyond_count = [ROOM_INFO_PAGE_COMMAND_TYPE_ID, 0, 9, 2, 2, 0, 0];
+        assert!(ClientReceiveCommand::decode_exact(&beyond_count, PROTOCOL_VERSION).is_err());
+
+        {
+            let too_many = RoomInfoPageCommand {
+                client_infos: (0..=255).map(|_| ClientInfo::default()).collect(),
+                ..pages[0].clone()
+            };
+            let err = too_many
+                .to_octets_with_version(&mut OutOctetStream::new(), PROTOCOL_VERSION)
+                .unwrap_err();
+            assert_eq!(DecodeError::from_io(&err).unwrap().path, "client_infos");
+        }
+    }
+
+    #[test]
+    fn check_room_info_page_election_info() {
+        let room_info = RoomInfoCommand {
+            term: 9,
+            leader_index: ConnectionIndex(3),
+            client_infos: (0..8)
+                .map(|index| ClientInfo {
+                    connection_index: ConnectionIndex(index),
+                    ..ClientInfo::default()
+                })
+                .collect(),
+            knowledge_vector: Some(KnowledgeVector::from_iter([
+                (ConnectionIndex(1), 40),
+                (ConnectionIndex(3), 42),
+            ])),
+            quorum_size: 5,
+            votes_for_leader: 6,
+            leader_lease_expires_in_ms: 1500,
+        };
+        let pages = RoomInfoPageCommand::pages(&room_info, 48, PROTOCOL_VERSION).unwrap();
+        assert!(pages.len() > 2);
+        assert!(pages[0].knowledge_vector.is_some());
+        assert!(pages[1..].iter().all(|page| page.knowledge_vector.is_none()
+            && page.quorum_size == 5
+            && page.votes_for_leader == 6
+            && page.leader_lease_expires_in_ms == 1500));
+        let mut accumulator = RoomInfoPageAccumulator::new();
+        let mut merged = None;
+        for page in pages.iter().rev() {
+            let octets = ClientReceiveCommand::RoomInfoPageType(page.clone())
+                .to_vec_with_version(PROTOCOL_VERSION)
+                .unwrap();
+            assert!(octets.len() <= 48);
+            let ClientReceiveCommand::RoomInfoPageType(decoded) =
+                ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap()
+            else {
+                panic!("not a room info page");
+            };
+            merged = accumulator.receive(decoded).unwrap();
+        }
+        assert_eq!(merged, Some(room_info.clone()));
+
+        // Older versions do not carry them
+        let old_pages = RoomInfoPageCommand::pages(&room_info, 48, PROTOCOL_VERSION_12).unwrap();
+        let mut accumulator = RoomInfoPageAccumulator::new();
+        for page in &old_pages {
+            let octets = ClientReceiveCommand::RoomInfoPageType(page.clone())
+                .to_vec_with_version(PROTOCOL_VERSION_12)
+                .unwrap();
+            let ClientReceiveCommand::RoomInfoPageType(decoded) =
+                ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION_12).unwrap()
+            else {
+                panic!("not a room info page");
+            };
+            merged = accumulator.receive(decoded).unwrap();
+        }
+        assert_eq!(
+            merged,
+            Some(RoomInfoCommand {
+                knowledge_vector: None,
+                quorum_size: 0,
+                votes_for_leader: 0,
+                leader_lease_expires_in_ms: 0,
+                ..room_info.clone()
+            })
+        );
+
+        let mut accumulator = RoomInfoPageAccumulator::new();
+        assert_eq!(accumulator.receive(pages[0].clone()).unwrap(), None);
+        let changed_votes = RoomInfoPageCommand {
+            votes_for_leader: 4,
+            ..pages[1].clone()
+        };
+        assert!(accumulator.receive(changed_votes).is_err());
+        let late_knowledge_vector = RoomInfoPageCommand {
+            knowledge_vector: pages[0].knowledge_vector.clone(),
+            ..pages[1].clone()
+        };
+        assert!(accumulator.receive(late_knowledge_vector).is_err());
+        let later_lease = RoomInfoPageCommand {
+            leader_lease_expires_in_ms: 1400,
+            ..pages[1].clone()
+        };
+        for page in &pages[2..] {
+            assert_eq!(accumulator.receive(page.clone()).unwrap(), None);
+        }
+        let merged = accumulator.receive(later_lease).unwrap().unwrap();
+        assert_eq!(merged.leader_lease_expires_in_ms, 1400);
+
+        let mut unknown_flags = vec![ROOM_INFO_PAGE_COMMAND_TYPE_ID, 0, 9, 0, 1, 0, 0, 0x80];
+        unknown_flags.resize(1 + RoomInfoPageCommand::min_octet_size(PROTOCOL_VERSION), 0);
+        let err = ClientReceiveCommand::decode_exact(&unknown_flags, PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().path,
+            "room_info_page.flags"
+        );
+        assert!(RoomInfoPageCommand::pages(&room_info, 12, PROTOCOL_VERSION).is_err());
+    }
+
+    #[test]
+    fn check_membership_sync() {
+        let client = |connection_index: u8, revision| ClientInfo {
+            identity: ClientIdentity::U64(connection_index as u64),
+            connection_index: ConnectionIndex(connection_index),
+            role_flags: ROLE_FLAG_PLAYER,
+            revision,
+            presence: PresenceState::Active,
+            network_stats: None,
+        };
+        let mut client_room_info = RoomInfoCommand {
+            term: 3,
+            leader_index: ConnectionIndex(2),
+            client_infos: ClientInfos::from_iter([client(1, 1), client(2, 1), client(3, 1)]),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        let server_room_info = RoomInfoCommand {
+            term: 4,
+            leader_index: ConnectionIndex(4),
+            client_infos: ClientInfos::from_iter([
+                client(1, 1),
+                ClientInfo {
+                    role_flags: ROLE_FLAG_HOST,
+                    ..client(3, 2)
+                },
+                client(4, 1),
+            ]),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+
+        let request = MembershipSyncRequestCommand::from_room_info(&client_room_info);
+        assert_eq!(
+            request.known_revisions[1],
+            KnownRevision {
+                connection_index: ConnectionIndex(2),
+                revision: 1
+            }
+        );
+        let octets = ServerReceiveCommand::MembershipSyncRequestType(request.clone())
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(octets.len(), 1 + 1 + 3 * KnownRevision::OCTET_SIZE);
+        assert_eq!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ServerReceiveCommand::MembershipSyncRequestType(request.clone())
+        );
+
+        let response = MembershipSyncResponseCommand::for_request(&server_room_info, &request);
+        assert_eq!(response.leader_connection_index, ConnectionIndex(4));
+        assert_eq!(
+            response.changed.as_slice(),
+            &server_room_info.client_infos[1..]
+        );
+        assert_eq!(response.removed, [ConnectionIndex(2)]);
+        let command = ClientReceiveCommand::MembershipSyncResponseType(response.clone());
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(octets[0], MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID);
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        response.apply_to(&mut client_room_info).unwrap();
+        assert_eq!(client_room_info, server_room_info);
+
+        // The response carries no knowledge vector, election info or leader lease, so they are lost
+        let mut with_election_info = RoomInfoCommand {
+            knowledge_vector: Some(KnowledgeVector::from_iter([(ConnectionIndex(1), 7)])),
+            quorum_size: 2,
+            votes_for_leader: 2,
+            leader_lease_expires_in_ms: 1500,
+            ..client_room_info.clone()
+        };
+        response.apply_to(&mut with_election_info).unwrap();
+        assert_eq!(with_election_info, server_room_info);
+
+        let unsynced = MembershipSyncResponseCommand {
+            leader_connection_index: ConnectionIndex(9),
+            ..response
+        };
+        let before = client_room_info.clone();
+        assert!(unsynced.apply_to(&mut client_room_info).is_err());
+        assert_eq!(client_room_info, before);
+
+        let with_revision = RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(1),
+            client_infos: ClientInfos::from_iter([client(1, 7)]),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        let mut out_stream = OutOctetStream::new();
+        with_revision
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_6)
+            .unwrap();
+        let decoded = RoomInfoCommand::from_cursor_with_version(
+            &mut InOctetStream::new(out_stream.data),
+            PROTOCOL_VERSION_6,
+        )
+        .unwrap();
+        assert_eq!(decoded.client_infos[0].revision, 0);
+    }
+
+    #[test]
+    fn check_presence() {
+        let command = ServerReceiveCommand::SetPresenceType(SetPresenceCommand {
+            presence: PresenceState::Away,
+        });
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(octets, [SET_PRESENCE_COMMAND_TYPE_ID, 0x01]);
+        assert_eq!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        let err = ServerReceiveCommand::decode_exact(
+            &[SET_PRESENCE_COMMAND_TYPE_ID, 0x03],
+            PROTOCOL_VERSION,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().path,
+            "set_presence.presence"
+        );
+
+        let client_info = ClientInfo {
+            identity: ClientIdentity::U64(1),
+            connection_index: ConnectionIndex(1),
+            role_flags: 0,
+            revision: 0,
+            presence: PresenceState::Busy,
+            network_stats: None,
+        };
+        for (version, presence) in [
+            (PROTOCOL_VERSION_7, PresenceState::Active),
+            (PROTOCOL_VERSION_8, PresenceState::Busy),
+        ] {
+            let mut out_stream = OutOctetStream::new();
+            client_info
+                .to_octets_with_version(&mut out_stream, version)
+                .unwrap();
+            let mut in_stream = InOctetStream::new(out_stream.data);
+            let decoded = ClientInfo::from_cursor_with_version(&mut in_stream, version).unwrap();
+            assert_eq!(decoded.presence, presence);
+        }
+    }
+
+    #[test]
+    fn check_room_info_network_stats() {
+        let client = |connection_index: u8, rtt_ms| ClientInfo {
+            identity: ClientIdentity::U64(connection_index as u64),
+            connection_index: ConnectionIndex(connection_index),
+            role_flags: 0,
+            revision: 0,
+            presence: PresenceState::Active,
+            network_stats: Some(ClientNetworkStats {
+                rtt_ms,
+                loss_percent: 2,
+            }),
+        };
+        let mut room_info = RoomInfoCommand {
+            term: 1,
+            leader_index: ConnectionIndex(1),
+            client_infos: ClientInfos::from_iter([client(1, 40), client(2, 300)]),
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        let command = ClientReceiveCommand::RoomInfoType(room_info.clone());
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        // The flags are followed by the election info and leader lease, the stats come last
+        let flags_offset = octets.len() - 13;
+        assert_eq!(octets[flags_offset], ROOM_INFO_FLAG_NETWORK_STATS);
+        assert_eq!(octets[octets.len() - 6..], [0x00, 40, 2, 0x01, 0x2C, 2]);
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        // Peers before version 9 do not get the section
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_8)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let decoded =
+            RoomInfoCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION_8).unwrap();
+        assert!(decoded
+            .client_infos
+            .iter()
+            .all(|client_info| client_info.network_stats.is_none()));
+
+        let mut flags_octets = octets.clone();
+        flags_octets[flags_offset] = 0x04;
+        let err =
+            ClientReceiveCommand::decode_checked(&flags_octets, PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(DecodeError::from_io(&err).unwrap().path, "room_info.flags");
+
+        room_info.client_infos[1].network_stats = None;
+        let err = ClientReceiveCommand::RoomInfoType(room_info)
+            .to_vec_with_version(PROTOCOL_VERSION)
+            .unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().path,
+            "client_infos[1].network_stats"
+        );
+    }
+
+    #[test]
+    fn check_knowledge_vector() {
+        let mut knowledge_vector =
+            KnowledgeVector::from_iter([(ConnectionIndex(1), 10), (ConnectionIndex(3), 4)]);
+        let other =
+            KnowledgeVector::from_iter([(ConnectionIndex(1), 7), (ConnectionIndex(2), 300)]);
+        assert!(!knowledge_vector.dominates(&other));
+        knowledge_vector.merge(&other);
+        assert!(knowledge_vector.dominates(&other));
+        assert_eq!(knowledge_vector.get(ConnectionIndex(1)), 10);
+        assert_eq!(knowledge_vector.get(ConnectionIndex(9)), 0);
+        assert_eq!(knowledge_vector.common_knowledge(), Some(4));
+        knowledge_vector.observe(ConnectionIndex(3), 2);
+        assert_eq!(knowledge_vector.get(ConnectionIndex(3)), 4);
+
+        let ping = ServerReceiveCommand::PingCommandType(PingCommand {
+            term: 1,
+            knowledge: 10,
+            knowledge_vector: Some(knowledge_vector.clone()),
+            ..Default::default()
+        });
+        let octets = ping.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            octets[15..],
+            [0x01, 0x03, 0x01, 10, 0x02, 0xAC, 0x02, 0x03, 4]
+        );
+        assert_eq!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ping
+        );
+
+        let mut unordered = octets.clone();
+        unordered[17] = 0x02;
+        let err = ServerReceiveCommand::decode_exact(&unordered, PROTOCOL_VERSION).unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().path,
+            "ping.knowledge_vector.entries[1].connection_index"
+        );
+
+        let room_info = RoomInfoCommand::builder()
+            .term(2)
+            .knowledge_vector(knowledge_vector)
+            .build()
+            .unwrap();
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(out_stream.data[4], ROOM_INFO_FLAG_KNOWLEDGE_VECTOR);
+        let mut in_stream = InOctetStream::new(out_stream.data.clone());
+        assert_eq!(
+            RoomInfoCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION).unwrap(),
+            room_info
+        );
+
+        // Version 9 neither sends nor accepts the knowledge vector
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let err = RoomInfoCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION_9)
+            .unwrap_err();
+        assert_eq!(DecodeError::from_io(&err).unwrap().path, "flags");
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_9)
+            .unwrap();
+        assert_eq!(out_stream.data, [0x00, 0x02, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn check_knowledge_ack() {
+        let knowledge_ack = KnowledgeAckCommand {
+            acked_knowledge: 0x1234,
+        };
+        assert!(knowledge_ack.acknowledges(0x1234));
+        assert!(!knowledge_ack.acknowledges(0x1235));
+
+        let command = ClientReceiveCommand::KnowledgeAckType(knowledge_ack);
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            octets,
+            [KNOWLEDGE_ACK_COMMAND_TYPE_ID, 0, 0, 0, 0, 0, 0, 0x12, 0x34]
+        );
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        let mut out_stream = OutOctetStream::new();
+        assert!(command
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_9)
+            .is_err());
+    }
+
+    #[test]
+    fn check_term_advanced() {
+        let command = ClientReceiveCommand::TermAdvancedType(TermAdvancedCommand {
+            new_term: 0x0102,
+            cause: TERM_ADVANCED_CAUSE_LEADER_TIMEOUT,
+        });
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            octets,
+            [
+                TERM_ADVANCED_COMMAND_TYPE_ID,
+                0x01,
+                0x02,
+                TERM_ADVANCED_CAUSE_LEADER_TIMEOUT
+            ]
+        );
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        let err = ClientReceiveCommand::decode_exact(
+            &[TERM_ADVANCED_COMMAND_TYPE_ID, 0xFF, 0xFF, 0],
+            PROTOCOL_VERSION,
+        )
+        .unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().path,
+            "term_advanced.new_term"
+        );
+    }
+
+    #[test]
+    fn check_room_info_quorum() {
+        let room_info = RoomInfoCommand::builder()
+            .term(3)
+            .election(3, 2)
+            .build()
+            .unwrap();
+        assert!(!room_info.leader_has_quorum());
+        assert!(RoomInfoCommand {
+            votes_for_leader: 3,
+            ..room_info.clone()
+        }
+        .leader_has_quorum());
+        assert!(!RoomInfoCommand::<ClientInfos>::default().leader_has_quorum());
+
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(
+            out_stream.data,
+            [0x00, 0x03, 0x00, 0x00, 0x00, 3, 2, 0, 0, 0, 0]
+        );
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert_eq!(
+            RoomInfoCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION).unwrap(),
+            room_info
+        );
+
+        // Older layouts decode as unknown
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_10)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let decoded =
+            RoomInfoCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION_10).unwrap();
+        assert_eq!((decoded.quorum_size, decoded.votes_for_leader), (0, 0));
+        assert!(room_info
+            .to_deterministic_octets(&mut OutOctetStream::new(), PROTOCOL_VERSION_10)
+            .is_err());
+    }
+
+    #[test]
+    fn check_room_info_leader_lease() {
+        let room_info = RoomInfoCommand::builder()
+            .term(3)
+            .leader_lease_expires_in(std::time::Duration::from_millis(0x0102_0304))
+            .build()
+            .unwrap();
+        assert_eq!(
+            room_info.leader_lease_expires_in(),
+            std::time::Duration::from_millis(0x0102_0304)
+        );
+        let saturated = RoomInfoCommand::builder()
+            .leader_lease_expires_in(std::time::Duration::from_secs(u64::MAX))
+            .build()
+            .unwrap();
+        assert_eq!(saturated.leader_lease_expires_in_ms, u32::MAX);
+
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(out_stream.data[7..], [0x01, 0x02, 0x03, 0x04]);
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        assert_eq!(
+            RoomInfoCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION).unwrap(),
+            room_info
+        );
+
+        // Older layouts decode as unknown
+        let mut out_stream = OutOctetStream::new();
+        room_info
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_11)
+            .unwrap();
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        let decoded =
+            RoomInfoCommand::from_cursor_with_version(&mut in_stream, PROTOCOL_VERSION_11).unwrap();
+        assert_eq!(decoded.leader_lease_expires_in_ms, 0);
+        assert!(room_info
+            .to_deterministic_octets(&mut OutOctetStream::new(), PROTOCOL_VERSION_11)
+            .is_err());
+    }
+
+    #[test]
+    fn check_pre_vote() {
+        let request = PreVoteRequestCommand {
+            next_term: 0x0102,
+            knowledge: 0x1234,
+        };
+        let command = ServerReceiveCommand::PreVoteRequestType(request);
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            octets,
+            [
+                PRE_VOTE_REQUEST_COMMAND_TYPE_ID,
+                0x01,
+                0x02,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0x12,
+                0x34
+            ]
+        );
+        assert_eq!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        let response = PreVoteResponseCommand {
+            next_term: 0x0102,
+            granted: true,
+        };
+        assert!(response.answers(&request));
+        assert!(!PreVoteResponseCommand {
+            next_term: 0x0101,
+            ..response
+        }
+        .answers(&request));
+        let command = ClientReceiveCommand::PreVoteResponseType(response);
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            octets,
+            [PRE_VOTE_RESPONSE_COMMAND_TYPE_ID, 0x01, 0x02, 0x01]
+        );
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+
+        let err = ClientReceiveCommand::decode_exact(
+            &[PRE_VOTE_RESPONSE_COMMAND_TYPE_ID, 0xFF, 0xFF, 1],
+            PROTOCOL_VERSION,
+        )
+        .unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().path,
+            "pre_vote_response.next_term"
+        );
+
+        let mut out_stream = OutOctetStream::new();
+        assert!(command
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_11)
+            .is_err());
+    }
+
+    #[test]
+    fn check_replicate() {
+        let replicate = ReplicateCommand {
+            term: 0x0102,
+            prev_knowledge: 0x10,
+            entries: vec![
+                LogEntry {
+                    payload: vec![0xAB],
+                },
+                LogEntry::default(),
+            ],
+        };
+        assert_eq!(replicate.last_knowledge(), 0x12);
+
+        let command = ServerReceiveCommand::ReplicateType(replicate.clone());
+        let octets = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            octets,
+            [
+                REPLICATE_COMMAND_TYPE_ID,
+                0x01,
+                0x02,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0x10,
+                2, // Entry count
+                0x00,
+                0x01,
+                0xAB,
+                0x00,
+                0x00
+            ]
+        );
+        assert_eq!(
+            ServerReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            command
+        );
+        // The Server forwards the entries of the leader as they are
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ClientReceiveCommand::ReplicateType(replicate)
+        );
+        let err = ServerReceiveCommand::from_cursor_with_options(
+            &mut InOctetStream::new(octets),
+            &DecodeOptions::new(PROTOCOL_VERSION).with_sender(SenderRole::Client),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+        let err = ServerReceiveCommand::decode_exact(
+            &[
+                REPLICATE_COMMAND_TYPE_ID,
+                0x00,
+                0x01,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                1,
+                0x00,
+                0x02,
+                0xAB,
+            ],
+            PROTOCOL_VERSION,
+        )
+        .unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&err).unwrap().path,
+            "replicate.entries[0].payload"
+        );
+
+        let ack = ClientReceiveCommand::ReplicateAckType(ReplicateAckCommand {
+            term: 0x0102,
+            knowledge: 0x12,
+            success: true,
+        });
+        let octets = ack.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            octets,
+            [
+                REPLICATE_ACK_COMMAND_TYPE_ID,
+                0x01,
+                0x02,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0x12,
+                0x01
+            ]
+        );
+        assert_eq!(
+            ClientReceiveCommand::decode_exact(&octets, PROTOCOL_VERSION).unwrap(),
+            ack
+        );
+
+        let mut out_stream = OutOctetStream::new();
+        assert!(ack
+            .to_octets_with_version(&mut out_stream, PROTOCOL_VERSION_11)
+            .is_err());
+    }
+
+    #[test]
+    fn check_reassembly_limits() {
+        let limits = ReassemblyLimits {
+            max_pending: 2,
+            max_buffered_octets: 1024,
+        };
+        let chunk = |transfer_id, chunk_index, chunk_count, data: &[u8]| TransferChunkCommand {
+            transfer_id,
+            chunk_index,
+            chunk_count,
+            data: data.to_vec(),
+        };
+        let mut receiver = ChunkReceiver::new().with_limits(limits);
+        assert_eq!(receiver.receive(chunk(1, 0, 2, &[1])).unwrap(), None);
+        assert_eq!(receiver.receive(chunk(2, 0, 2, &[2])).unwrap(), None);
+        assert_eq!(
+            receiver.receive(chunk(1, 1, 2, &[1])).unwrap(),
+            Some(vec![1, 1])
+        );
+        assert_eq!(receiver.receive(chunk(3, 0, 2, &[3])).unwrap(), None);
+        assert_eq!(receiver.receive(chunk(1, 0, 2, &[1])).unwrap(), None);
+        assert_eq!(receiver.pending_count(), 2);
+        assert!(receiver.resend_request(2).is_none());
+        assert!(receiver.resend_request(3).is_some());
+
+        assert!(receiver.receive(chunk(4, 0, u16::MAX, &[4])).is_err());
+        assert_eq!(receiver.pending_count(), 2);
+        assert!(receiver.receive(chunk(3, 1, 2, &[0; 1024])).is_err());
+        assert!(receiver.resend_request(3).is_none());
+
+        receiver.cancel(1);
+        assert_eq!(receiver.pending_count(), 0);
+        assert_eq!(receiver.buffered_octets(), 0);
+    }
+
+    #[test]
+    fn check_protocol_layout_covers_every_command() {
+        fn field_names(fields: &[FieldDescriptor], names: &mut Vec<&'static str>) {
+            for field in fields {
+                names.push(field.name);
+                let mut kind = &field.kind;
+                loop {
+                    match kind {
+                        FieldKind::List { element, .. } | FieldKind::Array { element, .. } => {
+                            kind = element
+                        }
+                        FieldKind::Flagged { kind: flagged, .. } => kind = flagged,
+                        FieldKind::Struct(descriptor) => {
+                            field_names(descriptor.fields, names);
+                            break;
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        }
+
+        let line = |type_id: u8| {
+            PROTOCOL_LAYOUT
+                .lines()
+                .find(|line| line.starts_with(&format!("0x{:02X} ", type_id)))
+        };
+        for descriptor in COMMAND_DESCRIPTORS {
+            let line = line(descriptor.type_id).expect("every command has a layout line");
+            assert!(line.starts_with(&format!(
+                "0x{:02X} {}: ",
+                descriptor.type_id, descriptor.name
+            )));
+            let mut names = Vec::new();
+            field_names(descriptor.fields, &mut names);
+            for name in names {
+                assert!(
+                    line.contains(name),
+                    "{} is missing {}",
+                    descriptor.name,
+                    name
+                );
+            }
         }
+        for type_id in CONTROL_COMMAND_TYPE_ID_RANGE {
+            let known = !matches!(
+                ControlCommand::from_slice(&[type_id, 0, 0, 0, 0, 0, 0]),
+                Ok(ControlCommand::Unknown(_))
+            );
+            assert_eq!(
+                line(type_id).is_some(),
+                known,
+                "control command 0x{:x}",
+                type_id
+            );
+        }
+        for name in ["envelope", "extensions", "layout_version", "network_stats"] {
+            assert!(PROTOCOL_LAYOUT.contains(&format!("\n{}: ", name)));
+        }
+    }
+
+    /// The layouts at the end of [`PROTOCOL_LAYOUT`] are written by hand, so each is checked
+    /// against what the code encodes
+    #[test]
+    fn check_protocol_layout_hand_written_lines() {
+        fn assert_line(line: String) {
+            assert!(
+                PROTOCOL_LAYOUT.lines().any(|found| found == line),
+                "missing layout line {}",
+                line
+            );
+        }
+        fn encoded(write: impl FnOnce(&mut OutOctetStream) -> std::io::Result<()>) -> Vec<u8> {
+            let mut out_stream = OutOctetStream::new();
+            write(&mut out_stream).unwrap();
+            out_stream.data
+        }
+
+        assert_line(
+            "client_identity: v4 discriminant u8 [u64 | u128 | utf8 u8-len], user_id before v4"
+                .to_string(),
+        );
+        let identity = |identity: ClientIdentity, version| {
+            encoded(|stream| identity.to_octets_with_version(stream, version))
+        };
+        assert_eq!(
+            identity(ClientIdentity::U64(5), PROTOCOL_VERSION_4),
+            [0x00, 0, 0, 0, 0, 0, 0, 0, 5]
+        );
+        assert_eq!(
+            identity(ClientIdentity::Uuid(5), PROTOCOL_VERSION_4)[..2],
+            [0x01, 0]
+        );
+        assert_eq!(
+            identity(ClientIdentity::Uuid(5), PROTOCOL_VERSION_4).len(),
+            17
+        );
+        assert_eq!(
+            identity(ClientIdentity::String("ab".to_string()), PROTOCOL_VERSION_4),
+            [0x02, 2, b'a', b'b']
+        );
+        assert_eq!(
+            identity(ClientIdentity::U64(5), PROTOCOL_VERSION_3),
+            encoded(|stream| UserId(5).to_octets_with_version(stream, PROTOCOL_VERSION_3))
+        );
+
+        assert_line("user_id: u64, v3 u128".to_string());
+        let user_id = |version| encoded(|stream| UserId(5).to_octets_with_version(stream, version));
+        assert_eq!(user_id(PROTOCOL_VERSION_2), [0, 0, 0, 0, 0, 0, 0, 5]);
+        assert_eq!(user_id(PROTOCOL_VERSION_3).len(), 16);
+        assert_eq!(user_id(PROTOCOL_VERSION_3)[15], 5);
+
+        assert_line("socket_addr: type u8, ip [u8; 4] | [u8; 16], port u16".to_string());
+        let socket_addr = |address: &str| {
+            let address = address.parse().unwrap();
+            encoded(|stream| crate::connect_token::write_socket_addr(stream, &address))
+        };
+        assert_eq!(socket_addr("10.0.0.1:4000"), [1, 10, 0, 0, 1, 0x0F, 0xA0]);
+        let ipv6 = socket_addr("[::1]:4001");
+        assert_eq!((ipv6.len(), ipv6[0], ipv6[16]), (19, 2, 1));
+
+        assert_line(format!(
+            "network_stats: after room_info if flags & 0x{:02X}, for each client_infos [rtt_ms u16, loss_percent u8]",
+            ROOM_INFO_FLAG_NETWORK_STATS
+        ));
+        let mut room_info: RoomInfoCommand = RoomInfoCommand::default();
+        room_info.client_infos.push(ClientInfo {
+            network_stats: Some(ClientNetworkStats {
+                rtt_ms: 0x0102,
+                loss_percent: 3,
+            }),
+            ..Default::default()
+        });
+        let with_stats =
+            encoded(|stream| room_info.to_octets_with_version(stream, PROTOCOL_VERSION));
+        room_info.client_infos[0].network_stats = None;
+        let without_stats =
+            encoded(|stream| room_info.to_octets_with_version(stream, PROTOCOL_VERSION));
+        assert_eq!(with_stats[without_stats.len()..], [0x01, 0x02, 3]);
+
+        assert_line(format!(
+            "envelope: flags u8, correlation_id if flags & 0x{:02X} u32, padding.len if flags & 0x{:02X} u16, command, extensions if flags & 0x{:02X}, padding [u8; padding.len]",
+            ENVELOPE_FLAG_CORRELATION_ID, ENVELOPE_FLAG_PADDING, ENVELOPE_FLAG_EXTENSIONS
+        ));
+        assert_line("extensions: u16 octet count [tag u8, value u16-len]".to_string());
+        let command = ClientReceiveCommand::RoomInfoType(RoomInfoCommand::default());
+        let payload = command.to_vec_with_version(PROTOCOL_VERSION).unwrap();
+        let mut extensions = Extensions::new();
+        extensions.insert(0x02, b"eu".to_vec());
+        let extension_octets = encoded(|stream| extensions.to_octets(stream));
+        assert_eq!(extension_octets, [0x00, 0x05, 0x02, 0x00, 0x02, b'e', b'u']);
+        assert_eq!(
+            encoded(|stream| command.encode_with_extensions(
+                Some(0x0A0B0C0D),
+                &extensions,
+                stream,
+                PROTOCOL_VERSION
+            )),
+            [
+                &[ENVELOPE_FLAG_CORRELATION_ID | ENVELOPE_FLAG_EXTENSIONS][..],
+                &[0x0A, 0x0B, 0x0C, 0x0D],
+                &payload,
+                &extension_octets,
+            ]
+            .concat()
+        );
+        let padded = encoded(|stream| {
+            command.encode_with_padding(None, 3 + payload.len() + 2, stream, PROTOCOL_VERSION)
+        });
+        assert_eq!(
+            padded,
+            [
+                &[ENVELOPE_FLAG_PADDING, 0x00, 0x02][..],
+                &payload,
+                &[0x00, 0x00]
+            ]
+            .concat()
+        );
+
+        assert_line("layout_version: command_type_id u8, layout_version u8, payload".to_string());
+        let with_layout_version =
+            encoded(|stream| command.to_octets_with_layout_version(stream, PROTOCOL_VERSION));
+        let layout_version = command_descriptor(ROOM_INFO_COMMAND_TYPE_ID)
+            .unwrap()
+            .layout_version(PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(
+            with_layout_version,
+            [&[payload[0], layout_version][..], &payload[1..]].concat()
+        );
     }
 }
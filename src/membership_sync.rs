@@ -0,0 +1,281 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Incremental sync of the client infos of a room, based on their revisions.
+//!
+//! The Client sends the revisions of the client infos it has in a
+//! [`MembershipSyncRequestCommand`]. The Server answers with a
+//! [`MembershipSyncResponseCommand`] holding only the client infos with another revision and
+//! the connections that have left, which the Client applies to its `RoomInfoCommand`.
+
+use std::io::{Error, ErrorKind, Result};
+
+use conclave_room::Term;
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{
+    reserve_client_infos, ClientInfo, ClientInfos, ConnectionIndex, ProtocolVersion,
+    RoomInfoCommand, DEFAULT_PROTOCOL_VERSION, PROTOCOL_VERSION_7,
+};
+
+/// Changed by the Server whenever a client info changes
+pub type ClientRevision = u32;
+
+pub const MEMBERSHIP_SYNC_REQUEST_COMMAND_TYPE_ID: u8 = 0x22;
+pub const MEMBERSHIP_SYNC_RESPONSE_COMMAND_TYPE_ID: u8 = 0x23;
+
+pub(crate) fn check_list_len(len: usize, field: &str) -> Result<()> {
+    if len > u8::MAX as usize {
+        return in_field(
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} entries, at most {} supported", len, u8::MAX),
+            )),
+            field,
+        );
+    }
+    Ok(())
+}
+
+/// The revision of a client info the Client has
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KnownRevision {
+    pub connection_index: ConnectionIndex,
+    pub revision: ClientRevision,
+}
+
+impl KnownRevision {
+    pub const OCTET_SIZE: usize = 1 + 4;
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.connection_index.to_octets(stream)?;
+        stream.write_u32(self.revision)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            connection_index: in_field(ConnectionIndex::from_cursor(stream), "connection_index")?,
+            revision: in_field(stream.read_u32(), "revision")?,
+        })
+    }
+}
+
+/// Sent from Client to Server, to only get the client infos that changed since the ones it has
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MembershipSyncRequestCommand {
+    /// At most `u8::MAX`
+    pub known_revisions: Vec<KnownRevision>,
+}
+
+impl MembershipSyncRequestCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "membership_sync_request";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_7;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        1
+    }
+
+    /// The revisions of all client infos in `room_info`
+    pub fn from_room_info(room_info: &RoomInfoCommand) -> Self {
+        Self {
+            known_revisions: room_info
+                .client_infos
+                .iter()
+                .map(|client_info| KnownRevision {
+                    connection_index: client_info.connection_index,
+                    revision: client_info.revision,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        check_list_len(self.known_revisions.len(), "known_revisions")?;
+        stream.write_u8(self.known_revisions.len() as u8)?;
+        for known_revision in &self.known_revisions {
+            known_revision.to_octets(stream)?;
+        }
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let length = in_field(stream.read_u8(), "known_revisions.len")?;
+        let mut known_revisions = Vec::with_capacity(length as usize);
+        for index in 0..length {
+            known_revisions.push(in_field(
+                KnownRevision::from_cursor(stream),
+                format_args!("known_revisions[{}]", index),
+            )?);
+        }
+        Ok(Self { known_revisions })
+    }
+}
+
+/// Sent from Server to Client, as the answer to a [`MembershipSyncRequestCommand`]
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MembershipSyncResponseCommand {
+    pub term: Term,
+    /// The connection index of the leader, as in [`RoomInfoCommand::leader_index`]
+    pub leader_connection_index: ConnectionIndex,
+    /// The client infos the Client does not have, or has with another revision
+    pub changed: ClientInfos,
+    /// Known to the Client, but no longer in the room
+    pub removed: Vec<ConnectionIndex>,
+}
+
+impl MembershipSyncResponseCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "membership_sync_response";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_7;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 1 + 1 + 1
+    }
+
+    /// The changes the Client that sent `request` is missing for `room_info`
+    pub fn for_request(
+        room_info: &RoomInfoCommand,
+        request: &MembershipSyncRequestCommand,
+    ) -> Self {
+        let known = |client_info: &ClientInfo| {
+            request.known_revisions.iter().any(|known_revision| {
+                known_revision.connection_index == client_info.connection_index
+                    && known_revision.revision == client_info.revision
+            })
+        };
+        Self {
+            term: room_info.term,
+            leader_connection_index: room_info.leader_index,
+            changed: room_info
+                .client_infos
+                .iter()
+                .filter(|client_info| !known(client_info))
+                .cloned()
+                .collect(),
+            removed: request
+                .known_revisions
+                .iter()
+                .map(|known_revision| known_revision.connection_index)
+                .filter(|connection_index| {
+                    !room_info
+                        .client_infos
+                        .iter()
+                        .any(|client_info| client_info.connection_index == *connection_index)
+                })
+                .collect(),
+        }
+    }
+
+    /// Updates `room_info` to the room the response was created for. Changed client infos
+    /// replace the ones with the same connection index, or are added after the others.
+    ///
+    /// The result must pass [`RoomInfoCommand::validate`], and have the leader in it unless the
+    /// room is empty. It has no knowledge vector, election info or leader lease, since the response
+    /// does not carry them.
+    /// `room_info` is unchanged on failure.
+    pub fn apply_to(&self, room_info: &mut RoomInfoCommand) -> Result<()> {
+        let mut client_infos = ClientInfos::new();
+        reserve_client_infos(&mut client_infos, room_info.client_infos.len())?;
+        client_infos.extend(
+            room_info
+                .client_infos
+                .iter()
+                .filter(|client_info| !self.removed.contains(&client_info.connection_index))
+                .cloned(),
+        );
+        for changed in self.changed.iter() {
+            match client_infos
+                .iter_mut()
+                .find(|client_info| client_info.connection_index == changed.connection_index)
+            {
+                Some(client_info) => *client_info = changed.clone(),
+                None => {
+                    reserve_client_infos(&mut client_infos, 1)?;
+                    client_infos.push(changed.clone());
+                }
+            }
+        }
+        let synced = RoomInfoCommand {
+            term: self.term,
+            leader_index: self.leader_connection_index,
+            client_infos,
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        synced.validate()?;
+        *room_info = synced;
+        Ok(())
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        check_list_len(self.changed.len(), "changed")?;
+        check_list_len(self.removed.len(), "removed")?;
+        stream.write_u16(self.term)?;
+        self.leader_connection_index.to_octets(stream)?;
+        stream.write_u8(self.changed.len() as u8)?;
+        for client_info in self.changed.iter() {
+            client_info.to_octets_with_version(stream, version)?;
+        }
+        stream.write_u8(self.removed.len() as u8)?;
+        for connection_index in &self.removed {
+            connection_index.to_octets(stream)?;
+        }
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        let term = in_field(stream.read_u16(), "term")?;
+        let leader_connection_index = in_field(
+            ConnectionIndex::from_cursor(stream),
+            "leader_connection_index",
+        )?;
+        let length = in_field(stream.read_u8(), "changed.len")? as usize;
+        let mut changed = ClientInfos::new();
+        in_field(reserve_client_infos(&mut changed, length), "changed.len")?;
+        for index in 0..length {
+            changed.push(in_field(
+                ClientInfo::from_cursor_with_version(stream, version),
+                format_args!("changed[{}]", index),
+            )?);
+        }
+        let length = in_field(stream.read_u8(), "removed.len")?;
+        let mut removed = Vec::with_capacity(length as usize);
+        for index in 0..length {
+            removed.push(in_field(
+                ConnectionIndex::from_cursor(stream),
+                format_args!("removed[{}]", index),
+            )?);
+        }
+        Ok(Self {
+            term,
+            leader_connection_index,
+            changed,
+            removed,
+        })
+    }
+}
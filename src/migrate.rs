@@ -0,0 +1,113 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! The commands as they were in [`crate::PROTOCOL_VERSION_1`], with conversions to the current
+//! structs, so code written against the old layouts can normalize everything to one form.
+//!
+//! Fields that did not exist yet get the value the decoder also gives them for old packets:
+//!
+//! * [`ClientInfo::role_flags`] (from [`crate::PROTOCOL_VERSION_2`]) is `0`, no roles.
+//! * The u64 user id becomes a [`ClientIdentity::U64`], identities replaced user ids in
+//!   [`crate::PROTOCOL_VERSION_4`].
+//! * [`PingCommand::connection_index`] (from [`crate::PROTOCOL_VERSION_5`]) is `0`, the
+//!   connection the ping arrived on.
+//! * [`PingCommand::clock_drift_ppm`] (from [`crate::PROTOCOL_VERSION_6`]) is `0`, no known
+//!   drift.
+//! * [`ClientInfo::revision`] (from [`crate::PROTOCOL_VERSION_7`]) is `0`.
+//! * [`ClientInfo::presence`] (from [`crate::PROTOCOL_VERSION_8`]) is
+//!   [`PresenceState::Active`].
+//! * [`ClientInfo::network_stats`] (from [`crate::PROTOCOL_VERSION_9`]) is `None`.
+//! * [`PingCommand::knowledge_vector`] and [`RoomInfoCommand::knowledge_vector`] (from
+//!   [`crate::PROTOCOL_VERSION_10`]) are `None`.
+//! * [`RoomInfoCommand::quorum_size`] and [`RoomInfoCommand::votes_for_leader`] (from
+//!   [`crate::PROTOCOL_VERSION_11`]) are `0`, unknown.
+//! * [`RoomInfoCommand::leader_lease_expires_in_ms`] (from [`crate::PROTOCOL_VERSION_12`]) is
+//!   `0`, no known lease.
+//!
+//! Conversions into a [`RoomInfoCommand`] can fail, as the result must fit the layout and pass
+//! [`RoomInfoCommand::validate`].
+
+use conclave_room::{Knowledge, Term};
+
+use crate::{
+    ClientIdentity, ClientInfo, ClientInfos, ConnectionIndex, PingCommand, PresenceState,
+    ProtocolError, RoomInfoCommand, MAX_CLIENT_INFOS,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PingCommandV1 {
+    pub term: Term,
+    pub knowledge: Knowledge,
+    pub has_connection_to_leader: bool,
+}
+
+impl From<PingCommandV1> for PingCommand {
+    fn from(ping: PingCommandV1) -> Self {
+        Self {
+            term: ping.term,
+            knowledge: ping.knowledge,
+            has_connection_to_leader: ping.has_connection_to_leader,
+            connection_index: ConnectionIndex(0),
+            clock_drift_ppm: 0,
+            knowledge_vector: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientInfoV1 {
+    pub custom_user_id: u64,
+    pub connection_index: ConnectionIndex,
+}
+
+impl From<ClientInfoV1> for ClientInfo {
+    fn from(client_info: ClientInfoV1) -> Self {
+        Self {
+            identity: ClientIdentity::U64(client_info.custom_user_id),
+            connection_index: client_info.connection_index,
+            role_flags: 0,
+            revision: 0,
+            presence: PresenceState::Active,
+            network_stats: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoomInfoCommandV1 {
+    pub term: Term,
+    pub client_infos: Vec<ClientInfoV1>,
+    pub leader_index: ConnectionIndex,
+}
+
+impl TryFrom<RoomInfoCommandV1> for RoomInfoCommand {
+    type Error = ProtocolError;
+
+    fn try_from(room_info: RoomInfoCommandV1) -> Result<Self, ProtocolError> {
+        if room_info.client_infos.len() > MAX_CLIENT_INFOS {
+            return Err(ProtocolError::TooManyClients {
+                count: room_info.client_infos.len(),
+                max: MAX_CLIENT_INFOS,
+            });
+        }
+        let command = Self {
+            term: room_info.term,
+            client_infos: room_info
+                .client_infos
+                .into_iter()
+                .map(ClientInfo::from)
+                .collect::<ClientInfos>(),
+            leader_index: room_info.leader_index,
+            knowledge_vector: None,
+            quorum_size: 0,
+            votes_for_leader: 0,
+            leader_lease_expires_in_ms: 0,
+        };
+        command.validate()?;
+        Ok(command)
+    }
+}
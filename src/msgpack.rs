@@ -0,0 +1,41 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! A MessagePack encoding of the commands, for msgpack based tooling. Like the CBOR encoding it
+//! uses the serde representation, with structs as maps keyed by field name.
+
+use std::io::{Error, ErrorKind, Result};
+
+use crate::{ClientReceiveCommand, ServerReceiveCommand};
+
+fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>> {
+    rmp_serde::to_vec_named(value).map_err(|err| Error::new(ErrorKind::InvalidInput, err))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(octets: &[u8]) -> Result<T> {
+    rmp_serde::from_slice(octets).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+impl ServerReceiveCommand {
+    pub fn to_msgpack(&self) -> Result<Vec<u8>> {
+        encode(self)
+    }
+
+    pub fn from_msgpack(octets: &[u8]) -> Result<Self> {
+        decode(octets)
+    }
+}
+
+impl ClientReceiveCommand {
+    pub fn to_msgpack(&self) -> Result<Vec<u8>> {
+        encode(self)
+    }
+
+    /// The command must pass [`Self::validate`], like in the binary format
+    pub fn from_msgpack(octets: &[u8]) -> Result<Self> {
+        let command: Self = decode(octets)?;
+        command.validate()?;
+        Ok(command)
+    }
+}
@@ -0,0 +1,157 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Datagram format for relays serving many rooms, coalescing the commands to the same peer.
+//!
+//! Layout: room count, followed by a room id, a command count and the commands for each room.
+//!
+//! The commands have no length of their own, so a command that runs to the end of the stream,
+//! like a [`crate::RawCommand`], can only be the last one.
+
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::raw_command::check_nothing_follows;
+use crate::{Command, ProtocolVersion, RoomId, DEFAULT_PROTOCOL_VERSION};
+
+#[derive(Debug, PartialEq)]
+pub struct MultiplexedDatagram<C> {
+    pub rooms: Vec<(RoomId, Vec<C>)>,
+}
+
+impl<C> Default for MultiplexedDatagram<C> {
+    fn default() -> Self {
+        Self { rooms: Vec::new() }
+    }
+}
+
+impl<C: Command> MultiplexedDatagram<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the command to the group of `room_id`, creating the group if needed.
+    pub fn push(&mut self, room_id: RoomId, command: C) {
+        match self.rooms.iter_mut().find(|(id, _)| *id == room_id) {
+            Some((_, commands)) => commands.push(command),
+            None => self.rooms.push((room_id, vec![command])),
+        }
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        if self.rooms.len() > u8::MAX as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("too many rooms in datagram ({})", self.rooms.len()),
+            ));
+        }
+        stream.write_u8(self.rooms.len() as u8)?;
+        for (room_index, (room_id, commands)) in self.rooms.iter().enumerate() {
+            if commands.len() > u8::MAX as usize {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "too many commands for room {} ({})",
+                        room_id,
+                        commands.len()
+                    ),
+                ));
+            }
+            stream.write_u32(*room_id)?;
+            stream.write_u8(commands.len() as u8)?;
+            for (index, command) in commands.iter().enumerate() {
+                let last = index + 1 == commands.len() && room_index + 1 == self.rooms.len();
+                check_nothing_follows(command, !last, ErrorKind::InvalidInput)?;
+                command.to_octets_with_version(stream, version)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        let mut datagram = Self::new();
+        for result in MultiplexedDecoder::with_version(stream, version)? {
+            let (room_id, command) = result?;
+            datagram.push(room_id, command);
+        }
+        Ok(datagram)
+    }
+
+    pub fn from_octets(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+}
+
+/// Decodes a multiplexed datagram one command at a time.
+pub struct MultiplexedDecoder<'a, S, C> {
+    stream: &'a mut S,
+    rooms_left: u8,
+    commands_left: u8,
+    room_id: RoomId,
+    version: ProtocolVersion,
+    failed: bool,
+    marker: std::marker::PhantomData<C>,
+}
+
+impl<'a, S: ReadOctetStream, C: Command> MultiplexedDecoder<'a, S, C> {
+    pub fn new(stream: &'a mut S) -> Result<Self> {
+        Self::with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    /// Decodes the commands with the layouts of `version`
+    pub fn with_version(stream: &'a mut S, version: ProtocolVersion) -> Result<Self> {
+        let rooms_left = stream.read_u8()?;
+        Ok(Self {
+            stream,
+            rooms_left,
+            commands_left: 0,
+            room_id: 0,
+            version,
+            failed: false,
+            marker: std::marker::PhantomData,
+        })
+    }
+
+    fn read_next(&mut self) -> Result<Option<(RoomId, C)>> {
+        while self.commands_left == 0 {
+            if self.rooms_left == 0 {
+                return Ok(None);
+            }
+            self.rooms_left -= 1;
+            self.room_id = self.stream.read_u32()?;
+            self.commands_left = self.stream.read_u8()?;
+        }
+        self.commands_left -= 1;
+        let command = C::from_octets_with_version(self.stream, self.version)?;
+        let followed = self.commands_left != 0 || self.rooms_left != 0;
+        check_nothing_follows(&command, followed, ErrorKind::InvalidData)?;
+        Ok(Some((self.room_id, command)))
+    }
+}
+
+impl<S: ReadOctetStream, C: Command> Iterator for MultiplexedDecoder<'_, S, C> {
+    type Item = Result<(RoomId, C)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read_next();
+        self.failed = result.is_err();
+        result.transpose()
+    }
+}
@@ -0,0 +1,49 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+/// The largest opaque payload that fits the `u16` length prefix
+pub const MAX_PAYLOAD_OCTETS: usize = u16::MAX as usize;
+
+/// The largest payload a Client may broadcast, since the server repeats it for every Client in the
+/// room
+pub const MAX_BROADCAST_PAYLOAD_OCTETS: usize = 1024;
+
+/// Writes `payload` prefixed with its length in octets. `max` must not exceed
+/// [`MAX_PAYLOAD_OCTETS`].
+pub(crate) fn write_payload(
+    stream: &mut impl WriteOctetStream,
+    payload: &[u8],
+    max: usize,
+) -> Result<()> {
+    if payload.len() > max {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "payload is too large ({} octets, max {})",
+                payload.len(),
+                max
+            ),
+        ));
+    }
+    stream.write_u16(payload.len() as u16)?;
+    stream.write(payload)
+}
+
+/// Reads a payload written by [`write_payload`], rejecting payloads larger than `max`.
+pub(crate) fn read_payload(stream: &mut impl ReadOctetStream, max: usize) -> Result<Vec<u8>> {
+    let length = stream.read_u16()? as usize;
+    if length > max {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("payload is too large ({} octets, max {})", length, max),
+        ));
+    }
+    let mut payload = vec![0u8; length];
+    stream.read(&mut payload)?;
+    Ok(payload)
+}
@@ -0,0 +1,270 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Reads the UDP datagrams of a classic pcap capture (not pcapng) and decodes them as commands,
+//! for looking at production captures offline.
+//!
+//! Ethernet, Linux cooked, loopback and raw IP captures are supported. Other packets, IP
+//! fragments and IPv6 packets with extension headers are skipped.
+
+use std::io::{Error, ErrorKind, Read, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::{ClientReceiveCommand, CommandDirection, ProtocolVersion, ServerReceiveCommand};
+
+const LINK_TYPE_NULL: u32 = 0;
+const LINK_TYPE_ETHERNET: u32 = 1;
+const LINK_TYPE_RAW: u32 = 101;
+const LINK_TYPE_LINUX_SLL: u32 = 113;
+const LINK_TYPE_IPV4: u32 = 228;
+const LINK_TYPE_IPV6: u32 = 229;
+
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const ETHER_TYPE_IPV6: u16 = 0x86DD;
+const ETHER_TYPE_VLAN: u16 = 0x8100;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CapturedCommand {
+    Server(ServerReceiveCommand),
+    Client(ClientReceiveCommand),
+}
+
+#[derive(Debug)]
+pub struct PcapRecord {
+    /// Since the Unix epoch, as recorded by the capturing host
+    pub timestamp: Duration,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    /// [`CommandDirection::Both`] if the datagram decodes in both directions and the ports do
+    /// not tell which side is the server
+    pub direction: CommandDirection,
+    pub payload: Vec<u8>,
+    /// The decoded command, or why it did not decode in the guessed direction
+    pub command: Result<CapturedCommand>,
+}
+
+/// Iterates the UDP datagrams of a capture as [`PcapRecord`]s
+#[derive(Debug)]
+pub struct PcapReader<R> {
+    reader: R,
+    big_endian: bool,
+    nanoseconds: bool,
+    link_type: u32,
+    version: ProtocolVersion,
+    server_port: Option<u16>,
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+fn be_u16(octets: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([octets[offset], octets[offset + 1]])
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Reads the file header. Commands are decoded with `version`.
+    pub fn new(mut reader: R, version: ProtocolVersion) -> Result<Self> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header)?;
+        let (big_endian, nanoseconds) = match header[..4] {
+            [0xD4, 0xC3, 0xB2, 0xA1] => (false, false),
+            [0xA1, 0xB2, 0xC3, 0xD4] => (true, false),
+            [0x4D, 0x3C, 0xB2, 0xA1] => (false, true),
+            [0xA1, 0xB2, 0x3C, 0x4D] => (true, true),
+            _ => return Err(invalid("not a pcap file, pcapng is not supported")),
+        };
+        let mut this = Self {
+            reader,
+            big_endian,
+            nanoseconds,
+            link_type: 0,
+            version,
+            server_port: None,
+        };
+        this.link_type = this.u32_at(&header, 20);
+        match this.link_type {
+            LINK_TYPE_NULL | LINK_TYPE_ETHERNET | LINK_TYPE_RAW | LINK_TYPE_LINUX_SLL
+            | LINK_TYPE_IPV4 | LINK_TYPE_IPV6 => Ok(this),
+            link_type => Err(invalid(format!("unsupported link type {}", link_type))),
+        }
+    }
+
+    /// Datagrams to `port` are client to server, datagrams from it server to client
+    pub fn with_server_port(mut self, port: u16) -> Self {
+        self.server_port = Some(port);
+        self
+    }
+
+    fn u32_at(&self, octets: &[u8], offset: usize) -> u32 {
+        let value = [
+            octets[offset],
+            octets[offset + 1],
+            octets[offset + 2],
+            octets[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(value)
+        } else {
+            u32::from_le_bytes(value)
+        }
+    }
+
+    /// `None` at the end of the file
+    fn read_packet(&mut self) -> Option<Result<(Duration, Vec<u8>)>> {
+        let mut header = [0u8; 16];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.reader.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => return Some(Err(ErrorKind::UnexpectedEof.into())),
+                Ok(count) => filled += count,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        let seconds = self.u32_at(&header, 0) as u64;
+        let fraction = self.u32_at(&header, 4);
+        let timestamp = if self.nanoseconds {
+            Duration::new(seconds, fraction)
+        } else {
+            Duration::new(seconds, 0) + Duration::from_micros(fraction as u64)
+        };
+        let mut packet = vec![0u8; self.u32_at(&header, 8) as usize];
+        if let Err(err) = self.reader.read_exact(&mut packet) {
+            return Some(Err(err));
+        }
+        Some(Ok((timestamp, packet)))
+    }
+
+    /// The IP packet inside the link layer frame
+    fn ip_packet<'a>(&self, frame: &'a [u8]) -> Option<&'a [u8]> {
+        match self.link_type {
+            LINK_TYPE_ETHERNET => {
+                let mut offset = 12;
+                let mut ether_type = be_u16(frame.get(..14)?, offset);
+                if ether_type == ETHER_TYPE_VLAN {
+                    offset += 4;
+                    ether_type = be_u16(frame.get(..18)?, offset);
+                }
+                matches!(ether_type, ETHER_TYPE_IPV4 | ETHER_TYPE_IPV6)
+                    .then(|| &frame[offset + 2..])
+            }
+            LINK_TYPE_LINUX_SLL => {
+                let ether_type = be_u16(frame.get(..16)?, 14);
+                matches!(ether_type, ETHER_TYPE_IPV4 | ETHER_TYPE_IPV6).then(|| &frame[16..])
+            }
+            LINK_TYPE_NULL => frame.get(4..),
+            _ => Some(frame),
+        }
+    }
+
+    fn guess_direction(
+        &self,
+        source: &SocketAddr,
+        destination: &SocketAddr,
+        payload: &[u8],
+    ) -> (CommandDirection, Result<CapturedCommand>) {
+        let as_server = || {
+            ServerReceiveCommand::decode_checked(payload, self.version).map(CapturedCommand::Server)
+        };
+        let as_client = || {
+            ClientReceiveCommand::decode_checked(payload, self.version).map(CapturedCommand::Client)
+        };
+        match self.server_port {
+            Some(port) if destination.port() == port => {
+                return (CommandDirection::ClientToServer, as_server())
+            }
+            Some(port) if source.port() == port => {
+                return (CommandDirection::ServerToClient, as_client())
+            }
+            _ => {}
+        }
+        match (as_server(), as_client()) {
+            (Ok(server), Err(_)) => (CommandDirection::ClientToServer, Ok(server)),
+            (Err(_), Ok(client)) => (CommandDirection::ServerToClient, Ok(client)),
+            // Servers tend to listen on the lower, well-known port
+            (server, client) => match destination.port().cmp(&source.port()) {
+                std::cmp::Ordering::Less => (CommandDirection::ClientToServer, server),
+                std::cmp::Ordering::Greater => (CommandDirection::ServerToClient, client),
+                std::cmp::Ordering::Equal => (CommandDirection::Both, server),
+            },
+        }
+    }
+}
+
+/// Returns the source, destination and payload of a UDP datagram
+fn udp_datagram(ip_packet: &[u8]) -> Option<(SocketAddr, SocketAddr, &[u8])> {
+    let (source_ip, destination_ip, udp): (IpAddr, IpAddr, &[u8]) = match ip_packet.first()? >> 4 {
+        4 => {
+            let header_length = ((ip_packet[0] & 0x0F) as usize) * 4;
+            let header = ip_packet.get(..20.max(header_length))?;
+            let more_fragments = header[6] & 0x20 != 0;
+            let fragment_offset = be_u16(header, 6) & 0x1FFF;
+            if header[9] != IP_PROTOCOL_UDP || more_fragments || fragment_offset != 0 {
+                return None;
+            }
+            let total_length = (be_u16(header, 2) as usize).min(ip_packet.len());
+            let source: [u8; 4] = header[12..16].try_into().ok()?;
+            let destination: [u8; 4] = header[16..20].try_into().ok()?;
+            (
+                Ipv4Addr::from(source).into(),
+                Ipv4Addr::from(destination).into(),
+                ip_packet.get(header.len()..total_length)?,
+            )
+        }
+        6 => {
+            let header = ip_packet.get(..40)?;
+            if header[6] != IP_PROTOCOL_UDP {
+                return None;
+            }
+            let total_length = (40 + be_u16(header, 4) as usize).min(ip_packet.len());
+            let source: [u8; 16] = header[8..24].try_into().ok()?;
+            let destination: [u8; 16] = header[24..40].try_into().ok()?;
+            (
+                Ipv6Addr::from(source).into(),
+                Ipv6Addr::from(destination).into(),
+                ip_packet.get(40..total_length)?,
+            )
+        }
+        _ => return None,
+    };
+    let header = udp.get(..8)?;
+    let length = (be_u16(header, 4) as usize).clamp(8, udp.len());
+    Some((
+        SocketAddr::new(source_ip, be_u16(header, 0)),
+        SocketAddr::new(destination_ip, be_u16(header, 2)),
+        &udp[8..length],
+    ))
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<PcapRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (timestamp, frame) = match self.read_packet()? {
+                Ok(packet) => packet,
+                Err(err) => return Some(Err(err)),
+            };
+            let Some((source, destination, payload)) =
+                self.ip_packet(&frame).and_then(udp_datagram)
+            else {
+                continue;
+            };
+            let (direction, command) = self.guess_direction(&source, &destination, payload);
+            return Some(Ok(PcapRecord {
+                timestamp,
+                source,
+                destination,
+                direction,
+                payload: payload.to_vec(),
+                command,
+            }));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! A pre-vote round ahead of an election, so a Client that was partitioned from the room does
+//! not advance the term of everyone when it rejoins.
+//!
+//! The Client sends a [`PreVoteRequestCommand`] for the term it would start an election for,
+//! and only advances to it if the [`PreVoteResponseCommand`] is granted. Neither command
+//! changes the term.
+
+use std::io::{Error, Result};
+
+use conclave_room::{Knowledge, Term};
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::term::check_known_term;
+use crate::{ProtocolVersion, PROTOCOL_VERSION_12};
+
+pub const PRE_VOTE_REQUEST_COMMAND_TYPE_ID: u8 = 0x27;
+pub const PRE_VOTE_RESPONSE_COMMAND_TYPE_ID: u8 = 0x28;
+
+fn write_next_term(next_term: Term, stream: &mut impl WriteOctetStream) -> Result<()> {
+    in_field(
+        check_known_term(next_term).map_err(Error::from),
+        "next_term",
+    )?;
+    stream.write_u16(next_term)
+}
+
+fn read_next_term(stream: &mut impl ReadOctetStream) -> Result<Term> {
+    let next_term = in_field(stream.read_u16(), "next_term")?;
+    in_field(
+        check_known_term(next_term).map_err(Error::from),
+        "next_term",
+    )?;
+    Ok(next_term)
+}
+
+/// Sent from Client to Server, asking if it would win an election for `next_term`
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PreVoteRequestCommand {
+    pub next_term: Term,
+    /// The knowledge of the Client, so a Client that is behind is not granted
+    pub knowledge: Knowledge,
+}
+
+impl PreVoteRequestCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "pre_vote_request";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_12;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 8
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        write_next_term(self.next_term, stream)?;
+        stream.write_u64(self.knowledge)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            next_term: read_next_term(stream)?,
+            knowledge: in_field(stream.read_u64(), "knowledge")?,
+        })
+    }
+}
+
+/// Sent from Server to Client, as the answer to a [`PreVoteRequestCommand`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PreVoteResponseCommand {
+    /// The `next_term` of the request that is answered
+    pub next_term: Term,
+    pub granted: bool,
+}
+
+impl PreVoteResponseCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "pre_vote_response";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_12;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 1
+    }
+
+    /// `true` if this answers `request`, and not an earlier one
+    pub fn answers(&self, request: &PreVoteRequestCommand) -> bool {
+        self.next_term == request.next_term
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        write_next_term(self.next_term, stream)?;
+        stream.write_u8(if self.granted { 0x01 } else { 0x00 })
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            next_term: read_next_term(stream)?,
+            granted: in_field(stream.read_u8(), "granted")? != 0,
+        })
+    }
+}
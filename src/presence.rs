@@ -0,0 +1,238 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Optional fields behind a presence mask: a u8 or u16 with one bit per field, followed by
+//! only the fields that are present, in bit order.
+//!
+//! A command serializes its optional fields with a [`PresenceWriter`], calling
+//! [`PresenceWriter::field`] once per field in increasing bit order, and decodes them with a
+//! [`PresenceReader`] making the same calls. Decoding fails on bits the command does not
+//! know, so a mask has a single meaning.
+
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum PresenceMaskWidth {
+    U8,
+    U16,
+}
+
+impl PresenceMaskWidth {
+    pub fn bits(self) -> u32 {
+        match self {
+            Self::U8 => u8::BITS,
+            Self::U16 => u16::BITS,
+        }
+    }
+
+    pub fn octet_size(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+        }
+    }
+}
+
+/// A value that can be an optional field
+pub trait PresenceField: Sized {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()>;
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self>;
+}
+
+impl PresenceField for u8 {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u8(*self)
+    }
+
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        stream.read_u8()
+    }
+}
+
+impl PresenceField for u16 {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u16(*self)
+    }
+
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        stream.read_u16()
+    }
+}
+
+impl PresenceField for u32 {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(*self)
+    }
+
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        stream.read_u32()
+    }
+}
+
+impl PresenceField for u64 {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u64(*self)
+    }
+
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        stream.read_u64()
+    }
+}
+
+impl PresenceField for i16 {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_i16(*self)
+    }
+
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        stream.read_i16()
+    }
+}
+
+/// One octet, which must be 0 or 1
+impl PresenceField for bool {
+    fn write_field(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u8(*self as u8)
+    }
+
+    fn read_field(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        match stream.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a bool", value),
+            )),
+        }
+    }
+}
+
+/// Checks that `bit` fits `width` and comes after the bits used so far
+fn check_bit(width: PresenceMaskWidth, next_bit: u32, bit: u32) -> Result<()> {
+    if bit >= width.bits() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "bit {} does not fit a {}-bit presence mask",
+                bit,
+                width.bits()
+            ),
+        ));
+    }
+    if bit < next_bit {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("bit {} must come after bit {}", bit, next_bit - 1),
+        ));
+    }
+    Ok(())
+}
+
+/// Collects the optional fields and writes the mask in front of them
+pub struct PresenceWriter {
+    width: PresenceMaskWidth,
+    mask: u16,
+    next_bit: u32,
+    fields: OutOctetStream,
+}
+
+impl PresenceWriter {
+    pub fn new(width: PresenceMaskWidth) -> Self {
+        Self {
+            width,
+            mask: 0,
+            next_bit: 0,
+            fields: OutOctetStream::new(),
+        }
+    }
+
+    /// Sets `bit` and writes the value if there is one
+    pub fn field<T: PresenceField>(&mut self, bit: u32, value: &Option<T>) -> Result<()> {
+        check_bit(self.width, self.next_bit, bit)?;
+        self.next_bit = bit + 1;
+        if let Some(value) = value {
+            self.mask |= 1 << bit;
+            value.write_field(&mut self.fields)?;
+        }
+        Ok(())
+    }
+
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    /// The mask and the present fields
+    pub fn octet_size(&self) -> usize {
+        self.width.octet_size() + self.fields.data.len()
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        match self.width {
+            PresenceMaskWidth::U8 => stream.write_u8(self.mask as u8)?,
+            PresenceMaskWidth::U16 => stream.write_u16(self.mask)?,
+        }
+        stream.write(&self.fields.data)
+    }
+}
+
+/// Reads the mask, and then the optional fields in the order they were written
+pub struct PresenceReader {
+    width: PresenceMaskWidth,
+    mask: u16,
+    next_bit: u32,
+}
+
+impl PresenceReader {
+    /// Fails if the mask has bits outside `known_mask`
+    pub fn from_cursor(
+        stream: &mut impl ReadOctetStream,
+        width: PresenceMaskWidth,
+        known_mask: u16,
+    ) -> Result<Self> {
+        let mask = match width {
+            PresenceMaskWidth::U8 => in_field(stream.read_u8(), "presence")? as u16,
+            PresenceMaskWidth::U16 => in_field(stream.read_u16(), "presence")?,
+        };
+        if mask & !known_mask != 0 {
+            return in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown presence bits 0x{:x}", mask & !known_mask),
+                )),
+                "presence",
+            );
+        }
+        Ok(Self {
+            width,
+            mask,
+            next_bit: 0,
+        })
+    }
+
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    pub fn is_present(&self, bit: u32) -> bool {
+        bit < self.width.bits() && self.mask & (1 << bit) != 0
+    }
+
+    /// Reads the value of `bit` if it is present
+    pub fn field<T: PresenceField>(
+        &mut self,
+        bit: u32,
+        stream: &mut impl ReadOctetStream,
+    ) -> Result<Option<T>> {
+        check_bit(self.width, self.next_bit, bit)?;
+        self.next_bit = bit + 1;
+        if !self.is_present(bit) {
+            return Ok(None);
+        }
+        T::read_field(stream).map(Some)
+    }
+}
@@ -0,0 +1,2141 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! An alternative encoding of the commands as protobuf messages, for backend services that only
+//! speak protobuf. The room protocol itself always uses the compact binary layout.
+//!
+//! [`proto_definition`] generates the `.proto` file from [`COMMAND_DESCRIPTORS`]. Each command
+//! is a message with the fields in serialization order, numbered from 1, and the
+//! `ServerReceiveCommand`/`ClientReceiveCommand` messages hold one of them, tagged with the
+//! command type id. The types in [`proto`] match that file.
+
+use std::fmt::Write;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use flood_rs::OutOctetStream;
+use prost::Message;
+
+use crate::descriptor::{FieldKind, StructDescriptor};
+use crate::error::in_field;
+use crate::room_info_page::check_page_index;
+use crate::{
+    BanClientCommand, BandwidthReportCommand, BroadcastPayloadCommand, BroadcastedPayloadCommand,
+    ChallengeCommand, ChannelProfile, ChannelProfileCommand, ChannelRoute, ClientIdentity,
+    ClientInfo, ClientInfos, ClientNetworkStats, ClientReceiveCommand, CommandDelivery,
+    ConnectToken, ConnectWithCookieCommand, ConnectWithTokenCommand, ConnectionIndex, Extensions,
+    HelloCommand, JoinAcceptedCommand, JoinDeniedCommand, JoinRequestCommand, KnowledgeAckCommand,
+    KnowledgeVector, KnownRevision, LatencyReportCommand, LogEntry, MaintenanceNoticeCommand,
+    MembershipSyncRequestCommand, MembershipSyncResponseCommand, MuteStateCommand, PingCommand,
+    PingResponseCommand, PreVoteRequestCommand, PreVoteResponseCommand, PresenceState,
+    PskAuthCommand, RedirectCommand, RelayToClientCommand, RelayedFromClientCommand,
+    ReplicateAckCommand, ReplicateCommand, ResyncDataCommand, ResyncRequestCommand,
+    RetryCookieCommand, RoomClosedCommand, RoomInfoCommand, RoomInfoPageCommand, ServerLoadCommand,
+    ServerReceiveCommand, SetPresenceCommand, SliceReader, SnapshotChunkCommand,
+    SnapshotRequestCommand, TermAdvancedCommand, ThrottledCommand, TimeoutConfigCommand,
+    TransferChunkCommand, TransferResendRequestCommand, UnbanClientCommand,
+    UpdateRoomSettingsCommand, UserId, COMMAND_DESCRIPTORS, LATENCY_BUCKET_COUNT, MAX_CLIENT_INFOS,
+    MAX_EXTENSIONS_OCTETS, MUTE_STATE_MUTED_FLAG, PROTOCOL_FINGERPRINT,
+};
+
+/// The protobuf package of the generated definition
+pub const PROTO_PACKAGE: &str = "conclave.room";
+
+fn message_name(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn scalar_type(kind: &FieldKind, structs: &mut Vec<&'static StructDescriptor>) -> String {
+    match kind {
+        FieldKind::U8 | FieldKind::U16 | FieldKind::U32 => "uint32".to_string(),
+        FieldKind::U64 | FieldKind::Varint => "uint64".to_string(),
+        FieldKind::I16 => "sint32".to_string(),
+        FieldKind::Bool => "bool".to_string(),
+        FieldKind::FixedOctets(_) | FieldKind::Octets { .. } => "bytes".to_string(),
+        FieldKind::Text { .. } => "string".to_string(),
+        FieldKind::Struct(descriptor) => {
+            if !structs.iter().any(|other| other.name == descriptor.name) {
+                structs.push(descriptor);
+            }
+            message_name(descriptor.name)
+        }
+        FieldKind::ClientIdentity => "ClientIdentity".to_string(),
+        FieldKind::UserId => "Uint128".to_string(),
+        FieldKind::SocketAddr => "SocketAddr".to_string(),
+        FieldKind::Flagged { kind, .. } => scalar_type(kind, structs),
+        FieldKind::Magic(_) | FieldKind::Array { .. } | FieldKind::List { .. } => {
+            unreachable!("{:?} is not a scalar", kind)
+        }
+    }
+}
+
+fn message(
+    out: &mut String,
+    name: &str,
+    fields: &[crate::FieldDescriptor],
+    structs: &mut Vec<&'static StructDescriptor>,
+) {
+    let _ = writeln!(out, "\nmessage {} {{", message_name(name));
+    let fields = fields
+        .iter()
+        .filter(|field| !matches!(field.kind, FieldKind::Magic(_)));
+    for (index, field) in fields.enumerate() {
+        let type_name = match &field.kind {
+            FieldKind::Array { element, .. } | FieldKind::List { element, .. } => {
+                format!("repeated {}", scalar_type(element, structs))
+            }
+            kind => scalar_type(kind, structs),
+        };
+        let _ = writeln!(out, "  {} {} = {};", type_name, field.name, index + 1);
+    }
+    out.push_str("}\n");
+}
+
+/// The `.proto` (proto3) definition of every command
+pub fn proto_definition() -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "// Generated by conclave-room-serialize, layout fingerprint {:016x}",
+        PROTOCOL_FINGERPRINT
+    );
+    let _ = writeln!(out, "syntax = \"proto3\";\n\npackage {};", PROTO_PACKAGE);
+
+    let mut structs = Vec::new();
+    for descriptor in COMMAND_DESCRIPTORS {
+        message(&mut out, descriptor.name, descriptor.fields, &mut structs);
+    }
+    let mut index = 0;
+    while index < structs.len() {
+        let descriptor = structs[index];
+        if !COMMAND_DESCRIPTORS
+            .iter()
+            .any(|command| command.name == descriptor.name)
+        {
+            message(&mut out, descriptor.name, descriptor.fields, &mut structs);
+        }
+        index += 1;
+    }
+    out.push_str(concat!(
+        "\nmessage Uint128 {\n",
+        "  uint64 high = 1;\n",
+        "  uint64 low = 2;\n",
+        "}\n",
+        "\nmessage ClientIdentity {\n",
+        "  oneof value {\n",
+        "    uint64 u64 = 1;\n",
+        "    Uint128 uuid = 2;\n",
+        "    string string = 3;\n",
+        "  }\n",
+        "}\n",
+        "\nmessage SocketAddr {\n",
+        "  // 4 octets for IPv4, 16 for IPv6\n",
+        "  bytes ip = 1;\n",
+        "  uint32 port = 2;\n",
+        "}\n",
+    ));
+
+    for (envelope, server_receive) in [
+        ("ServerReceiveCommand", true),
+        ("ClientReceiveCommand", false),
+    ] {
+        let _ = writeln!(out, "\nmessage {} {{\n  oneof command {{", envelope);
+        for descriptor in COMMAND_DESCRIPTORS.iter().filter(|descriptor| {
+            if server_receive {
+                descriptor.direction.is_server_receive()
+            } else {
+                descriptor.direction.is_client_receive()
+            }
+        }) {
+            let _ = writeln!(
+                out,
+                "    {} {} = {};",
+                message_name(descriptor.name),
+                descriptor.name,
+                descriptor.type_id
+            );
+        }
+        out.push_str("  }\n}\n");
+    }
+    out
+}
+
+/// The messages of [`proto_definition`]
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Ping {
+        #[prost(uint32, tag = "1")]
+        pub term: u32,
+        #[prost(uint64, tag = "2")]
+        pub knowledge: u64,
+        #[prost(bool, tag = "3")]
+        pub has_connection_to_leader: bool,
+        #[prost(uint32, tag = "4")]
+        pub connection_index: u32,
+        #[prost(sint32, tag = "5")]
+        pub clock_drift_ppm: i32,
+        #[prost(message, optional, tag = "7")]
+        pub knowledge_vector: Option<KnowledgeVector>,
+    }
+
+    /// The map is encoded like the repeated `KnowledgeVectorEntry` of the definition
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct KnowledgeVector {
+        #[prost(btree_map = "uint32, uint64", tag = "1")]
+        pub entries: std::collections::BTreeMap<u32, u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RoomInfo {
+        #[prost(uint32, tag = "1")]
+        pub term: u32,
+        #[prost(message, repeated, tag = "2")]
+        pub client_infos: Vec<ClientInfo>,
+        #[prost(uint32, tag = "3")]
+        pub leader_index: u32,
+        #[prost(message, optional, tag = "5")]
+        pub knowledge_vector: Option<KnowledgeVector>,
+        #[prost(uint32, tag = "6")]
+        pub quorum_size: u32,
+        #[prost(uint32, tag = "7")]
+        pub votes_for_leader: u32,
+        #[prost(uint32, tag = "8")]
+        pub leader_lease_expires_in_ms: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RoomClosed {
+        #[prost(uint32, tag = "1")]
+        pub reason_code: u32,
+        #[prost(uint32, tag = "2")]
+        pub closes_at_ms: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MuteState {
+        #[prost(uint32, tag = "1")]
+        pub connection_index: u32,
+        #[prost(uint32, tag = "2")]
+        pub flags: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PingResponse {
+        #[prost(uint32, tag = "1")]
+        pub term: u32,
+        #[prost(uint64, tag = "2")]
+        pub server_knowledge: u64,
+        #[prost(uint64, tag = "3")]
+        pub your_knowledge_ack: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RelayToClient {
+        #[prost(uint32, tag = "1")]
+        pub target_connection_index: u32,
+        #[prost(bytes = "vec", tag = "2")]
+        pub payload: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RelayedFromClient {
+        #[prost(uint32, tag = "1")]
+        pub source_connection_index: u32,
+        #[prost(bytes = "vec", tag = "2")]
+        pub payload: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BroadcastPayload {
+        #[prost(bytes = "vec", tag = "1")]
+        pub payload: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BroadcastedPayload {
+        #[prost(uint32, tag = "1")]
+        pub source_connection_index: u32,
+        #[prost(bytes = "vec", tag = "2")]
+        pub payload: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct JoinRequest {
+        #[prost(message, optional, tag = "1")]
+        pub identity: Option<ClientIdentity>,
+        #[prost(uint32, tag = "2")]
+        pub requested_role_flags: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct JoinAccepted {
+        #[prost(uint32, tag = "1")]
+        pub connection_index: u32,
+        #[prost(message, optional, tag = "2")]
+        pub room_info: Option<RoomInfo>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct JoinDenied {
+        #[prost(uint32, tag = "1")]
+        pub reason_code: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ResyncRequest {
+        #[prost(uint32, tag = "1")]
+        pub from_term: u32,
+        #[prost(uint64, tag = "2")]
+        pub from_knowledge: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ResyncData {
+        #[prost(uint32, tag = "1")]
+        pub term: u32,
+        #[prost(uint64, tag = "2")]
+        pub knowledge: u64,
+        #[prost(uint32, tag = "3")]
+        pub chunk_index: u32,
+        #[prost(uint32, tag = "4")]
+        pub chunk_count: u32,
+        #[prost(bytes = "vec", tag = "5")]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SnapshotRequest {
+        #[prost(uint32, tag = "1")]
+        pub snapshot_id: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SnapshotChunk {
+        #[prost(uint32, tag = "1")]
+        pub snapshot_id: u32,
+        #[prost(uint32, tag = "2")]
+        pub chunk_index: u32,
+        #[prost(uint32, tag = "3")]
+        pub chunk_count: u32,
+        #[prost(bytes = "vec", tag = "4")]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TransferChunk {
+        #[prost(uint32, tag = "1")]
+        pub transfer_id: u32,
+        #[prost(uint32, tag = "2")]
+        pub chunk_index: u32,
+        #[prost(uint32, tag = "3")]
+        pub chunk_count: u32,
+        #[prost(bytes = "vec", tag = "4")]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TransferResendRequest {
+        #[prost(uint32, tag = "1")]
+        pub transfer_id: u32,
+        #[prost(uint32, repeated, tag = "2")]
+        pub missing_chunk_indices: Vec<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BandwidthReport {
+        #[prost(uint64, tag = "1")]
+        pub bytes_sent: u64,
+        #[prost(uint64, tag = "2")]
+        pub bytes_received: u64,
+        #[prost(uint64, tag = "3")]
+        pub window_ms: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LatencyReport {
+        #[prost(uint32, repeated, tag = "1")]
+        pub buckets: Vec<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MaintenanceNotice {
+        #[prost(uint32, tag = "1")]
+        pub starts_in_s: u32,
+        #[prost(uint32, tag = "2")]
+        pub expected_duration_s: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Redirect {
+        #[prost(string, tag = "1")]
+        pub host: String,
+        #[prost(uint32, tag = "2")]
+        pub port: u32,
+        #[prost(bytes = "vec", tag = "3")]
+        pub token: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ServerLoad {
+        #[prost(uint32, tag = "1")]
+        pub cpu_permille: u32,
+        #[prost(uint32, tag = "2")]
+        pub rooms: u32,
+        #[prost(uint32, tag = "3")]
+        pub headroom: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PskAuth {
+        #[prost(uint32, tag = "1")]
+        pub key_id: u32,
+        #[prost(bytes = "vec", tag = "2")]
+        pub proof: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Challenge {
+        #[prost(bytes = "vec", tag = "1")]
+        pub challenge: Vec<u8>,
+        #[prost(message, optional, tag = "3")]
+        pub server_load: Option<ServerLoad>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ConnectWithToken {
+        #[prost(message, optional, tag = "1")]
+        pub token: Option<ConnectToken>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RetryCookie {
+        #[prost(bytes = "vec", tag = "1")]
+        pub cookie: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ConnectWithCookie {
+        #[prost(bytes = "vec", tag = "1")]
+        pub cookie: Vec<u8>,
+        #[prost(message, optional, tag = "2")]
+        pub join_request: Option<JoinRequest>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Throttled {
+        #[prost(uint32, tag = "1")]
+        pub retry_after_ms: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TimeoutConfig {
+        #[prost(uint32, tag = "1")]
+        pub disconnect_after_ms: u32,
+        #[prost(uint32, tag = "2")]
+        pub ping_interval_ms: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct UpdateRoomSettings {
+        #[prost(uint32, tag = "1")]
+        pub settings_version: u32,
+        /// The entries of the extension area, without its octet count
+        #[prost(bytes = "vec", tag = "2")]
+        pub settings: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ChannelRoute {
+        #[prost(uint32, tag = "1")]
+        pub channel_id: u32,
+        /// 0 for reliable ordered, 1 for unreliable
+        #[prost(uint32, tag = "2")]
+        pub delivery: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ChannelProfileRoute {
+        #[prost(uint32, tag = "1")]
+        pub command_type_id: u32,
+        #[prost(uint32, tag = "2")]
+        pub channel_id: u32,
+        #[prost(uint32, tag = "3")]
+        pub delivery: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ChannelProfile {
+        #[prost(message, optional, tag = "1")]
+        pub fallback: Option<ChannelRoute>,
+        #[prost(message, repeated, tag = "2")]
+        pub routes: Vec<ChannelProfileRoute>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RoomInfoPage {
+        #[prost(uint32, tag = "1")]
+        pub term: u32,
+        #[prost(uint32, tag = "2")]
+        pub page_index: u32,
+        #[prost(uint32, tag = "3")]
+        pub page_count: u32,
+        #[prost(message, repeated, tag = "4")]
+        pub client_infos: Vec<ClientInfo>,
+        #[prost(uint32, tag = "5")]
+        pub leader_index: u32,
+        #[prost(message, optional, tag = "7")]
+        pub knowledge_vector: Option<KnowledgeVector>,
+        #[prost(uint32, tag = "8")]
+        pub quorum_size: u32,
+        #[prost(uint32, tag = "9")]
+        pub votes_for_leader: u32,
+        #[prost(uint32, tag = "10")]
+        pub leader_lease_expires_in_ms: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct KnownRevision {
+        #[prost(uint32, tag = "1")]
+        pub connection_index: u32,
+        #[prost(uint32, tag = "2")]
+        pub revision: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MembershipSyncRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub known_revisions: Vec<KnownRevision>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MembershipSyncResponse {
+        #[prost(uint32, tag = "1")]
+        pub term: u32,
+        #[prost(uint32, tag = "2")]
+        pub leader_connection_index: u32,
+        #[prost(message, repeated, tag = "3")]
+        pub changed: Vec<ClientInfo>,
+        #[prost(uint32, repeated, tag = "4")]
+        pub removed: Vec<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SetPresence {
+        #[prost(uint32, tag = "1")]
+        pub presence: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct KnowledgeAck {
+        #[prost(uint64, tag = "1")]
+        pub acked_knowledge: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TermAdvanced {
+        #[prost(uint32, tag = "1")]
+        pub new_term: u32,
+        #[prost(uint32, tag = "2")]
+        pub cause: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PreVoteRequest {
+        #[prost(uint32, tag = "1")]
+        pub next_term: u32,
+        #[prost(uint64, tag = "2")]
+        pub knowledge: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PreVoteResponse {
+        #[prost(uint32, tag = "1")]
+        pub next_term: u32,
+        #[prost(bool, tag = "2")]
+        pub granted: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Replicate {
+        #[prost(uint32, tag = "1")]
+        pub term: u32,
+        #[prost(uint64, tag = "2")]
+        pub prev_knowledge: u64,
+        #[prost(message, repeated, tag = "3")]
+        pub entries: Vec<LogEntry>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ReplicateAck {
+        #[prost(uint32, tag = "1")]
+        pub term: u32,
+        #[prost(uint64, tag = "2")]
+        pub knowledge: u64,
+        #[prost(bool, tag = "3")]
+        pub success: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LogEntry {
+        #[prost(bytes = "vec", tag = "1")]
+        pub payload: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BanClient {
+        #[prost(message, optional, tag = "1")]
+        pub custom_user_id: Option<Uint128>,
+        #[prost(uint32, tag = "2")]
+        pub duration_s: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct UnbanClient {
+        #[prost(message, optional, tag = "1")]
+        pub custom_user_id: Option<Uint128>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Hello {
+        #[prost(uint32, tag = "1")]
+        pub version: u32,
+        #[prost(uint64, tag = "2")]
+        pub fingerprint: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ClientInfo {
+        #[prost(uint32, tag = "1")]
+        pub connection_index: u32,
+        #[prost(message, optional, tag = "2")]
+        pub identity: Option<ClientIdentity>,
+        #[prost(uint32, tag = "3")]
+        pub role_flags: u32,
+        #[prost(uint32, tag = "4")]
+        pub revision: u32,
+        #[prost(uint32, tag = "5")]
+        pub presence: u32,
+        #[prost(message, optional, tag = "6")]
+        pub network_stats: Option<ClientNetworkStats>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ClientNetworkStats {
+        #[prost(uint32, tag = "1")]
+        pub rtt_ms: u32,
+        #[prost(uint32, tag = "2")]
+        pub loss_percent: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ConnectToken {
+        #[prost(uint64, tag = "1")]
+        pub expires_at_s: u64,
+        #[prost(uint64, tag = "2")]
+        pub client_id: u64,
+        #[prost(message, repeated, tag = "3")]
+        pub server_addresses: Vec<SocketAddr>,
+        #[prost(bytes = "vec", tag = "4")]
+        pub private_data: Vec<u8>,
+    }
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct Uint128 {
+        #[prost(uint64, tag = "1")]
+        pub high: u64,
+        #[prost(uint64, tag = "2")]
+        pub low: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ClientIdentity {
+        #[prost(oneof = "client_identity::Value", tags = "1, 2, 3")]
+        pub value: Option<client_identity::Value>,
+    }
+
+    pub mod client_identity {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Value {
+            #[prost(uint64, tag = "1")]
+            U64(u64),
+            #[prost(message, tag = "2")]
+            Uuid(super::Uint128),
+            #[prost(string, tag = "3")]
+            String(String),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SocketAddr {
+        #[prost(bytes = "vec", tag = "1")]
+        pub ip: Vec<u8>,
+        #[prost(uint32, tag = "2")]
+        pub port: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ServerReceiveCommand {
+        #[prost(
+            oneof = "server_receive_command::Command",
+            tags = "1, 4, 6, 8, 10, 13, 15, 17, 18, 19, 20, 24, 26, 28, 30, 31, 34, 36, 39, 41, 42, 64, 65, 199"
+        )]
+        pub command: Option<server_receive_command::Command>,
+    }
+
+    pub mod server_receive_command {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Command {
+            #[prost(message, tag = "1")]
+            Ping(super::Ping),
+            #[prost(message, tag = "4")]
+            MuteState(super::MuteState),
+            #[prost(message, tag = "6")]
+            RelayToClient(super::RelayToClient),
+            #[prost(message, tag = "8")]
+            BroadcastPayload(super::BroadcastPayload),
+            #[prost(message, tag = "10")]
+            JoinRequest(super::JoinRequest),
+            #[prost(message, tag = "13")]
+            ResyncRequest(super::ResyncRequest),
+            #[prost(message, tag = "15")]
+            SnapshotRequest(super::SnapshotRequest),
+            #[prost(message, tag = "17")]
+            TransferChunk(super::TransferChunk),
+            #[prost(message, tag = "18")]
+            TransferResendRequest(super::TransferResendRequest),
+            #[prost(message, tag = "19")]
+            BandwidthReport(super::BandwidthReport),
+            #[prost(message, tag = "20")]
+            LatencyReport(super::LatencyReport),
+            #[prost(message, tag = "24")]
+            PskAuth(super::PskAuth),
+            #[prost(message, tag = "26")]
+            ConnectWithToken(super::ConnectWithToken),
+            #[prost(message, tag = "28")]
+            ConnectWithCookie(super::ConnectWithCookie),
+            #[prost(message, tag = "30")]
+            TimeoutConfig(super::TimeoutConfig),
+            #[prost(message, tag = "31")]
+            UpdateRoomSettings(super::UpdateRoomSettings),
+            #[prost(message, tag = "34")]
+            MembershipSyncRequest(super::MembershipSyncRequest),
+            #[prost(message, tag = "36")]
+            SetPresence(super::SetPresence),
+            #[prost(message, tag = "39")]
+            PreVoteRequest(super::PreVoteRequest),
+            #[prost(message, tag = "41")]
+            Replicate(super::Replicate),
+            #[prost(message, tag = "42")]
+            ReplicateAck(super::ReplicateAck),
+            #[prost(message, tag = "64")]
+            BanClient(super::BanClient),
+            #[prost(message, tag = "65")]
+            UnbanClient(super::UnbanClient),
+            #[prost(message, tag = "199")]
+            Hello(super::Hello),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ClientReceiveCommand {
+        #[prost(
+            oneof = "client_receive_command::Command",
+            tags = "2, 3, 4, 5, 7, 9, 11, 12, 14, 16, 17, 18, 21, 22, 23, 25, 27, 29, 30, 31, 32, 33, 35, 37, 38, 40, 41, 42, 199"
+        )]
+        pub command: Option<client_receive_command::Command>,
+    }
+
+    pub mod client_receive_command {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Command {
+            #[prost(message, tag = "2")]
+            RoomInfo(super::RoomInfo),
+            #[prost(message, tag = "3")]
+            RoomClosed(super::RoomClosed),
+            #[prost(message, tag = "4")]
+            MuteState(super::MuteState),
+            #[prost(message, tag = "5")]
+            PingResponse(super::PingResponse),
+            #[prost(message, tag = "7")]
+            RelayedFromClient(super::RelayedFromClient),
+            #[prost(message, tag = "9")]
+            BroadcastedPayload(super::BroadcastedPayload),
+            #[prost(message, tag = "11")]
+            JoinAccepted(super::JoinAccepted),
+            #[prost(message, tag = "12")]
+            JoinDenied(super::JoinDenied),
+            #[prost(message, tag = "14")]
+            ResyncData(super::ResyncData),
+            #[prost(message, tag = "16")]
+            SnapshotChunk(super::SnapshotChunk),
+            #[prost(message, tag = "17")]
+            TransferChunk(super::TransferChunk),
+            #[prost(message, tag = "18")]
+            TransferResendRequest(super::TransferResendRequest),
+            #[prost(message, tag = "21")]
+            MaintenanceNotice(super::MaintenanceNotice),
+            #[prost(message, tag = "22")]
+            Redirect(super::Redirect),
+            #[prost(message, tag = "23")]
+            ServerLoad(super::ServerLoad),
+            #[prost(message, tag = "25")]
+            Challenge(super::Challenge),
+            #[prost(message, tag = "27")]
+            RetryCookie(super::RetryCookie),
+            #[prost(message, tag = "29")]
+            Throttled(super::Throttled),
+            #[prost(message, tag = "30")]
+            TimeoutConfig(super::TimeoutConfig),
+            #[prost(message, tag = "31")]
+            UpdateRoomSettings(super::UpdateRoomSettings),
+            #[prost(message, tag = "32")]
+            ChannelProfile(super::ChannelProfile),
+            #[prost(message, tag = "33")]
+            RoomInfoPage(super::RoomInfoPage),
+            #[prost(message, tag = "35")]
+            MembershipSyncResponse(super::MembershipSyncResponse),
+            #[prost(message, tag = "37")]
+            KnowledgeAck(super::KnowledgeAck),
+            #[prost(message, tag = "38")]
+            TermAdvanced(super::TermAdvanced),
+            #[prost(message, tag = "40")]
+            PreVoteResponse(super::PreVoteResponse),
+            #[prost(message, tag = "41")]
+            Replicate(super::Replicate),
+            #[prost(message, tag = "42")]
+            ReplicateAck(super::ReplicateAck),
+            #[prost(message, tag = "199")]
+            Hello(super::Hello),
+        }
+    }
+}
+
+fn out_of_range(value: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{} is out of range", value))
+}
+
+fn narrow<T: TryFrom<u64>>(value: impl Into<u64> + Copy, field: &str) -> Result<T> {
+    in_field(
+        T::try_from(value.into()).map_err(|_| out_of_range(value.into())),
+        field,
+    )
+}
+
+fn connection_index_from(value: u32, field: &str) -> Result<ConnectionIndex> {
+    narrow(value, field).map(ConnectionIndex)
+}
+
+fn presence_from(value: u32) -> Result<PresenceState> {
+    narrow(value, "presence")
+        .and_then(|value: u8| in_field(PresenceState::try_from(value), "presence"))
+}
+
+fn network_stats_from(network_stats: proto::ClientNetworkStats) -> Result<ClientNetworkStats> {
+    in_field(
+        narrow(network_stats.rtt_ms, "rtt_ms").and_then(|rtt_ms| {
+            Ok(ClientNetworkStats {
+                rtt_ms,
+                loss_percent: narrow(network_stats.loss_percent, "loss_percent")?,
+            })
+        }),
+        "network_stats",
+    )
+}
+
+fn required<T>(value: Option<T>, field: &str) -> Result<T> {
+    in_field(
+        value.ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing message")),
+        field,
+    )
+}
+
+fn fixed_octets<const N: usize>(octets: Vec<u8>, field: &str) -> Result<[u8; N]> {
+    in_field(
+        octets.try_into().map_err(|octets: Vec<u8>| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("expected {} octets, got {}", N, octets.len()),
+            )
+        }),
+        field,
+    )
+}
+
+impl From<u128> for proto::Uint128 {
+    fn from(value: u128) -> Self {
+        Self {
+            high: (value >> 64) as u64,
+            low: value as u64,
+        }
+    }
+}
+
+impl From<proto::Uint128> for u128 {
+    fn from(value: proto::Uint128) -> Self {
+        ((value.high as u128) << 64) | value.low as u128
+    }
+}
+
+impl From<&ClientIdentity> for proto::ClientIdentity {
+    fn from(identity: &ClientIdentity) -> Self {
+        use proto::client_identity::Value;
+        Self {
+            value: Some(match identity {
+                ClientIdentity::U64(id) => Value::U64(*id),
+                ClientIdentity::Uuid(id) => Value::Uuid((*id).into()),
+                ClientIdentity::String(id) => Value::String(id.clone()),
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::ClientIdentity> for ClientIdentity {
+    type Error = Error;
+
+    fn try_from(identity: proto::ClientIdentity) -> Result<Self> {
+        use proto::client_identity::Value;
+        Ok(match required(identity.value, "value")? {
+            Value::U64(id) => Self::U64(id),
+            Value::Uuid(id) => Self::Uuid(id.into()),
+            Value::String(id) => Self::String(id),
+        })
+    }
+}
+
+fn client_identity_from(identity: Option<proto::ClientIdentity>) -> Result<ClientIdentity> {
+    in_field(
+        required(identity, "identity").and_then(ClientIdentity::try_from),
+        "identity",
+    )
+}
+
+impl From<&SocketAddr> for proto::SocketAddr {
+    fn from(address: &SocketAddr) -> Self {
+        Self {
+            ip: match address.ip() {
+                IpAddr::V4(ip) => ip.octets().to_vec(),
+                IpAddr::V6(ip) => ip.octets().to_vec(),
+            },
+            port: address.port() as u32,
+        }
+    }
+}
+
+impl TryFrom<proto::SocketAddr> for SocketAddr {
+    type Error = Error;
+
+    fn try_from(address: proto::SocketAddr) -> Result<Self> {
+        let ip: IpAddr = match address.ip.len() {
+            4 => Ipv4Addr::from(fixed_octets::<4>(address.ip, "ip")?).into(),
+            16 => Ipv6Addr::from(fixed_octets::<16>(address.ip, "ip")?).into(),
+            count => {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("expected 4 or 16 octets, got {}", count),
+                    )),
+                    "ip",
+                )
+            }
+        };
+        Ok(SocketAddr::new(ip, narrow(address.port, "port")?))
+    }
+}
+
+impl From<&ClientInfo> for proto::ClientInfo {
+    fn from(client_info: &ClientInfo) -> Self {
+        Self {
+            connection_index: client_info.connection_index.0 as u32,
+            identity: Some((&client_info.identity).into()),
+            role_flags: client_info.role_flags as u32,
+            revision: client_info.revision,
+            presence: client_info.presence as u32,
+            network_stats: client_info.network_stats.map(|network_stats| {
+                proto::ClientNetworkStats {
+                    rtt_ms: network_stats.rtt_ms as u32,
+                    loss_percent: network_stats.loss_percent as u32,
+                }
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::ClientInfo> for ClientInfo {
+    type Error = Error;
+
+    fn try_from(client_info: proto::ClientInfo) -> Result<Self> {
+        Ok(Self {
+            identity: client_identity_from(client_info.identity)?,
+            connection_index: connection_index_from(
+                client_info.connection_index,
+                "connection_index",
+            )?,
+            role_flags: narrow(client_info.role_flags, "role_flags")?,
+            revision: client_info.revision,
+            presence: presence_from(client_info.presence)?,
+            network_stats: client_info
+                .network_stats
+                .map(network_stats_from)
+                .transpose()?,
+        })
+    }
+}
+
+impl From<&KnowledgeVector> for proto::KnowledgeVector {
+    fn from(knowledge_vector: &KnowledgeVector) -> Self {
+        Self {
+            entries: knowledge_vector
+                .iter()
+                .map(|(connection_index, knowledge)| (connection_index.0 as u32, knowledge))
+                .collect(),
+        }
+    }
+}
+
+fn knowledge_vector_from(message: proto::KnowledgeVector) -> Result<KnowledgeVector> {
+    if message.entries.len() > u8::MAX as usize {
+        return in_field(
+            Err(out_of_range(message.entries.len())),
+            "knowledge_vector.entries.len",
+        );
+    }
+    message
+        .entries
+        .into_iter()
+        .map(|(connection_index, knowledge)| {
+            Ok((
+                connection_index_from(connection_index, "knowledge_vector.entries")?,
+                knowledge,
+            ))
+        })
+        .collect()
+}
+
+impl From<&RoomInfoCommand> for proto::RoomInfo {
+    fn from(command: &RoomInfoCommand) -> Self {
+        Self {
+            term: command.term as u32,
+            client_infos: command.client_infos.iter().map(Into::into).collect(),
+            leader_index: command.leader_index.0 as u32,
+            knowledge_vector: command.knowledge_vector.as_ref().map(Into::into),
+            quorum_size: command.quorum_size as u32,
+            votes_for_leader: command.votes_for_leader as u32,
+            leader_lease_expires_in_ms: command.leader_lease_expires_in_ms,
+        }
+    }
+}
+
+impl TryFrom<proto::RoomInfo> for RoomInfoCommand {
+    type Error = Error;
+
+    /// The room info must pass [`RoomInfoCommand::validate`]
+    fn try_from(message: proto::RoomInfo) -> Result<Self> {
+        if message.client_infos.len() > MAX_CLIENT_INFOS {
+            return in_field(
+                Err(out_of_range(message.client_infos.len())),
+                "client_infos.len",
+            );
+        }
+        let client_infos = message
+            .client_infos
+            .into_iter()
+            .enumerate()
+            .map(|(index, client_info)| {
+                in_field(
+                    ClientInfo::try_from(client_info),
+                    format_args!("client_infos[{}]", index),
+                )
+            })
+            .collect::<Result<ClientInfos>>()?;
+        let command = Self {
+            term: narrow(message.term, "term")?,
+            leader_index: connection_index_from(message.leader_index, "leader_index")?,
+            client_infos,
+            knowledge_vector: message
+                .knowledge_vector
+                .map(knowledge_vector_from)
+                .transpose()?,
+            quorum_size: narrow(message.quorum_size, "quorum_size")?,
+            votes_for_leader: narrow(message.votes_for_leader, "votes_for_leader")?,
+            leader_lease_expires_in_ms: message.leader_lease_expires_in_ms,
+        };
+        command.validate()?;
+        Ok(command)
+    }
+}
+
+impl From<&JoinRequestCommand> for proto::JoinRequest {
+    fn from(command: &JoinRequestCommand) -> Self {
+        Self {
+            identity: Some((&command.identity).into()),
+            requested_role_flags: command.requested_role_flags as u32,
+        }
+    }
+}
+
+impl TryFrom<proto::JoinRequest> for JoinRequestCommand {
+    type Error = Error;
+
+    fn try_from(message: proto::JoinRequest) -> Result<Self> {
+        Ok(Self {
+            identity: client_identity_from(message.identity)?,
+            requested_role_flags: narrow(message.requested_role_flags, "requested_role_flags")?,
+        })
+    }
+}
+
+impl From<&ConnectToken> for proto::ConnectToken {
+    fn from(token: &ConnectToken) -> Self {
+        Self {
+            expires_at_s: token.expires_at_s,
+            client_id: token.client_id,
+            server_addresses: token.server_addresses.iter().map(Into::into).collect(),
+            private_data: token.private_data.clone(),
+        }
+    }
+}
+
+impl TryFrom<proto::ConnectToken> for ConnectToken {
+    type Error = Error;
+
+    fn try_from(message: proto::ConnectToken) -> Result<Self> {
+        Ok(Self {
+            expires_at_s: message.expires_at_s,
+            client_id: message.client_id,
+            server_addresses: message
+                .server_addresses
+                .into_iter()
+                .enumerate()
+                .map(|(index, address)| {
+                    in_field(
+                        SocketAddr::try_from(address),
+                        format_args!("server_addresses[{}]", index),
+                    )
+                })
+                .collect::<Result<_>>()?,
+            private_data: message.private_data,
+        })
+    }
+}
+
+fn ping_to_proto(command: &PingCommand) -> proto::Ping {
+    proto::Ping {
+        term: command.term as u32,
+        knowledge: command.knowledge,
+        has_connection_to_leader: command.has_connection_to_leader,
+        connection_index: command.connection_index.0 as u32,
+        clock_drift_ppm: command.clock_drift_ppm as i32,
+        knowledge_vector: command.knowledge_vector.as_ref().map(Into::into),
+    }
+}
+
+fn ping_from_proto(message: proto::Ping) -> Result<PingCommand> {
+    Ok(PingCommand {
+        term: narrow(message.term, "term")?,
+        knowledge: message.knowledge,
+        has_connection_to_leader: message.has_connection_to_leader,
+        connection_index: connection_index_from(message.connection_index, "connection_index")?,
+        clock_drift_ppm: in_field(
+            i16::try_from(message.clock_drift_ppm)
+                .map_err(|_| out_of_range(message.clock_drift_ppm)),
+            "clock_drift_ppm",
+        )?,
+        knowledge_vector: message
+            .knowledge_vector
+            .map(knowledge_vector_from)
+            .transpose()?,
+    })
+}
+
+fn mute_state_to_proto(command: &MuteStateCommand) -> proto::MuteState {
+    proto::MuteState {
+        connection_index: command.connection_index.0 as u32,
+        flags: if command.muted {
+            MUTE_STATE_MUTED_FLAG as u32
+        } else {
+            0
+        },
+    }
+}
+
+fn mute_state_from_proto(message: proto::MuteState) -> Result<MuteStateCommand> {
+    let flags: u8 = narrow(message.flags, "flags")?;
+    Ok(MuteStateCommand {
+        connection_index: connection_index_from(message.connection_index, "connection_index")?,
+        muted: flags & MUTE_STATE_MUTED_FLAG != 0,
+    })
+}
+
+fn transfer_chunk_to_proto(command: &TransferChunkCommand) -> proto::TransferChunk {
+    proto::TransferChunk {
+        transfer_id: command.transfer_id,
+        chunk_index: command.chunk_index as u32,
+        chunk_count: command.chunk_count as u32,
+        data: command.data.clone(),
+    }
+}
+
+fn transfer_chunk_from_proto(message: proto::TransferChunk) -> Result<TransferChunkCommand> {
+    Ok(TransferChunkCommand {
+        transfer_id: message.transfer_id,
+        chunk_index: narrow(message.chunk_index, "chunk_index")?,
+        chunk_count: narrow(message.chunk_count, "chunk_count")?,
+        data: message.data,
+    })
+}
+
+fn timeout_config_to_proto(command: &TimeoutConfigCommand) -> proto::TimeoutConfig {
+    proto::TimeoutConfig {
+        disconnect_after_ms: command.disconnect_after_ms,
+        ping_interval_ms: command.ping_interval_ms as u32,
+    }
+}
+
+fn timeout_config_from_proto(message: proto::TimeoutConfig) -> Result<TimeoutConfigCommand> {
+    Ok(TimeoutConfigCommand {
+        disconnect_after_ms: message.disconnect_after_ms,
+        ping_interval_ms: narrow(message.ping_interval_ms, "ping_interval_ms")?,
+    })
+}
+
+fn update_room_settings_to_proto(
+    command: &UpdateRoomSettingsCommand,
+) -> Result<proto::UpdateRoomSettings> {
+    let mut out_stream = OutOctetStream::new();
+    in_field(command.settings.to_octets(&mut out_stream), "settings")?;
+    Ok(proto::UpdateRoomSettings {
+        settings_version: command.settings_version,
+        settings: out_stream.data.split_off(2),
+    })
+}
+
+fn update_room_settings_from_proto(
+    message: proto::UpdateRoomSettings,
+) -> Result<UpdateRoomSettingsCommand> {
+    if message.settings.len() > MAX_EXTENSIONS_OCTETS {
+        return in_field(Err(out_of_range(message.settings.len())), "settings");
+    }
+    let mut octets = (message.settings.len() as u16).to_be_bytes().to_vec();
+    octets.extend_from_slice(&message.settings);
+    Ok(UpdateRoomSettingsCommand {
+        settings_version: message.settings_version,
+        settings: in_field(
+            Extensions::from_cursor(&mut SliceReader::new(&octets)),
+            "settings",
+        )?,
+    })
+}
+
+fn delivery_to_proto(delivery: CommandDelivery) -> u32 {
+    match delivery {
+        CommandDelivery::ReliableOrdered => 0,
+        CommandDelivery::Unreliable => 1,
+    }
+}
+
+fn channel_route_from_proto(channel_id: u32, delivery: u32) -> Result<ChannelRoute> {
+    Ok(ChannelRoute {
+        channel_id: narrow(channel_id, "channel_id")?,
+        delivery: match delivery {
+            0 => CommandDelivery::ReliableOrdered,
+            1 => CommandDelivery::Unreliable,
+            _ => return in_field(Err(out_of_range(delivery)), "delivery"),
+        },
+    })
+}
+
+fn channel_profile_to_proto(command: &ChannelProfileCommand) -> proto::ChannelProfile {
+    let fallback = command.profile.fallback();
+    proto::ChannelProfile {
+        fallback: Some(proto::ChannelRoute {
+            channel_id: fallback.channel_id as u32,
+            delivery: delivery_to_proto(fallback.delivery),
+        }),
+        routes: command
+            .profile
+            .routes()
+            .map(|(command_type_id, route)| proto::ChannelProfileRoute {
+                command_type_id: command_type_id as u32,
+                channel_id: route.channel_id as u32,
+                delivery: delivery_to_proto(route.delivery),
+            })
+            .collect(),
+    }
+}
+
+fn channel_profile_from_proto(message: proto::ChannelProfile) -> Result<ChannelProfileCommand> {
+    let fallback = required(message.fallback, "fallback")?;
+    let mut profile = ChannelProfile::new(in_field(
+        channel_route_from_proto(fallback.channel_id, fallback.delivery),
+        "fallback",
+    )?);
+    for (index, route) in message.routes.into_iter().enumerate() {
+        let command_type_id = in_field(
+            narrow(route.command_type_id, "command_type_id"),
+            format_args!("routes[{}]", index),
+        )?;
+        let route = in_field(
+            channel_route_from_proto(route.channel_id, route.delivery),
+            format_args!("routes[{}]", index),
+        )?;
+        profile.set_route(command_type_id, route);
+    }
+    Ok(ChannelProfileCommand { profile })
+}
+
+fn server_load_to_proto(command: &ServerLoadCommand) -> proto::ServerLoad {
+    proto::ServerLoad {
+        cpu_permille: command.cpu_permille as u32,
+        rooms: command.rooms as u32,
+        headroom: command.headroom as u32,
+    }
+}
+
+fn server_load_from_proto(message: proto::ServerLoad) -> Result<ServerLoadCommand> {
+    Ok(ServerLoadCommand {
+        cpu_permille: narrow(message.cpu_permille, "cpu_permille")?,
+        rooms: narrow(message.rooms, "rooms")?,
+        headroom: narrow(message.headroom, "headroom")?,
+    })
+}
+
+fn room_info_page_to_proto(command: &RoomInfoPageCommand) -> proto::RoomInfoPage {
+    proto::RoomInfoPage {
+        term: command.term as u32,
+        page_index: command.page_index as u32,
+        page_count: command.page_count as u32,
+        client_infos: command.client_infos.iter().map(Into::into).collect(),
+        leader_index: command.leader_index.0 as u32,
+        knowledge_vector: command.knowledge_vector.as_ref().map(Into::into),
+        quorum_size: command.quorum_size as u32,
+        votes_for_leader: command.votes_for_leader as u32,
+        leader_lease_expires_in_ms: command.leader_lease_expires_in_ms,
+    }
+}
+
+fn room_info_page_from_proto(message: proto::RoomInfoPage) -> Result<RoomInfoPageCommand> {
+    if message.client_infos.len() > MAX_CLIENT_INFOS {
+        return in_field(
+            Err(out_of_range(message.client_infos.len())),
+            "client_infos.len",
+        );
+    }
+    let page_index = narrow(message.page_index, "page_index")?;
+    let page_count = narrow(message.page_count, "page_count")?;
+    in_field(check_page_index(page_index, page_count), "page_index")?;
+    let client_infos = message
+        .client_infos
+        .into_iter()
+        .enumerate()
+        .map(|(index, client_info)| {
+            in_field(
+                ClientInfo::try_from(client_info),
+                format_args!("client_infos[{}]", index),
+            )
+        })
+        .collect::<Result<ClientInfos>>()?;
+    Ok(RoomInfoPageCommand {
+        term: narrow(message.term, "term")?,
+        leader_index: connection_index_from(message.leader_index, "leader_index")?,
+        page_index,
+        page_count,
+        client_infos,
+        knowledge_vector: message
+            .knowledge_vector
+            .map(knowledge_vector_from)
+            .transpose()?,
+        quorum_size: narrow(message.quorum_size, "quorum_size")?,
+        votes_for_leader: narrow(message.votes_for_leader, "votes_for_leader")?,
+        leader_lease_expires_in_ms: message.leader_lease_expires_in_ms,
+    })
+}
+
+fn transfer_resend_request_to_proto(
+    command: &TransferResendRequestCommand,
+) -> proto::TransferResendRequest {
+    proto::TransferResendRequest {
+        transfer_id: command.transfer_id,
+        missing_chunk_indices: command
+            .missing_chunk_indices
+            .iter()
+            .map(|chunk_index| *chunk_index as u32)
+            .collect(),
+    }
+}
+
+fn transfer_resend_request_from_proto(
+    message: proto::TransferResendRequest,
+) -> Result<TransferResendRequestCommand> {
+    Ok(TransferResendRequestCommand {
+        transfer_id: message.transfer_id,
+        missing_chunk_indices: message
+            .missing_chunk_indices
+            .iter()
+            .enumerate()
+            .map(|(index, chunk_index)| {
+                narrow(*chunk_index, &format!("missing_chunk_indices[{}]", index))
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn membership_sync_request_to_proto(
+    command: &MembershipSyncRequestCommand,
+) -> proto::MembershipSyncRequest {
+    proto::MembershipSyncRequest {
+        known_revisions: command
+            .known_revisions
+            .iter()
+            .map(|known_revision| proto::KnownRevision {
+                connection_index: known_revision.connection_index.0 as u32,
+                revision: known_revision.revision,
+            })
+            .collect(),
+    }
+}
+
+fn membership_sync_request_from_proto(
+    message: proto::MembershipSyncRequest,
+) -> Result<MembershipSyncRequestCommand> {
+    if message.known_revisions.len() > u8::MAX as usize {
+        return in_field(
+            Err(out_of_range(message.known_revisions.len())),
+            "known_revisions.len",
+        );
+    }
+    Ok(MembershipSyncRequestCommand {
+        known_revisions: message
+            .known_revisions
+            .into_iter()
+            .enumerate()
+            .map(|(index, known_revision)| {
+                in_field(
+                    connection_index_from(known_revision.connection_index, "connection_index"),
+                    format_args!("known_revisions[{}]", index),
+                )
+                .map(|connection_index| KnownRevision {
+                    connection_index,
+                    revision: known_revision.revision,
+                })
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn set_presence_from_proto(message: proto::SetPresence) -> Result<SetPresenceCommand> {
+    Ok(SetPresenceCommand {
+        presence: presence_from(message.presence)?,
+    })
+}
+
+fn term_advanced_from_proto(message: proto::TermAdvanced) -> Result<TermAdvancedCommand> {
+    Ok(TermAdvancedCommand {
+        new_term: narrow(message.new_term, "new_term")?,
+        cause: narrow(message.cause, "cause")?,
+    })
+}
+
+fn pre_vote_request_from_proto(message: proto::PreVoteRequest) -> Result<PreVoteRequestCommand> {
+    Ok(PreVoteRequestCommand {
+        next_term: narrow(message.next_term, "next_term")?,
+        knowledge: message.knowledge,
+    })
+}
+
+fn pre_vote_response_from_proto(message: proto::PreVoteResponse) -> Result<PreVoteResponseCommand> {
+    Ok(PreVoteResponseCommand {
+        next_term: narrow(message.next_term, "next_term")?,
+        granted: message.granted,
+    })
+}
+
+fn replicate_to_proto(command: &ReplicateCommand) -> proto::Replicate {
+    proto::Replicate {
+        term: command.term as u32,
+        prev_knowledge: command.prev_knowledge,
+        entries: command
+            .entries
+            .iter()
+            .map(|entry| proto::LogEntry {
+                payload: entry.payload.clone(),
+            })
+            .collect(),
+    }
+}
+
+fn replicate_from_proto(message: proto::Replicate) -> Result<ReplicateCommand> {
+    Ok(ReplicateCommand {
+        term: narrow(message.term, "term")?,
+        prev_knowledge: message.prev_knowledge,
+        entries: message
+            .entries
+            .into_iter()
+            .map(|entry| LogEntry {
+                payload: entry.payload,
+            })
+            .collect(),
+    })
+}
+
+fn replicate_ack_to_proto(command: &ReplicateAckCommand) -> proto::ReplicateAck {
+    proto::ReplicateAck {
+        term: command.term as u32,
+        knowledge: command.knowledge,
+        success: command.success,
+    }
+}
+
+fn replicate_ack_from_proto(message: proto::ReplicateAck) -> Result<ReplicateAckCommand> {
+    Ok(ReplicateAckCommand {
+        term: narrow(message.term, "term")?,
+        knowledge: message.knowledge,
+        success: message.success,
+    })
+}
+
+fn membership_sync_response_to_proto(
+    command: &MembershipSyncResponseCommand,
+) -> proto::MembershipSyncResponse {
+    proto::MembershipSyncResponse {
+        term: command.term as u32,
+        leader_connection_index: command.leader_connection_index.0 as u32,
+        changed: command.changed.iter().map(Into::into).collect(),
+        removed: command
+            .removed
+            .iter()
+            .map(|connection_index| connection_index.0 as u32)
+            .collect(),
+    }
+}
+
+fn membership_sync_response_from_proto(
+    message: proto::MembershipSyncResponse,
+) -> Result<MembershipSyncResponseCommand> {
+    if message.changed.len() > MAX_CLIENT_INFOS {
+        return in_field(Err(out_of_range(message.changed.len())), "changed.len");
+    }
+    if message.removed.len() > u8::MAX as usize {
+        return in_field(Err(out_of_range(message.removed.len())), "removed.len");
+    }
+    Ok(MembershipSyncResponseCommand {
+        term: narrow(message.term, "term")?,
+        leader_connection_index: connection_index_from(
+            message.leader_connection_index,
+            "leader_connection_index",
+        )?,
+        changed: message
+            .changed
+            .into_iter()
+            .enumerate()
+            .map(|(index, client_info)| {
+                in_field(
+                    ClientInfo::try_from(client_info),
+                    format_args!("changed[{}]", index),
+                )
+            })
+            .collect::<Result<ClientInfos>>()?,
+        removed: message
+            .removed
+            .iter()
+            .enumerate()
+            .map(|(index, connection_index)| {
+                connection_index_from(*connection_index, &format!("removed[{}]", index))
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn hello_to_proto(command: &HelloCommand) -> proto::Hello {
+    proto::Hello {
+        version: command.version as u32,
+        fingerprint: command.fingerprint,
+    }
+}
+
+fn hello_from_proto(message: proto::Hello) -> Result<HelloCommand> {
+    Ok(HelloCommand {
+        version: narrow(message.version, "version")?,
+        fingerprint: message.fingerprint,
+    })
+}
+
+fn unsupported(command_type_id: u8) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+            "command type id 0x{:02X} has no protobuf message",
+            command_type_id
+        ),
+    )
+}
+
+impl ServerReceiveCommand {
+    pub fn to_proto(&self) -> Result<proto::ServerReceiveCommand> {
+        use proto::server_receive_command::Command;
+        let command = match self {
+            Self::PingCommandType(command) => Command::Ping(ping_to_proto(command)),
+            Self::BanClientType(command) => Command::BanClient(proto::BanClient {
+                custom_user_id: Some(command.custom_user_id.0.into()),
+                duration_s: command.duration_s,
+            }),
+            Self::UnbanClientType(command) => Command::UnbanClient(proto::UnbanClient {
+                custom_user_id: Some(command.custom_user_id.0.into()),
+            }),
+            Self::MuteStateType(command) => Command::MuteState(mute_state_to_proto(command)),
+            Self::RelayToClientType(command) => Command::RelayToClient(proto::RelayToClient {
+                target_connection_index: command.target_connection_index.0 as u32,
+                payload: command.payload.clone(),
+            }),
+            Self::BroadcastPayloadType(command) => {
+                Command::BroadcastPayload(proto::BroadcastPayload {
+                    payload: command.payload.clone(),
+                })
+            }
+            Self::JoinRequestType(command) => Command::JoinRequest(command.into()),
+            Self::ResyncRequestType(command) => Command::ResyncRequest(proto::ResyncRequest {
+                from_term: command.from_term as u32,
+                from_knowledge: command.from_knowledge,
+            }),
+            Self::SnapshotRequestType(command) => {
+                Command::SnapshotRequest(proto::SnapshotRequest {
+                    snapshot_id: command.snapshot_id,
+                })
+            }
+            Self::TransferChunkType(command) => {
+                Command::TransferChunk(transfer_chunk_to_proto(command))
+            }
+            Self::TransferResendRequestType(command) => {
+                Command::TransferResendRequest(transfer_resend_request_to_proto(command))
+            }
+            Self::BandwidthReportType(command) => {
+                Command::BandwidthReport(proto::BandwidthReport {
+                    bytes_sent: command.bytes_sent,
+                    bytes_received: command.bytes_received,
+                    window_ms: command.window_ms as u64,
+                })
+            }
+            Self::LatencyReportType(command) => Command::LatencyReport(proto::LatencyReport {
+                buckets: command.buckets.iter().map(|count| *count as u32).collect(),
+            }),
+            Self::HelloType(command) => Command::Hello(hello_to_proto(command)),
+            Self::PskAuthType(command) => Command::PskAuth(proto::PskAuth {
+                key_id: command.key_id as u32,
+                proof: command.proof.to_vec(),
+            }),
+            Self::ConnectWithTokenType(command) => {
+                Command::ConnectWithToken(proto::ConnectWithToken {
+                    token: Some((&command.token).into()),
+                })
+            }
+            Self::ConnectWithCookieType(command) => {
+                Command::ConnectWithCookie(proto::ConnectWithCookie {
+                    cookie: command.cookie.to_vec(),
+                    join_request: Some((&command.join_request).into()),
+                })
+            }
+            Self::TimeoutConfigType(command) => {
+                Command::TimeoutConfig(timeout_config_to_proto(command))
+            }
+            Self::UpdateRoomSettingsType(command) => {
+                Command::UpdateRoomSettings(update_room_settings_to_proto(command)?)
+            }
+            Self::MembershipSyncRequestType(command) => {
+                Command::MembershipSyncRequest(membership_sync_request_to_proto(command))
+            }
+            Self::SetPresenceType(command) => Command::SetPresence(proto::SetPresence {
+                presence: command.presence as u32,
+            }),
+            Self::PreVoteRequestType(command) => Command::PreVoteRequest(proto::PreVoteRequest {
+                next_term: command.next_term as u32,
+                knowledge: command.knowledge,
+            }),
+            Self::ReplicateType(command) => Command::Replicate(replicate_to_proto(command)),
+            Self::ReplicateAckType(command) => {
+                Command::ReplicateAck(replicate_ack_to_proto(command))
+            }
+            Self::Unknown(raw) => return Err(unsupported(raw.type_id)),
+        };
+        Ok(proto::ServerReceiveCommand {
+            command: Some(command),
+        })
+    }
+
+    pub fn from_proto(message: proto::ServerReceiveCommand) -> Result<Self> {
+        use proto::server_receive_command::Command;
+        Ok(match required(message.command, "command")? {
+            Command::Ping(message) => {
+                Self::PingCommandType(in_field(ping_from_proto(message), PingCommand::NAME)?)
+            }
+            Command::BanClient(message) => Self::BanClientType(BanClientCommand {
+                custom_user_id: UserId(
+                    in_field(
+                        required(message.custom_user_id, "custom_user_id"),
+                        BanClientCommand::NAME,
+                    )?
+                    .into(),
+                ),
+                duration_s: message.duration_s,
+            }),
+            Command::UnbanClient(message) => Self::UnbanClientType(UnbanClientCommand {
+                custom_user_id: UserId(
+                    in_field(
+                        required(message.custom_user_id, "custom_user_id"),
+                        UnbanClientCommand::NAME,
+                    )?
+                    .into(),
+                ),
+            }),
+            Command::MuteState(message) => Self::MuteStateType(in_field(
+                mute_state_from_proto(message),
+                MuteStateCommand::NAME,
+            )?),
+            Command::RelayToClient(message) => Self::RelayToClientType(RelayToClientCommand {
+                target_connection_index: in_field(
+                    connection_index_from(
+                        message.target_connection_index,
+                        "target_connection_index",
+                    ),
+                    RelayToClientCommand::NAME,
+                )?,
+                payload: message.payload,
+            }),
+            Command::BroadcastPayload(message) => {
+                Self::BroadcastPayloadType(BroadcastPayloadCommand {
+                    payload: message.payload,
+                })
+            }
+            Command::JoinRequest(message) => {
+                Self::JoinRequestType(in_field(message.try_into(), JoinRequestCommand::NAME)?)
+            }
+            Command::ResyncRequest(message) => Self::ResyncRequestType(ResyncRequestCommand {
+                from_term: in_field(
+                    narrow(message.from_term, "from_term"),
+                    ResyncRequestCommand::NAME,
+                )?,
+                from_knowledge: message.from_knowledge,
+            }),
+            Command::SnapshotRequest(message) => {
+                Self::SnapshotRequestType(SnapshotRequestCommand {
+                    snapshot_id: message.snapshot_id,
+                })
+            }
+            Command::TransferChunk(message) => Self::TransferChunkType(in_field(
+                transfer_chunk_from_proto(message),
+                TransferChunkCommand::NAME,
+            )?),
+            Command::TransferResendRequest(message) => Self::TransferResendRequestType(in_field(
+                transfer_resend_request_from_proto(message),
+                TransferResendRequestCommand::NAME,
+            )?),
+            Command::BandwidthReport(message) => {
+                Self::BandwidthReportType(BandwidthReportCommand {
+                    bytes_sent: message.bytes_sent,
+                    bytes_received: message.bytes_received,
+                    window_ms: in_field(
+                        narrow(message.window_ms, "window_ms"),
+                        BandwidthReportCommand::NAME,
+                    )?,
+                })
+            }
+            Command::LatencyReport(message) => {
+                if message.buckets.len() != LATENCY_BUCKET_COUNT {
+                    return in_field(
+                        in_field(Err(out_of_range(message.buckets.len())), "buckets.len"),
+                        LatencyReportCommand::NAME,
+                    );
+                }
+                let mut buckets = [0u16; LATENCY_BUCKET_COUNT];
+                for (index, count) in message.buckets.iter().enumerate() {
+                    buckets[index] = in_field(
+                        narrow(*count, &format!("buckets[{}]", index)),
+                        LatencyReportCommand::NAME,
+                    )?;
+                }
+                Self::LatencyReportType(LatencyReportCommand { buckets })
+            }
+            Command::Hello(message) => {
+                Self::HelloType(in_field(hello_from_proto(message), HelloCommand::NAME)?)
+            }
+            Command::PskAuth(message) => Self::PskAuthType(PskAuthCommand {
+                key_id: in_field(narrow(message.key_id, "key_id"), PskAuthCommand::NAME)?,
+                proof: in_field(fixed_octets(message.proof, "proof"), PskAuthCommand::NAME)?,
+            }),
+            Command::ConnectWithToken(message) => {
+                Self::ConnectWithTokenType(ConnectWithTokenCommand {
+                    token: in_field(
+                        required(message.token, "token").and_then(ConnectToken::try_from),
+                        ConnectWithTokenCommand::NAME,
+                    )?,
+                })
+            }
+            Command::ConnectWithCookie(message) => {
+                Self::ConnectWithCookieType(ConnectWithCookieCommand {
+                    cookie: in_field(
+                        fixed_octets(message.cookie, "cookie"),
+                        ConnectWithCookieCommand::NAME,
+                    )?,
+                    join_request: in_field(
+                        required(message.join_request, "join_request")
+                            .and_then(JoinRequestCommand::try_from),
+                        ConnectWithCookieCommand::NAME,
+                    )?,
+                })
+            }
+            Command::TimeoutConfig(message) => Self::TimeoutConfigType(in_field(
+                timeout_config_from_proto(message),
+                TimeoutConfigCommand::NAME,
+            )?),
+            Command::UpdateRoomSettings(message) => Self::UpdateRoomSettingsType(in_field(
+                update_room_settings_from_proto(message),
+                UpdateRoomSettingsCommand::NAME,
+            )?),
+            Command::MembershipSyncRequest(message) => Self::MembershipSyncRequestType(in_field(
+                membership_sync_request_from_proto(message),
+                MembershipSyncRequestCommand::NAME,
+            )?),
+            Command::SetPresence(message) => Self::SetPresenceType(in_field(
+                set_presence_from_proto(message),
+                SetPresenceCommand::NAME,
+            )?),
+            Command::PreVoteRequest(message) => Self::PreVoteRequestType(in_field(
+                pre_vote_request_from_proto(message),
+                PreVoteRequestCommand::NAME,
+            )?),
+            Command::Replicate(message) => Self::ReplicateType(in_field(
+                replicate_from_proto(message),
+                ReplicateCommand::NAME,
+            )?),
+            Command::ReplicateAck(message) => Self::ReplicateAckType(in_field(
+                replicate_ack_from_proto(message),
+                ReplicateAckCommand::NAME,
+            )?),
+        })
+    }
+
+    /// The command as an encoded `ServerReceiveCommand` protobuf message
+    pub fn to_protobuf(&self) -> Result<Vec<u8>> {
+        Ok(self.to_proto()?.encode_to_vec())
+    }
+
+    pub fn from_protobuf(octets: &[u8]) -> Result<Self> {
+        let message = proto::ServerReceiveCommand::decode(octets)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        Self::from_proto(message)
+    }
+}
+
+impl ClientReceiveCommand {
+    pub fn to_proto(&self) -> Result<proto::ClientReceiveCommand> {
+        use proto::client_receive_command::Command;
+        let command = match self {
+            Self::RoomInfoType(command) => Command::RoomInfo(command.into()),
+            Self::RoomClosedType(command) => Command::RoomClosed(proto::RoomClosed {
+                reason_code: command.reason_code as u32,
+                closes_at_ms: command.closes_at_ms,
+            }),
+            Self::MuteStateType(command) => Command::MuteState(mute_state_to_proto(command)),
+            Self::PingResponseType(command) => Command::PingResponse(proto::PingResponse {
+                term: command.term as u32,
+                server_knowledge: command.server_knowledge,
+                your_knowledge_ack: command.your_knowledge_ack,
+            }),
+            Self::RelayedFromClientType(command) => {
+                Command::RelayedFromClient(proto::RelayedFromClient {
+                    source_connection_index: command.source_connection_index.0 as u32,
+                    payload: command.payload.clone(),
+                })
+            }
+            Self::BroadcastedPayloadType(command) => {
+                Command::BroadcastedPayload(proto::BroadcastedPayload {
+                    source_connection_index: command.source_connection_index.0 as u32,
+                    payload: command.payload.clone(),
+                })
+            }
+            Self::JoinAcceptedType(command) => Command::JoinAccepted(proto::JoinAccepted {
+                connection_index: command.connection_index.0 as u32,
+                room_info: Some((&command.room_info).into()),
+            }),
+            Self::JoinDeniedType(command) => Command::JoinDenied(proto::JoinDenied {
+                reason_code: command.reason_code as u32,
+            }),
+            Self::ResyncDataType(command) => Command::ResyncData(proto::ResyncData {
+                term: command.term as u32,
+                knowledge: command.knowledge,
+                chunk_index: command.chunk_index as u32,
+                chunk_count: command.chunk_count as u32,
+                data: command.data.clone(),
+            }),
+            Self::SnapshotChunkType(command) => Command::SnapshotChunk(proto::SnapshotChunk {
+                snapshot_id: command.snapshot_id,
+                chunk_index: command.chunk_index as u32,
+                chunk_count: command.chunk_count as u32,
+                data: command.data.clone(),
+            }),
+            Self::TransferChunkType(command) => {
+                Command::TransferChunk(transfer_chunk_to_proto(command))
+            }
+            Self::TransferResendRequestType(command) => {
+                Command::TransferResendRequest(transfer_resend_request_to_proto(command))
+            }
+            Self::MaintenanceNoticeType(command) => {
+                Command::MaintenanceNotice(proto::MaintenanceNotice {
+                    starts_in_s: command.starts_in_s,
+                    expected_duration_s: command.expected_duration_s,
+                })
+            }
+            Self::RedirectType(command) => Command::Redirect(proto::Redirect {
+                host: command.host.clone(),
+                port: command.port as u32,
+                token: command.token.clone(),
+            }),
+            Self::ServerLoadType(command) => Command::ServerLoad(server_load_to_proto(command)),
+            Self::HelloType(command) => Command::Hello(hello_to_proto(command)),
+            Self::ChallengeType(command) => Command::Challenge(proto::Challenge {
+                challenge: command.challenge.to_vec(),
+                server_load: command.server_load.as_ref().map(server_load_to_proto),
+            }),
+            Self::RetryCookieType(command) => Command::RetryCookie(proto::RetryCookie {
+                cookie: command.cookie.to_vec(),
+            }),
+            Self::ThrottledType(command) => Command::Throttled(proto::Throttled {
+                retry_after_ms: command.retry_after_ms as u32,
+            }),
+            Self::TimeoutConfigType(command) => {
+                Command::TimeoutConfig(timeout_config_to_proto(command))
+            }
+            Self::UpdateRoomSettingsType(command) => {
+                Command::UpdateRoomSettings(update_room_settings_to_proto(command)?)
+            }
+            Self::ChannelProfileType(command) => {
+                Command::ChannelProfile(channel_profile_to_proto(command))
+            }
+            Self::RoomInfoPageType(command) => {
+                Command::RoomInfoPage(room_info_page_to_proto(command))
+            }
+            Self::MembershipSyncResponseType(command) => {
+                Command::MembershipSyncResponse(membership_sync_response_to_proto(command))
+            }
+            Self::KnowledgeAckType(command) => Command::KnowledgeAck(proto::KnowledgeAck {
+                acked_knowledge: command.acked_knowledge,
+            }),
+            Self::TermAdvancedType(command) => Command::TermAdvanced(proto::TermAdvanced {
+                new_term: command.new_term as u32,
+                cause: command.cause as u32,
+            }),
+            Self::PreVoteResponseType(command) => {
+                Command::PreVoteResponse(proto::PreVoteResponse {
+                    next_term: command.next_term as u32,
+                    granted: command.granted,
+                })
+            }
+            Self::ReplicateType(command) => Command::Replicate(replicate_to_proto(command)),
+            Self::ReplicateAckType(command) => {
+                Command::ReplicateAck(replicate_ack_to_proto(command))
+            }
+            Self::Unknown(raw) => return Err(unsupported(raw.type_id)),
+        };
+        Ok(proto::ClientReceiveCommand {
+            command: Some(command),
+        })
+    }
+
+    pub fn from_proto(message: proto::ClientReceiveCommand) -> Result<Self> {
+        use proto::client_receive_command::Command;
+        Ok(match required(message.command, "command")? {
+            Command::RoomInfo(message) => {
+                Self::RoomInfoType(in_field(message.try_into(), RoomInfoCommand::NAME)?)
+            }
+            Command::RoomClosed(message) => Self::RoomClosedType(RoomClosedCommand {
+                reason_code: in_field(
+                    narrow(message.reason_code, "reason_code"),
+                    RoomClosedCommand::NAME,
+                )?,
+                closes_at_ms: message.closes_at_ms,
+            }),
+            Command::MuteState(message) => Self::MuteStateType(in_field(
+                mute_state_from_proto(message),
+                MuteStateCommand::NAME,
+            )?),
+            Command::PingResponse(message) => Self::PingResponseType(PingResponseCommand {
+                term: in_field(narrow(message.term, "term"), PingResponseCommand::NAME)?,
+                server_knowledge: message.server_knowledge,
+                your_knowledge_ack: message.your_knowledge_ack,
+            }),
+            Command::RelayedFromClient(message) => {
+                Self::RelayedFromClientType(RelayedFromClientCommand {
+                    source_connection_index: in_field(
+                        connection_index_from(
+                            message.source_connection_index,
+                            "source_connection_index",
+                        ),
+                        RelayedFromClientCommand::NAME,
+                    )?,
+                    payload: message.payload,
+                })
+            }
+            Command::BroadcastedPayload(message) => {
+                Self::BroadcastedPayloadType(BroadcastedPayloadCommand {
+                    source_connection_index: in_field(
+                        connection_index_from(
+                            message.source_connection_index,
+                            "source_connection_index",
+                        ),
+                        BroadcastedPayloadCommand::NAME,
+                    )?,
+                    payload: message.payload,
+                })
+            }
+            Command::JoinAccepted(message) => Self::JoinAcceptedType(JoinAcceptedCommand {
+                connection_index: in_field(
+                    connection_index_from(message.connection_index, "connection_index"),
+                    JoinAcceptedCommand::NAME,
+                )?,
+                room_info: in_field(
+                    required(message.room_info, "room_info").and_then(RoomInfoCommand::try_from),
+                    JoinAcceptedCommand::NAME,
+                )?,
+            }),
+            Command::JoinDenied(message) => Self::JoinDeniedType(JoinDeniedCommand {
+                reason_code: in_field(
+                    narrow(message.reason_code, "reason_code"),
+                    JoinDeniedCommand::NAME,
+                )?,
+            }),
+            Command::ResyncData(message) => Self::ResyncDataType(ResyncDataCommand {
+                term: in_field(narrow(message.term, "term"), ResyncDataCommand::NAME)?,
+                knowledge: message.knowledge,
+                chunk_index: in_field(
+                    narrow(message.chunk_index, "chunk_index"),
+                    ResyncDataCommand::NAME,
+                )?,
+                chunk_count: in_field(
+                    narrow(message.chunk_count, "chunk_count"),
+                    ResyncDataCommand::NAME,
+                )?,
+                data: message.data,
+            }),
+            Command::SnapshotChunk(message) => Self::SnapshotChunkType(SnapshotChunkCommand {
+                snapshot_id: message.snapshot_id,
+                chunk_index: in_field(
+                    narrow(message.chunk_index, "chunk_index"),
+                    SnapshotChunkCommand::NAME,
+                )?,
+                chunk_count: in_field(
+                    narrow(message.chunk_count, "chunk_count"),
+                    SnapshotChunkCommand::NAME,
+                )?,
+                data: message.data,
+            }),
+            Command::TransferChunk(message) => Self::TransferChunkType(in_field(
+                transfer_chunk_from_proto(message),
+                TransferChunkCommand::NAME,
+            )?),
+            Command::TransferResendRequest(message) => Self::TransferResendRequestType(in_field(
+                transfer_resend_request_from_proto(message),
+                TransferResendRequestCommand::NAME,
+            )?),
+            Command::MaintenanceNotice(message) => {
+                Self::MaintenanceNoticeType(MaintenanceNoticeCommand {
+                    starts_in_s: message.starts_in_s,
+                    expected_duration_s: message.expected_duration_s,
+                })
+            }
+            Command::Redirect(message) => Self::RedirectType(RedirectCommand {
+                host: message.host,
+                port: in_field(narrow(message.port, "port"), RedirectCommand::NAME)?,
+                token: message.token,
+            }),
+            Command::ServerLoad(message) => Self::ServerLoadType(in_field(
+                server_load_from_proto(message),
+                ServerLoadCommand::NAME,
+            )?),
+            Command::Hello(message) => {
+                Self::HelloType(in_field(hello_from_proto(message), HelloCommand::NAME)?)
+            }
+            Command::Challenge(message) => Self::ChallengeType(ChallengeCommand {
+                challenge: in_field(
+                    fixed_octets(message.challenge, "challenge"),
+                    ChallengeCommand::NAME,
+                )?,
+                server_load: in_field(
+                    in_field(
+                        message.server_load.map(server_load_from_proto).transpose(),
+                        "server_load",
+                    ),
+                    ChallengeCommand::NAME,
+                )?,
+            }),
+            Command::RetryCookie(message) => Self::RetryCookieType(RetryCookieCommand {
+                cookie: in_field(
+                    fixed_octets(message.cookie, "cookie"),
+                    RetryCookieCommand::NAME,
+                )?,
+            }),
+            Command::Throttled(message) => Self::ThrottledType(ThrottledCommand {
+                retry_after_ms: in_field(
+                    narrow(message.retry_after_ms, "retry_after_ms"),
+                    ThrottledCommand::NAME,
+                )?,
+            }),
+            Command::TimeoutConfig(message) => Self::TimeoutConfigType(in_field(
+                timeout_config_from_proto(message),
+                TimeoutConfigCommand::NAME,
+            )?),
+            Command::UpdateRoomSettings(message) => Self::UpdateRoomSettingsType(in_field(
+                update_room_settings_from_proto(message),
+                UpdateRoomSettingsCommand::NAME,
+            )?),
+            Command::ChannelProfile(message) => Self::ChannelProfileType(in_field(
+                channel_profile_from_proto(message),
+                ChannelProfileCommand::NAME,
+            )?),
+            Command::RoomInfoPage(message) => Self::RoomInfoPageType(in_field(
+                room_info_page_from_proto(message),
+                RoomInfoPageCommand::NAME,
+            )?),
+            Command::MembershipSyncResponse(message) => Self::MembershipSyncResponseType(in_field(
+                membership_sync_response_from_proto(message),
+                MembershipSyncResponseCommand::NAME,
+            )?),
+            Command::KnowledgeAck(message) => Self::KnowledgeAckType(KnowledgeAckCommand {
+                acked_knowledge: message.acked_knowledge,
+            }),
+            Command::TermAdvanced(message) => Self::TermAdvancedType(in_field(
+                term_advanced_from_proto(message),
+                TermAdvancedCommand::NAME,
+            )?),
+            Command::PreVoteResponse(message) => Self::PreVoteResponseType(in_field(
+                pre_vote_response_from_proto(message),
+                PreVoteResponseCommand::NAME,
+            )?),
+            Command::Replicate(message) => Self::ReplicateType(in_field(
+                replicate_from_proto(message),
+                ReplicateCommand::NAME,
+            )?),
+            Command::ReplicateAck(message) => Self::ReplicateAckType(in_field(
+                replicate_ack_from_proto(message),
+                ReplicateAckCommand::NAME,
+            )?),
+        })
+    }
+
+    /// The command as an encoded `ClientReceiveCommand` protobuf message
+    pub fn to_protobuf(&self) -> Result<Vec<u8>> {
+        Ok(self.to_proto()?.encode_to_vec())
+    }
+
+    pub fn from_protobuf(octets: &[u8]) -> Result<Self> {
+        let message = proto::ClientReceiveCommand::decode(octets)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        Self::from_proto(message)
+    }
+}
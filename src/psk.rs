@@ -0,0 +1,212 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Pre-shared-key authentication: the client proves it knows the key identified by `key_id`
+//! with an HMAC-SHA256 over the challenge sent by the server.
+//!
+//! The command is always available, computing and verifying the proof needs the `psk` feature.
+
+use std::io::Result;
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{
+    PresenceMaskWidth, PresenceReader, PresenceWriter, ProtocolVersion, ServerLoadCommand,
+    DEFAULT_PROTOCOL_VERSION, PROTOCOL_VERSION_14, PROTOCOL_VERSION_6,
+};
+
+pub const PSK_PROOF_OCTETS: usize = 32;
+pub const PSK_CHALLENGE_OCTETS: usize = 32;
+pub const PSK_SESSION_KEY_OCTETS: usize = 32;
+pub const PSK_AUTH_COMMAND_TYPE_ID: u8 = 0x18;
+pub const CHALLENGE_COMMAND_TYPE_ID: u8 = 0x19;
+/// Bit of [`ChallengeCommand::server_load`] in the presence mask
+pub const CHALLENGE_SERVER_LOAD_BIT: u32 = 0;
+
+/// Separate the session keys from the proof, which is an HMAC over the bare challenge, and
+/// from each other, so the two directions never seal with the same key and nonce
+#[cfg(feature = "psk")]
+const CLIENT_TO_SERVER_KEY_LABEL: &[u8] = b"conclave session key client to server";
+#[cfg(feature = "psk")]
+const SERVER_TO_CLIENT_KEY_LABEL: &[u8] = b"conclave session key server to client";
+
+/// Sent from Server to Client, answered with a [`PskAuthCommand`]
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChallengeCommand {
+    /// Must be unpredictable and never reused, so old proofs can not be replayed
+    pub challenge: [u8; PSK_CHALLENGE_OCTETS],
+    /// The load of the server, so a client can look for a less loaded host before it
+    /// authenticates. Behind a presence mask from [`PROTOCOL_VERSION_14`], not serialized
+    /// before.
+    pub server_load: Option<ServerLoadCommand>,
+}
+
+impl ChallengeCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "challenge";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        if version >= PROTOCOL_VERSION_14 {
+            PSK_CHALLENGE_OCTETS + 1
+        } else {
+            PSK_CHALLENGE_OCTETS
+        }
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        stream.write(&self.challenge)?;
+        if version >= PROTOCOL_VERSION_14 {
+            let mut writer = PresenceWriter::new(PresenceMaskWidth::U8);
+            in_field(
+                writer.field(CHALLENGE_SERVER_LOAD_BIT, &self.server_load),
+                "server_load",
+            )?;
+            writer.to_octets(stream)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        let mut challenge = [0u8; PSK_CHALLENGE_OCTETS];
+        in_field(stream.read(&mut challenge), "challenge")?;
+        let server_load = if version >= PROTOCOL_VERSION_14 {
+            let mut reader = PresenceReader::from_cursor(
+                stream,
+                PresenceMaskWidth::U8,
+                1 << CHALLENGE_SERVER_LOAD_BIT,
+            )?;
+            in_field(
+                reader.field(CHALLENGE_SERVER_LOAD_BIT, stream),
+                "server_load",
+            )?
+        } else {
+            None
+        };
+        Ok(Self {
+            challenge,
+            server_load,
+        })
+    }
+}
+
+/// Sent from Client to Server as the answer to a server challenge
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PskAuthCommand {
+    /// Selects the pre-shared key, so keys can be rotated
+    pub key_id: u16,
+    /// HMAC-SHA256 of the challenge, keyed with the pre-shared key
+    pub proof: [u8; PSK_PROOF_OCTETS],
+}
+
+impl PskAuthCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "psk_auth";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + PSK_PROOF_OCTETS
+    }
+
+    /// Answers `challenge` with the proof for `key`
+    #[cfg(feature = "psk")]
+    pub fn new(key_id: u16, key: &[u8], challenge: &[u8]) -> Self {
+        Self {
+            key_id,
+            proof: compute_psk_proof(key, challenge),
+        }
+    }
+
+    /// `true` if the proof was computed with `key` over `challenge`. Compares in constant time.
+    #[cfg(feature = "psk")]
+    pub fn verify(&self, key: &[u8], challenge: &[u8]) -> bool {
+        use hmac::Mac;
+
+        psk_mac(key, challenge).verify_slice(&self.proof).is_ok()
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u16(self.key_id)?;
+        stream.write(&self.proof)?;
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let key_id = in_field(stream.read_u16(), "key_id")?;
+        let mut proof = [0u8; PSK_PROOF_OCTETS];
+        in_field(stream.read(&mut proof), "proof")?;
+        Ok(Self { key_id, proof })
+    }
+}
+
+#[cfg(feature = "psk")]
+fn psk_mac(key: &[u8], challenge: &[u8]) -> hmac::Hmac<sha2::Sha256> {
+    use hmac::Mac;
+
+    // HMAC accepts keys of any length
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(key).expect("any key length");
+    mac.update(challenge);
+    mac
+}
+
+/// The keys both sides derive from the pre-shared key once the challenge is answered, one for
+/// each direction. The client seals with `client_to_server` and opens with `server_to_client`,
+/// the server the other way around, e.g. as the send and receive keys of a `SecureSession`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PskSessionKeys {
+    pub client_to_server: [u8; PSK_SESSION_KEY_OCTETS],
+    pub server_to_client: [u8; PSK_SESSION_KEY_OCTETS],
+}
+
+#[cfg(feature = "psk")]
+fn derive_psk_session_key(
+    key: &[u8],
+    label: &[u8],
+    challenge: &[u8],
+) -> [u8; PSK_SESSION_KEY_OCTETS] {
+    use hmac::Mac;
+
+    let mut mac = psk_mac(key, label);
+    mac.update(challenge);
+    mac.finalize().into_bytes().into()
+}
+
+/// Derives the [`PskSessionKeys`] for `challenge`
+#[cfg(feature = "psk")]
+pub fn derive_psk_session_keys(key: &[u8], challenge: &[u8]) -> PskSessionKeys {
+    PskSessionKeys {
+        client_to_server: derive_psk_session_key(key, CLIENT_TO_SERVER_KEY_LABEL, challenge),
+        server_to_client: derive_psk_session_key(key, SERVER_TO_CLIENT_KEY_LABEL, challenge),
+    }
+}
+
+/// HMAC-SHA256 of `challenge`, keyed with `key`
+#[cfg(feature = "psk")]
+pub fn compute_psk_proof(key: &[u8], challenge: &[u8]) -> [u8; PSK_PROOF_OCTETS] {
+    use hmac::Mac;
+
+    psk_mac(key, challenge).finalize().into_bytes().into()
+}
@@ -0,0 +1,101 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{InOctetStream, OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{Command, ProtocolVersion};
+
+/// A command that is not interpreted, so relays can forward commands they do not understand
+/// byte-for-byte. Also produced for unknown command type ids when decoding leniently.
+///
+/// The payload runs to the end of the stream, so the command must be alone in it, e.g. a
+/// datagram or a [`crate::Frame`], or last in a [`crate::MultiplexedDatagram`].
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawCommand {
+    pub type_id: u8,
+    /// Everything after the command type id
+    pub payload: Vec<u8>,
+}
+
+impl RawCommand {
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u8(self.type_id)?;
+        stream.write(&self.payload)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            type_id: in_field(stream.read_u8(), "command_type_id")?,
+            payload: in_field(read_to_end(stream), "payload")?,
+        })
+    }
+
+    /// Encodes `command` without keeping its interpretation
+    pub fn from_command<C: Command>(command: &C, version: ProtocolVersion) -> Result<Self> {
+        let mut out_stream = OutOctetStream::new();
+        command.to_octets_with_version(&mut out_stream, version)?;
+        let mut in_stream = InOctetStream::new(out_stream.data);
+        Self::from_cursor(&mut in_stream)
+    }
+
+    /// Interprets the command, e.g. once it reaches an endpoint that understands it
+    pub fn decode<C: Command>(&self, version: ProtocolVersion) -> Result<C> {
+        let mut octets = Vec::with_capacity(1 + self.payload.len());
+        octets.push(self.type_id);
+        octets.extend_from_slice(&self.payload);
+        C::from_octets_with_version(&mut InOctetStream::new(octets), version)
+    }
+}
+
+impl Command for RawCommand {
+    fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        _version: ProtocolVersion,
+    ) -> Result<()> {
+        RawCommand::to_octets(self, stream)
+    }
+
+    fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        _version: ProtocolVersion,
+    ) -> Result<Self> {
+        Self::from_cursor(stream)
+    }
+
+    fn runs_to_end(&self) -> bool {
+        true
+    }
+}
+
+/// Fails if `command` runs to the end of the stream, but something follows it
+pub(crate) fn check_nothing_follows<C: Command>(
+    command: &C,
+    followed: bool,
+    kind: ErrorKind,
+) -> Result<()> {
+    if followed && command.runs_to_end() {
+        return Err(Error::new(
+            kind,
+            "a command without a length runs to the end of the stream, nothing can follow it",
+        ));
+    }
+    Ok(())
+}
+
+/// Reads until the stream is exhausted, the stream can not tell how many octets remain.
+pub(crate) fn read_to_end(stream: &mut impl ReadOctetStream) -> Result<Vec<u8>> {
+    let mut octets = Vec::new();
+    loop {
+        match stream.read_u8() {
+            Ok(octet) => octets.push(octet),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(octets),
+            Err(err) => return Err(err),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Commands over [renet](https://crates.io/crates/renet) channels.
+//!
+//! Every command is a renet message on the channel for its [`CommandDelivery`], e.g.
+//! `RoomInfoCommand` on the reliable ordered channel and `PingCommand` on the unreliable one.
+//! Both ends have to use the channels of [`renet_connection_config`].
+
+use std::io::Result;
+use std::time::Duration;
+
+use flood_rs::OutOctetStream;
+use renet::{Bytes, ChannelConfig, ClientId, ConnectionConfig, RenetClient, RenetServer, SendType};
+
+use crate::{
+    command_descriptor, ClientReceiveCommand, ClientToServer, Command, CommandDelivery,
+    ProtocolVersion, ServerReceiveCommand, ServerToClient,
+};
+
+pub const RENET_RELIABLE_ORDERED_CHANNEL_ID: u8 = 0;
+pub const RENET_UNRELIABLE_CHANNEL_ID: u8 = 1;
+
+const CHANNEL_MAX_MEMORY_USAGE_OCTETS: usize = 5 * 1024 * 1024;
+const RELIABLE_RESEND_TIME: Duration = Duration::from_millis(300);
+
+impl CommandDelivery {
+    pub fn renet_channel_id(self) -> u8 {
+        match self {
+            Self::ReliableOrdered => RENET_RELIABLE_ORDERED_CHANNEL_ID,
+            Self::Unreliable => RENET_UNRELIABLE_CHANNEL_ID,
+        }
+    }
+}
+
+/// The channels in both directions, the reliable ordered one first so it has priority
+pub fn renet_channel_configs() -> Vec<ChannelConfig> {
+    vec![
+        ChannelConfig {
+            channel_id: RENET_RELIABLE_ORDERED_CHANNEL_ID,
+            max_memory_usage_bytes: CHANNEL_MAX_MEMORY_USAGE_OCTETS,
+            send_type: SendType::ReliableOrdered {
+                resend_time: RELIABLE_RESEND_TIME,
+            },
+        },
+        ChannelConfig {
+            channel_id: RENET_UNRELIABLE_CHANNEL_ID,
+            max_memory_usage_bytes: CHANNEL_MAX_MEMORY_USAGE_OCTETS,
+            send_type: SendType::Unreliable,
+        },
+    ]
+}
+
+/// The renet defaults, with the channels of [`renet_channel_configs`]
+pub fn renet_connection_config() -> ConnectionConfig {
+    ConnectionConfig {
+        server_channels_config: renet_channel_configs(),
+        client_channels_config: renet_channel_configs(),
+        ..ConnectionConfig::default()
+    }
+}
+
+/// Unknown command type ids use the reliable ordered channel
+pub fn renet_channel_id(command_type_id: u8) -> u8 {
+    command_descriptor(command_type_id)
+        .map_or(CommandDelivery::ReliableOrdered, |descriptor| {
+            descriptor.delivery
+        })
+        .renet_channel_id()
+}
+
+/// Encodes the command into a renet message, and returns it with the channel to send it on
+pub fn encode_renet_message<C: Command>(
+    command: &C,
+    version: ProtocolVersion,
+) -> Result<(u8, Bytes)> {
+    let mut out_stream = OutOctetStream::new();
+    command.to_octets_with_version(&mut out_stream, version)?;
+    let channel_id = renet_channel_id(out_stream.data[0]);
+    Ok((channel_id, Bytes::from(out_stream.data)))
+}
+
+pub fn send_to_renet_server(
+    client: &mut RenetClient,
+    command: impl ClientToServer,
+    version: ProtocolVersion,
+) -> Result<()> {
+    let (channel_id, message) = encode_renet_message(&command.into(), version)?;
+    client.send_message(channel_id, message);
+    Ok(())
+}
+
+pub fn send_to_renet_client(
+    server: &mut RenetServer,
+    client_id: ClientId,
+    command: impl ServerToClient,
+    version: ProtocolVersion,
+) -> Result<()> {
+    let (channel_id, message) = encode_renet_message(&command.into(), version)?;
+    server.send_message(client_id, channel_id, message);
+    Ok(())
+}
+
+/// Decodes the received messages of the reliable ordered channel, followed by the ones of the
+/// unreliable channel. A message that fails to decode does not stop the others.
+pub fn receive_from_renet_server(
+    client: &mut RenetClient,
+    version: ProtocolVersion,
+) -> Vec<Result<ClientReceiveCommand>> {
+    let mut commands = Vec::new();
+    for channel_id in [
+        RENET_RELIABLE_ORDERED_CHANNEL_ID,
+        RENET_UNRELIABLE_CHANNEL_ID,
+    ] {
+        while let Some(message) = client.receive_message(channel_id) {
+            commands.push(ClientReceiveCommand::decode_exact(&message, version));
+        }
+    }
+    commands
+}
+
+/// Like [`receive_from_renet_server`], for the messages from `client_id`
+pub fn receive_from_renet_client(
+    server: &mut RenetServer,
+    client_id: ClientId,
+    version: ProtocolVersion,
+) -> Vec<Result<ServerReceiveCommand>> {
+    let mut commands = Vec::new();
+    for channel_id in [
+        RENET_RELIABLE_ORDERED_CHANNEL_ID,
+        RENET_UNRELIABLE_CHANNEL_ID,
+    ] {
+        while let Some(message) = server.receive_message(client_id, channel_id) {
+            commands.push(ServerReceiveCommand::decode_exact(&message, version));
+        }
+    }
+    commands
+}
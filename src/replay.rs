@@ -0,0 +1,306 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Append-only recording of the commands of a session, for later analysis.
+//!
+//! A replay starts with a [`ReplayHeader`]: [`REPLAY_MAGIC`], the protocol version the commands
+//! are serialized with and the [`PROTOCOL_FINGERPRINT`] of the recording build. Each record
+//! that follows is:
+//!
+//! * the time since the previous record, or since the start for the first one, in
+//!   microseconds as a varint
+//! * the [`ReplayDirection`] octet
+//! * the length of the command as a varint, and the command with its command type id
+
+use std::io::{Error, ErrorKind, Result, Write};
+use std::time::Duration;
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::{at_offset, in_field};
+use crate::varint::{read_varint, write_varint};
+use crate::{
+    ClientReceiveCommand, ProtocolVersion, ServerReceiveCommand, SliceReader, PROTOCOL_FINGERPRINT,
+};
+
+pub const REPLAY_MAGIC: [u8; 4] = *b"CRPL";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct ReplayHeader {
+    pub version: ProtocolVersion,
+    pub fingerprint: u64,
+}
+
+impl ReplayHeader {
+    pub const OCTET_SIZE: usize = 4 + 1 + 8;
+
+    pub fn new(version: ProtocolVersion) -> Self {
+        Self {
+            version,
+            fingerprint: PROTOCOL_FINGERPRINT,
+        }
+    }
+
+    /// If the replay was recorded with the command layouts of this build
+    pub fn matches_build(&self) -> bool {
+        self.fingerprint == PROTOCOL_FINGERPRINT
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write(&REPLAY_MAGIC)?;
+        stream.write_u8(self.version)?;
+        stream.write_u64(self.fingerprint)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        in_field(stream.read(&mut magic), "magic")?;
+        if magic != REPLAY_MAGIC {
+            return in_field(
+                Err(Error::new(ErrorKind::InvalidData, "not a replay")),
+                "magic",
+            );
+        }
+        Ok(Self {
+            version: in_field(stream.read_u8(), "version")?,
+            fingerprint: in_field(stream.read_u64(), "fingerprint")?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ReplayDirection {
+    /// Sent by a client, decoded as a [`ServerReceiveCommand`]
+    ClientToServer = 0,
+    /// Sent by the server, decoded as a [`ClientReceiveCommand`]
+    ServerToClient = 1,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ReplayCommand {
+    ClientToServer(ServerReceiveCommand),
+    ServerToClient(ClientReceiveCommand),
+}
+
+impl ReplayCommand {
+    pub fn command_type_id(&self) -> u8 {
+        match self {
+            Self::ClientToServer(command) => command.command_type_id(),
+            Self::ServerToClient(command) => command.command_type_id(),
+        }
+    }
+
+    pub fn direction(&self) -> ReplayDirection {
+        match self {
+            Self::ClientToServer(_) => ReplayDirection::ClientToServer,
+            Self::ServerToClient(_) => ReplayDirection::ServerToClient,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ReplayRecord {
+    /// Since the start of the recording
+    pub timestamp: Duration,
+    /// Since the previous record the reader returned, or since the start for the first one,
+    /// i.e. how long to wait before sending the command when playing back at the original pace
+    pub delay: Duration,
+    pub command: ReplayCommand,
+}
+
+/// Writes the header on creation and a record per command
+pub struct ReplayWriter<W: Write> {
+    writer: W,
+    version: ProtocolVersion,
+    last_timestamp: Duration,
+}
+
+impl<W: Write> ReplayWriter<W> {
+    pub fn new(mut writer: W, version: ProtocolVersion) -> Result<Self> {
+        let mut out_stream = OutOctetStream::new();
+        ReplayHeader::new(version).to_octets(&mut out_stream)?;
+        writer.write_all(&out_stream.data)?;
+        Ok(Self {
+            writer,
+            version,
+            last_timestamp: Duration::ZERO,
+        })
+    }
+
+    /// `timestamp` is the time since the start of the recording, and can not be before the
+    /// previous record
+    pub fn record(&mut self, timestamp: Duration, command: &ReplayCommand) -> Result<()> {
+        let delta = timestamp.checked_sub(self.last_timestamp).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "timestamp {:?} is before the previous record at {:?}",
+                    timestamp, self.last_timestamp
+                ),
+            )
+        })?;
+        let mut encoded = OutOctetStream::new();
+        match command {
+            ReplayCommand::ClientToServer(command) => {
+                command.to_octets_with_version(&mut encoded, self.version)?
+            }
+            ReplayCommand::ServerToClient(command) => {
+                command.to_octets_with_version(&mut encoded, self.version)?
+            }
+        }
+        let mut out_stream = OutOctetStream::new();
+        write_varint(
+            &mut out_stream,
+            delta.as_micros().min(u64::MAX as u128) as u64,
+        )?;
+        out_stream.write_u8(command.direction() as u8)?;
+        write_varint(&mut out_stream, encoded.data.len() as u64)?;
+        out_stream.write(&encoded.data)?;
+        self.writer.write_all(&out_stream.data)?;
+        self.last_timestamp = timestamp;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Iterates the records of a replay. The first failure, including a truncated last record,
+/// ends the iteration.
+///
+/// Records can be filtered by direction and command type id. Filtered records are skipped
+/// without being decoded, so they can not fail the iteration.
+pub struct ReplayReader<'a> {
+    reader: SliceReader<'a>,
+    header: ReplayHeader,
+    timestamp: Duration,
+    last_returned_timestamp: Duration,
+    direction: Option<ReplayDirection>,
+    command_type_ids: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl<'a> ReplayReader<'a> {
+    /// Reads the header. The commands are decoded with the version in it, also for a replay
+    /// that does not [`ReplayHeader::matches_build`].
+    pub fn new(octets: &'a [u8]) -> Result<Self> {
+        let mut reader = SliceReader::new(octets);
+        let result = ReplayHeader::from_cursor(&mut reader);
+        let header = at_offset(result, reader.last_read_position())?;
+        Ok(Self {
+            reader,
+            header,
+            timestamp: Duration::ZERO,
+            last_returned_timestamp: Duration::ZERO,
+            direction: None,
+            command_type_ids: None,
+            done: false,
+        })
+    }
+
+    /// Only returns the records sent in `direction`
+    pub fn with_direction(mut self, direction: ReplayDirection) -> Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    /// Only returns the records with one of `command_type_ids`
+    pub fn with_command_type_ids(mut self, command_type_ids: &[u8]) -> Self {
+        self.command_type_ids = Some(command_type_ids.to_vec());
+        self
+    }
+
+    pub fn header(&self) -> ReplayHeader {
+        self.header
+    }
+
+    fn is_included(&self, direction: u8, command_type_id: Option<u8>) -> bool {
+        let direction_included = self
+            .direction
+            .is_none_or(|included| included as u8 == direction);
+        let command_type_included = match &self.command_type_ids {
+            Some(command_type_ids) => {
+                command_type_id.is_some_and(|type_id| command_type_ids.contains(&type_id))
+            }
+            None => true,
+        };
+        direction_included && command_type_included
+    }
+
+    /// Reads records until one is included by the filters, `None` at the end of the replay
+    fn read_included_record(&mut self) -> Result<Option<ReplayRecord>> {
+        while self.reader.remaining() != 0 {
+            let delta = in_field(read_varint(&mut self.reader), "timestamp_delta")?;
+            self.timestamp += Duration::from_micros(delta);
+            let direction = in_field(self.reader.read_u8(), "direction")?;
+            let length = in_field(read_varint(&mut self.reader), "command.len")? as usize;
+            in_field(self.reader.require(length), "command")?;
+            let command_type_id = if length == 0 {
+                None
+            } else {
+                self.reader.peek_u8()
+            };
+            if !self.is_included(direction, command_type_id) {
+                self.reader.skip(length)?;
+                continue;
+            }
+            let command = self.read_command(direction, length)?;
+            let delay = self.timestamp - self.last_returned_timestamp;
+            self.last_returned_timestamp = self.timestamp;
+            return Ok(Some(ReplayRecord {
+                timestamp: self.timestamp,
+                delay,
+                command,
+            }));
+        }
+        Ok(None)
+    }
+
+    fn read_command(&mut self, direction: u8, length: usize) -> Result<ReplayCommand> {
+        let end = self.reader.position() + length;
+        let version = self.header.version;
+        let command = match direction {
+            0 => ServerReceiveCommand::from_cursor_with_version(&mut self.reader, version)
+                .map(ReplayCommand::ClientToServer),
+            1 => ClientReceiveCommand::from_octets_with_version(&mut self.reader, version)
+                .map(ReplayCommand::ServerToClient),
+            _ => in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown direction {}", direction),
+                )),
+                "direction",
+            ),
+        }?;
+        if self.reader.position() != end {
+            return in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("command does not fill its {} octets", length),
+                )),
+                "command",
+            );
+        }
+        Ok(command)
+    }
+}
+
+impl Iterator for ReplayReader<'_> {
+    type Item = Result<ReplayRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_included_record();
+        let result = at_offset(result, self.reader.last_read_position());
+        self.done = !matches!(result, Ok(Some(_)));
+        result.transpose()
+    }
+}
@@ -0,0 +1,160 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Log replication in the style of Raft `AppendEntries`, for rooms that keep a replicated log.
+//!
+//! The leader sends [`ReplicateCommand`]s with the entries that follow `prev_knowledge`, through
+//! the Server, and every member answers with a [`ReplicateAckCommand`]. The entries are opaque
+//! to this crate.
+
+use std::io::{Error, ErrorKind, Result};
+
+use conclave_room::{Knowledge, Term};
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::payload::{read_payload, write_payload, MAX_PAYLOAD_OCTETS};
+use crate::term::check_known_term;
+use crate::{ProtocolVersion, PROTOCOL_VERSION_12};
+
+pub const REPLICATE_COMMAND_TYPE_ID: u8 = 0x29;
+pub const REPLICATE_ACK_COMMAND_TYPE_ID: u8 = 0x2A;
+
+/// One entry of the replicated log
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LogEntry {
+    /// At most [`MAX_PAYLOAD_OCTETS`]
+    pub payload: Vec<u8>,
+}
+
+impl LogEntry {
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(
+            write_payload(stream, &self.payload, MAX_PAYLOAD_OCTETS),
+            "payload",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Ok(Self {
+            payload: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "payload")?,
+        })
+    }
+}
+
+/// Sent from the leader to the Server, and from the Server to the other Clients, with the
+/// entries following `prev_knowledge`. Without entries it is a heartbeat of the leader.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplicateCommand {
+    pub term: Term,
+    /// The knowledge of the entry before the first one in `entries`
+    pub prev_knowledge: Knowledge,
+    /// In log order, the first one has the knowledge right after `prev_knowledge`. At most
+    /// `u8::MAX`.
+    pub entries: Vec<LogEntry>,
+}
+
+impl ReplicateCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "replicate";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_12;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 8 + 1
+    }
+
+    /// The knowledge of the last entry, `prev_knowledge` if there are none
+    pub fn last_knowledge(&self) -> Knowledge {
+        self.prev_knowledge
+            .saturating_add(self.entries.len() as Knowledge)
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(check_known_term(self.term).map_err(Error::from), "term")?;
+        if self.entries.len() > u8::MAX as usize {
+            return in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{} entries, at most {} supported",
+                        self.entries.len(),
+                        u8::MAX
+                    ),
+                )),
+                "entries.len",
+            );
+        }
+        stream.write_u16(self.term)?;
+        stream.write_u64(self.prev_knowledge)?;
+        stream.write_u8(self.entries.len() as u8)?;
+        for (index, entry) in self.entries.iter().enumerate() {
+            in_field(entry.to_octets(stream), format_args!("entries[{}]", index))?;
+        }
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let term = in_field(stream.read_u16(), "term")?;
+        in_field(check_known_term(term).map_err(Error::from), "term")?;
+        let prev_knowledge = in_field(stream.read_u64(), "prev_knowledge")?;
+        let length = in_field(stream.read_u8(), "entries.len")?;
+        let mut entries = Vec::with_capacity(length as usize);
+        for index in 0..length {
+            entries.push(in_field(
+                LogEntry::from_cursor(stream),
+                format_args!("entries[{}]", index),
+            )?);
+        }
+        Ok(Self {
+            term,
+            prev_knowledge,
+            entries,
+        })
+    }
+}
+
+/// Sent as the answer to a [`ReplicateCommand`], from a Client to the Server, and from the
+/// Server to the leader
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplicateAckCommand {
+    /// The term of the member that answers, higher than the one of the leader if it is stale
+    pub term: Term,
+    /// The knowledge of the last entry the member has in its log
+    pub knowledge: Knowledge,
+    /// `false` if the log of the member had no entry at `prev_knowledge`, so the leader must
+    /// replicate from an earlier one
+    pub success: bool,
+}
+
+impl ReplicateAckCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "replicate_ack";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_12;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 8 + 1
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(check_known_term(self.term).map_err(Error::from), "term")?;
+        stream.write_u16(self.term)?;
+        stream.write_u64(self.knowledge)?;
+        stream.write_u8(if self.success { 0x01 } else { 0x00 })
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let term = in_field(stream.read_u16(), "term")?;
+        in_field(check_known_term(term).map_err(Error::from), "term")?;
+        Ok(Self {
+            term,
+            knowledge: in_field(stream.read_u64(), "knowledge")?,
+            success: in_field(stream.read_u8(), "success")? != 0,
+        })
+    }
+}
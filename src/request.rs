@@ -0,0 +1,148 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Request and response wrappers built on the correlation envelope.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::correlation::{read_envelope, write_envelope};
+use crate::{Command, CorrelationId, ProtocolVersion, DEFAULT_PROTOCOL_VERSION};
+
+fn read_correlation_id(stream: &mut impl ReadOctetStream) -> Result<CorrelationId> {
+    read_envelope(stream)?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            "envelope is missing the correlation id",
+        )
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Request<T> {
+    pub correlation_id: CorrelationId,
+    pub command: T,
+}
+
+impl<T: Command> Request<T> {
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        write_envelope(Some(self.correlation_id), stream)?;
+        self.command.to_octets_with_version(stream, version)
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Ok(Self {
+            correlation_id: read_correlation_id(stream)?,
+            command: T::from_octets_with_version(stream, version)?,
+        })
+    }
+
+    pub fn from_octets(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response<T> {
+    pub correlation_id: CorrelationId,
+    pub command: T,
+}
+
+impl<T: Command> Response<T> {
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        write_envelope(Some(self.correlation_id), stream)?;
+        self.command.to_octets_with_version(stream, version)
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        Ok(Self {
+            correlation_id: read_correlation_id(stream)?,
+            command: T::from_octets_with_version(stream, version)?,
+        })
+    }
+
+    pub fn from_octets(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    /// Creates the response to `request`, reusing its correlation id.
+    pub fn to<R>(request: &Request<R>, command: T) -> Self {
+        Self {
+            correlation_id: request.correlation_id,
+            command,
+        }
+    }
+}
+
+/// Hands out correlation ids and pairs incoming responses with the pending requests.
+///
+/// `P` is whatever the caller needs to remember about a request until its response arrives.
+#[derive(Debug)]
+pub struct ResponseMatcher<P> {
+    next_correlation_id: CorrelationId,
+    pending: HashMap<CorrelationId, P>,
+}
+
+impl<P> Default for ResponseMatcher<P> {
+    fn default() -> Self {
+        Self {
+            next_correlation_id: 0,
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl<P> ResponseMatcher<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn request<T>(&mut self, command: T, pending: P) -> Request<T> {
+        let correlation_id = self.next_correlation_id;
+        self.next_correlation_id = self.next_correlation_id.wrapping_add(1);
+        self.pending.insert(correlation_id, pending);
+        Request {
+            correlation_id,
+            command,
+        }
+    }
+
+    /// Returns the pending value for the request, or `None` if the response is unsolicited
+    /// or a duplicate.
+    pub fn on_response<T>(&mut self, response: &Response<T>) -> Option<P> {
+        self.pending.remove(&response.correlation_id)
+    }
+
+    pub fn cancel(&mut self, correlation_id: CorrelationId) -> Option<P> {
+        self.pending.remove(&correlation_id)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+}
@@ -0,0 +1,146 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Stateless retry: before allocating any state for a join, the server answers with a
+//! `RetryCookieCommand` and only accepts the join when it comes back in a
+//! `ConnectWithCookieCommand`, which proves the client receives datagrams at its address.
+//!
+//! Minting and verifying cookies needs the `cookie` feature.
+
+use std::io::Result;
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{JoinRequestCommand, ProtocolVersion, DEFAULT_PROTOCOL_VERSION, PROTOCOL_VERSION_6};
+
+pub const RETRY_COOKIE_OCTETS: usize = 16;
+/// Cookies are accepted during the period they were minted in and the one after it
+pub const RETRY_COOKIE_PERIOD_S: u64 = 30;
+pub const RETRY_COOKIE_COMMAND_TYPE_ID: u8 = 0x1B;
+pub const CONNECT_WITH_COOKIE_COMMAND_TYPE_ID: u8 = 0x1C;
+
+#[cfg(feature = "cookie")]
+fn cookie_for(
+    secret: &[u8],
+    address: &std::net::SocketAddr,
+    period: u64,
+) -> [u8; RETRY_COOKIE_OCTETS] {
+    use hmac::Mac;
+
+    let mut message = flood_rs::OutOctetStream::new();
+    message.write_u64(period).expect("writes to memory");
+    crate::connect_token::write_socket_addr(&mut message, address).expect("writes to memory");
+    // HMAC accepts keys of any length
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret).expect("any key length");
+    mac.update(&message.data);
+    let mut cookie = [0u8; RETRY_COOKIE_OCTETS];
+    cookie.copy_from_slice(&mac.finalize().into_bytes()[..RETRY_COOKIE_OCTETS]);
+    cookie
+}
+
+/// Sent from Server to Client instead of accepting a `JoinRequestCommand`
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryCookieCommand {
+    pub cookie: [u8; RETRY_COOKIE_OCTETS],
+}
+
+impl RetryCookieCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "retry_cookie";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        RETRY_COOKIE_OCTETS
+    }
+
+    /// A cookie for `address`, with `now_s` in seconds since the Unix epoch
+    #[cfg(feature = "cookie")]
+    pub fn mint(secret: &[u8], address: &std::net::SocketAddr, now_s: u64) -> Self {
+        Self {
+            cookie: cookie_for(secret, address, now_s / RETRY_COOKIE_PERIOD_S),
+        }
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write(&self.cookie)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let mut cookie = [0u8; RETRY_COOKIE_OCTETS];
+        in_field(stream.read(&mut cookie), "cookie")?;
+        Ok(Self { cookie })
+    }
+}
+
+/// Sent from Client to Server, the `JoinRequestCommand` again with the cookie from the
+/// `RetryCookieCommand`
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectWithCookieCommand {
+    pub cookie: [u8; RETRY_COOKIE_OCTETS],
+    pub join_request: JoinRequestCommand,
+}
+
+impl ConnectWithCookieCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "connect_with_cookie";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        RETRY_COOKIE_OCTETS + JoinRequestCommand::min_octet_size(version)
+    }
+
+    /// `true` if the cookie was minted with `secret` for `address` in the current or the
+    /// previous period. Compares in constant time.
+    #[cfg(feature = "cookie")]
+    pub fn verify(&self, secret: &[u8], address: &std::net::SocketAddr, now_s: u64) -> bool {
+        let period = now_s / RETRY_COOKIE_PERIOD_S;
+        let mut matches = 0u8;
+        for candidate in [Some(period), period.checked_sub(1)].into_iter().flatten() {
+            let expected = cookie_for(secret, address, candidate);
+            let difference = expected
+                .iter()
+                .zip(self.cookie.iter())
+                .fold(0u8, |difference, (a, b)| difference | (a ^ b));
+            matches |= (difference == 0) as u8;
+        }
+        matches != 0
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        stream.write(&self.cookie)?;
+        self.join_request.to_octets_with_version(stream, version)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        let mut cookie = [0u8; RETRY_COOKIE_OCTETS];
+        in_field(stream.read(&mut cookie), "cookie")?;
+        Ok(Self {
+            cookie,
+            join_request: in_field(
+                JoinRequestCommand::from_cursor_with_version(stream, version),
+                "join_request",
+            )?,
+        })
+    }
+}
@@ -0,0 +1,117 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Room lifecycle events, for servers that keep an audit trail of their rooms.
+//!
+//! An event is a tag octet followed by its fields. [`RoomEvent`] implements [`Command`], so a
+//! log can be written with [`crate::write_frame`] and read back with [`crate::FrameDecoder`].
+
+use std::io::{Error, ErrorKind, Result};
+
+use conclave_room::Term;
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::{ClientIdentity, Command, ConnectionIndex, ProtocolVersion};
+
+pub const ROOM_EVENT_LEADER_ELECTED: u8 = 0x01;
+pub const ROOM_EVENT_CLIENT_JOINED: u8 = 0x02;
+pub const ROOM_EVENT_CLIENT_LOST: u8 = 0x03;
+pub const ROOM_EVENT_TERM_ADVANCED: u8 = 0x04;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RoomEvent {
+    LeaderElected {
+        term: Term,
+        leader_index: ConnectionIndex,
+    },
+    ClientJoined {
+        connection_index: ConnectionIndex,
+        identity: ClientIdentity,
+    },
+    /// The connection timed out or was closed
+    ClientLost {
+        connection_index: ConnectionIndex,
+    },
+    TermAdvanced {
+        term: Term,
+    },
+}
+
+impl RoomEvent {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::LeaderElected { .. } => ROOM_EVENT_LEADER_ELECTED,
+            Self::ClientJoined { .. } => ROOM_EVENT_CLIENT_JOINED,
+            Self::ClientLost { .. } => ROOM_EVENT_CLIENT_LOST,
+            Self::TermAdvanced { .. } => ROOM_EVENT_TERM_ADVANCED,
+        }
+    }
+}
+
+/// The version is the one the identities are serialized with
+impl Command for RoomEvent {
+    fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        stream.write_u8(self.tag())?;
+        match self {
+            Self::LeaderElected { term, leader_index } => {
+                stream.write_u16(*term)?;
+                leader_index.to_octets(stream)
+            }
+            Self::ClientJoined {
+                connection_index,
+                identity,
+            } => {
+                connection_index.to_octets(stream)?;
+                in_field(identity.to_octets_with_version(stream, version), "identity")
+            }
+            Self::ClientLost { connection_index } => connection_index.to_octets(stream),
+            Self::TermAdvanced { term } => stream.write_u16(*term),
+        }
+    }
+
+    fn from_octets_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        let tag = in_field(stream.read_u8(), "tag")?;
+        match tag {
+            ROOM_EVENT_LEADER_ELECTED => Ok(Self::LeaderElected {
+                term: in_field(stream.read_u16(), "term")?,
+                leader_index: in_field(ConnectionIndex::from_cursor(stream), "leader_index")?,
+            }),
+            ROOM_EVENT_CLIENT_JOINED => Ok(Self::ClientJoined {
+                connection_index: in_field(
+                    ConnectionIndex::from_cursor(stream),
+                    "connection_index",
+                )?,
+                identity: in_field(
+                    ClientIdentity::from_cursor_with_version(stream, version),
+                    "identity",
+                )?,
+            }),
+            ROOM_EVENT_CLIENT_LOST => Ok(Self::ClientLost {
+                connection_index: in_field(
+                    ConnectionIndex::from_cursor(stream),
+                    "connection_index",
+                )?,
+            }),
+            ROOM_EVENT_TERM_ADVANCED => Ok(Self::TermAdvanced {
+                term: in_field(stream.read_u16(), "term")?,
+            }),
+            _ => in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown room event 0x{:x}", tag),
+                )),
+                "tag",
+            ),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use conclave_room::Term;
+
+use crate::term::check_known_term;
+use crate::{
+    ClientInfo, ConnectionIndex, KnowledgeVector, ProtocolError, RoomInfoCommand, MAX_CLIENT_INFOS,
+};
+
+/// Builds a [`RoomInfoCommand`] that is guaranteed to pass [`RoomInfoCommand::validate`], with
+/// a term other than [`crate::TERM_UNKNOWN`], as the server sends the authoritative term.
+#[derive(Debug, Default)]
+pub struct RoomInfoCommandBuilder {
+    term: Term,
+    leader_index: ConnectionIndex,
+    client_infos: Vec<ClientInfo>,
+    knowledge_vector: Option<KnowledgeVector>,
+    quorum_size: u8,
+    votes_for_leader: u8,
+    leader_lease_expires_in_ms: u32,
+}
+
+impl RoomInfoCommandBuilder {
+    pub fn term(mut self, term: Term) -> Self {
+        self.term = term;
+        self
+    }
+
+    /// The connection index of the leader, which must be one of the clients
+    pub fn leader_index(mut self, leader_index: ConnectionIndex) -> Self {
+        self.leader_index = leader_index;
+        self
+    }
+
+    pub fn client(mut self, client_info: ClientInfo) -> Self {
+        self.client_infos.push(client_info);
+        self
+    }
+
+    pub fn clients(mut self, client_infos: impl IntoIterator<Item = ClientInfo>) -> Self {
+        self.client_infos.extend(client_infos);
+        self
+    }
+
+    pub fn knowledge_vector(mut self, knowledge_vector: KnowledgeVector) -> Self {
+        self.knowledge_vector = Some(knowledge_vector);
+        self
+    }
+
+    /// The members needed to elect a leader, and how many voted for the current one
+    pub fn election(mut self, quorum_size: u8, votes_for_leader: u8) -> Self {
+        self.quorum_size = quorum_size;
+        self.votes_for_leader = votes_for_leader;
+        self
+    }
+
+    /// Saturates `expires_in` to the serialized range
+    pub fn leader_lease_expires_in(mut self, expires_in: std::time::Duration) -> Self {
+        self.leader_lease_expires_in_ms = expires_in.as_millis().min(u32::MAX as u128) as u32;
+        self
+    }
+
+    pub fn build(self) -> Result<RoomInfoCommand, ProtocolError> {
+        check_known_term(self.term)?;
+        if self.client_infos.len() > MAX_CLIENT_INFOS {
+            return Err(ProtocolError::TooManyClients {
+                count: self.client_infos.len(),
+                max: MAX_CLIENT_INFOS,
+            });
+        }
+        let room_info = RoomInfoCommand {
+            term: self.term,
+            leader_index: self.leader_index,
+            client_infos: self.client_infos.into_iter().collect(),
+            knowledge_vector: self.knowledge_vector,
+            quorum_size: self.quorum_size,
+            votes_for_leader: self.votes_for_leader,
+            leader_lease_expires_in_ms: self.leader_lease_expires_in_ms,
+        };
+        room_info.validate()?;
+        Ok(room_info)
+    }
+}
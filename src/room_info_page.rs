@@ -0,0 +1,396 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! `RoomInfoCommand` split into pages, for rooms with too many clients for a single datagram.
+//!
+//! The Server sends [`RoomInfoPageCommand`]s from [`RoomInfoPageCommand::pages`], and the
+//! Client merges them back into a `RoomInfoCommand` with a [`RoomInfoPageAccumulator`].
+//!
+//! From [`PROTOCOL_VERSION_13`] every page ends with flags, the election info and the leader
+//! lease of the room info, and the first page can carry its knowledge vector. Before, the merged
+//! room info has none of them.
+
+use std::io::{Error, ErrorKind, Result};
+
+use conclave_room::Term;
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::membership_sync::check_list_len;
+use crate::{
+    reserve_client_infos, ClientInfo, ClientInfos, ConnectionIndex, KnowledgeVector,
+    ProtocolVersion, RoomInfoCommand, DEFAULT_PROTOCOL_VERSION, PROTOCOL_VERSION_13,
+    PROTOCOL_VERSION_6, ROOM_INFO_FLAG_KNOWLEDGE_VECTOR,
+};
+
+pub const ROOM_INFO_PAGE_COMMAND_TYPE_ID: u8 = 0x21;
+
+pub(crate) fn check_page_index(page_index: u8, page_count: u8) -> Result<()> {
+    if page_index >= page_count {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "page index {} is not below page count {}",
+                page_index, page_count
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Sent from Server to Client, one page of the client infos of a `RoomInfoCommand`
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoomInfoPageCommand {
+    pub term: Term,
+    /// The connection index of the leader, as in [`crate::RoomInfoCommand::leader_index`]
+    pub leader_index: ConnectionIndex,
+    pub page_index: u8,
+    pub page_count: u8,
+    pub client_infos: ClientInfos,
+    /// The knowledge vector of the room info, only carried by the first page. Not serialized
+    /// before [`PROTOCOL_VERSION_13`].
+    pub knowledge_vector: Option<KnowledgeVector>,
+    /// Same on every page. Not serialized before [`PROTOCOL_VERSION_13`].
+    pub quorum_size: u8,
+    /// Same on every page. Not serialized before [`PROTOCOL_VERSION_13`].
+    pub votes_for_leader: u8,
+    /// As of sending the page, so it may differ between pages. Not serialized before
+    /// [`PROTOCOL_VERSION_13`].
+    pub leader_lease_expires_in_ms: u32,
+}
+
+impl RoomInfoPageCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "room_info_page";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_6;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(version: ProtocolVersion) -> usize {
+        if version >= PROTOCOL_VERSION_13 {
+            2 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 4
+        } else {
+            2 + 1 + 1 + 1 + 1
+        }
+    }
+
+    /// Splits the client infos of `room_info` into pages of at most `max_page_octets` each,
+    /// counting the command type id. A room without clients still results in a single page.
+    /// The knowledge vector goes on the first page, the election info and leader lease on every
+    /// page.
+    pub fn pages(
+        room_info: &RoomInfoCommand,
+        max_page_octets: usize,
+        version: ProtocolVersion,
+    ) -> Result<Vec<Self>> {
+        let empty_page_octets = 1 + Self::min_octet_size(version);
+        let knowledge_vector = room_info
+            .knowledge_vector
+            .as_ref()
+            .filter(|_| version >= PROTOCOL_VERSION_13);
+        let mut knowledge_vector_octets = 0;
+        if let Some(knowledge_vector) = knowledge_vector {
+            let mut out_stream = OutOctetStream::new();
+            in_field(
+                knowledge_vector.to_octets(&mut out_stream),
+                "knowledge_vector",
+            )?;
+            knowledge_vector_octets = out_stream.data.len();
+            if empty_page_octets + knowledge_vector_octets > max_page_octets {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "{} octets do not fit in a page of {} octets",
+                            knowledge_vector_octets, max_page_octets
+                        ),
+                    )),
+                    "knowledge_vector",
+                );
+            }
+        }
+        let mut page_ranges = Vec::new();
+        let mut page_start = 0;
+        let mut page_octets = empty_page_octets + knowledge_vector_octets;
+        for (index, client_info) in room_info.client_infos.iter().enumerate() {
+            let mut out_stream = OutOctetStream::new();
+            client_info.to_octets_with_version(&mut out_stream, version)?;
+            let client_info_octets = out_stream.data.len();
+            if empty_page_octets + client_info_octets > max_page_octets {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "{} octets do not fit in a page of {} octets",
+                            client_info_octets, max_page_octets
+                        ),
+                    )),
+                    format_args!("client_infos[{}]", index),
+                );
+            }
+            if page_octets + client_info_octets > max_page_octets {
+                page_ranges.push(page_start..index);
+                page_start = index;
+                page_octets = empty_page_octets;
+            }
+            page_octets += client_info_octets;
+        }
+        page_ranges.push(page_start..room_info.client_infos.len());
+        if page_ranges.len() > u8::MAX as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("room info needs too many pages ({})", page_ranges.len()),
+            ));
+        }
+
+        let page_count = page_ranges.len() as u8;
+        Ok(page_ranges
+            .into_iter()
+            .enumerate()
+            .map(|(page_index, range)| Self {
+                term: room_info.term,
+                leader_index: room_info.leader_index,
+                page_index: page_index as u8,
+                page_count,
+                client_infos: room_info
+                    .client_infos
+                    .iter()
+                    .skip(range.start)
+                    .take(range.len())
+                    .cloned()
+                    .collect(),
+                knowledge_vector: knowledge_vector.filter(|_| page_index == 0).cloned(),
+                quorum_size: room_info.quorum_size,
+                votes_for_leader: room_info.votes_for_leader,
+                leader_lease_expires_in_ms: room_info.leader_lease_expires_in_ms,
+            })
+            .collect())
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        self.to_octets_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        stream.write_u16(self.term)?;
+        stream.write_u8(self.page_index)?;
+        stream.write_u8(self.page_count)?;
+        check_list_len(self.client_infos.len(), "client_infos")?;
+        stream.write_u8(self.client_infos.len() as u8)?;
+        for client_info in self.client_infos.iter() {
+            client_info.to_octets_with_version(stream, version)?;
+        }
+        self.leader_index.to_octets(stream)?;
+        if version >= PROTOCOL_VERSION_13 {
+            let mut flags = 0;
+            if self.knowledge_vector.is_some() {
+                flags |= ROOM_INFO_FLAG_KNOWLEDGE_VECTOR;
+            }
+            stream.write_u8(flags)?;
+            if let Some(knowledge_vector) = &self.knowledge_vector {
+                in_field(knowledge_vector.to_octets(stream), "knowledge_vector")?;
+            }
+            stream.write_u8(self.quorum_size)?;
+            stream.write_u8(self.votes_for_leader)?;
+            stream.write_u32(self.leader_lease_expires_in_ms)?;
+        }
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        Self::from_cursor_with_version(stream, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        let term = in_field(stream.read_u16(), "term")?;
+        let page_index = in_field(stream.read_u8(), "page_index")?;
+        let page_count = in_field(stream.read_u8(), "page_count")?;
+        in_field(check_page_index(page_index, page_count), "page_index")?;
+        let length = in_field(stream.read_u8(), "client_infos.len")? as usize;
+        let mut client_infos = ClientInfos::new();
+        in_field(
+            reserve_client_infos(&mut client_infos, length),
+            "client_infos.len",
+        )?;
+        for index in 0..length {
+            client_infos.push(in_field(
+                ClientInfo::from_cursor_with_version(stream, version),
+                format_args!("client_infos[{}]", index),
+            )?);
+        }
+        let mut page = Self {
+            term,
+            leader_index: in_field(ConnectionIndex::from_cursor(stream), "leader_index")?,
+            page_index,
+            page_count,
+            client_infos,
+            ..Self::default()
+        };
+        if version >= PROTOCOL_VERSION_13 {
+            let flags = in_field(stream.read_u8(), "flags")?;
+            if flags & !ROOM_INFO_FLAG_KNOWLEDGE_VECTOR != 0 {
+                return in_field(
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "unknown room info page flags 0x{:02x}",
+                            flags & !ROOM_INFO_FLAG_KNOWLEDGE_VECTOR
+                        ),
+                    )),
+                    "flags",
+                );
+            }
+            if flags & ROOM_INFO_FLAG_KNOWLEDGE_VECTOR != 0 {
+                page.knowledge_vector = Some(in_field(
+                    KnowledgeVector::from_cursor(stream),
+                    "knowledge_vector",
+                )?);
+            }
+            page.quorum_size = in_field(stream.read_u8(), "quorum_size")?;
+            page.votes_for_leader = in_field(stream.read_u8(), "votes_for_leader")?;
+            page.leader_lease_expires_in_ms =
+                in_field(stream.read_u32(), "leader_lease_expires_in_ms")?;
+        }
+        Ok(page)
+    }
+}
+
+struct PendingRoomInfo {
+    term: Term,
+    leader_index: ConnectionIndex,
+    knowledge_vector: Option<KnowledgeVector>,
+    quorum_size: u8,
+    votes_for_leader: u8,
+    leader_lease_expires_in_ms: u32,
+    pages: Vec<Option<ClientInfos>>,
+    received_count: usize,
+}
+
+/// Merges `RoomInfoPageCommand`s, arriving in any order, into complete `RoomInfoCommand`s.
+///
+/// Only the pages of one term are collected at a time. A page of another term discards the
+/// pages received so far, since the room has changed since they were sent.
+#[derive(Default)]
+pub struct RoomInfoPageAccumulator {
+    pending: Option<PendingRoomInfo>,
+}
+
+impl RoomInfoPageAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the room info once the last missing page is received. Duplicate pages are
+    /// ignored, a page count, leader index or election info that differs from earlier pages of
+    /// the term is an error, as is a knowledge vector on any but the first page. The merged room
+    /// info has the leader lease of the last page received, and must pass
+    /// [`RoomInfoCommand::validate`].
+    pub fn receive(&mut self, page: RoomInfoPageCommand) -> Result<Option<RoomInfoCommand>> {
+        check_page_index(page.page_index, page.page_count)?;
+        let pending = match &mut self.pending {
+            Some(pending) if pending.term == page.term => pending,
+            pending => pending.insert(PendingRoomInfo {
+                term: page.term,
+                leader_index: page.leader_index,
+                knowledge_vector: None,
+                quorum_size: page.quorum_size,
+                votes_for_leader: page.votes_for_leader,
+                leader_lease_expires_in_ms: page.leader_lease_expires_in_ms,
+                pages: vec![None; page.page_count as usize],
+                received_count: 0,
+            }),
+        };
+        if pending.pages.len() != page.page_count as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "term {} page count changed from {} to {}",
+                    page.term,
+                    pending.pages.len(),
+                    page.page_count
+                ),
+            ));
+        }
+        if pending.leader_index != page.leader_index {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "term {} leader index changed from {} to {}",
+                    page.term, pending.leader_index, page.leader_index
+                ),
+            ));
+        }
+        if (pending.quorum_size, pending.votes_for_leader)
+            != (page.quorum_size, page.votes_for_leader)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "term {} election info changed from {}/{} to {}/{} votes",
+                    page.term,
+                    pending.votes_for_leader,
+                    pending.quorum_size,
+                    page.votes_for_leader,
+                    page.quorum_size
+                ),
+            ));
+        }
+        if page.page_index != 0 && page.knowledge_vector.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "term {} page {} carries a knowledge vector, only the first page does",
+                    page.term, page.page_index
+                ),
+            ));
+        }
+        let slot = &mut pending.pages[page.page_index as usize];
+        if slot.is_some() {
+            return Ok(None);
+        }
+        if page.page_index == 0 {
+            pending.knowledge_vector = page.knowledge_vector;
+        }
+        pending.leader_lease_expires_in_ms = page.leader_lease_expires_in_ms;
+        *slot = Some(page.client_infos);
+        pending.received_count += 1;
+        if pending.received_count < pending.pages.len() {
+            return Ok(None);
+        }
+
+        let pending = self.pending.take().expect("room info is pending");
+        let mut client_infos = ClientInfos::new();
+        for page_client_infos in pending.pages.into_iter().flatten() {
+            reserve_client_infos(&mut client_infos, page_client_infos.len())?;
+            client_infos.extend(page_client_infos);
+        }
+        let room_info = RoomInfoCommand {
+            term: pending.term,
+            leader_index: pending.leader_index,
+            client_infos,
+            knowledge_vector: pending.knowledge_vector,
+            quorum_size: pending.quorum_size,
+            votes_for_leader: pending.votes_for_leader,
+            leader_lease_expires_in_ms: pending.leader_lease_expires_in_ms,
+        };
+        room_info.validate()?;
+        Ok(Some(room_info))
+    }
+
+    /// The term of the pages received so far, if any
+    pub fn pending_term(&self) -> Option<Term> {
+        self.pending.as_ref().map(|pending| pending.term)
+    }
+
+    pub fn clear(&mut self) {
+        self.pending = None;
+    }
+}
@@ -0,0 +1,97 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use crate::{
+    ClientIdentity, RoomInfoCommand, ROLE_FLAG_HOST, ROLE_FLAG_MODERATOR, ROLE_FLAG_PLAYER,
+    ROLE_FLAG_SPECTATOR,
+};
+
+const ROLE_FLAG_NAMES: [(u16, &str); 4] = [
+    (ROLE_FLAG_HOST, "host"),
+    (ROLE_FLAG_MODERATOR, "moderator"),
+    (ROLE_FLAG_PLAYER, "player"),
+    (ROLE_FLAG_SPECTATOR, "spectator"),
+];
+
+fn identity_text(identity: &ClientIdentity) -> String {
+    match identity {
+        ClientIdentity::U64(id) => format!("u64 0x{:x}", id),
+        ClientIdentity::Uuid(id) => format!("uuid 0x{:032x}", id),
+        ClientIdentity::String(id) => format!("{:?}", id),
+    }
+}
+
+fn role_flags_text(role_flags: u16) -> String {
+    let mut names = ROLE_FLAG_NAMES
+        .iter()
+        .filter(|(flag, _)| role_flags & flag != 0)
+        .map(|(_, name)| name.to_string())
+        .collect::<Vec<_>>();
+    let unknown = ROLE_FLAG_NAMES
+        .iter()
+        .fold(role_flags, |flags, (flag, _)| flags & !flag);
+    if unknown != 0 {
+        names.push(format!("0x{:04x}", unknown));
+    }
+    if names.is_empty() {
+        "-".to_string()
+    } else {
+        names.join(",")
+    }
+}
+
+/// Renders the clients of `room_info` as an aligned table for console diagnostics, with the
+/// leader marked with `*`
+pub fn format_room_table(room_info: &RoomInfoCommand) -> String {
+    let header = ["", "connection", "identity", "roles"];
+    let rows = room_info
+        .client_infos
+        .iter()
+        .map(|client_info| {
+            [
+                if client_info.connection_index == room_info.leader_index {
+                    "*".to_string()
+                } else {
+                    String::new()
+                },
+                client_info.connection_index.to_string(),
+                identity_text(&client_info.identity),
+                role_flags_text(client_info.role_flags),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let line = |cells: &[&str]| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut table = format!(
+        "term {}, {} clients\n",
+        room_info.term,
+        room_info.client_infos.len()
+    );
+    table += &line(&header);
+    table.push('\n');
+    table += &widths.map(|width| "-".repeat(width)).join("-+-");
+    table.push('\n');
+    for row in &rows {
+        table += &line(&row.each_ref().map(String::as_str));
+        table.push('\n');
+    }
+    table
+}
@@ -0,0 +1,55 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Optional routing header, so several rooms can be multiplexed over one socket.
+//!
+//! The header is the [`RoomId`] written before the command type id.
+
+use std::io::Result;
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::{ClientReceiveCommand, ProtocolVersion, ServerReceiveCommand};
+
+pub type RoomId = u32;
+
+impl ServerReceiveCommand {
+    pub fn encode_with_room(
+        &self,
+        room_id: RoomId,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        stream.write_u32(room_id)?;
+        self.to_octets_with_version(stream, version)
+    }
+
+    pub fn decode_with_room(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<(RoomId, Self)> {
+        let room_id = stream.read_u32()?;
+        Ok((room_id, Self::from_cursor_with_version(stream, version)?))
+    }
+}
+
+impl ClientReceiveCommand {
+    pub fn encode_with_room(
+        &self,
+        room_id: RoomId,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        stream.write_u32(room_id)?;
+        self.to_octets_with_version(stream, version)
+    }
+
+    pub fn decode_with_room(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<(RoomId, Self)> {
+        let room_id = stream.read_u32()?;
+        Ok((room_id, Self::from_octets_with_version(stream, version)?))
+    }
+}
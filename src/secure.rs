@@ -0,0 +1,155 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Authenticated encryption of whole commands with XChaCha20-Poly1305.
+//!
+//! A sealed datagram is the sequence number (u64) followed by the encrypted command and the
+//! authentication tag. The nonce is derived from the sequence number, so it never travels in
+//! full and never repeats for a key as long as each direction has a key of its own.
+//!
+//! With [`SecureSession::with_truncated_sequences`] only the low 16 bits of the sequence number
+//! are sent, and the receiver reconstructs the rest from its [`SequenceWindow`].
+
+use std::io::Result;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use flood_rs::OutOctetStream;
+
+use crate::slice_reader::reject_trailing;
+use crate::{Command, ProtocolError, ProtocolVersion, SequenceWindow, SliceReader};
+
+pub const SECURE_KEY_OCTETS: usize = 32;
+pub const SECURE_TAG_OCTETS: usize = 16;
+pub const SECURE_SEQUENCE_OCTETS: usize = 8;
+pub const SECURE_TRUNCATED_SEQUENCE_OCTETS: usize = 2;
+/// What sealing adds to the serialized command
+pub const SECURE_OVERHEAD_OCTETS: usize = SECURE_SEQUENCE_OCTETS + SECURE_TAG_OCTETS;
+/// What sealing adds to the serialized command with truncated sequence numbers
+pub const SECURE_TRUNCATED_OVERHEAD_OCTETS: usize =
+    SECURE_TRUNCATED_SEQUENCE_OCTETS + SECURE_TAG_OCTETS;
+
+fn nonce_for(sequence: u64) -> XNonce {
+    let mut nonce = XNonce::default();
+    nonce[24 - SECURE_SEQUENCE_OCTETS..].copy_from_slice(&sequence.to_be_bytes());
+    nonce
+}
+
+/// Seals outgoing and opens incoming commands, with one key for each direction
+pub struct SecureSession {
+    send_cipher: XChaCha20Poly1305,
+    receive_cipher: XChaCha20Poly1305,
+    version: ProtocolVersion,
+    next_send_sequence: u64,
+    truncated_sequences: bool,
+    receive_window: SequenceWindow,
+}
+
+impl SecureSession {
+    /// The peer must use the same keys with `send_key` and `receive_key` swapped
+    pub fn new(
+        send_key: &[u8; SECURE_KEY_OCTETS],
+        receive_key: &[u8; SECURE_KEY_OCTETS],
+        version: ProtocolVersion,
+    ) -> Self {
+        Self {
+            send_cipher: XChaCha20Poly1305::new(send_key.into()),
+            receive_cipher: XChaCha20Poly1305::new(receive_key.into()),
+            version,
+            next_send_sequence: 0,
+            truncated_sequences: false,
+            receive_window: SequenceWindow::new(),
+        }
+    }
+
+    /// Sends only the low 16 bits of the sequence numbers. Both peers must agree on it.
+    pub fn with_truncated_sequences(mut self) -> Self {
+        self.truncated_sequences = true;
+        self
+    }
+
+    fn sequence_octets(&self) -> usize {
+        if self.truncated_sequences {
+            SECURE_TRUNCATED_SEQUENCE_OCTETS
+        } else {
+            SECURE_SEQUENCE_OCTETS
+        }
+    }
+
+    /// What sealing adds to the serialized command
+    pub fn overhead_octets(&self) -> usize {
+        self.sequence_octets() + SECURE_TAG_OCTETS
+    }
+
+    /// The sequence number the next sealed command will use
+    pub fn next_send_sequence(&self) -> u64 {
+        self.next_send_sequence
+    }
+
+    /// Serializes and encrypts `command`
+    pub fn seal<C: Command>(&mut self, command: &C) -> Result<Vec<u8>> {
+        let mut out_stream = OutOctetStream::new();
+        command.to_octets_with_version(&mut out_stream, self.version)?;
+
+        let sequence = self.next_send_sequence;
+        let full_header = sequence.to_be_bytes();
+        let header = &full_header[SECURE_SEQUENCE_OCTETS - self.sequence_octets()..];
+        let sealed = self
+            .send_cipher
+            .encrypt(
+                &nonce_for(sequence),
+                Payload {
+                    msg: &out_stream.data,
+                    aad: header,
+                },
+            )
+            .map_err(|_| ProtocolError::EncryptionFailed)?;
+        self.next_send_sequence = sequence
+            .checked_add(1)
+            .ok_or(ProtocolError::EncryptionFailed)?;
+
+        let mut octets = Vec::with_capacity(header.len() + sealed.len());
+        octets.extend_from_slice(header);
+        octets.extend_from_slice(&sealed);
+        Ok(octets)
+    }
+
+    /// Decrypts and decodes a datagram sealed by the peer. Datagrams that have already been
+    /// opened, or are too old for the [`SequenceWindow`], are refused.
+    pub fn open<C: Command>(&mut self, octets: &[u8]) -> Result<C> {
+        let overhead = self.overhead_octets();
+        if octets.len() < overhead {
+            return Err(ProtocolError::UnexpectedEof {
+                needed: overhead,
+                available: octets.len(),
+            }
+            .into());
+        }
+        let (header, sealed) = octets.split_at(self.sequence_octets());
+        let sequence = if self.truncated_sequences {
+            self.receive_window.expand(u16::from_be_bytes(
+                header.try_into().expect("sequence octets"),
+            ))
+        } else {
+            u64::from_be_bytes(header.try_into().expect("sequence octets"))
+        };
+        self.receive_window.check(sequence)?;
+        let plain = self
+            .receive_cipher
+            .decrypt(
+                &nonce_for(sequence),
+                Payload {
+                    msg: sealed,
+                    aad: header,
+                },
+            )
+            .map_err(|_| ProtocolError::DecryptionFailed)?;
+        self.receive_window.mark(sequence);
+
+        let mut reader = SliceReader::new(&plain);
+        let command = C::from_octets_with_version(&mut reader, self.version)?;
+        reject_trailing(&plain, reader.position())?;
+        Ok(command)
+    }
+}
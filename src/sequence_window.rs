@@ -0,0 +1,88 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Tracks the sequence numbers received from the peer, so replayed datagrams are refused while
+//! datagrams reordered by the network are still accepted.
+
+use crate::ProtocolError;
+
+/// How many sequence numbers below the highest received are still accepted
+pub const SEQUENCE_WINDOW_SIZE: u64 = 64;
+
+#[derive(Debug, Default, Clone)]
+pub struct SequenceWindow {
+    highest: Option<u64>,
+    /// Bit `n` is set if `highest - n` has been received
+    received: u64,
+}
+
+impl SequenceWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The highest sequence number marked as received
+    pub fn highest(&self) -> Option<u64> {
+        self.highest
+    }
+
+    /// Reconstructs the full sequence number from its low 16 bits, picking the one closest to
+    /// the highest received. Works as long as the sender is less than 32768 sequence numbers away.
+    pub fn expand(&self, low: u16) -> u64 {
+        let Some(highest) = self.highest else {
+            return low as u64;
+        };
+        let candidate = (highest & !0xFFFF) | low as u64;
+        if candidate > highest && candidate - highest > 0x8000 && candidate >= 0x1_0000 {
+            candidate - 0x1_0000
+        } else if candidate < highest && highest - candidate > 0x8000 {
+            candidate.checked_add(0x1_0000).unwrap_or(candidate)
+        } else {
+            candidate
+        }
+    }
+
+    /// Fails if `sequence` has been received or is too far behind to tell
+    pub fn check(&self, sequence: u64) -> Result<(), ProtocolError> {
+        let Some(highest) = self.highest else {
+            return Ok(());
+        };
+        if sequence > highest {
+            return Ok(());
+        }
+        let behind = highest - sequence;
+        if behind >= SEQUENCE_WINDOW_SIZE {
+            return Err(ProtocolError::SequenceTooOld { sequence, highest });
+        }
+        if self.received & (1 << behind) != 0 {
+            return Err(ProtocolError::ReplayedSequence { sequence });
+        }
+        Ok(())
+    }
+
+    /// Marks `sequence` as received. Only call it once the datagram has been authenticated.
+    pub fn mark(&mut self, sequence: u64) {
+        match self.highest {
+            Some(highest) if sequence <= highest => {
+                let behind = highest - sequence;
+                if behind < SEQUENCE_WINDOW_SIZE {
+                    self.received |= 1 << behind;
+                }
+            }
+            Some(highest) => {
+                let ahead = sequence - highest;
+                self.received = if ahead >= SEQUENCE_WINDOW_SIZE {
+                    0
+                } else {
+                    self.received << ahead
+                } | 1;
+                self.highest = Some(sequence);
+            }
+            None => {
+                self.received = 1;
+                self.highest = Some(sequence);
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Decoding from octet slices that reports truncation as [`ProtocolError::UnexpectedEof`],
+//! so transports can tell "wait for more octets" from corrupt data.
+
+use std::io::Result;
+
+use flood_rs::{OutOctetStream, ReadOctetStream};
+
+use crate::error::at_offset;
+use crate::{
+    ClientReceiveCommand, DecodeError, ProtocolError, ProtocolVersion, ServerReceiveCommand,
+    DEFAULT_PROTOCOL_VERSION,
+};
+
+/// Reads from an octet slice and keeps track of the position.
+pub struct SliceReader<'a> {
+    octets: &'a [u8],
+    position: usize,
+    last_read_position: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(octets: &'a [u8]) -> Self {
+        Self {
+            octets,
+            position: 0,
+            last_read_position: 0,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Where the latest read started, used as the offset of failed decodes
+    pub fn last_read_position(&self) -> usize {
+        self.last_read_position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.octets.len() - self.position
+    }
+
+    /// Fails with [`ProtocolError::UnexpectedEof`] unless `count` more octets are available.
+    pub fn require(&self, count: usize) -> Result<()> {
+        if self.remaining() < count {
+            return Err(ProtocolError::UnexpectedEof {
+                needed: self.position + count,
+                available: self.octets.len(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The next octet, without moving past it
+    pub(crate) fn peek_u8(&self) -> Option<u8> {
+        self.octets.get(self.position).copied()
+    }
+
+    /// Moves past `count` octets without reading them
+    pub(crate) fn skip(&mut self, count: usize) -> Result<()> {
+        self.require(count)?;
+        self.last_read_position = self.position;
+        self.position += count;
+        Ok(())
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.read(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl ReadOctetStream for SliceReader<'_> {
+    fn read(&mut self, v: &mut [u8]) -> Result<()> {
+        self.last_read_position = self.position;
+        self.require(v.len())?;
+        v.copy_from_slice(&self.octets[self.position..self.position + v.len()]);
+        self.position += v.len();
+        Ok(())
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+
+    fn read_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.take()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take()?))
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take()?))
+    }
+
+    fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.take()?))
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn read_i8(&mut self) -> Result<i8> {
+        Ok(self.take::<1>()?[0] as i8)
+    }
+}
+
+/// Checks the minimum size of the command that starts at the reader position. If the octets
+/// are too short, the command is decoded anyway to find the field and offset they end in, which
+/// are reported with the [`ProtocolError::UnexpectedEof`] of the whole minimum size.
+fn require_min_size<C>(
+    reader: &SliceReader,
+    min_octet_size: impl Fn(u8, ProtocolVersion) -> Option<usize>,
+    decode: impl FnOnce(&mut SliceReader) -> Result<C>,
+    version: ProtocolVersion,
+) -> Result<()> {
+    reader.require(1)?;
+    let command_type_id = reader.octets[reader.position];
+    let Some(size) = min_octet_size(command_type_id, version) else {
+        return Ok(());
+    };
+    let Err(err) = reader.require(1 + size) else {
+        return Ok(());
+    };
+    let mut truncated = SliceReader {
+        octets: reader.octets,
+        position: reader.position,
+        last_read_position: reader.position,
+    };
+    let result = decode(&mut truncated);
+    let Err(located) = at_offset(result, truncated.last_read_position()) else {
+        return Err(err);
+    };
+    Err(match DecodeError::from_io(&located) {
+        Some(decode_error) if !decode_error.path.is_empty() => DecodeError {
+            offset: decode_error.offset,
+            path: decode_error.path.clone(),
+            source: err,
+        }
+        .into(),
+        _ => err,
+    })
+}
+
+/// Fails with [`ProtocolError::TrailingBytes`] unless `consumed` is all of `octets`
+pub(crate) fn reject_trailing(octets: &[u8], consumed: usize) -> Result<()> {
+    if consumed != octets.len() {
+        return at_offset(
+            Err(ProtocolError::TrailingBytes {
+                count: octets.len() - consumed,
+            }
+            .into()),
+            consumed,
+        );
+    }
+    Ok(())
+}
+
+impl ServerReceiveCommand {
+    /// Decodes a command from `octets`, reporting truncated input as
+    /// [`ProtocolError::UnexpectedEof`] and other failures with their offset.
+    pub fn decode_checked(octets: &[u8], version: ProtocolVersion) -> Result<Self> {
+        Self::decode_prefix(octets, version).map(|(command, _)| command)
+    }
+
+    /// Like [`Self::decode_checked`], but also returns the number of octets the command used,
+    /// i.e. where the next command in `octets` starts.
+    pub fn decode_prefix(octets: &[u8], version: ProtocolVersion) -> Result<(Self, usize)> {
+        let mut reader = SliceReader::new(octets);
+        require_min_size(
+            &reader,
+            Self::min_octet_size,
+            |reader| Self::from_cursor_with_version(reader, version),
+            version,
+        )?;
+        let result = Self::from_cursor_with_version(&mut reader, version);
+        let command = at_offset(result, reader.last_read_position())?;
+        Ok((command, reader.position()))
+    }
+
+    /// Like [`Self::decode_checked`], but the command must use all of `octets`, otherwise it
+    /// fails with [`ProtocolError::TrailingBytes`].
+    pub fn decode_exact(octets: &[u8], version: ProtocolVersion) -> Result<Self> {
+        let (command, consumed) = Self::decode_prefix(octets, version)?;
+        reject_trailing(octets, consumed)?;
+        Ok(command)
+    }
+
+    /// [`Self::decode_exact`] with the [`DEFAULT_PROTOCOL_VERSION`].
+    pub fn from_slice(octets: &[u8]) -> Result<Self> {
+        Self::decode_exact(octets, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    /// Encodes the command with the layouts of `version`.
+    pub fn to_vec_with_version(&self, version: ProtocolVersion) -> Result<Vec<u8>> {
+        let mut out_stream = OutOctetStream::new();
+        self.to_octets_with_version(&mut out_stream, version)?;
+        Ok(out_stream.data)
+    }
+
+    /// [`Self::to_vec_with_version`] with the [`DEFAULT_PROTOCOL_VERSION`].
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        self.to_vec_with_version(DEFAULT_PROTOCOL_VERSION)
+    }
+}
+
+impl ClientReceiveCommand {
+    /// Decodes a command from `octets`, reporting truncated input as
+    /// [`ProtocolError::UnexpectedEof`] and other failures with their offset.
+    pub fn decode_checked(octets: &[u8], version: ProtocolVersion) -> Result<Self> {
+        Self::decode_prefix(octets, version).map(|(command, _)| command)
+    }
+
+    /// Like [`Self::decode_checked`], but also returns the number of octets the command used,
+    /// i.e. where the next command in `octets` starts.
+    pub fn decode_prefix(octets: &[u8], version: ProtocolVersion) -> Result<(Self, usize)> {
+        let mut reader = SliceReader::new(octets);
+        require_min_size(
+            &reader,
+            Self::min_octet_size,
+            |reader| Self::from_octets_with_version(reader, version),
+            version,
+        )?;
+        let result = Self::from_octets_with_version(&mut reader, version);
+        let command = at_offset(result, reader.last_read_position())?;
+        Ok((command, reader.position()))
+    }
+
+    /// Like [`Self::decode_checked`], but the command must use all of `octets`, otherwise it
+    /// fails with [`ProtocolError::TrailingBytes`].
+    pub fn decode_exact(octets: &[u8], version: ProtocolVersion) -> Result<Self> {
+        let (command, consumed) = Self::decode_prefix(octets, version)?;
+        reject_trailing(octets, consumed)?;
+        Ok(command)
+    }
+
+    /// [`Self::decode_exact`] with the [`DEFAULT_PROTOCOL_VERSION`].
+    pub fn from_slice(octets: &[u8]) -> Result<Self> {
+        Self::decode_exact(octets, DEFAULT_PROTOCOL_VERSION)
+    }
+
+    /// Encodes the command with the layouts of `version`.
+    pub fn to_vec_with_version(&self, version: ProtocolVersion) -> Result<Vec<u8>> {
+        let mut out_stream = OutOctetStream::new();
+        self.to_octets_with_version(&mut out_stream, version)?;
+        Ok(out_stream.data)
+    }
+
+    /// [`Self::to_vec_with_version`] with the [`DEFAULT_PROTOCOL_VERSION`].
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        self.to_vec_with_version(DEFAULT_PROTOCOL_VERSION)
+    }
+}
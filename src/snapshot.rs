@@ -0,0 +1,52 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::Result;
+
+use crate::transfer::{Reassembly, ReassemblyLimits};
+use crate::SnapshotChunkCommand;
+
+/// Chosen by the Client in the `SnapshotRequestCommand`, and repeated in every chunk
+pub type SnapshotId = u32;
+
+/// Collects `SnapshotChunkCommand`s, arriving in any order, into complete snapshots.
+#[derive(Default)]
+pub struct SnapshotReassembler {
+    reassembly: Reassembly<SnapshotId>,
+}
+
+impl SnapshotReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limits(mut self, limits: ReassemblyLimits) -> Self {
+        self.reassembly = Reassembly::new(limits);
+        self
+    }
+
+    /// Returns the snapshot data once the last missing chunk is received. Duplicate chunks are
+    /// ignored, a chunk count that differs from earlier chunks of the snapshot is an error. Other
+    /// snapshots are dropped to stay within the [`ReassemblyLimits`].
+    pub fn receive(&mut self, chunk: SnapshotChunkCommand) -> Result<Option<Vec<u8>>> {
+        self.reassembly.receive(
+            chunk.snapshot_id,
+            chunk.chunk_index,
+            chunk.chunk_count,
+            chunk.data,
+        )
+    }
+
+    pub fn cancel(&mut self, snapshot_id: SnapshotId) -> bool {
+        self.reassembly.cancel(snapshot_id)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.reassembly.pending_count()
+    }
+
+    pub fn buffered_octets(&self) -> usize {
+        self.reassembly.buffered_octets()
+    }
+}
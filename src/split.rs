@@ -0,0 +1,176 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Datagram format for a single command that does not fit in one datagram, e.g. a
+//! `RoomInfoCommand` with hundreds of clients.
+//!
+//! Each datagram is a [`SplitHeader`] followed by one part of the serialized command. The
+//! [`PartialMessageBuffer`] collects the parts, in any order, and decodes the command once all
+//! of them have arrived.
+
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{OutOctetStream, ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::slice_reader::reject_trailing;
+use crate::transfer::{check_chunk_index, split_chunks, Reassembly, ReassemblyLimits};
+use crate::{Command, ProtocolVersion, SliceReader, DEFAULT_PROTOCOL_VERSION};
+
+/// Chosen by the sender, and repeated in every part of the message
+pub type MessageId = u32;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub struct SplitHeader {
+    pub message_id: MessageId,
+    pub part_index: u16,
+    pub part_count: u16,
+}
+
+impl SplitHeader {
+    pub const OCTET_SIZE: usize = 4 + 2 + 2;
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.message_id)?;
+        stream.write_u16(self.part_index)?;
+        stream.write_u16(self.part_count)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let message_id = in_field(stream.read_u32(), "message_id")?;
+        let part_index = in_field(stream.read_u16(), "part_index")?;
+        let part_count = in_field(stream.read_u16(), "part_count")?;
+        in_field(check_chunk_index(part_index, part_count), "part_index")?;
+        Ok(Self {
+            message_id,
+            part_index,
+            part_count,
+        })
+    }
+}
+
+/// Serializes `command` into datagrams of at most `max_datagram_octets`, header included.
+/// A command that fits still results in a single datagram with a header.
+pub fn split_command<C: Command>(
+    command: &C,
+    message_id: MessageId,
+    max_datagram_octets: usize,
+) -> Result<Vec<Vec<u8>>> {
+    split_command_with_version(
+        command,
+        message_id,
+        max_datagram_octets,
+        DEFAULT_PROTOCOL_VERSION,
+    )
+}
+
+pub fn split_command_with_version<C: Command>(
+    command: &C,
+    message_id: MessageId,
+    max_datagram_octets: usize,
+    version: ProtocolVersion,
+) -> Result<Vec<Vec<u8>>> {
+    if max_datagram_octets <= SplitHeader::OCTET_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "max_datagram_octets must be above the {} octet header",
+                SplitHeader::OCTET_SIZE
+            ),
+        ));
+    }
+    let mut encoded = OutOctetStream::new();
+    command.to_octets_with_version(&mut encoded, version)?;
+    let parts = split_chunks(&encoded.data, max_datagram_octets - SplitHeader::OCTET_SIZE)?;
+    let part_count = parts.len() as u16;
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(part_index, part)| {
+            let mut out_stream = OutOctetStream::new();
+            SplitHeader {
+                message_id,
+                part_index: part_index as u16,
+                part_count,
+            }
+            .to_octets(&mut out_stream)?;
+            out_stream.write(part)?;
+            Ok(out_stream.data)
+        })
+        .collect()
+}
+
+/// Collects the datagrams of [`split_command`], for any number of concurrent messages, and
+/// decodes them with the [`DEFAULT_PROTOCOL_VERSION`] unless another one is set. Messages
+/// that never complete, e.g. because a datagram was lost, are dropped once the
+/// [`ReassemblyLimits`] are reached.
+pub struct PartialMessageBuffer<C> {
+    reassembly: Reassembly<MessageId>,
+    version: ProtocolVersion,
+    marker: std::marker::PhantomData<C>,
+}
+
+impl<C> Default for PartialMessageBuffer<C> {
+    fn default() -> Self {
+        Self {
+            reassembly: Reassembly::default(),
+            version: DEFAULT_PROTOCOL_VERSION,
+            marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<C: Command> PartialMessageBuffer<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limits(mut self, limits: ReassemblyLimits) -> Self {
+        self.reassembly = Reassembly::new(limits);
+        self
+    }
+
+    pub fn with_version(mut self, version: ProtocolVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Returns the command once the last missing part is received, which must use all of the
+    /// reassembled octets. Duplicate parts are ignored, a part count that differs from earlier
+    /// parts of the message is an error.
+    pub fn receive(&mut self, datagram: &[u8]) -> Result<Option<C>> {
+        let mut reader = SliceReader::new(datagram);
+        let header = SplitHeader::from_cursor(&mut reader)?;
+        let Some(octets) = self.reassembly.receive(
+            header.message_id,
+            header.part_index,
+            header.part_count,
+            datagram[reader.position()..].to_vec(),
+        )?
+        else {
+            return Ok(None);
+        };
+        let mut reader = SliceReader::new(&octets);
+        let command = C::from_octets_with_version(&mut reader, self.version)?;
+        reject_trailing(&octets, reader.position())?;
+        Ok(Some(command))
+    }
+
+    /// The part indices not yet received, `None` if the message is not pending.
+    pub fn missing_part_indices(&self, message_id: MessageId) -> Option<Vec<u16>> {
+        self.reassembly.missing_chunk_indices(message_id)
+    }
+
+    pub fn cancel(&mut self, message_id: MessageId) -> bool {
+        self.reassembly.cancel(message_id)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.reassembly.pending_count()
+    }
+
+    pub fn buffered_octets(&self) -> usize {
+        self.reassembly.buffered_octets()
+    }
+}
@@ -0,0 +1,103 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Traffic statistics per command type, for bandwidth budgeting during development.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::command_descriptor;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub struct CommandTypeStats {
+    pub count: u64,
+    /// Including the command type id
+    pub total_octets: u64,
+}
+
+impl CommandTypeStats {
+    /// Zero if no command was recorded
+    pub fn average_octets(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total_octets as f64 / self.count as f64
+        }
+    }
+}
+
+/// Accumulates [`CommandTypeStats`] for every command it is given.
+///
+/// Since a command type id means the same command in both directions, feed the commands of
+/// each direction to their own `CommandStats` to tell them apart.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct CommandStats {
+    by_command_type_id: BTreeMap<u8, CommandTypeStats>,
+}
+
+impl CommandStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an encoded command, starting with its command type id. Empty `octets` are
+    /// ignored.
+    pub fn record(&mut self, octets: &[u8]) {
+        if let Some(&command_type_id) = octets.first() {
+            self.record_octet_count(command_type_id, octets.len());
+        }
+    }
+
+    pub fn record_octet_count(&mut self, command_type_id: u8, octet_count: usize) {
+        let stats = self.by_command_type_id.entry(command_type_id).or_default();
+        stats.count += 1;
+        stats.total_octets += octet_count as u64;
+    }
+
+    pub fn get(&self, command_type_id: u8) -> Option<&CommandTypeStats> {
+        self.by_command_type_id.get(&command_type_id)
+    }
+
+    /// In increasing command type id order
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &CommandTypeStats)> {
+        self.by_command_type_id
+            .iter()
+            .map(|(command_type_id, stats)| (*command_type_id, stats))
+    }
+
+    /// All command types together
+    pub fn total(&self) -> CommandTypeStats {
+        self.by_command_type_id
+            .values()
+            .fold(CommandTypeStats::default(), |total, stats| {
+                CommandTypeStats {
+                    count: total.count + stats.count,
+                    total_octets: total.total_octets + stats.total_octets,
+                }
+            })
+    }
+
+    pub fn clear(&mut self) {
+        self.by_command_type_id.clear();
+    }
+}
+
+/// One line per command type, with the command name if it is known
+impl fmt::Display for CommandStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (command_type_id, stats) in self.iter() {
+            let name = command_descriptor(command_type_id).map_or("unknown", |d| d.name);
+            writeln!(
+                f,
+                "0x{:02x} {}: {} commands, {} octets, {:.1} octets on average",
+                command_type_id,
+                name,
+                stats.count,
+                stats.total_octets,
+                stats.average_octets()
+            )?;
+        }
+        Ok(())
+    }
+}
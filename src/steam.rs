@@ -0,0 +1,93 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Commands as `ISteamNetworkingSockets` message payloads.
+//!
+//! A [`SteamMessage`] has the fields of a `SteamNetworkingMessage_t` to send: the payload, the
+//! send flags and the lane, both chosen from the [`crate::CommandDelivery`] of the command.
+//! Configure the lanes of a connection with [`STEAM_LANE_PRIORITIES`] and
+//! [`STEAM_LANE_WEIGHTS`].
+
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::OutOctetStream;
+
+use crate::framing::decode_payload;
+use crate::{command_descriptor, Command, CommandDelivery, ProtocolVersion};
+
+/// `k_cbMaxSteamNetworkingSocketsMessageSizeSend`
+pub const STEAM_MAX_MESSAGE_OCTETS: usize = 512 * 1024;
+
+/// `k_nSteamNetworkingSend_Unreliable`
+pub const STEAM_SEND_UNRELIABLE: i32 = 0;
+/// `k_nSteamNetworkingSend_NoNagle`
+pub const STEAM_SEND_NO_NAGLE: i32 = 1;
+/// `k_nSteamNetworkingSend_Reliable`
+pub const STEAM_SEND_RELIABLE: i32 = 8;
+
+pub const STEAM_RELIABLE_LANE: u16 = 0;
+pub const STEAM_UNRELIABLE_LANE: u16 = 1;
+
+/// Indexed by lane, lower values go first. The unreliable lane only carries small, time
+/// sensitive measurements, so they are not queued behind a large `RoomInfoCommand`.
+pub const STEAM_LANE_PRIORITIES: [i32; 2] = [1, 0];
+/// Indexed by lane
+pub const STEAM_LANE_WEIGHTS: [u16; 2] = [1, 1];
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SteamMessage {
+    pub payload: Vec<u8>,
+    pub send_flags: i32,
+    pub lane: u16,
+}
+
+impl CommandDelivery {
+    /// Unreliable messages skip the Nagle delay, since waiting for more would defeat them
+    pub fn steam_send_flags(self) -> i32 {
+        match self {
+            Self::ReliableOrdered => STEAM_SEND_RELIABLE,
+            Self::Unreliable => STEAM_SEND_UNRELIABLE | STEAM_SEND_NO_NAGLE,
+        }
+    }
+
+    pub fn steam_lane(self) -> u16 {
+        match self {
+            Self::ReliableOrdered => STEAM_RELIABLE_LANE,
+            Self::Unreliable => STEAM_UNRELIABLE_LANE,
+        }
+    }
+}
+
+/// Fails if the command is larger than [`STEAM_MAX_MESSAGE_OCTETS`]. Unknown command type ids
+/// are sent reliably.
+pub fn encode_steam_message<C: Command>(
+    command: &C,
+    version: ProtocolVersion,
+) -> Result<SteamMessage> {
+    let mut out_stream = OutOctetStream::new();
+    command.to_octets_with_version(&mut out_stream, version)?;
+    if out_stream.data.len() > STEAM_MAX_MESSAGE_OCTETS {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "command is too large for a steam message ({} octets)",
+                out_stream.data.len()
+            ),
+        ));
+    }
+    let delivery = command_descriptor(out_stream.data[0])
+        .map_or(CommandDelivery::ReliableOrdered, |descriptor| {
+            descriptor.delivery
+        });
+    Ok(SteamMessage {
+        payload: out_stream.data,
+        send_flags: delivery.steam_send_flags(),
+        lane: delivery.steam_lane(),
+    })
+}
+
+/// Decodes the payload of a received message, which must be a single command
+pub fn decode_steam_message<C: Command>(payload: &[u8], version: ProtocolVersion) -> Result<C> {
+    decode_payload(payload, 0..payload.len(), version)
+}
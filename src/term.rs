@@ -0,0 +1,39 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Reserved [`Term`] wire values.
+//!
+//! `Term` is defined by `conclave_room`, so the reserved values are exposed through the
+//! [`TermExt`] extension trait instead of a newtype.
+
+use conclave_room::Term;
+
+use crate::ProtocolError;
+
+/// Sent when the term is not known, e.g. in a `PingCommand` before any `RoomInfoCommand` has
+/// been received. Commands carrying an authoritative term reject it.
+pub const TERM_UNKNOWN: Term = 0xFFFF;
+
+pub trait TermExt {
+    /// Same as [`TERM_UNKNOWN`]
+    const UNKNOWN: Term;
+
+    fn is_unknown(&self) -> bool;
+}
+
+impl TermExt for Term {
+    const UNKNOWN: Term = TERM_UNKNOWN;
+
+    fn is_unknown(&self) -> bool {
+        *self == TERM_UNKNOWN
+    }
+}
+
+/// Fails for terms that are reserved on the wire
+pub(crate) fn check_known_term(term: Term) -> Result<(), ProtocolError> {
+    if term.is_unknown() {
+        return Err(ProtocolError::ReservedTerm { term });
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::{Error, Result};
+
+use conclave_room::Term;
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::term::check_known_term;
+use crate::{ProtocolVersion, PROTOCOL_VERSION_10};
+
+pub const TERM_ADVANCED_COMMAND_TYPE_ID: u8 = 0x26;
+
+pub const TERM_ADVANCED_CAUSE_ELECTION: u8 = 0x01;
+pub const TERM_ADVANCED_CAUSE_LEADER_TIMEOUT: u8 = 0x02;
+pub const TERM_ADVANCED_CAUSE_LEADER_LEFT: u8 = 0x03;
+
+/// Sent from Server to all Clients as soon as the term changes, ahead of the
+/// `RoomInfoCommand` with the new room, e.g. so Clients can annotate their logs
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TermAdvancedCommand {
+    pub new_term: Term,
+    /// One of the `TERM_ADVANCED_CAUSE_*` constants, or an application specific code
+    pub cause: u8,
+}
+
+impl TermAdvancedCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "term_advanced";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_10;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        2 + 1
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        in_field(
+            check_known_term(self.new_term).map_err(Error::from),
+            "new_term",
+        )?;
+        stream.write_u16(self.new_term)?;
+        stream.write_u8(self.cause)
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let new_term = in_field(stream.read_u16(), "new_term")?;
+        in_field(check_known_term(new_term).map_err(Error::from), "new_term")?;
+        Ok(Self {
+            new_term,
+            cause: in_field(stream.read_u8(), "cause")?,
+        })
+    }
+}
@@ -0,0 +1,1365 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Test vectors for implementations of the protocol in other languages: every command with
+//! each field at its boundary values, encoded with this build. Besides the hand-written vectors,
+//! every field of every command in [`crate::COMMAND_DESCRIPTORS`] is set to each of its boundary
+//! values in turn, so new fields get vectors without being listed here.
+//!
+//! `conclave-test-vectors` writes them out as a JSON manifest and one binary file per vector.
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use flood_rs::{OutOctetStream, WriteOctetStream};
+
+use crate::connect_token::write_socket_addr;
+use crate::varint::write_varint;
+use crate::{
+    BanClientCommand, BandwidthReportCommand, BroadcastPayloadCommand, BroadcastedPayloadCommand,
+    ChallengeCommand, ChannelProfile, ChannelProfileCommand, ClientIdentity, ClientInfo,
+    ClientNetworkStats, ClientReceiveCommand, CommandDescriptor, ConnectToken,
+    ConnectWithCookieCommand, ConnectWithTokenCommand, ConnectionIndex, Extensions,
+    FieldDescriptor, FieldKind, HelloCommand, JoinAcceptedCommand, JoinDeniedCommand,
+    JoinRequestCommand, KnowledgeAckCommand, KnowledgeVector, KnownRevision, LatencyReportCommand,
+    LengthPrefix, LogEntry, MaintenanceNoticeCommand, MembershipSyncRequestCommand,
+    MembershipSyncResponseCommand, MuteStateCommand, PingCommand, PingResponseCommand,
+    PreVoteRequestCommand, PreVoteResponseCommand, PresenceState, ProtocolVersion, PskAuthCommand,
+    RedirectCommand, RelayToClientCommand, RelayedFromClientCommand, ReplicateAckCommand,
+    ReplicateCommand, ResyncDataCommand, ResyncRequestCommand, RetryCookieCommand,
+    RoomClosedCommand, RoomInfoCommand, RoomInfoPageCommand, ServerLoadCommand,
+    ServerReceiveCommand, SetPresenceCommand, SnapshotChunkCommand, SnapshotRequestCommand,
+    TermAdvancedCommand, ThrottledCommand, TimeoutConfigCommand, TransferChunkCommand,
+    TransferResendRequestCommand, UnbanClientCommand, UpdateRoomSettingsCommand, UserId,
+    COMMAND_DESCRIPTORS, LATENCY_BUCKET_COUNT, MAX_BROADCAST_PAYLOAD_OCTETS,
+    MAX_CONNECT_TOKEN_PRIVATE_OCTETS, MAX_CONNECT_TOKEN_SERVER_ADDRESSES, MAX_EXTENSIONS_OCTETS,
+    MAX_PAYLOAD_OCTETS, MAX_REDIRECT_HOST_OCTETS, PROTOCOL_FINGERPRINT, PROTOCOL_VERSION,
+    PROTOCOL_VERSION_1, PROTOCOL_VERSION_11, PROTOCOL_VERSION_12, PROTOCOL_VERSION_2,
+    PROTOCOL_VERSION_3, PROTOCOL_VERSION_4, PROTOCOL_VERSION_5, PROTOCOL_VERSION_6,
+    PROTOCOL_VERSION_7, PROTOCOL_VERSION_8, PROTOCOL_VERSION_9,
+};
+use conclave_room::{Knowledge, Term};
+
+/// The largest term that can be sent, the one above is reserved for "unknown"
+const MAX_TERM: Term = Term::MAX - 1;
+
+/// Few enough clients to also fit the inline `ArrayClientInfos`
+const ROOM_INFO_MAX_CLIENTS: usize = 32;
+
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[serde(untagged)]
+pub enum TestVectorCommand {
+    Server(ServerReceiveCommand),
+    Client(ClientReceiveCommand),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TestVector {
+    /// Unique, usable as a file name
+    pub name: String,
+    pub version: ProtocolVersion,
+    /// What the octets must decode to
+    pub command: TestVectorCommand,
+}
+
+impl TestVector {
+    fn server(name: &str, version: ProtocolVersion, command: ServerReceiveCommand) -> Self {
+        Self {
+            name: name.to_string(),
+            version,
+            command: TestVectorCommand::Server(command),
+        }
+    }
+
+    fn client(name: &str, version: ProtocolVersion, command: ClientReceiveCommand) -> Self {
+        Self {
+            name: name.to_string(),
+            version,
+            command: TestVectorCommand::Client(command),
+        }
+    }
+
+    pub fn command_type_id(&self) -> u8 {
+        match &self.command {
+            TestVectorCommand::Server(command) => command.command_type_id(),
+            TestVectorCommand::Client(command) => command.command_type_id(),
+        }
+    }
+
+    /// `"client_to_server"` or `"server_to_client"`
+    pub fn direction(&self) -> &'static str {
+        match self.command {
+            TestVectorCommand::Server(_) => "client_to_server",
+            TestVectorCommand::Client(_) => "server_to_client",
+        }
+    }
+
+    /// The command encoded with the vector's version, including the command type id
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut out_stream = flood_rs::OutOctetStream::new();
+        match &self.command {
+            TestVectorCommand::Server(command) => {
+                command.to_octets_with_version(&mut out_stream, self.version)?
+            }
+            TestVectorCommand::Client(command) => {
+                command.to_octets_with_version(&mut out_stream, self.version)?
+            }
+        }
+        Ok(out_stream.data)
+    }
+
+    /// Fails unless `octets` decode to the vector's command
+    pub fn check(&self, octets: &[u8]) -> Result<()> {
+        let matches = match &self.command {
+            TestVectorCommand::Server(command) => {
+                ServerReceiveCommand::decode_checked(octets, self.version)? == *command
+            }
+            TestVectorCommand::Client(command) => {
+                ClientReceiveCommand::decode_checked(octets, self.version)? == *command
+            }
+        };
+        if !matches {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} decoded to another command", self.name),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ManifestEntry<'a> {
+    name: &'a str,
+    file: String,
+    direction: &'static str,
+    version: ProtocolVersion,
+    command_type_id: u8,
+    octet_count: usize,
+    command: &'a TestVectorCommand,
+}
+
+#[derive(serde::Serialize)]
+struct Manifest<'a> {
+    protocol_version: ProtocolVersion,
+    fingerprint: String,
+    vectors: Vec<ManifestEntry<'a>>,
+}
+
+/// The JSON manifest for `vectors`, each expected in a file named after the vector with a
+/// `.bin` extension. Identities wider than 64 bits are written as plain JSON numbers.
+pub fn test_vector_manifest(vectors: &[TestVector]) -> Result<String> {
+    let entries = vectors
+        .iter()
+        .map(|vector| {
+            Ok(ManifestEntry {
+                name: &vector.name,
+                file: format!("{}.bin", vector.name),
+                direction: vector.direction(),
+                version: vector.version,
+                command_type_id: vector.command_type_id(),
+                octet_count: vector.to_vec()?.len(),
+                command: &vector.command,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let manifest = Manifest {
+        protocol_version: PROTOCOL_VERSION,
+        fingerprint: format!("{:016x}", PROTOCOL_FINGERPRINT),
+        vectors: entries,
+    };
+    Ok(serde_json::to_string_pretty(&manifest)?)
+}
+
+fn client_infos(count: usize, identity: impl Fn(usize) -> ClientIdentity) -> crate::ClientInfos {
+    (0..count)
+        .map(|index| ClientInfo {
+            identity: identity(index),
+            connection_index: ConnectionIndex((u8::MAX as usize - index) as u8),
+            role_flags: u16::MAX,
+            revision: u32::MAX,
+            presence: PresenceState::Busy,
+            network_stats: None,
+        })
+        .collect()
+}
+
+fn room_info_max() -> RoomInfoCommand {
+    RoomInfoCommand {
+        term: MAX_TERM,
+        leader_index: ConnectionIndex(u8::MAX),
+        client_infos: client_infos(ROOM_INFO_MAX_CLIENTS, |index| match index % 3 {
+            0 => ClientIdentity::U64(u64::MAX),
+            1 => ClientIdentity::Uuid(u128::MAX),
+            _ => ClientIdentity::String("\u{ff}".repeat(u8::MAX as usize / 2)),
+        }),
+        knowledge_vector: None,
+        quorum_size: u8::MAX,
+        votes_for_leader: u8::MAX,
+        leader_lease_expires_in_ms: u32::MAX,
+    }
+}
+
+fn room_info_network_stats_max() -> RoomInfoCommand {
+    let mut room_info = room_info_max();
+    for client_info in room_info.client_infos.iter_mut() {
+        client_info.network_stats = Some(ClientNetworkStats {
+            rtt_ms: u16::MAX,
+            loss_percent: u8::MAX,
+        });
+    }
+    room_info
+}
+
+fn knowledge_vector_max() -> KnowledgeVector {
+    (0..u8::MAX)
+        .map(|connection_index| (ConnectionIndex(connection_index), Knowledge::MAX))
+        .collect()
+}
+
+/// Drops what the version can not carry, as the decoder of that version would
+fn room_info_for_version(version: ProtocolVersion) -> RoomInfoCommand {
+    let identity = |index: usize| {
+        let id = u64::MAX - index as u64;
+        if version >= PROTOCOL_VERSION_3 {
+            ClientIdentity::Uuid(((id as u128) << 64) | id as u128)
+        } else {
+            ClientIdentity::U64(id)
+        }
+    };
+    let mut client_infos = client_infos(2, identity);
+    for client_info in &mut client_infos {
+        if version < PROTOCOL_VERSION_2 {
+            client_info.role_flags = 0;
+        }
+        if version < PROTOCOL_VERSION_7 {
+            client_info.revision = 0;
+        }
+        if version < PROTOCOL_VERSION_8 {
+            client_info.presence = PresenceState::Active;
+        }
+    }
+    RoomInfoCommand {
+        term: MAX_TERM,
+        leader_index: ConnectionIndex(u8::MAX - 1),
+        client_infos,
+        knowledge_vector: None,
+        quorum_size: if version >= PROTOCOL_VERSION_11 { 2 } else { 0 },
+        votes_for_leader: if version >= PROTOCOL_VERSION_11 { 1 } else { 0 },
+        leader_lease_expires_in_ms: if version >= PROTOCOL_VERSION_12 {
+            1500
+        } else {
+            0
+        },
+    }
+}
+
+fn join_request_max() -> JoinRequestCommand {
+    JoinRequestCommand {
+        identity: ClientIdentity::String("z".repeat(u8::MAX as usize)),
+        requested_role_flags: u16::MAX,
+    }
+}
+
+fn connect_token_max() -> ConnectToken {
+    ConnectToken {
+        expires_at_s: u64::MAX,
+        client_id: u64::MAX,
+        server_addresses: (0..MAX_CONNECT_TOKEN_SERVER_ADDRESSES)
+            .map(|index| {
+                if index % 2 == 0 {
+                    SocketAddr::new(Ipv4Addr::BROADCAST.into(), u16::MAX)
+                } else {
+                    SocketAddr::new(
+                        Ipv6Addr::new(0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0)
+                            .into(),
+                        index as u16,
+                    )
+                }
+            })
+            .collect(),
+        private_data: vec![0xFF; MAX_CONNECT_TOKEN_PRIVATE_OCTETS],
+    }
+}
+
+fn connect_token_zero() -> ConnectToken {
+    ConnectToken {
+        server_addresses: vec![SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)],
+        ..Default::default()
+    }
+}
+
+fn server_vectors() -> Vec<TestVector> {
+    use ServerReceiveCommand as S;
+    let v = PROTOCOL_VERSION;
+    let mut vectors = vec![
+        TestVector::server("ping_zero", v, S::PingCommandType(PingCommand::default())),
+        TestVector::server(
+            "ping_max",
+            v,
+            S::PingCommandType(PingCommand {
+                term: MAX_TERM,
+                knowledge: Knowledge::MAX,
+                has_connection_to_leader: true,
+                connection_index: ConnectionIndex(u8::MAX),
+                clock_drift_ppm: i16::MAX,
+                knowledge_vector: None,
+            }),
+        ),
+        TestVector::server(
+            "ping_knowledge_vector_max",
+            v,
+            S::PingCommandType(PingCommand {
+                knowledge_vector: Some(knowledge_vector_max()),
+                ..Default::default()
+            }),
+        ),
+        TestVector::server(
+            "ping_min_clock_drift",
+            v,
+            S::PingCommandType(PingCommand {
+                clock_drift_ppm: i16::MIN,
+                ..Default::default()
+            }),
+        ),
+        TestVector::server(
+            "ping_knowledge_wrap",
+            v,
+            S::PingCommandType(PingCommand {
+                term: 0x8000,
+                knowledge: 1 << 63,
+                ..Default::default()
+            }),
+        ),
+        TestVector::server(
+            "ban_client_zero",
+            v,
+            S::BanClientType(BanClientCommand::default()),
+        ),
+        TestVector::server(
+            "ban_client_max",
+            v,
+            S::BanClientType(BanClientCommand {
+                custom_user_id: UserId(u128::MAX),
+                duration_s: u32::MAX,
+            }),
+        ),
+        TestVector::server(
+            "ban_client_narrow_v2",
+            PROTOCOL_VERSION_2,
+            S::BanClientType(BanClientCommand {
+                custom_user_id: UserId::from(u64::MAX),
+                duration_s: u32::MAX,
+            }),
+        ),
+        TestVector::server(
+            "unban_client_zero",
+            v,
+            S::UnbanClientType(UnbanClientCommand::default()),
+        ),
+        TestVector::server(
+            "unban_client_max",
+            v,
+            S::UnbanClientType(UnbanClientCommand {
+                custom_user_id: UserId(u128::MAX),
+            }),
+        ),
+        TestVector::server(
+            "mute_state_zero",
+            v,
+            S::MuteStateType(MuteStateCommand::default()),
+        ),
+        TestVector::server(
+            "mute_state_max",
+            v,
+            S::MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(u8::MAX),
+                muted: true,
+            }),
+        ),
+        TestVector::server(
+            "relay_to_client_zero",
+            v,
+            S::RelayToClientType(RelayToClientCommand::default()),
+        ),
+        TestVector::server(
+            "relay_to_client_max",
+            v,
+            S::RelayToClientType(RelayToClientCommand {
+                target_connection_index: ConnectionIndex(u8::MAX),
+                payload: vec![0xFF; MAX_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::server(
+            "broadcast_payload_zero",
+            v,
+            S::BroadcastPayloadType(BroadcastPayloadCommand::default()),
+        ),
+        TestVector::server(
+            "broadcast_payload_max",
+            v,
+            S::BroadcastPayloadType(BroadcastPayloadCommand {
+                payload: vec![0xFF; MAX_BROADCAST_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::server(
+            "join_request_zero",
+            v,
+            S::JoinRequestType(JoinRequestCommand::default()),
+        ),
+        TestVector::server(
+            "join_request_max",
+            v,
+            S::JoinRequestType(join_request_max()),
+        ),
+        TestVector::server(
+            "join_request_empty_string",
+            v,
+            S::JoinRequestType(JoinRequestCommand {
+                identity: ClientIdentity::String(String::new()),
+                requested_role_flags: 0,
+            }),
+        ),
+        TestVector::server(
+            "join_request_uuid",
+            v,
+            S::JoinRequestType(JoinRequestCommand {
+                identity: ClientIdentity::Uuid(u128::MAX),
+                requested_role_flags: 0,
+            }),
+        ),
+        TestVector::server(
+            "resync_request_zero",
+            v,
+            S::ResyncRequestType(ResyncRequestCommand::default()),
+        ),
+        TestVector::server(
+            "resync_request_max",
+            v,
+            S::ResyncRequestType(ResyncRequestCommand {
+                from_term: MAX_TERM,
+                from_knowledge: Knowledge::MAX,
+            }),
+        ),
+        TestVector::server(
+            "snapshot_request_zero",
+            v,
+            S::SnapshotRequestType(SnapshotRequestCommand::default()),
+        ),
+        TestVector::server(
+            "snapshot_request_max",
+            v,
+            S::SnapshotRequestType(SnapshotRequestCommand {
+                snapshot_id: u32::MAX,
+            }),
+        ),
+        TestVector::server(
+            "transfer_chunk_min",
+            v,
+            S::TransferChunkType(TransferChunkCommand {
+                chunk_count: 1,
+                ..Default::default()
+            }),
+        ),
+        TestVector::server(
+            "transfer_chunk_max",
+            v,
+            S::TransferChunkType(TransferChunkCommand {
+                transfer_id: u32::MAX,
+                chunk_index: u16::MAX - 1,
+                chunk_count: u16::MAX,
+                data: vec![0xFF; MAX_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::server(
+            "transfer_resend_request_zero",
+            v,
+            S::TransferResendRequestType(TransferResendRequestCommand::default()),
+        ),
+        TestVector::server(
+            "transfer_resend_request_max",
+            v,
+            S::TransferResendRequestType(TransferResendRequestCommand {
+                transfer_id: u32::MAX,
+                missing_chunk_indices: (0..u16::MAX).rev().collect(),
+            }),
+        ),
+        TestVector::server(
+            "bandwidth_report_zero",
+            v,
+            S::BandwidthReportType(BandwidthReportCommand::default()),
+        ),
+        TestVector::server(
+            "bandwidth_report_max",
+            v,
+            S::BandwidthReportType(BandwidthReportCommand {
+                bytes_sent: u64::MAX,
+                bytes_received: u64::MAX,
+                window_ms: u32::MAX,
+            }),
+        ),
+        TestVector::server(
+            "latency_report_zero",
+            v,
+            S::LatencyReportType(LatencyReportCommand::default()),
+        ),
+        TestVector::server(
+            "latency_report_max",
+            v,
+            S::LatencyReportType(LatencyReportCommand {
+                buckets: [u16::MAX; LATENCY_BUCKET_COUNT],
+            }),
+        ),
+        TestVector::server("hello_zero", v, S::HelloType(HelloCommand::default())),
+        TestVector::server(
+            "hello_max",
+            v,
+            S::HelloType(HelloCommand {
+                version: ProtocolVersion::MAX,
+                fingerprint: u64::MAX,
+            }),
+        ),
+        TestVector::server(
+            "psk_auth_zero",
+            v,
+            S::PskAuthType(PskAuthCommand::default()),
+        ),
+        TestVector::server(
+            "psk_auth_max",
+            v,
+            S::PskAuthType(PskAuthCommand {
+                key_id: u16::MAX,
+                proof: [0xFF; crate::PSK_PROOF_OCTETS],
+            }),
+        ),
+        TestVector::server(
+            "connect_with_token_zero",
+            v,
+            S::ConnectWithTokenType(ConnectWithTokenCommand {
+                token: connect_token_zero(),
+            }),
+        ),
+        TestVector::server(
+            "connect_with_token_max",
+            v,
+            S::ConnectWithTokenType(ConnectWithTokenCommand {
+                token: connect_token_max(),
+            }),
+        ),
+        TestVector::server(
+            "connect_with_cookie_zero",
+            v,
+            S::ConnectWithCookieType(ConnectWithCookieCommand::default()),
+        ),
+        TestVector::server(
+            "connect_with_cookie_max",
+            v,
+            S::ConnectWithCookieType(ConnectWithCookieCommand {
+                cookie: [0xFF; crate::RETRY_COOKIE_OCTETS],
+                join_request: join_request_max(),
+            }),
+        ),
+        TestVector::server(
+            "timeout_config_zero",
+            v,
+            S::TimeoutConfigType(TimeoutConfigCommand::default()),
+        ),
+        TestVector::server(
+            "timeout_config_max",
+            v,
+            S::TimeoutConfigType(TimeoutConfigCommand {
+                disconnect_after_ms: u32::MAX,
+                ping_interval_ms: u16::MAX,
+            }),
+        ),
+        TestVector::server(
+            "update_room_settings_zero",
+            v,
+            S::UpdateRoomSettingsType(UpdateRoomSettingsCommand::default()),
+        ),
+        TestVector::server(
+            "update_room_settings_max",
+            v,
+            S::UpdateRoomSettingsType(UpdateRoomSettingsCommand {
+                settings_version: u32::MAX,
+                settings: Extensions::from_iter([
+                    (0x00, vec![]),
+                    (0xFF, vec![0xFF; MAX_EXTENSIONS_OCTETS - 3 - 3]),
+                ]),
+            }),
+        ),
+        TestVector::server(
+            "membership_sync_request_zero",
+            v,
+            S::MembershipSyncRequestType(MembershipSyncRequestCommand::default()),
+        ),
+        TestVector::server(
+            "membership_sync_request_max",
+            v,
+            S::MembershipSyncRequestType(MembershipSyncRequestCommand {
+                known_revisions: (1..=u8::MAX)
+                    .map(|connection_index| KnownRevision {
+                        connection_index: ConnectionIndex(connection_index),
+                        revision: u32::MAX,
+                    })
+                    .collect(),
+            }),
+        ),
+        TestVector::server(
+            "set_presence_zero",
+            v,
+            S::SetPresenceType(SetPresenceCommand::default()),
+        ),
+        TestVector::server(
+            "set_presence_busy",
+            v,
+            S::SetPresenceType(SetPresenceCommand {
+                presence: PresenceState::Busy,
+            }),
+        ),
+        TestVector::server(
+            "pre_vote_request_zero",
+            v,
+            S::PreVoteRequestType(PreVoteRequestCommand::default()),
+        ),
+        TestVector::server(
+            "pre_vote_request_max",
+            v,
+            S::PreVoteRequestType(PreVoteRequestCommand {
+                next_term: MAX_TERM,
+                knowledge: Knowledge::MAX,
+            }),
+        ),
+        TestVector::server(
+            "replicate_zero",
+            v,
+            S::ReplicateType(ReplicateCommand::default()),
+        ),
+        TestVector::server(
+            "replicate_max",
+            v,
+            S::ReplicateType(ReplicateCommand {
+                term: MAX_TERM,
+                prev_knowledge: Knowledge::MAX,
+                entries: vec![
+                    LogEntry {
+                        payload: vec![0xFF; u8::MAX as usize],
+                    };
+                    u8::MAX as usize
+                ],
+            }),
+        ),
+        TestVector::server(
+            "replicate_ack_zero",
+            v,
+            S::ReplicateAckType(ReplicateAckCommand::default()),
+        ),
+        TestVector::server(
+            "replicate_ack_max",
+            v,
+            S::ReplicateAckType(ReplicateAckCommand {
+                term: MAX_TERM,
+                knowledge: Knowledge::MAX,
+                success: true,
+            }),
+        ),
+    ];
+
+    for version in PROTOCOL_VERSION_1..=PROTOCOL_VERSION_6 {
+        vectors.push(TestVector::server(
+            &format!("ping_v{}", version),
+            version,
+            S::PingCommandType(PingCommand {
+                term: MAX_TERM,
+                knowledge: Knowledge::MAX,
+                has_connection_to_leader: true,
+                connection_index: if version >= PROTOCOL_VERSION_5 {
+                    ConnectionIndex(u8::MAX)
+                } else {
+                    ConnectionIndex(0)
+                },
+                clock_drift_ppm: if version >= PROTOCOL_VERSION_6 {
+                    i16::MIN
+                } else {
+                    0
+                },
+                knowledge_vector: None,
+            }),
+        ));
+    }
+    vectors
+}
+
+fn client_vectors() -> Vec<TestVector> {
+    use ClientReceiveCommand as C;
+    let v = PROTOCOL_VERSION;
+    let mut vectors = vec![
+        TestVector::client(
+            "room_info_zero",
+            v,
+            C::RoomInfoType(RoomInfoCommand::default()),
+        ),
+        TestVector::client("room_info_max", v, C::RoomInfoType(room_info_max())),
+        TestVector::client(
+            "room_info_knowledge_vector_max",
+            v,
+            C::RoomInfoType(RoomInfoCommand {
+                knowledge_vector: Some(knowledge_vector_max()),
+                ..room_info_network_stats_max()
+            }),
+        ),
+        TestVector::client(
+            "room_info_network_stats_max",
+            v,
+            C::RoomInfoType(room_info_network_stats_max()),
+        ),
+        TestVector::client(
+            "room_closed_zero",
+            v,
+            C::RoomClosedType(RoomClosedCommand::default()),
+        ),
+        TestVector::client(
+            "room_closed_max",
+            v,
+            C::RoomClosedType(RoomClosedCommand {
+                reason_code: u8::MAX,
+                closes_at_ms: u32::MAX,
+            }),
+        ),
+        TestVector::client(
+            "mute_state_to_client_zero",
+            v,
+            C::MuteStateType(MuteStateCommand::default()),
+        ),
+        TestVector::client(
+            "mute_state_to_client_max",
+            v,
+            C::MuteStateType(MuteStateCommand {
+                connection_index: ConnectionIndex(u8::MAX),
+                muted: true,
+            }),
+        ),
+        TestVector::client(
+            "ping_response_zero",
+            v,
+            C::PingResponseType(PingResponseCommand::default()),
+        ),
+        TestVector::client(
+            "ping_response_max",
+            v,
+            C::PingResponseType(PingResponseCommand {
+                term: MAX_TERM,
+                server_knowledge: Knowledge::MAX,
+                your_knowledge_ack: Knowledge::MAX,
+            }),
+        ),
+        TestVector::client(
+            "relayed_from_client_zero",
+            v,
+            C::RelayedFromClientType(RelayedFromClientCommand::default()),
+        ),
+        TestVector::client(
+            "relayed_from_client_max",
+            v,
+            C::RelayedFromClientType(RelayedFromClientCommand {
+                source_connection_index: ConnectionIndex(u8::MAX),
+                payload: vec![0xFF; MAX_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::client(
+            "broadcasted_payload_zero",
+            v,
+            C::BroadcastedPayloadType(BroadcastedPayloadCommand::default()),
+        ),
+        TestVector::client(
+            "broadcasted_payload_max",
+            v,
+            C::BroadcastedPayloadType(BroadcastedPayloadCommand {
+                source_connection_index: ConnectionIndex(u8::MAX),
+                payload: vec![0xFF; MAX_BROADCAST_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::client(
+            "join_accepted_zero",
+            v,
+            C::JoinAcceptedType(JoinAcceptedCommand::default()),
+        ),
+        TestVector::client(
+            "join_accepted_max",
+            v,
+            C::JoinAcceptedType(JoinAcceptedCommand {
+                connection_index: ConnectionIndex(u8::MAX),
+                room_info: room_info_max(),
+            }),
+        ),
+        TestVector::client(
+            "join_denied_zero",
+            v,
+            C::JoinDeniedType(JoinDeniedCommand::default()),
+        ),
+        TestVector::client(
+            "join_denied_max",
+            v,
+            C::JoinDeniedType(JoinDeniedCommand {
+                reason_code: u8::MAX,
+            }),
+        ),
+        TestVector::client(
+            "resync_data_min",
+            v,
+            C::ResyncDataType(ResyncDataCommand {
+                chunk_count: 1,
+                ..Default::default()
+            }),
+        ),
+        TestVector::client(
+            "resync_data_max",
+            v,
+            C::ResyncDataType(ResyncDataCommand {
+                term: MAX_TERM,
+                knowledge: Knowledge::MAX,
+                chunk_index: u16::MAX - 1,
+                chunk_count: u16::MAX,
+                data: vec![0xFF; MAX_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::client(
+            "snapshot_chunk_min",
+            v,
+            C::SnapshotChunkType(SnapshotChunkCommand {
+                chunk_count: 1,
+                ..Default::default()
+            }),
+        ),
+        TestVector::client(
+            "snapshot_chunk_max",
+            v,
+            C::SnapshotChunkType(SnapshotChunkCommand {
+                snapshot_id: u32::MAX,
+                chunk_index: u16::MAX - 1,
+                chunk_count: u16::MAX,
+                data: vec![0xFF; MAX_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::client(
+            "transfer_chunk_to_client_max",
+            v,
+            C::TransferChunkType(TransferChunkCommand {
+                transfer_id: u32::MAX,
+                chunk_index: u16::MAX - 1,
+                chunk_count: u16::MAX,
+                data: vec![0xFF; MAX_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::client(
+            "transfer_resend_request_to_client_max",
+            v,
+            C::TransferResendRequestType(TransferResendRequestCommand {
+                transfer_id: u32::MAX,
+                missing_chunk_indices: vec![u16::MAX - 1],
+            }),
+        ),
+        TestVector::client(
+            "maintenance_notice_zero",
+            v,
+            C::MaintenanceNoticeType(MaintenanceNoticeCommand::default()),
+        ),
+        TestVector::client(
+            "maintenance_notice_max",
+            v,
+            C::MaintenanceNoticeType(MaintenanceNoticeCommand {
+                starts_in_s: u32::MAX,
+                expected_duration_s: u32::MAX,
+            }),
+        ),
+        TestVector::client(
+            "redirect_min",
+            v,
+            C::RedirectType(RedirectCommand {
+                host: "a".to_string(),
+                port: 0,
+                token: Vec::new(),
+            }),
+        ),
+        TestVector::client(
+            "redirect_max",
+            v,
+            C::RedirectType(RedirectCommand {
+                host: "h".repeat(MAX_REDIRECT_HOST_OCTETS),
+                port: u16::MAX,
+                token: vec![0xFF; MAX_PAYLOAD_OCTETS],
+            }),
+        ),
+        TestVector::client(
+            "server_load_zero",
+            v,
+            C::ServerLoadType(ServerLoadCommand::default()),
+        ),
+        TestVector::client(
+            "server_load_max",
+            v,
+            C::ServerLoadType(ServerLoadCommand {
+                cpu_permille: u16::MAX,
+                rooms: u16::MAX,
+                headroom: u8::MAX,
+            }),
+        ),
+        TestVector::client("hello_to_client", v, C::HelloType(HelloCommand::new(v))),
+        TestVector::client(
+            "challenge_zero",
+            v,
+            C::ChallengeType(ChallengeCommand::default()),
+        ),
+        TestVector::client(
+            "challenge_max",
+            v,
+            C::ChallengeType(ChallengeCommand {
+                challenge: [0xFF; crate::PSK_CHALLENGE_OCTETS],
+                server_load: Some(ServerLoadCommand {
+                    cpu_permille: u16::MAX,
+                    rooms: u16::MAX,
+                    headroom: u8::MAX,
+                }),
+            }),
+        ),
+        TestVector::client(
+            "retry_cookie_zero",
+            v,
+            C::RetryCookieType(RetryCookieCommand::default()),
+        ),
+        TestVector::client(
+            "retry_cookie_max",
+            v,
+            C::RetryCookieType(RetryCookieCommand {
+                cookie: [0xFF; crate::RETRY_COOKIE_OCTETS],
+            }),
+        ),
+        TestVector::client(
+            "throttled_zero",
+            v,
+            C::ThrottledType(ThrottledCommand::default()),
+        ),
+        TestVector::client(
+            "throttled_max",
+            v,
+            C::ThrottledType(ThrottledCommand {
+                retry_after_ms: u16::MAX,
+            }),
+        ),
+        TestVector::client(
+            "timeout_config_typical",
+            v,
+            C::TimeoutConfigType(TimeoutConfigCommand {
+                disconnect_after_ms: 10_000,
+                ping_interval_ms: 500,
+            }),
+        ),
+        TestVector::client(
+            "update_room_settings_typical",
+            v,
+            C::UpdateRoomSettingsType(UpdateRoomSettingsCommand {
+                settings_version: 3,
+                settings: Extensions::from_iter([(0x01, vec![0x08]), (0x02, b"ffa".to_vec())]),
+            }),
+        ),
+        TestVector::client(
+            "channel_profile_zero",
+            v,
+            C::ChannelProfileType(ChannelProfileCommand::default()),
+        ),
+        TestVector::client(
+            "channel_profile_from_descriptors",
+            v,
+            C::ChannelProfileType(ChannelProfileCommand {
+                profile: ChannelProfile::from_descriptors(0, 1),
+            }),
+        ),
+        TestVector::client(
+            "membership_sync_response_zero",
+            v,
+            C::MembershipSyncResponseType(MembershipSyncResponseCommand::default()),
+        ),
+        TestVector::client(
+            "membership_sync_response_max",
+            v,
+            C::MembershipSyncResponseType(MembershipSyncResponseCommand {
+                term: MAX_TERM,
+                leader_connection_index: ConnectionIndex(u8::MAX),
+                changed: room_info_max().client_infos,
+                removed: (0..u8::MAX).map(ConnectionIndex).collect(),
+            }),
+        ),
+        TestVector::client(
+            "room_info_page_single",
+            v,
+            C::RoomInfoPageType(RoomInfoPageCommand {
+                page_count: 1,
+                ..RoomInfoPageCommand::default()
+            }),
+        ),
+        TestVector::client(
+            "room_info_page_max",
+            v,
+            C::RoomInfoPageType({
+                let room_info = room_info_max();
+                RoomInfoPageCommand {
+                    term: room_info.term,
+                    leader_index: room_info.leader_index,
+                    page_index: u8::MAX - 1,
+                    page_count: u8::MAX,
+                    client_infos: room_info.client_infos,
+                    knowledge_vector: None,
+                    quorum_size: room_info.quorum_size,
+                    votes_for_leader: room_info.votes_for_leader,
+                    leader_lease_expires_in_ms: room_info.leader_lease_expires_in_ms,
+                }
+            }),
+        ),
+        TestVector::client(
+            "room_info_page_knowledge_vector",
+            v,
+            C::RoomInfoPageType(RoomInfoPageCommand {
+                page_count: 1,
+                knowledge_vector: Some(knowledge_vector_max()),
+                ..RoomInfoPageCommand::default()
+            }),
+        ),
+        TestVector::client(
+            "knowledge_ack_zero",
+            v,
+            C::KnowledgeAckType(KnowledgeAckCommand::default()),
+        ),
+        TestVector::client(
+            "knowledge_ack_max",
+            v,
+            C::KnowledgeAckType(KnowledgeAckCommand {
+                acked_knowledge: Knowledge::MAX,
+            }),
+        ),
+        TestVector::client(
+            "term_advanced_zero",
+            v,
+            C::TermAdvancedType(TermAdvancedCommand::default()),
+        ),
+        TestVector::client(
+            "term_advanced_max",
+            v,
+            C::TermAdvancedType(TermAdvancedCommand {
+                new_term: MAX_TERM,
+                cause: u8::MAX,
+            }),
+        ),
+        TestVector::client(
+            "pre_vote_response_zero",
+            v,
+            C::PreVoteResponseType(PreVoteResponseCommand::default()),
+        ),
+        TestVector::client(
+            "pre_vote_response_granted",
+            v,
+            C::PreVoteResponseType(PreVoteResponseCommand {
+                next_term: MAX_TERM,
+                granted: true,
+            }),
+        ),
+        TestVector::client(
+            "replicate_to_client_max",
+            v,
+            C::ReplicateType(ReplicateCommand {
+                term: MAX_TERM,
+                prev_knowledge: Knowledge::MAX,
+                entries: vec![
+                    LogEntry {
+                        payload: vec![0xFF; u8::MAX as usize],
+                    };
+                    u8::MAX as usize
+                ],
+            }),
+        ),
+        TestVector::client(
+            "replicate_ack_to_client_max",
+            v,
+            C::ReplicateAckType(ReplicateAckCommand {
+                term: MAX_TERM,
+                knowledge: Knowledge::MAX,
+                success: true,
+            }),
+        ),
+    ];
+
+    for version in [
+        PROTOCOL_VERSION_1,
+        PROTOCOL_VERSION_2,
+        PROTOCOL_VERSION_3,
+        PROTOCOL_VERSION_4,
+        PROTOCOL_VERSION_6,
+        PROTOCOL_VERSION_7,
+        PROTOCOL_VERSION_8,
+        PROTOCOL_VERSION_9,
+        PROTOCOL_VERSION_11,
+    ] {
+        vectors.push(TestVector::client(
+            &format!("room_info_v{}", version),
+            version,
+            C::RoomInfoType(room_info_for_version(version)),
+        ));
+    }
+    vectors
+}
+
+/// Every command at its boundary values: all fields zero, all fields at their maximum and the
+/// layouts of older protocol versions
+/// The values to try for a field, besides the zero or minimum count every field starts at
+fn boundaries(kind: &FieldKind) -> &'static [u64] {
+    match kind {
+        FieldKind::U8 => &[0xFF],
+        FieldKind::Bool => &[1],
+        // Crossing into the high octet, and the last value before wrapping to zero
+        FieldKind::U16 => &[0xFF, 0x100, 0xFFFF],
+        FieldKind::I16 => &[i16::MAX as u16 as u64, i16::MIN as u16 as u64],
+        FieldKind::U32 => &[u32::MAX as u64],
+        FieldKind::U64 => &[u64::MAX],
+        // The largest values of one and two octets, and the smallest of two and three
+        FieldKind::Varint => &[127, 128, 16383, 16384, u64::MAX],
+        FieldKind::Octets { prefix, .. }
+        | FieldKind::Text { prefix, .. }
+        | FieldKind::List { prefix, .. } => match prefix {
+            LengthPrefix::U8 => &[0xFF],
+            LengthPrefix::U16 => &[0xFF, 0x100, 0xFFFF],
+        },
+        _ => &[],
+    }
+}
+
+/// The fields of `fields` present in `version`, depth first, with their paths. List and array
+/// elements follow the list, once, with `[0]` in the path. Flagged fields are left out, as
+/// their flags start cleared.
+fn push_boundary_fields(
+    fields: &'static [FieldDescriptor],
+    version: ProtocolVersion,
+    prefix: &str,
+    out: &mut Vec<(String, &'static FieldKind)>,
+) {
+    for field in fields.iter().filter(|field| field.since <= version) {
+        push_boundary_kind(
+            &field.kind,
+            version,
+            format!("{}{}", prefix, field.name),
+            out,
+        );
+    }
+}
+
+fn push_boundary_kind(
+    kind: &'static FieldKind,
+    version: ProtocolVersion,
+    path: String,
+    out: &mut Vec<(String, &'static FieldKind)>,
+) {
+    out.push((path.clone(), kind));
+    match kind {
+        FieldKind::Array { element, .. } | FieldKind::List { element, .. } => {
+            push_boundary_kind(element, version, format!("{}[0]", path), out)
+        }
+        FieldKind::Struct(descriptor) => {
+            push_boundary_fields(descriptor.fields, version, &format!("{}.", path), out)
+        }
+        _ => {}
+    }
+}
+
+fn boundary_field_count(kind: &FieldKind, version: ProtocolVersion) -> usize {
+    1 + match kind {
+        FieldKind::Array { element, .. } | FieldKind::List { element, .. } => {
+            boundary_field_count(element, version)
+        }
+        FieldKind::Struct(descriptor) => descriptor
+            .fields
+            .iter()
+            .filter(|field| field.since <= version)
+            .map(|field| boundary_field_count(&field.kind, version))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Writes the fields of a descriptor with every field at zero or its minimum count, and counts of
+/// chunks or pages at one, except the
+/// one numbered `target` in the order of [`push_boundary_fields`], which gets `value`
+struct BoundaryWriter {
+    version: ProtocolVersion,
+    target: usize,
+    value: u64,
+    next_field: usize,
+    out: OutOctetStream,
+}
+
+impl BoundaryWriter {
+    fn write_fields(&mut self, fields: &[FieldDescriptor]) -> Result<()> {
+        let version = self.version;
+        for field in fields.iter().filter(|field| field.since <= version) {
+            // The chunk and page indices must be below their count
+            let min = u64::from(field.name.ends_with("_count"));
+            self.write_kind(&field.kind, min)?;
+        }
+        Ok(())
+    }
+
+    fn write_prefix(&mut self, prefix: LengthPrefix, count: usize) -> Result<()> {
+        match prefix {
+            LengthPrefix::U8 => self.out.write_u8(count as u8),
+            LengthPrefix::U16 => self.out.write_u16(count as u16),
+        }
+    }
+
+    fn write_kind(&mut self, kind: &FieldKind, min: u64) -> Result<()> {
+        let index = self.next_field;
+        self.next_field += 1;
+        let value = if index == self.target {
+            self.value
+        } else {
+            min
+        };
+        match kind {
+            FieldKind::U8 | FieldKind::Bool => self.out.write_u8(value as u8),
+            FieldKind::U16 | FieldKind::I16 => self.out.write_u16(value as u16),
+            FieldKind::U32 => self.out.write_u32(value as u32),
+            FieldKind::U64 => self.out.write_u64(value),
+            FieldKind::Varint => write_varint(&mut self.out, value),
+            FieldKind::Magic(octets) => self.out.write(octets),
+            FieldKind::FixedOctets(count) => self.out.write(&vec![0; *count]),
+            FieldKind::Octets { prefix, min } => {
+                let count = (value as usize).max(*min);
+                self.write_prefix(*prefix, count)?;
+                self.out.write(&vec![0; count])
+            }
+            FieldKind::Text { prefix, min } => {
+                let count = (value as usize).max(*min);
+                self.write_prefix(*prefix, count)?;
+                self.out.write(&vec![b'a'; count])
+            }
+            FieldKind::Array { count, element } => self.write_elements(*count, element),
+            FieldKind::List {
+                prefix,
+                min,
+                element,
+            } => {
+                let elements =
+                    self.next_field..self.next_field + boundary_field_count(element, self.version);
+                let mut count = (value as usize).max(*min);
+                if elements.contains(&self.target) {
+                    count = count.max(1);
+                }
+                self.write_prefix(*prefix, count)?;
+                self.write_elements(count, element)
+            }
+            FieldKind::Struct(descriptor) => self.write_fields(descriptor.fields),
+            FieldKind::ClientIdentity => {
+                ClientIdentity::default().to_octets_with_version(&mut self.out, self.version)
+            }
+            FieldKind::UserId => self.out.write(&vec![0; UserId::octet_size(self.version)]),
+            FieldKind::SocketAddr => write_socket_addr(
+                &mut self.out,
+                &SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            ),
+            FieldKind::Flagged { .. } => Ok(()),
+        }
+    }
+
+    /// Every element gets the same fields, so a target in the element is set in all of them
+    fn write_elements(&mut self, count: usize, element: &FieldKind) -> Result<()> {
+        let first_field = self.next_field;
+        for _ in 0..count {
+            self.next_field = first_field;
+            self.write_kind(element, 0)?;
+        }
+        self.next_field = first_field + boundary_field_count(element, self.version);
+        Ok(())
+    }
+}
+
+/// Decodes the octets of `descriptor` with the field numbered `target` set to `value`
+fn boundary_vector(
+    descriptor: &CommandDescriptor,
+    name: &str,
+    version: ProtocolVersion,
+    target: usize,
+    value: u64,
+) -> Result<TestVector> {
+    let mut writer = BoundaryWriter {
+        version,
+        target,
+        value,
+        next_field: 0,
+        out: OutOctetStream::new(),
+    };
+    writer.out.write_u8(descriptor.type_id)?;
+    writer.write_fields(descriptor.fields)?;
+    let octets = writer.out.data;
+    let vector = if descriptor.direction.is_server_receive() {
+        TestVector::server(
+            name,
+            version,
+            ServerReceiveCommand::decode_exact(&octets, version)?,
+        )
+    } else {
+        TestVector::client(
+            name,
+            version,
+            ClientReceiveCommand::decode_exact(&octets, version)?,
+        )
+    };
+    if vector.to_vec()? != octets {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "decoded command does not encode to the same octets",
+        ));
+    }
+    Ok(vector)
+}
+
+/// Generated from [`COMMAND_DESCRIPTORS`]: each command with every field at zero or its
+/// minimum count, then with one field at a time at each of its [`boundaries`], e.g. a term of
+/// `0xFFFF`, the last before it wraps to zero, varints at the limits of every width and lengths
+/// at the limits of their prefix. Values a command does not accept, e.g. a payload above a limit
+/// lower than its prefix allows or a reserved term, are left out, and so are values that do not
+/// encode back to the same octets, e.g. unknown flag bits.
+fn boundary_vectors() -> Vec<TestVector> {
+    let version = PROTOCOL_VERSION;
+    let mut vectors = Vec::new();
+    for descriptor in COMMAND_DESCRIPTORS
+        .iter()
+        .filter(|descriptor| descriptor.min_version <= version)
+    {
+        let Ok(vector) = boundary_vector(
+            descriptor,
+            &format!("{}_boundary_min", descriptor.name),
+            version,
+            usize::MAX,
+            0,
+        ) else {
+            continue;
+        };
+        vectors.push(vector);
+        let mut fields = Vec::new();
+        push_boundary_fields(descriptor.fields, version, "", &mut fields);
+        for (target, (path, kind)) in fields.iter().enumerate() {
+            let path = path.replace("[0]", "_0").replace('.', "_");
+            for &value in boundaries(kind) {
+                let name = format!("{}_boundary_{}_{}", descriptor.name, path, value);
+                if let Ok(vector) = boundary_vector(descriptor, &name, version, target, value) {
+                    vectors.push(vector);
+                }
+            }
+        }
+    }
+    vectors
+}
+
+pub fn test_vectors() -> Vec<TestVector> {
+    let mut vectors = server_vectors();
+    vectors.extend(client_vectors());
+    vectors.extend(boundary_vectors());
+    vectors
+}
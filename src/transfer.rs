@@ -0,0 +1,454 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::error::in_field;
+use crate::payload::{read_payload, write_payload, MAX_PAYLOAD_OCTETS};
+use crate::{ProtocolVersion, PROTOCOL_VERSION_5};
+
+/// Chosen by the sender, and repeated in every chunk of the transfer
+pub type TransferId = u32;
+
+/// Splits `data` into at most `u16::MAX` parts of at most `max_chunk_octets` each. Empty `data`
+/// still results in a single, empty, chunk.
+pub(crate) fn split_chunks(data: &[u8], max_chunk_octets: usize) -> Result<Vec<&[u8]>> {
+    let max_chunk_octets = max_chunk_octets.min(MAX_PAYLOAD_OCTETS);
+    if max_chunk_octets == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "max_chunk_octets must be at least one",
+        ));
+    }
+    let chunk_count = data.len().div_ceil(max_chunk_octets).max(1);
+    if chunk_count > u16::MAX as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("data needs too many chunks ({})", chunk_count),
+        ));
+    }
+    if data.is_empty() {
+        return Ok(vec![data]);
+    }
+    Ok(data.chunks(max_chunk_octets).collect())
+}
+
+pub(crate) fn check_chunk_index(chunk_index: u16, chunk_count: u16) -> Result<()> {
+    if chunk_index >= chunk_count {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "chunk index {} is not below chunk count {}",
+                chunk_index, chunk_count
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Bounds what is kept for transfers that have not completed, so a peer can not make the
+/// receiver grow without limit, e.g. by naming a new transfer in every chunk. When a limit is
+/// reached, the transfer that received a chunk the longest ago is dropped.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ReassemblyLimits {
+    /// Transfers collected at the same time
+    pub max_pending: usize,
+    /// The chunk slots and the received chunks of all pending transfers together
+    pub max_buffered_octets: usize,
+}
+
+impl ReassemblyLimits {
+    pub const DEFAULT: Self = Self {
+        max_pending: 16,
+        max_buffered_octets: 4 * 1024 * 1024,
+    };
+}
+
+impl Default for ReassemblyLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+struct ChunkAssembly {
+    chunks: Vec<Option<Vec<u8>>>,
+    received_count: usize,
+    buffered_octets: usize,
+    last_received: u64,
+}
+
+impl ChunkAssembly {
+    fn missing_chunk_indices(&self) -> Vec<u16> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.is_none())
+            .map(|(index, _)| index as u16)
+            .collect()
+    }
+}
+
+fn slot_octets(chunk_count: u16) -> usize {
+    chunk_count as usize * std::mem::size_of::<Option<Vec<u8>>>()
+}
+
+/// Collects chunks, arriving in any order, for several concurrent transfers keyed by `K`.
+pub(crate) struct Reassembly<K> {
+    partial: HashMap<K, ChunkAssembly>,
+    limits: ReassemblyLimits,
+    buffered_octets: usize,
+    receive_count: u64,
+}
+
+impl<K> Default for Reassembly<K> {
+    fn default() -> Self {
+        Self::new(ReassemblyLimits::DEFAULT)
+    }
+}
+
+impl<K> Reassembly<K> {
+    pub fn new(limits: ReassemblyLimits) -> Self {
+        Self {
+            partial: HashMap::new(),
+            limits,
+            buffered_octets: 0,
+            receive_count: 0,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Copy + std::fmt::Display> Reassembly<K> {
+    /// Returns the data once the last missing chunk is received. Duplicate chunks are ignored, a
+    /// chunk count that differs from earlier chunks of the transfer is an error. Other
+    /// transfers are dropped, least recently received first, to stay within the limits. A
+    /// transfer that can not fit the limits on its own is dropped, and is an error.
+    pub fn receive(
+        &mut self,
+        key: K,
+        chunk_index: u16,
+        chunk_count: u16,
+        data: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>> {
+        check_chunk_index(chunk_index, chunk_count)?;
+        self.receive_count += 1;
+        if !self.partial.contains_key(&key) {
+            let octets = slot_octets(chunk_count);
+            if self.limits.max_pending == 0 || octets > self.limits.max_buffered_octets {
+                return Err(Self::over_limits(key));
+            }
+            while self.partial.len() >= self.limits.max_pending {
+                self.evict_oldest_except(key);
+            }
+            self.buffered_octets += octets;
+            self.partial.insert(
+                key,
+                ChunkAssembly {
+                    chunks: vec![None; chunk_count as usize],
+                    received_count: 0,
+                    buffered_octets: octets,
+                    last_received: 0,
+                },
+            );
+        }
+        let assembly = self.partial.get_mut(&key).expect("transfer is pending");
+        if assembly.chunks.len() != chunk_count as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} chunk count changed from {} to {}",
+                    key,
+                    assembly.chunks.len(),
+                    chunk_count
+                ),
+            ));
+        }
+        assembly.last_received = self.receive_count;
+        let slot = &mut assembly.chunks[chunk_index as usize];
+        if slot.is_some() {
+            return Ok(None);
+        }
+        if assembly.buffered_octets + data.len() > self.limits.max_buffered_octets {
+            self.remove(key);
+            return Err(Self::over_limits(key));
+        }
+        assembly.buffered_octets += data.len();
+        self.buffered_octets += data.len();
+        *slot = Some(data);
+        assembly.received_count += 1;
+        let complete = assembly.received_count == assembly.chunks.len();
+        while self.buffered_octets > self.limits.max_buffered_octets {
+            self.evict_oldest_except(key);
+        }
+        if !complete {
+            return Ok(None);
+        }
+
+        let assembly = self.remove(key).expect("transfer is pending");
+        Ok(Some(
+            assembly.chunks.into_iter().flatten().flatten().collect(),
+        ))
+    }
+
+    fn over_limits(key: K) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{} does not fit the reassembly limits", key),
+        )
+    }
+
+    fn evict_oldest_except(&mut self, key: K) {
+        let oldest = self
+            .partial
+            .iter()
+            .filter(|(other, _)| **other != key)
+            .min_by_key(|(_, assembly)| assembly.last_received)
+            .map(|(other, _)| *other)
+            .expect("another transfer is pending");
+        self.remove(oldest);
+    }
+
+    fn remove(&mut self, key: K) -> Option<ChunkAssembly> {
+        let assembly = self.partial.remove(&key)?;
+        self.buffered_octets -= assembly.buffered_octets;
+        Some(assembly)
+    }
+
+    pub fn missing_chunk_indices(&self, key: K) -> Option<Vec<u16>> {
+        self.partial
+            .get(&key)
+            .map(ChunkAssembly::missing_chunk_indices)
+    }
+
+    pub fn cancel(&mut self, key: K) -> bool {
+        self.remove(key).is_some()
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.partial.len()
+    }
+
+    pub fn buffered_octets(&self) -> usize {
+        self.buffered_octets
+    }
+}
+
+/// One part of a transfer, sent in either direction
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransferChunkCommand {
+    pub transfer_id: TransferId,
+    pub chunk_index: u16,
+    pub chunk_count: u16,
+    /// At most [`MAX_PAYLOAD_OCTETS`]
+    pub data: Vec<u8>,
+}
+
+impl TransferChunkCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "transfer_chunk";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        4 + 2 + 2 + 2
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        stream.write_u32(self.transfer_id)?;
+        stream.write_u16(self.chunk_index)?;
+        stream.write_u16(self.chunk_count)?;
+        in_field(
+            write_payload(stream, &self.data, MAX_PAYLOAD_OCTETS),
+            "data",
+        )
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let transfer_id = in_field(stream.read_u32(), "transfer_id")?;
+        let chunk_index = in_field(stream.read_u16(), "chunk_index")?;
+        let chunk_count = in_field(stream.read_u16(), "chunk_count")?;
+        in_field(check_chunk_index(chunk_index, chunk_count), "chunk_index")?;
+        Ok(Self {
+            transfer_id,
+            chunk_index,
+            chunk_count,
+            data: in_field(read_payload(stream, MAX_PAYLOAD_OCTETS), "data")?,
+        })
+    }
+}
+
+/// Sent by the receiver of a transfer, asking for the chunks it has not received
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransferResendRequestCommand {
+    pub transfer_id: TransferId,
+    pub missing_chunk_indices: Vec<u16>,
+}
+
+impl TransferResendRequestCommand {
+    /// Name of the command in decode error paths
+    pub const NAME: &'static str = "transfer_resend_request";
+    pub const MIN_VERSION: ProtocolVersion = PROTOCOL_VERSION_5;
+
+    /// The smallest serialized payload, not counting the command type id
+    pub fn min_octet_size(_version: ProtocolVersion) -> usize {
+        4 + 2
+    }
+
+    pub fn to_octets(&self, stream: &mut impl WriteOctetStream) -> Result<()> {
+        if self.missing_chunk_indices.len() > u16::MAX as usize {
+            return in_field(
+                Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "too many missing chunks ({})",
+                        self.missing_chunk_indices.len()
+                    ),
+                )),
+                "missing_chunk_indices.len",
+            );
+        }
+        stream.write_u32(self.transfer_id)?;
+        stream.write_u16(self.missing_chunk_indices.len() as u16)?;
+        for chunk_index in &self.missing_chunk_indices {
+            stream.write_u16(*chunk_index)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn from_cursor(stream: &mut impl ReadOctetStream) -> Result<Self> {
+        let transfer_id = in_field(stream.read_u32(), "transfer_id")?;
+        let length = in_field(stream.read_u16(), "missing_chunk_indices.len")? as usize;
+        let mut missing_chunk_indices = Vec::with_capacity(length);
+        for index in 0..length {
+            missing_chunk_indices.push(in_field(
+                stream.read_u16(),
+                format_args!("missing_chunk_indices[{}]", index),
+            )?);
+        }
+        Ok(Self {
+            transfer_id,
+            missing_chunk_indices,
+        })
+    }
+}
+
+/// Splits a byte blob into [`TransferChunkCommand`]s and keeps it around to answer
+/// [`TransferResendRequestCommand`]s.
+#[derive(Debug)]
+pub struct ChunkSender {
+    transfer_id: TransferId,
+    data: Vec<u8>,
+    max_chunk_octets: usize,
+    chunk_count: u16,
+}
+
+impl ChunkSender {
+    pub fn new(transfer_id: TransferId, data: Vec<u8>, max_chunk_octets: usize) -> Result<Self> {
+        let chunk_count = split_chunks(&data, max_chunk_octets)?.len() as u16;
+        Ok(Self {
+            transfer_id,
+            data,
+            max_chunk_octets: max_chunk_octets.min(MAX_PAYLOAD_OCTETS),
+            chunk_count,
+        })
+    }
+
+    pub fn transfer_id(&self) -> TransferId {
+        self.transfer_id
+    }
+
+    pub fn chunk_count(&self) -> u16 {
+        self.chunk_count
+    }
+
+    pub fn chunk(&self, chunk_index: u16) -> Option<TransferChunkCommand> {
+        if chunk_index >= self.chunk_count {
+            return None;
+        }
+        let start = chunk_index as usize * self.max_chunk_octets;
+        let end = (start + self.max_chunk_octets).min(self.data.len());
+        Some(TransferChunkCommand {
+            transfer_id: self.transfer_id,
+            chunk_index,
+            chunk_count: self.chunk_count,
+            data: self.data[start..end].to_vec(),
+        })
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = TransferChunkCommand> + '_ {
+        (0..self.chunk_count).filter_map(|chunk_index| self.chunk(chunk_index))
+    }
+
+    /// The chunks asked for, ignoring requests for other transfers and unknown chunk indices.
+    pub fn on_resend_request(
+        &self,
+        request: &TransferResendRequestCommand,
+    ) -> Vec<TransferChunkCommand> {
+        if request.transfer_id != self.transfer_id {
+            return Vec::new();
+        }
+        request
+            .missing_chunk_indices
+            .iter()
+            .filter_map(|chunk_index| self.chunk(*chunk_index))
+            .collect()
+    }
+}
+
+/// Reassembles [`TransferChunkCommand`]s for any number of concurrent transfers.
+#[derive(Default)]
+pub struct ChunkReceiver {
+    reassembly: Reassembly<TransferId>,
+}
+
+impl ChunkReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limits(mut self, limits: ReassemblyLimits) -> Self {
+        self.reassembly = Reassembly::new(limits);
+        self
+    }
+
+    /// Returns the data of the transfer once the last missing chunk is received. Other
+    /// transfers are dropped to stay within the [`ReassemblyLimits`].
+    pub fn receive(&mut self, chunk: TransferChunkCommand) -> Result<Option<Vec<u8>>> {
+        self.reassembly.receive(
+            chunk.transfer_id,
+            chunk.chunk_index,
+            chunk.chunk_count,
+            chunk.data,
+        )
+    }
+
+    /// Asks for the chunks not yet received, `None` if the transfer is not pending.
+    pub fn resend_request(&self, transfer_id: TransferId) -> Option<TransferResendRequestCommand> {
+        self.reassembly
+            .missing_chunk_indices(transfer_id)
+            .map(|missing_chunk_indices| TransferResendRequestCommand {
+                transfer_id,
+                missing_chunk_indices,
+            })
+    }
+
+    pub fn cancel(&mut self, transfer_id: TransferId) -> bool {
+        self.reassembly.cancel(transfer_id)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.reassembly.pending_count()
+    }
+
+    /// What the pending transfers use of [`ReassemblyLimits::max_buffered_octets`]
+    pub fn buffered_octets(&self) -> usize {
+        self.reassembly.buffered_octets()
+    }
+}
@@ -0,0 +1,96 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+use crate::{ProtocolVersion, PROTOCOL_VERSION_3};
+
+/// Custom user id, either a plain 64-bit id or a 128-bit id such as a UUID.
+///
+/// Serialized as 8 octets before [`PROTOCOL_VERSION_3`] and as 16 octets from it on.
+/// Displayed as hex with a `0x` prefix, which [`FromStr`] also accepts without the prefix.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UserId(pub u128);
+
+impl UserId {
+    pub fn octet_size(version: ProtocolVersion) -> usize {
+        if version >= PROTOCOL_VERSION_3 {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub fn is_wide(&self) -> bool {
+        self.0 > u64::MAX as u128
+    }
+
+    pub fn to_octets_with_version(
+        &self,
+        stream: &mut impl WriteOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<()> {
+        if version >= PROTOCOL_VERSION_3 {
+            stream.write_u64((self.0 >> 64) as u64)?;
+        } else if self.is_wide() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "user id 0x{:x} needs the wide layout of protocol version {}",
+                    self.0, PROTOCOL_VERSION_3
+                ),
+            ));
+        }
+        stream.write_u64(self.0 as u64)
+    }
+
+    pub fn from_cursor_with_version(
+        stream: &mut impl ReadOctetStream,
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        let high = if version >= PROTOCOL_VERSION_3 {
+            stream.read_u64()? as u128
+        } else {
+            0
+        };
+        let low = stream.read_u64()? as u128;
+        Ok(Self((high << 64) | low))
+    }
+}
+
+impl From<u64> for UserId {
+    fn from(value: u64) -> Self {
+        Self(value as u128)
+    }
+}
+
+impl From<u128> for UserId {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:x}", self.0)
+    }
+}
+
+impl FromStr for UserId {
+    type Err = ParseIntError;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let digits = text
+            .strip_prefix("0x")
+            .or_else(|| text.strip_prefix("0X"))
+            .unwrap_or(text);
+        u128::from_str_radix(digits, 16).map(Self)
+    }
+}
@@ -0,0 +1,45 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::io::{Error, ErrorKind, Result};
+
+use flood_rs::{ReadOctetStream, WriteOctetStream};
+
+/// The most octets a `u64` varint occupies
+pub const MAX_VARINT_OCTETS: usize = 10;
+
+/// Writes `value` seven bits at a time, least significant group first. The high bit of each
+/// octet is set when more octets follow.
+pub(crate) fn write_varint(stream: &mut impl WriteOctetStream, mut value: u64) -> Result<()> {
+    while value >= 0x80 {
+        stream.write_u8((value as u8 & 0x7F) | 0x80)?;
+        value >>= 7;
+    }
+    stream.write_u8(value as u8)
+}
+
+/// Reads a varint written by [`write_varint`], rejecting overlong and overflowing encodings.
+pub(crate) fn read_varint(stream: &mut impl ReadOctetStream) -> Result<u64> {
+    let mut value = 0u64;
+    for index in 0..MAX_VARINT_OCTETS {
+        let octet = stream.read_u8()?;
+        let group = (octet & 0x7F) as u64;
+        if index == MAX_VARINT_OCTETS - 1 && group > 1 {
+            return Err(Error::new(ErrorKind::InvalidData, "varint overflows u64"));
+        }
+        value |= group << (7 * index);
+        if octet & 0x80 == 0 {
+            if index > 0 && group == 0 {
+                return Err(Error::new(ErrorKind::InvalidData, "overlong varint"));
+            }
+            return Ok(value);
+        }
+    }
+    Err(Error::new(ErrorKind::InvalidData, "varint overflows u64"))
+}
+
+/// The number of octets `write_varint` uses for `value`
+pub fn varint_octet_size(value: u64) -> usize {
+    (64 - value.max(1).leading_zeros() as usize).div_ceil(7)
+}
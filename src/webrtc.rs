@@ -0,0 +1,109 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/piot/conclave-room-serialize-rs
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+//! Commands over WebRTC data channels, for room clients running in a browser.
+//!
+//! SCTP limits the size of a data channel message, and browsers only agree on
+//! [`WEBRTC_MAX_MESSAGE_OCTETS`] unless a larger `max-message-size` is negotiated. Every
+//! command is sent as the datagrams of [`crate::split_command`], one per message, so a large
+//! `RoomInfoCommand` still arrives, also on an unordered channel.
+//!
+//! Both ends use the [`DEFAULT_PROTOCOL_VERSION`] unless `with_version` sets the negotiated one.
+
+use std::io::Result;
+
+use crate::{
+    split_command_with_version, Command, MessageId, PartialMessageBuffer, ProtocolVersion,
+    ReassemblyLimits, DEFAULT_PROTOCOL_VERSION,
+};
+
+/// The largest message every browser accepts without a negotiated `max-message-size`
+pub const WEBRTC_MAX_MESSAGE_OCTETS: usize = 16 * 1024;
+
+/// Encodes commands into data channel messages.
+pub struct DataChannelSender {
+    max_message_octets: usize,
+    next_message_id: MessageId,
+    version: ProtocolVersion,
+}
+
+impl Default for DataChannelSender {
+    fn default() -> Self {
+        Self {
+            max_message_octets: WEBRTC_MAX_MESSAGE_OCTETS,
+            next_message_id: 0,
+            version: DEFAULT_PROTOCOL_VERSION,
+        }
+    }
+}
+
+impl DataChannelSender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// E.g. the `max-message-size` of the remote SDP
+    pub fn with_max_message_octets(mut self, max_message_octets: usize) -> Self {
+        self.max_message_octets = max_message_octets;
+        self
+    }
+
+    pub fn with_version(mut self, version: ProtocolVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The messages to send, in order, each at most the max message size
+    pub fn encode<C: Command>(&mut self, command: &C) -> Result<Vec<Vec<u8>>> {
+        let messages = split_command_with_version(
+            command,
+            self.next_message_id,
+            self.max_message_octets,
+            self.version,
+        )?;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+        Ok(messages)
+    }
+}
+
+/// Decodes data channel messages, and calls `on_command` for every command that completes or
+/// message that fails. On an unordered or lossy channel, commands that lose one of their messages
+/// are dropped once the [`ReassemblyLimits`] are reached.
+pub struct DataChannelReceiver<C, F> {
+    buffer: PartialMessageBuffer<C>,
+    on_command: F,
+}
+
+impl<C: Command, F: FnMut(Result<C>)> DataChannelReceiver<C, F> {
+    pub fn new(on_command: F) -> Self {
+        Self {
+            buffer: PartialMessageBuffer::new(),
+            on_command,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ReassemblyLimits) -> Self {
+        self.buffer = self.buffer.with_limits(limits);
+        self
+    }
+
+    pub fn with_version(mut self, version: ProtocolVersion) -> Self {
+        self.buffer = self.buffer.with_version(version);
+        self
+    }
+
+    /// Call from the `message` event of the data channel
+    pub fn on_message(&mut self, message: &[u8]) {
+        match self.buffer.receive(message) {
+            Ok(Some(command)) => (self.on_command)(Ok(command)),
+            Ok(None) => {}
+            Err(err) => (self.on_command)(Err(err)),
+        }
+    }
+
+    /// Commands that still wait for some of their messages
+    pub fn pending_count(&self) -> usize {
+        self.buffer.pending_count()
+    }
+}